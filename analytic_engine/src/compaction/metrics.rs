@@ -3,7 +3,10 @@
 //! Metrics of compaction.
 
 use lazy_static::lazy_static;
-use prometheus::{register_int_gauge, IntGauge};
+use prometheus::{
+    exponential_buckets, register_histogram, register_histogram_vec, register_int_counter,
+    register_int_gauge, Histogram, HistogramVec, IntCounter, IntGauge,
+};
 
 lazy_static! {
     // Counters:
@@ -12,4 +15,43 @@ lazy_static! {
         "Pending request queue length of compaction"
     )
         .unwrap();
+    pub static ref COMPACTION_MEMORY_LIMITED_COUNTER: IntCounter = register_int_counter!(
+        "compaction_memory_limited_counter",
+        "Number of compaction tasks put back because of the memory limit"
+    )
+        .unwrap();
+    pub static ref COMPACTION_DISK_LIMITED_COUNTER: IntCounter = register_int_counter!(
+        "compaction_disk_limited_counter",
+        "Number of compaction tasks deferred because of the insufficient disk space"
+    )
+        .unwrap();
+    pub static ref COMPACTION_THROTTLED_COUNTER: IntCounter = register_int_counter!(
+        "compaction_throttled_counter",
+        "Number of compaction tasks deferred by the compaction throttle"
+    )
+        .unwrap();
+
+    // Histograms:
+    // Buckets: 0, 1, .., 2^9
+    pub static ref COMPACTION_INPUT_FILE_NUM_HISTOGRAM: Histogram = register_histogram!(
+        "compaction_input_file_num",
+        "Histogram for number of input files of the scheduled compaction tasks",
+        exponential_buckets(1.0, 2.0, 10).unwrap()
+    )
+        .unwrap();
+    // Buckets: 0.01s, 0.02s, .., 2^14 * 0.01s
+    pub static ref SCHEDULED_FLUSH_DURATION_HISTOGRAM: HistogramVec = register_histogram_vec!(
+        "scheduled_flush_duration",
+        "Histogram for duration (in seconds) of the table flushes triggered by the scheduler",
+        &["table"],
+        exponential_buckets(0.01, 2.0, 15).unwrap()
+    )
+        .unwrap();
+    // Buckets: 0.01s, 0.02s, .., 2^14 * 0.01s
+    pub static ref COMPACTION_PENDING_WAIT_DURATION_HISTOGRAM: Histogram = register_histogram!(
+        "compaction_pending_wait_duration",
+        "Histogram for duration (in seconds) of the compaction requests waiting in the pending queue",
+        exponential_buckets(0.01, 2.0, 15).unwrap()
+    )
+        .unwrap();
 }
@@ -2,11 +2,17 @@
 
 //! Compaction.
 
-use std::{collections::HashMap, str::FromStr, sync::Arc};
+use std::{
+    collections::HashMap,
+    str::FromStr,
+    sync::{Arc, RwLock},
+    time::Instant,
+};
 
 use common_util::config::{ReadableSize, TimeUnit};
 use serde_derive::Deserialize;
 use snafu::{ensure, Backtrace, GenerateBacktrace, ResultExt, Snafu};
+use table_engine::table::{CompactionSummary, TableId};
 use tokio::sync::oneshot;
 
 use crate::{
@@ -353,15 +359,27 @@ impl CompactionTask {
         total_input_size as usize
     }
 
+    // Estimate the size of the output files, the output of merging the input
+    // files is not supposed to be larger than the input.
+    pub fn estimated_output_file_size(&self) -> usize {
+        self.estimated_total_input_file_size()
+    }
+
     pub fn num_input_files(&self) -> usize {
         self.compaction_inputs.iter().map(|v| v.files.len()).sum()
     }
 }
 
+/// Pickers of the compaction strategies, and the pickers of specific tables
+/// can be overridden, e.g. to experiment with a custom picker.
+///
+/// The overrides are shared among the clones of the manager.
+#[derive(Clone)]
 pub struct PickerManager {
     default_picker: CompactionPickerRef,
     time_window_picker: CompactionPickerRef,
     size_tiered_picker: CompactionPickerRef,
+    table_pickers: Arc<RwLock<HashMap<TableId, CompactionPickerRef>>>,
 }
 
 impl Default for PickerManager {
@@ -377,6 +395,7 @@ impl Default for PickerManager {
             default_picker: time_window_picker.clone(),
             size_tiered_picker,
             time_window_picker,
+            table_pickers: Arc::new(RwLock::new(HashMap::new())),
         }
     }
 }
@@ -389,6 +408,36 @@ impl PickerManager {
             CompactionStrategy::TimeWindow(_) => self.time_window_picker.clone(),
         }
     }
+
+    /// Get the picker of the table, the override registered for the table is
+    /// preferred to the picker of the `strategy`.
+    pub fn get_table_picker(
+        &self,
+        table_id: TableId,
+        strategy: CompactionStrategy,
+    ) -> CompactionPickerRef {
+        if let Some(picker) = self.table_pickers.read().unwrap().get(&table_id) {
+            return picker.clone();
+        }
+
+        self.get_picker(strategy)
+    }
+
+    /// Override the picker of the table, and the previous override is
+    /// returned.
+    pub fn set_table_picker(
+        &self,
+        table_id: TableId,
+        picker: CompactionPickerRef,
+    ) -> Option<CompactionPickerRef> {
+        self.table_pickers.write().unwrap().insert(table_id, picker)
+    }
+
+    /// Remove the override of the table, so the picker of its strategy is
+    /// used again.
+    pub fn remove_table_picker(&self, table_id: TableId) -> Option<CompactionPickerRef> {
+        self.table_pickers.write().unwrap().remove(&table_id)
+    }
 }
 
 #[derive(Debug, Snafu)]
@@ -405,15 +454,15 @@ pub enum WaitError {
 pub type WaitResult<T> = std::result::Result<T, WaitError>;
 
 pub struct WaiterNotifier {
-    waiter: Option<oneshot::Sender<WaitResult<()>>>,
+    waiter: Option<oneshot::Sender<WaitResult<CompactionSummary>>>,
 }
 
 impl WaiterNotifier {
-    pub fn new(waiter: Option<oneshot::Sender<WaitResult<()>>>) -> Self {
+    pub fn new(waiter: Option<oneshot::Sender<WaitResult<CompactionSummary>>>) -> Self {
         Self { waiter }
     }
 
-    pub fn notify_wait_result(mut self, res: WaitResult<()>) {
+    pub fn notify_wait_result(mut self, res: WaitResult<CompactionSummary>) {
         // Ignore error if failed to send result.
         if let Some(waiter) = self.waiter.take() {
             let _ = waiter.send(res);
@@ -435,7 +484,13 @@ impl Drop for WaiterNotifier {
 pub struct TableCompactionRequest {
     pub table_data: TableDataRef,
     pub compaction_notifier: Option<CompactionNotifier>,
-    pub waiter: Option<oneshot::Sender<WaitResult<()>>>,
+    pub waiter: Option<oneshot::Sender<WaitResult<CompactionSummary>>>,
+    /// The time when the request is put into the pending queue of the
+    /// scheduler, None if it isn't pending.
+    pub enqueue_time: Option<Instant>,
+    /// The request is scheduled ahead of the others once the deadline is
+    /// approaching, None if the request has no deadline.
+    pub deadline: Option<Instant>,
 }
 
 impl TableCompactionRequest {
@@ -447,6 +502,8 @@ impl TableCompactionRequest {
             table_data,
             compaction_notifier,
             waiter: None,
+            enqueue_time: None,
+            deadline: None,
         }
     }
 }
@@ -456,6 +513,56 @@ mod tests {
     use std::collections::HashMap;
 
     use super::*;
+    use crate::{
+        compaction::picker::{self, CompactionPicker, PickerContext},
+        sst::manager::LevelsController,
+    };
+
+    struct MockPicker;
+
+    impl CompactionPicker for MockPicker {
+        fn pick_compaction(
+            &self,
+            _ctx: PickerContext,
+            _levels_controller: &LevelsController,
+        ) -> picker::Result<CompactionTask> {
+            Ok(CompactionTask::default())
+        }
+    }
+
+    fn is_same_picker(a: &CompactionPickerRef, b: &CompactionPickerRef) -> bool {
+        // Only compare the data pointers as the vtables may differ.
+        Arc::as_ptr(a) as *const () == Arc::as_ptr(b) as *const ()
+    }
+
+    #[test]
+    fn test_table_picker_override() {
+        let manager = PickerManager::default();
+        let strategy = CompactionStrategy::SizeTiered(SizeTieredCompactionOptions::default());
+        let default_picker = manager.get_picker(strategy);
+        let override_picker: CompactionPickerRef = Arc::new(MockPicker);
+
+        // The override is shared with the clones, e.g. the one in the scheduler.
+        let shared = manager.clone();
+        assert!(shared
+            .set_table_picker(TableId::from(1), override_picker.clone())
+            .is_none());
+
+        assert!(is_same_picker(
+            &override_picker,
+            &manager.get_table_picker(TableId::from(1), strategy)
+        ));
+        assert!(is_same_picker(
+            &default_picker,
+            &manager.get_table_picker(TableId::from(2), strategy)
+        ));
+
+        assert!(manager.remove_table_picker(TableId::from(1)).is_some());
+        assert!(is_same_picker(
+            &default_picker,
+            &manager.get_table_picker(TableId::from(1), strategy)
+        ));
+    }
 
     #[test]
     fn test_fill_raw_map_then_parse() {
@@ -618,16 +618,19 @@ mod tests {
     };
 
     fn build_sst_meta_data(time_range: TimeRange, size: u64) -> SstMetaData {
+        let schema = build_schema();
         SstMetaData {
             min_key: Bytes::from_static(b"100"),
             max_key: Bytes::from_static(b"200"),
             time_range,
             max_sequence: 200,
-            schema: build_schema(),
+            schema_fingerprint: schema.fingerprint(),
+            schema,
             size,
             row_num: 2,
             storage_format_opts: Default::default(),
             bloom_filter: Default::default(),
+            row_group_time_ranges: Vec::new(),
         }
     }
 
@@ -5,21 +5,24 @@
 use std::{
     collections::{HashMap, VecDeque},
     hash::Hash,
+    io,
+    path::PathBuf,
     sync::{
-        atomic::{AtomicBool, AtomicUsize, Ordering},
-        Arc, RwLock,
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex as StdMutex, RwLock,
     },
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use async_trait::async_trait;
-use common_types::request_id::RequestId;
+use common_types::{request_id::RequestId, time::Timestamp};
 use common_util::{
     config::{ReadableDuration, ReadableSize},
     define_result,
     runtime::{JoinHandle, Runtime},
     time::DurationExt,
 };
+use futures::{future::Future, stream, StreamExt};
 use log::{debug, error, info, warn};
 use serde_derive::Deserialize;
 use snafu::{ResultExt, Snafu};
@@ -27,15 +30,21 @@ use table_engine::table::TableId;
 use tokio::{
     sync::{
         mpsc::{self, error::SendError, Receiver, Sender},
-        Mutex,
+        Mutex, Notify,
     },
     time,
 };
 
 use crate::{
     compaction::{
-        metrics::COMPACTION_PENDING_REQUEST_GAUGE, picker::PickerContext, CompactionTask,
-        PickerManager, TableCompactionRequest, WaitError, WaiterNotifier,
+        metrics::{
+            COMPACTION_DISK_LIMITED_COUNTER, COMPACTION_INPUT_FILE_NUM_HISTOGRAM,
+            COMPACTION_MEMORY_LIMITED_COUNTER, COMPACTION_PENDING_REQUEST_GAUGE,
+            COMPACTION_PENDING_WAIT_DURATION_HISTOGRAM, COMPACTION_THROTTLED_COUNTER,
+            SCHEDULED_FLUSH_DURATION_HISTOGRAM,
+        },
+        picker::{CompactionPickerRef, PickerContext},
+        CompactionTask, PickerManager, TableCompactionRequest, WaitError, WaiterNotifier,
     },
     instance::{
         flush_compaction::{self, TableFlushOptions},
@@ -62,11 +71,32 @@ pub struct SchedulerConfig {
     pub max_ongoing_tasks: usize,
     pub max_unflushed_duration: ReadableDuration,
     pub memory_limit: ReadableSize,
+    pub max_concurrent_flushes: usize,
+    /// Extra free disk space required besides the estimated output size of a
+    /// compaction task, otherwise the task is deferred. Only takes effect if
+    /// the sst files are stored on the local disk.
+    pub disk_space_margin: ReadableSize,
+    /// Record the duration of the flushes triggered by the scheduler for each
+    /// table, disabled by default as the metric is labeled by the table name.
+    pub flush_duration_metrics: bool,
+    /// Time to wait for the ongoing tasks to finish before dropping the older
+    /// pending requests once the pending requests reach the limit, zero
+    /// disables the waiting.
+    ///
+    /// The schedule worker handles no other requests while waiting, so it is
+    /// capped by [MAX_PENDING_REQUEST_GRACE_PERIOD].
+    pub pending_request_grace_period: ReadableDuration,
+    /// The pending requests whose deadlines are within the window from now
+    /// are scheduled before the others, the earliest deadline first.
+    pub compaction_deadline_window: ReadableDuration,
 }
 
 // TODO(boyan), a better default value?
 const MAX_GOING_COMPACTION_TASKS: usize = 8;
 const MAX_PENDING_COMPACTION_TASKS: usize = 1024;
+const MAX_CONCURRENT_FLUSHES: usize = 4;
+const DEFAULT_COMPACTION_DEADLINE_WINDOW: Duration = Duration::from_secs(60 * 5);
+pub const MAX_PENDING_REQUEST_GRACE_PERIOD: Duration = Duration::from_secs(10);
 
 impl Default for SchedulerConfig {
     fn default() -> Self {
@@ -78,16 +108,196 @@ impl Default for SchedulerConfig {
             // flush_interval default is 5h.
             max_unflushed_duration: ReadableDuration(Duration::from_secs(60 * 60 * 5)),
             memory_limit: ReadableSize::gb(4),
+            max_concurrent_flushes: MAX_CONCURRENT_FLUSHES,
+            disk_space_margin: ReadableSize::gb(1),
+            flush_duration_metrics: false,
+            pending_request_grace_period: ReadableDuration(Duration::ZERO),
+            compaction_deadline_window: ReadableDuration(DEFAULT_COMPACTION_DEADLINE_WINDOW),
         }
     }
 }
 
+/// Part of the [SchedulerConfig] which can be updated at runtime, and the
+/// fields which are not set are left unchanged.
+#[derive(Debug, Clone, Default)]
+pub struct SchedulerConfigUpdate {
+    pub max_ongoing_tasks: Option<usize>,
+    pub memory_limit: Option<ReadableSize>,
+    pub schedule_interval: Option<ReadableDuration>,
+}
+
 enum ScheduleTask {
     Request(TableCompactionRequest),
     Schedule,
     Exit,
 }
 
+/// Clock to drive the periodical schedule of the compaction scheduler.
+#[async_trait]
+pub trait ScheduleClock: Send + Sync {
+    /// Current time in millis on this clock.
+    fn now_millis(&self) -> u64;
+
+    /// Wait until the `duration` elapses on this clock.
+    async fn sleep(&self, duration: Duration);
+}
+
+pub type ScheduleClockRef = Arc<dyn ScheduleClock>;
+
+/// Clock based on the tokio timer.
+struct TokioClock;
+
+#[async_trait]
+impl ScheduleClock for TokioClock {
+    fn now_millis(&self) -> u64 {
+        common_util::time::current_time_millis()
+    }
+
+    async fn sleep(&self, duration: Duration) {
+        time::sleep(duration).await;
+    }
+}
+
+/// Clock which only advances by explicit ticks, so the periodical schedule can
+/// be triggered deterministically in tests and benchmarks.
+#[derive(Clone, Default)]
+pub struct ManualClock {
+    inner: Arc<ManualClockInner>,
+}
+
+#[derive(Default)]
+struct ManualClockInner {
+    now: StdMutex<Duration>,
+    notify: Notify,
+}
+
+impl ManualClock {
+    /// Advance the clock by `duration` and wake up the sleepers.
+    pub fn advance(&self, duration: Duration) {
+        *self.inner.now.lock().unwrap() += duration;
+        self.inner.notify.notify_waiters();
+    }
+
+    /// Time elapsed since the clock is created.
+    pub fn now(&self) -> Duration {
+        *self.inner.now.lock().unwrap()
+    }
+}
+
+#[async_trait]
+impl ScheduleClock for ManualClock {
+    /// Millis elapsed since the clock is created.
+    fn now_millis(&self) -> u64 {
+        self.now().as_millis_u64()
+    }
+
+    async fn sleep(&self, duration: Duration) {
+        let deadline = self.now() + duration;
+        loop {
+            // Register the notification before checking the time, so the advance
+            // between them won't be missed.
+            let notified = self.inner.notify.notified();
+            if self.now() >= deadline {
+                return;
+            }
+            notified.await;
+        }
+    }
+}
+
+/// Provider of the available disk space for the compaction outputs.
+pub trait DiskSpaceProvider: Send + Sync {
+    /// Available disk space in bytes.
+    fn available_space(&self) -> io::Result<u64>;
+}
+
+pub type DiskSpaceProviderRef = Arc<dyn DiskSpaceProvider>;
+
+/// Provide the available space of the disk containing the `path`.
+pub struct LocalDiskSpaceProvider {
+    path: PathBuf,
+}
+
+impl LocalDiskSpaceProvider {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl DiskSpaceProvider for LocalDiskSpaceProvider {
+    fn available_space(&self) -> io::Result<u64> {
+        common_util::fs::available_space(&self.path)
+    }
+}
+
+/// Check whether there is enough disk space for the compaction outputs.
+struct DiskSpaceGuard {
+    provider: DiskSpaceProviderRef,
+    margin: u64,
+}
+
+impl DiskSpaceGuard {
+    /// Returns false if the available disk space is less than the estimated
+    /// output size of the `task` plus the margin.
+    ///
+    /// The task is allowed if the available space is unknown.
+    fn allow(&self, task: &CompactionTask) -> bool {
+        let available = match self.provider.available_space() {
+            Ok(v) => v,
+            Err(e) => {
+                warn!(
+                    "Failed to get available disk space for compaction, err:{}",
+                    e
+                );
+                return true;
+            }
+        };
+        let required = task.estimated_output_file_size() as u64 + self.margin;
+
+        debug!(
+            "Check disk space for compaction, available:{}, required:{}",
+            available, required
+        );
+
+        if available < required {
+            COMPACTION_DISK_LIMITED_COUNTER.inc();
+            return false;
+        }
+
+        true
+    }
+}
+
+/// Permit of a compaction task granted by the [CompactionThrottle], which is
+/// held until the task finishes.
+pub type ThrottlePermit = Box<dyn Send>;
+
+/// Decision of the [CompactionThrottle] on a compaction task.
+pub enum ThrottleDecision {
+    /// The task can be started.
+    Permit(ThrottlePermit),
+    /// The task should wait, and it is retried in the next schedule.
+    Wait,
+}
+
+/// Hook to integrate the external throttling, e.g. the io budget of the
+/// cgroup, which is consulted before starting every compaction task.
+pub trait CompactionThrottle: Send + Sync {
+    fn acquire(&self, task: &CompactionTask) -> ThrottleDecision;
+}
+
+pub type CompactionThrottleRef = Arc<dyn CompactionThrottle>;
+
+/// The throttle permitting all the compaction tasks.
+#[derive(Debug, Default)]
+pub struct NoopCompactionThrottle;
+
+impl CompactionThrottle for NoopCompactionThrottle {
+    fn acquire(&self, _task: &CompactionTask) -> ThrottleDecision {
+        ThrottleDecision::Permit(Box::new(()))
+    }
+}
+
 #[async_trait]
 pub trait CompactionScheduler {
     /// Stop the scheduler.
@@ -95,6 +305,35 @@ pub trait CompactionScheduler {
 
     /// Schedule a compaction job to background workers.
     async fn schedule_table_compaction(&self, request: TableCompactionRequest);
+
+    /// Update the config of the scheduler at runtime.
+    fn update_config(&self, update: SchedulerConfigUpdate);
+
+    /// Cancel all the pending compaction requests and notify their waiters,
+    /// the ids of the tables whose requests are canceled are returned.
+    fn clear_pending_requests(&self) -> Vec<TableId>;
+
+    /// Get the statistics of the scheduler.
+    fn stats(&self) -> SchedulerStats;
+
+    /// Override the compaction picker of the table, which is used instead of
+    /// the picker of its compaction strategy since the next compaction.
+    fn set_table_picker(&self, table_id: TableId, picker: CompactionPickerRef);
+
+    /// Remove the picker override of the table.
+    fn remove_table_picker(&self, table_id: TableId);
+}
+
+/// Statistics of the compaction scheduler.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SchedulerStats {
+    pub ongoing_tasks: usize,
+    pub pending_requests: usize,
+    /// The time on the schedule clock when the next periodical schedule fires,
+    /// None if the worker isn't waiting for it yet.
+    ///
+    /// The periodical schedule is postponed if any task is received before it.
+    pub next_schedule_time: Option<Timestamp>,
 }
 
 // A FIFO queue that remove duplicate values by key.
@@ -128,6 +367,29 @@ impl<K: Eq + Hash + Clone, V> RequestQueue<K, V> {
         None
     }
 
+    /// Remove the value of the `key` from the queue.
+    fn remove(&mut self, key: &K) -> Option<V> {
+        let value = self.values.remove(key)?;
+        self.keys.retain(|k| k != key);
+        Some(value)
+    }
+
+    /// Iterate the keys and values in FIFO order.
+    fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.keys
+            .iter()
+            .filter_map(|key| self.values.get(key).map(|value| (key, value)))
+    }
+
+    /// Remove all the values in the queue and return them in FIFO order.
+    fn drain(&mut self) -> Vec<V> {
+        let values = &mut self.values;
+        self.keys
+            .drain(..)
+            .filter_map(|key| values.remove(&key))
+            .collect()
+    }
+
     #[inline]
     fn len(&self) -> usize {
         self.values.len()
@@ -146,8 +408,7 @@ type RequestBuf = RwLock<RequestQueue<TableId, TableCompactionRequest>>;
 #[derive(Clone, Debug)]
 struct MemoryLimit {
     usage: Arc<AtomicUsize>,
-    // TODO: support to adjust this threshold dynamically.
-    limit: usize,
+    limit: Arc<AtomicUsize>,
 }
 
 /// The token for the memory usage, which should not derive Clone.
@@ -169,10 +430,15 @@ impl MemoryLimit {
     fn new(limit: usize) -> Self {
         Self {
             usage: Arc::new(AtomicUsize::new(0)),
-            limit,
+            limit: Arc::new(AtomicUsize::new(limit)),
         }
     }
 
+    #[inline]
+    fn set_limit(&self, limit: usize) {
+        self.limit.store(limit, Ordering::Relaxed);
+    }
+
     /// Try to apply a token if possible.
     fn try_apply_token(&self, bytes: usize) -> Option<MemoryUsageToken> {
         let token = self.apply_token(bytes);
@@ -194,17 +460,35 @@ impl MemoryLimit {
 
     #[inline]
     fn is_exceeded(&self) -> bool {
-        self.usage.load(Ordering::Relaxed) > self.limit
+        self.usage.load(Ordering::Relaxed) > self.limit.load(Ordering::Relaxed)
     }
 }
 
 struct OngoingTaskLimit {
     ongoing_tasks: AtomicUsize,
+    max_ongoing_tasks: AtomicUsize,
+    /// Older requests are dropped once the pending requests reach this limit.
+    max_pending_requests: usize,
     /// Buffer to hold pending requests
     request_buf: RequestBuf,
+    /// The requests whose deadlines are within the window are drained first.
+    deadline_window: Duration,
+    /// Notified once an ongoing task finishes.
+    task_finished: Notify,
 }
 
 impl OngoingTaskLimit {
+    fn new(max_ongoing_tasks: usize) -> Self {
+        Self {
+            ongoing_tasks: AtomicUsize::new(0),
+            max_ongoing_tasks: AtomicUsize::new(max_ongoing_tasks),
+            max_pending_requests: MAX_PENDING_COMPACTION_TASKS,
+            request_buf: RwLock::new(RequestQueue::default()),
+            deadline_window: DEFAULT_COMPACTION_DEADLINE_WINDOW,
+            task_finished: Notify::new(),
+        }
+    }
+
     #[inline]
     fn start_task(&self) {
         self.ongoing_tasks.fetch_add(1, Ordering::SeqCst);
@@ -213,18 +497,24 @@ impl OngoingTaskLimit {
     #[inline]
     fn finish_task(&self) {
         self.ongoing_tasks.fetch_sub(1, Ordering::SeqCst);
+        self.task_finished.notify_waiters();
     }
 
+    /// Add the request to the pending requests, and the older requests are
+    /// dropped if there are too many pending requests.
+    ///
+    /// Returns the number of the dropped requests.
     #[inline]
-    fn add_request(&self, request: TableCompactionRequest) {
+    fn add_request(&self, mut request: TableCompactionRequest) -> usize {
         let mut dropped = 0;
+        request.enqueue_time = Some(Instant::now());
 
         {
             let mut req_buf = self.request_buf.write().unwrap();
 
             // Remove older requests
-            if req_buf.len() >= MAX_PENDING_COMPACTION_TASKS {
-                while req_buf.len() >= MAX_PENDING_COMPACTION_TASKS {
+            if req_buf.len() >= self.max_pending_requests {
+                while req_buf.len() >= self.max_pending_requests {
                     req_buf.pop_front();
                     dropped += 1;
                 }
@@ -239,17 +529,81 @@ impl OngoingTaskLimit {
         if dropped > 0 {
             warn!(
                 "Too many compaction pending tasks,  limit: {}, dropped {} older tasks.",
-                MAX_PENDING_COMPACTION_TASKS, dropped,
+                self.max_pending_requests, dropped,
             );
         }
+
+        dropped as usize
+    }
+
+    /// Add the request to the pending requests like
+    /// [OngoingTaskLimit::add_request], but wait at most `grace_period` for
+    /// the ongoing tasks to finish before dropping the older requests.
+    ///
+    /// The pending requests which can be scheduled once the ongoing tasks
+    /// finish are returned, so they don't need to be dropped.
+    ///
+    /// The waiting is aborted once the `shutdown` is notified.
+    async fn add_request_with_grace_period(
+        &self,
+        request: TableCompactionRequest,
+        clock: &dyn ScheduleClock,
+        shutdown: &Notify,
+        grace_period: Duration,
+    ) -> Vec<TableCompactionRequest> {
+        let mut schedulable = Vec::new();
+        if !grace_period.is_zero()
+            && self.request_buf_len() >= self.max_pending_requests
+            && self.wait_schedulable(clock, shutdown, grace_period).await
+        {
+            schedulable = self.drain_requests(self.num_schedulable_tasks());
+        }
+        self.add_request(request);
+
+        schedulable
+    }
+
+    /// Wait at most `timeout` until some tasks can be scheduled, returns false
+    /// if no task can be scheduled after the timeout or the `shutdown` is
+    /// notified.
+    async fn wait_schedulable(
+        &self,
+        clock: &dyn ScheduleClock,
+        shutdown: &Notify,
+        timeout: Duration,
+    ) -> bool {
+        let wait_finished = async {
+            loop {
+                // Register the notification before checking the tasks, so the finish
+                // between them won't be missed.
+                let notified = self.task_finished.notified();
+                if self.num_schedulable_tasks() > 0 {
+                    return;
+                }
+                notified.await;
+            }
+        };
+
+        tokio::select! {
+            _ = wait_finished => true,
+            _ = clock.sleep(timeout) => self.num_schedulable_tasks() > 0,
+            _ = shutdown.notified() => {
+                info!("Compaction scheduler stops waiting for the grace period by shutdown");
+                false
+            }
+        }
     }
 
+    /// Drain at most `max_num` pending requests, the requests whose deadlines
+    /// are approaching come first and then the others in FIFO order.
     fn drain_requests(&self, max_num: usize) -> Vec<TableCompactionRequest> {
         let mut result = Vec::with_capacity(max_num);
         let mut req_buf = self.request_buf.write().unwrap();
+        let now = Instant::now();
 
         while result.len() < max_num {
-            if let Some(req) = req_buf.pop_front() {
+            if let Some(mut req) = self.pop_next_request(&mut req_buf, now) {
+                observe_pending_wait(&mut req);
                 result.push(req);
             } else {
                 break;
@@ -260,6 +614,47 @@ impl OngoingTaskLimit {
         result
     }
 
+    /// Pop the request with the earliest deadline within the
+    /// `self.deadline_window` from `now`, or the oldest request if there is
+    /// no such request.
+    fn pop_next_request(
+        &self,
+        req_buf: &mut RequestQueue<TableId, TableCompactionRequest>,
+        now: Instant,
+    ) -> Option<TableCompactionRequest> {
+        let urgent = req_buf
+            .iter()
+            .filter_map(|(table_id, req)| {
+                req.deadline
+                    .filter(|deadline| {
+                        deadline.saturating_duration_since(now) <= self.deadline_window
+                    })
+                    .map(|deadline| (deadline, *table_id))
+            })
+            .min_by_key(|(deadline, _)| *deadline);
+
+        match urgent {
+            Some((_, table_id)) => req_buf.remove(&table_id),
+            None => req_buf.pop_front(),
+        }
+    }
+
+    /// Remove all the pending requests and notify their waiters that the
+    /// requests are canceled.
+    fn cancel_requests(&self) -> Vec<TableId> {
+        let requests = self.request_buf.write().unwrap().drain();
+        COMPACTION_PENDING_REQUEST_GAUGE.sub(requests.len() as i64);
+
+        requests
+            .into_iter()
+            .map(|request| {
+                let waiter_notifier = WaiterNotifier::new(request.waiter);
+                waiter_notifier.notify_wait_result(Err(WaitError::Canceled));
+                request.table_data.id
+            })
+            .collect()
+    }
+
     #[inline]
     fn has_pending_requests(&self) -> bool {
         !self.request_buf.read().unwrap().is_empty()
@@ -274,6 +669,24 @@ impl OngoingTaskLimit {
     fn ongoing_tasks(&self) -> usize {
         self.ongoing_tasks.load(Ordering::SeqCst)
     }
+
+    #[inline]
+    fn max_ongoing_tasks(&self) -> usize {
+        self.max_ongoing_tasks.load(Ordering::SeqCst)
+    }
+
+    #[inline]
+    fn set_max_ongoing_tasks(&self, max_ongoing_tasks: usize) {
+        self.max_ongoing_tasks
+            .store(max_ongoing_tasks, Ordering::SeqCst);
+    }
+
+    /// Number of the tasks which can be started without exceeding the limit.
+    #[inline]
+    fn num_schedulable_tasks(&self) -> usize {
+        self.max_ongoing_tasks()
+            .saturating_sub(self.ongoing_tasks())
+    }
 }
 
 pub type CompactionSchedulerRef = Arc<dyn CompactionScheduler + Send + Sync>;
@@ -281,33 +694,91 @@ pub type CompactionSchedulerRef = Arc<dyn CompactionScheduler + Send + Sync>;
 pub struct SchedulerImpl {
     sender: Sender<ScheduleTask>,
     running: Arc<AtomicBool>,
+    shutdown: Arc<Notify>,
     handle: Mutex<JoinHandle<()>>,
+    limit: Arc<OngoingTaskLimit>,
+    memory_limit: MemoryLimit,
+    schedule_interval: Arc<RwLock<Duration>>,
+    next_schedule_time: Arc<AtomicU64>,
+    /// Shared with the worker to override the pickers of the tables.
+    picker_manager: PickerManager,
 }
 
 impl SchedulerImpl {
+    /// Create a scheduler, the compaction tasks are deferred if the disk space
+    /// reported by `disk_space_provider` is insufficient, or the `throttle`
+    /// asks them to wait.
     pub fn new(
         space_store: Arc<SpaceStore>,
         runtime: Arc<Runtime>,
         config: SchedulerConfig,
+        disk_space_provider: Option<DiskSpaceProviderRef>,
+        throttle: CompactionThrottleRef,
+    ) -> Self {
+        Self::new_with_clock(
+            space_store,
+            runtime,
+            config,
+            disk_space_provider,
+            throttle,
+            Arc::new(TokioClock),
+        )
+    }
+
+    /// Create a scheduler whose periodical schedule is driven by the given
+    /// `clock`, e.g. a [ManualClock] to advance the time deterministically.
+    pub fn new_with_clock(
+        space_store: Arc<SpaceStore>,
+        runtime: Arc<Runtime>,
+        config: SchedulerConfig,
+        disk_space_provider: Option<DiskSpaceProviderRef>,
+        throttle: CompactionThrottleRef,
+        clock: ScheduleClockRef,
     ) -> Self {
         let (tx, rx) = mpsc::channel(config.schedule_channel_len);
         let running = Arc::new(AtomicBool::new(true));
+        let shutdown = Arc::new(Notify::new());
+        let mut limit = OngoingTaskLimit::new(config.max_ongoing_tasks);
+        limit.deadline_window = config.compaction_deadline_window.0;
+        let limit = Arc::new(limit);
+        let memory_limit = MemoryLimit::new(config.memory_limit.as_bytes() as usize);
+        let schedule_interval = Arc::new(RwLock::new(config.schedule_interval.0));
+        let next_schedule_time = Arc::new(AtomicU64::new(0));
+        let picker_manager = PickerManager::default();
+        let pending_request_grace_period = config.pending_request_grace_period.0;
+        if pending_request_grace_period > MAX_PENDING_REQUEST_GRACE_PERIOD {
+            warn!(
+                "Pending request grace period is too long and capped, grace_period:{:?}, max:{:?}",
+                pending_request_grace_period, MAX_PENDING_REQUEST_GRACE_PERIOD
+            );
+        }
+        let disk_space_guard = disk_space_provider.map(|provider| DiskSpaceGuard {
+            provider,
+            margin: config.disk_space_margin.as_bytes(),
+        });
 
         let mut worker = ScheduleWorker {
             sender: tx.clone(),
-            receiver: rx,
+            events: ScheduleEvents {
+                receiver: rx,
+                clock,
+                schedule_interval: schedule_interval.clone(),
+                next_schedule_time: next_schedule_time.clone(),
+                shutdown: shutdown.clone(),
+            },
             space_store,
             runtime: runtime.clone(),
-            schedule_interval: config.schedule_interval.0,
-            picker_manager: PickerManager::default(),
-            max_ongoing_tasks: config.max_ongoing_tasks,
+            picker_manager: picker_manager.clone(),
             max_unflushed_duration: config.max_unflushed_duration.0,
-            limit: Arc::new(OngoingTaskLimit {
-                ongoing_tasks: AtomicUsize::new(0),
-                request_buf: RwLock::new(RequestQueue::default()),
-            }),
+            max_concurrent_flushes: config.max_concurrent_flushes,
+            flush_duration_metrics: config.flush_duration_metrics,
+            pending_request_grace_period: pending_request_grace_period
+                .min(MAX_PENDING_REQUEST_GRACE_PERIOD),
+            limit: limit.clone(),
             running: running.clone(),
-            memory_limit: MemoryLimit::new(config.memory_limit.as_bytes() as usize),
+            memory_limit: memory_limit.clone(),
+            disk_space_guard,
+            throttle,
         };
 
         let handle = runtime.spawn(async move {
@@ -317,7 +788,13 @@ impl SchedulerImpl {
         Self {
             sender: tx,
             running,
+            shutdown,
             handle: Mutex::new(handle),
+            limit,
+            memory_limit,
+            schedule_interval,
+            next_schedule_time,
+            picker_manager,
         }
     }
 }
@@ -326,8 +803,9 @@ impl SchedulerImpl {
 impl CompactionScheduler for SchedulerImpl {
     async fn stop_scheduler(&self) -> Result<()> {
         self.running.store(false, Ordering::Relaxed);
-        // Wake up the receiver, if the channel is full, the worker should be busy and
-        // check the running flag later.
+        // The permit is stored if the worker is not waiting now, so the worker won't
+        // miss the shutdown even if the channel is full.
+        self.shutdown.notify_one();
         let _ = self.sender.try_send(ScheduleTask::Exit);
 
         let mut handle = self.handle.lock().await;
@@ -343,6 +821,69 @@ impl CompactionScheduler for SchedulerImpl {
             error!("Compaction scheduler failed to send request, err:{}", e);
         }
     }
+
+    fn update_config(&self, update: SchedulerConfigUpdate) {
+        info!("Compaction scheduler update config, update:{:?}", update);
+
+        if let Some(max_ongoing_tasks) = update.max_ongoing_tasks {
+            self.limit.set_max_ongoing_tasks(max_ongoing_tasks);
+        }
+        if let Some(memory_limit) = update.memory_limit {
+            self.memory_limit
+                .set_limit(memory_limit.as_bytes() as usize);
+        }
+        if let Some(schedule_interval) = update.schedule_interval {
+            // Takes effect since the next schedule.
+            *self.schedule_interval.write().unwrap() = schedule_interval.0;
+        }
+
+        // Schedule the pending requests as the ongoing tasks limit may be raised.
+        if self.limit.has_pending_requests() {
+            let _ = self.sender.try_send(ScheduleTask::Schedule);
+        }
+    }
+
+    fn clear_pending_requests(&self) -> Vec<TableId> {
+        let canceled = self.limit.cancel_requests();
+        info!(
+            "Compaction scheduler clear pending requests, num_canceled:{}, tables:{:?}",
+            canceled.len(),
+            canceled
+        );
+
+        canceled
+    }
+
+    fn stats(&self) -> SchedulerStats {
+        let next_schedule_time = match self.next_schedule_time.load(Ordering::Relaxed) {
+            0 => None,
+            millis => Some(Timestamp::new(millis as i64)),
+        };
+
+        SchedulerStats {
+            ongoing_tasks: self.limit.ongoing_tasks(),
+            pending_requests: self.limit.request_buf_len(),
+            next_schedule_time,
+        }
+    }
+
+    fn set_table_picker(&self, table_id: TableId, picker: CompactionPickerRef) {
+        info!(
+            "Compaction scheduler set picker override, table_id:{}",
+            table_id
+        );
+
+        self.picker_manager.set_table_picker(table_id, picker);
+    }
+
+    fn remove_table_picker(&self, table_id: TableId) {
+        info!(
+            "Compaction scheduler remove picker override, table_id:{}",
+            table_id
+        );
+
+        self.picker_manager.remove_table_picker(table_id);
+    }
 }
 
 struct OngoingTask {
@@ -360,18 +901,66 @@ impl OngoingTask {
     }
 }
 
+enum ScheduleEvent {
+    Task(ScheduleTask),
+    /// The schedule interval elapses without any task received.
+    Periodical,
+    /// The channel is disconnected.
+    Disconnected,
+    /// The scheduler is stopped.
+    Shutdown,
+}
+
+/// Source of the events driving the schedule loop.
+struct ScheduleEvents {
+    receiver: Receiver<ScheduleTask>,
+    clock: ScheduleClockRef,
+    /// Shared with the scheduler to be updated at runtime.
+    schedule_interval: Arc<RwLock<Duration>>,
+    /// Time in millis on the `clock` when the next periodical schedule fires,
+    /// zero if unknown. Shared with the scheduler to report it.
+    next_schedule_time: Arc<AtomicU64>,
+    /// Notified once the scheduler is stopped.
+    shutdown: Arc<Notify>,
+}
+
+impl ScheduleEvents {
+    async fn next_event(&mut self) -> ScheduleEvent {
+        let schedule_interval = *self.schedule_interval.read().unwrap();
+        self.next_schedule_time.store(
+            self.clock.now_millis() + schedule_interval.as_millis_u64(),
+            Ordering::Relaxed,
+        );
+        // TODO(yingwen): Maybe add a random offset to the interval.
+        tokio::select! {
+            // Prefer the received tasks to the periodical schedule.
+            biased;
+
+            _ = self.shutdown.notified() => ScheduleEvent::Shutdown,
+            schedule_task = self.receiver.recv() => match schedule_task {
+                Some(schedule_task) => ScheduleEvent::Task(schedule_task),
+                None => ScheduleEvent::Disconnected,
+            },
+            _ = self.clock.sleep(schedule_interval) => ScheduleEvent::Periodical,
+        }
+    }
+}
+
 struct ScheduleWorker {
     sender: Sender<ScheduleTask>,
-    receiver: Receiver<ScheduleTask>,
+    events: ScheduleEvents,
     space_store: Arc<SpaceStore>,
     runtime: Arc<Runtime>,
-    schedule_interval: Duration,
     max_unflushed_duration: Duration,
+    max_concurrent_flushes: usize,
+    flush_duration_metrics: bool,
+    pending_request_grace_period: Duration,
     picker_manager: PickerManager,
-    max_ongoing_tasks: usize,
     limit: Arc<OngoingTaskLimit>,
     running: Arc<AtomicBool>,
     memory_limit: MemoryLimit,
+    disk_space_guard: Option<DiskSpaceGuard>,
+    throttle: CompactionThrottleRef,
 }
 
 #[inline]
@@ -384,21 +973,28 @@ async fn schedule_table_compaction(sender: Sender<ScheduleTask>, request: TableC
 impl ScheduleWorker {
     async fn schedule_loop(&mut self) {
         while self.running.load(Ordering::Relaxed) {
-            // TODO(yingwen): Maybe add a random offset to the interval.
-            match time::timeout(self.schedule_interval, self.receiver.recv()).await {
-                Ok(Some(schedule_task)) => {
+            match self.events.next_event().await {
+                ScheduleEvent::Task(schedule_task) => {
                     self.handle_schedule_task(schedule_task).await;
                 }
-                Ok(None) => {
-                    // The channel is disconnected.
+                ScheduleEvent::Disconnected => {
                     info!("Channel disconnected, compaction schedule worker exit");
                     break;
                 }
-                Err(_) => {
-                    // Timeout.
+                ScheduleEvent::Shutdown => break,
+                ScheduleEvent::Periodical => {
                     info!("Periodical compaction schedule start");
 
-                    self.schedule().await;
+                    // The periodical schedule may wait for flushing the tables for a
+                    // long time, so abort it once the scheduler is stopped.
+                    let shutdown = self.events.shutdown.clone();
+                    tokio::select! {
+                        _ = self.schedule() => (),
+                        _ = shutdown.notified() => {
+                            info!("Periodical compaction schedule is aborted by shutdown");
+                            break;
+                        }
+                    }
 
                     info!("Periodical compaction schedule end");
                 }
@@ -412,24 +1008,36 @@ impl ScheduleWorker {
     // without race.
     async fn handle_schedule_task(&self, schedule_task: ScheduleTask) {
         let ongoing = self.limit.ongoing_tasks();
+        let schedulable = self.limit.num_schedulable_tasks();
         match schedule_task {
             ScheduleTask::Request(compact_req) => {
                 debug!("Ongoing compaction tasks:{}", ongoing);
-                if ongoing >= self.max_ongoing_tasks {
-                    self.limit.add_request(compact_req);
+                if schedulable == 0 {
+                    let pending = self
+                        .limit
+                        .add_request_with_grace_period(
+                            compact_req,
+                            self.events.clock.as_ref(),
+                            &self.events.shutdown,
+                            self.pending_request_grace_period,
+                        )
+                        .await;
                     warn!(
                         "Too many compaction ongoing tasks:{}, max:{}, buf_len:{}",
                         ongoing,
-                        self.max_ongoing_tasks,
+                        self.limit.max_ongoing_tasks(),
                         self.limit.request_buf_len()
                     );
+                    for compact_req in pending {
+                        self.handle_table_compaction_request(compact_req).await;
+                    }
                 } else {
                     self.handle_table_compaction_request(compact_req).await;
                 }
             }
             ScheduleTask::Schedule => {
-                if self.max_ongoing_tasks > ongoing {
-                    let pending = self.limit.drain_requests(self.max_ongoing_tasks - ongoing);
+                if schedulable > 0 {
+                    let pending = self.limit.drain_requests(schedulable);
                     let len = pending.len();
                     for compact_req in pending {
                         self.handle_table_compaction_request(compact_req).await;
@@ -448,6 +1056,7 @@ impl ScheduleWorker {
         compaction_notifier: Option<CompactionNotifier>,
         waiter_notifier: WaiterNotifier,
         token: MemoryUsageToken,
+        permit: ThrottlePermit,
     ) {
         // Mark files being in compaction.
         compaction_task.mark_files_being_compacted(true);
@@ -466,8 +1075,9 @@ impl ScheduleWorker {
         let request_id = RequestId::next_id();
         // Do actual costly compact job in background.
         self.runtime.spawn(async move {
-            // Release the token after compaction finished.
+            // Release the token and the permit after compaction finished.
             let _token = token;
+            let _permit = permit;
 
             let res = space_store
                 .compact_table(runtime, &table_data, request_id, &compaction_task)
@@ -488,11 +1098,11 @@ impl ScheduleWorker {
 
             // Notify the background compact table result.
             match res {
-                Ok(()) => {
+                Ok(summary) => {
                     if let Some(notifier) = compaction_notifier.clone() {
                         notifier.notify_ok();
                     }
-                    waiter_notifier.notify_wait_result(Ok(()));
+                    waiter_notifier.notify_wait_result(Ok(summary));
 
                     if keep_scheduling_compaction {
                         schedule_table_compaction(
@@ -524,26 +1134,16 @@ impl ScheduleWorker {
         &self,
         task: &CompactionTask,
     ) -> Option<MemoryUsageToken> {
-        let input_size = task.estimated_total_input_file_size();
-        let estimate_memory_usage = input_size * 2;
-
-        let token = self.memory_limit.try_apply_token(estimate_memory_usage);
-
-        debug!(
-            "Apply memory for compaction, current usage:{}, applied:{}, applied_result:{:?}",
-            self.memory_limit.usage.load(Ordering::Relaxed),
-            estimate_memory_usage,
-            token,
-        );
-
-        token
+        try_apply_memory_usage_token(&self.memory_limit, task)
     }
 
     async fn handle_table_compaction_request(&self, compact_req: TableCompactionRequest) {
         let table_data = compact_req.table_data.clone();
         let table_options = table_data.table_options();
         let compaction_strategy = table_options.compaction_strategy;
-        let picker = self.picker_manager.get_picker(compaction_strategy);
+        let picker = self
+            .picker_manager
+            .get_table_picker(table_data.id, compaction_strategy);
         let picker_ctx = match new_picker_context(&table_options) {
             Some(v) => v,
             None => {
@@ -569,6 +1169,25 @@ impl ScheduleWorker {
             }
         };
 
+        let compact_req = match defer_if_disk_insufficient(
+            self.disk_space_guard.as_ref(),
+            &self.limit,
+            &compaction_task,
+            compact_req,
+        ) {
+            Some(v) => v,
+            None => return,
+        };
+        let (compact_req, permit) = match defer_if_throttled(
+            self.throttle.as_ref(),
+            &self.limit,
+            &compaction_task,
+            compact_req,
+        ) {
+            Some(v) => v,
+            None => return,
+        };
+
         let token = match self.try_apply_memory_usage_token_for_task(&compaction_task) {
             Some(v) => v,
             None => {
@@ -584,6 +1203,8 @@ impl ScheduleWorker {
             }
         };
 
+        observe_picked_task(&table_data.name, table_data.id, &compaction_task);
+
         let compaction_notifier = compact_req.compaction_notifier;
         let waiter_notifier = WaiterNotifier::new(compact_req.waiter);
 
@@ -593,6 +1214,7 @@ impl ScheduleWorker {
             compaction_notifier,
             waiter_notifier,
             token,
+            permit,
         );
     }
 
@@ -620,6 +1242,9 @@ impl ScheduleWorker {
     }
 
     async fn schedule(&mut self) {
+        // Retry the pending requests, e.g. the ones deferred for insufficient disk
+        // space.
+        self.handle_schedule_task(ScheduleTask::Schedule).await;
         self.compact_tables().await;
         self.flush_tables().await;
     }
@@ -648,22 +1273,152 @@ impl ScheduleWorker {
         let mut tables_buf = Vec::new();
         self.space_store.list_all_tables(&mut tables_buf);
 
-        for table_data in &tables_buf {
+        let tables_to_flush = tables_buf.into_iter().filter(|table_data| {
             let last_flush_time = table_data.last_flush_time();
-            if last_flush_time + self.max_unflushed_duration.as_millis_u64()
+            last_flush_time + self.max_unflushed_duration.as_millis_u64()
                 > common_util::time::current_time_millis()
-            {
+        });
+
+        let flush_duration_metrics = self.flush_duration_metrics;
+        for_each_with_concurrency_limit(
+            tables_to_flush,
+            self.max_concurrent_flushes,
+            |table_data| async move {
+                let table_name = table_data.name.clone();
                 // Instance flush the table asynchronously.
-                if let Err(e) =
-                    Instance::flush_table(table_data.clone(), TableFlushOptions::default()).await
-                {
-                    error!("Failed to flush table, err:{}", e);
+                let flush = Instance::flush_table(table_data, TableFlushOptions::default());
+                let res = if flush_duration_metrics {
+                    observe_flush_duration(&table_name, flush).await
+                } else {
+                    flush.await
+                };
+                if let Err(e) = res {
+                    error!("Failed to flush table, table:{}, err:{}", table_name, e);
                 }
-            }
+            },
+        )
+        .await;
+    }
+}
+
+/// Wait for the `flush` and record its duration to the histogram of the table.
+async fn observe_flush_duration<Fut: Future>(table_name: &str, flush: Fut) -> Fut::Output {
+    let begin = Instant::now();
+    let res = flush.await;
+    SCHEDULED_FLUSH_DURATION_HISTOGRAM
+        .with_label_values(&[table_name])
+        .observe(begin.elapsed().as_secs_f64());
+
+    res
+}
+
+/// Run `f` on all the `items` while at most `max_concurrency` of them are
+/// running at the same time.
+async fn for_each_with_concurrency_limit<T, F, Fut>(
+    items: impl IntoIterator<Item = T>,
+    max_concurrency: usize,
+    f: F,
+) where
+    F: FnMut(T) -> Fut,
+    Fut: Future<Output = ()>,
+{
+    // Zero means no limit for `for_each_concurrent`, so at least one is required.
+    stream::iter(items)
+        .for_each_concurrent(max_concurrency.max(1), f)
+        .await;
+}
+
+/// Try to apply the memory usage token for the compaction task, and record the
+/// rejection if the memory usage exceeds the limit.
+fn try_apply_memory_usage_token(
+    memory_limit: &MemoryLimit,
+    task: &CompactionTask,
+) -> Option<MemoryUsageToken> {
+    let input_size = task.estimated_total_input_file_size();
+    let estimate_memory_usage = input_size * 2;
+
+    let token = memory_limit.try_apply_token(estimate_memory_usage);
+
+    debug!(
+        "Apply memory for compaction, current usage:{}, applied:{}, applied_result:{:?}",
+        memory_limit.usage.load(Ordering::Relaxed),
+        estimate_memory_usage,
+        token,
+    );
+
+    if token.is_none() {
+        COMPACTION_MEMORY_LIMITED_COUNTER.inc();
+    }
+
+    token
+}
+
+/// Defer the request to the pending requests of `limit` if the disk space is
+/// insufficient for the compaction `task`, otherwise the request is returned
+/// to be scheduled.
+///
+/// The deferred request is retried in the next schedule, putting it back to
+/// the channel may retry it immediately.
+fn defer_if_disk_insufficient(
+    guard: Option<&DiskSpaceGuard>,
+    limit: &OngoingTaskLimit,
+    task: &CompactionTask,
+    request: TableCompactionRequest,
+) -> Option<TableCompactionRequest> {
+    match guard {
+        Some(guard) if !guard.allow(task) => {
+            warn!(
+                "Compaction task is deferred because of insufficient disk space, table:{}, table_id:{}, task:{:?}",
+                request.table_data.name, request.table_data.id, task,
+            );
+            limit.add_request(request);
+            None
+        }
+        _ => Some(request),
+    }
+}
+
+/// Defer the compaction `request` to the next schedule if the `throttle` asks
+/// the `task` to wait, otherwise the request is returned with the permit.
+fn defer_if_throttled(
+    throttle: &dyn CompactionThrottle,
+    limit: &OngoingTaskLimit,
+    task: &CompactionTask,
+    request: TableCompactionRequest,
+) -> Option<(TableCompactionRequest, ThrottlePermit)> {
+    match throttle.acquire(task) {
+        ThrottleDecision::Permit(permit) => Some((request, permit)),
+        ThrottleDecision::Wait => {
+            debug!(
+                "Compaction task is deferred by the throttle, table:{}, table_id:{}, task:{:?}",
+                request.table_data.name, request.table_data.id, task,
+            );
+            COMPACTION_THROTTLED_COUNTER.inc();
+            limit.add_request(request);
+            None
         }
     }
 }
 
+/// Record the time the `request` waits in the pending queue, and clear its
+/// enqueue time.
+fn observe_pending_wait(request: &mut TableCompactionRequest) {
+    if let Some(enqueue_time) = request.enqueue_time.take() {
+        COMPACTION_PENDING_WAIT_DURATION_HISTOGRAM.observe(enqueue_time.elapsed().as_secs_f64());
+    }
+}
+
+/// Log and record the number of input files of the picked compaction task.
+fn observe_picked_task(table_name: &str, table_id: TableId, task: &CompactionTask) {
+    let num_input_files = task.num_input_files();
+    info!(
+        "Schedule compaction task, table:{}, table_id:{}, input_files:{}",
+        table_name, table_id, num_input_files
+    );
+
+    COMPACTION_INPUT_FILE_NUM_HISTOGRAM.observe(num_input_files as f64);
+}
+
 // If segment duration is None, then no compaction should be triggered, but we
 // return a None context instead of panic here.
 fn new_picker_context(table_opts: &TableOptions) -> Option<PickerContext> {
@@ -678,7 +1433,16 @@ fn new_picker_context(table_opts: &TableOptions) -> Option<PickerContext> {
 
 #[cfg(test)]
 mod tests {
+    use common_types::{bytes::Bytes, tests::build_schema, time::TimeRange};
+    use tokio::sync::oneshot;
+
     use super::*;
+    use crate::{
+        compaction::CompactionInputFiles,
+        sst::file::{FileHandle, FileMeta, FilePurgeQueue, SstMetaData},
+        table::data::tests::TableDataMocker,
+        tests::table,
+    };
 
     #[test]
     fn test_memory_usage_limit_apply() {
@@ -779,4 +1543,630 @@ mod tests {
         assert!(q.is_empty());
         assert_eq!(0, q.len());
     }
+
+    #[tokio::test]
+    async fn test_periodical_schedule_with_manual_clock() {
+        let clock = ManualClock::default();
+        let (tx, rx) = mpsc::channel(4);
+        let mut events = ScheduleEvents {
+            receiver: rx,
+            clock: Arc::new(clock.clone()),
+            schedule_interval: Arc::new(RwLock::new(Duration::from_secs(10))),
+            next_schedule_time: Arc::new(AtomicU64::new(0)),
+            shutdown: Arc::new(Notify::new()),
+        };
+
+        {
+            let next_event = events.next_event();
+            tokio::pin!(next_event);
+            assert!(futures::poll!(&mut next_event).is_pending());
+
+            clock.advance(Duration::from_secs(9));
+            assert!(futures::poll!(&mut next_event).is_pending());
+
+            clock.advance(Duration::from_secs(1));
+            assert!(matches!(next_event.await, ScheduleEvent::Periodical));
+        }
+
+        // The next periodical schedule is not triggered until another interval
+        // elapses.
+        {
+            let next_event = events.next_event();
+            tokio::pin!(next_event);
+            assert!(futures::poll!(&mut next_event).is_pending());
+            clock.advance(Duration::from_secs(5));
+            assert!(futures::poll!(&mut next_event).is_pending());
+        }
+
+        tx.send(ScheduleTask::Exit).await.unwrap();
+        assert!(matches!(
+            events.next_event().await,
+            ScheduleEvent::Task(ScheduleTask::Exit)
+        ));
+
+        drop(tx);
+        assert!(matches!(
+            events.next_event().await,
+            ScheduleEvent::Disconnected
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_during_schedule_wait() {
+        let schedule_interval = Duration::from_secs(60 * 30);
+        let (_tx, rx) = mpsc::channel(4);
+        let shutdown = Arc::new(Notify::new());
+        let mut events = ScheduleEvents {
+            receiver: rx,
+            clock: Arc::new(TokioClock),
+            schedule_interval: Arc::new(RwLock::new(schedule_interval)),
+            next_schedule_time: Arc::new(AtomicU64::new(0)),
+            shutdown: shutdown.clone(),
+        };
+
+        // Shutdown while waiting for the next event.
+        let begin = Instant::now();
+        let handle = tokio::spawn(async move {
+            let event = events.next_event().await;
+            (events, event)
+        });
+        time::sleep(Duration::from_millis(10)).await;
+        shutdown.notify_one();
+        let (mut events, event) = time::timeout(Duration::from_secs(5), handle)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(matches!(event, ScheduleEvent::Shutdown));
+
+        // Shutdown before waiting is not missed.
+        shutdown.notify_one();
+        let event = time::timeout(Duration::from_secs(5), events.next_event())
+            .await
+            .unwrap();
+        assert!(matches!(event, ScheduleEvent::Shutdown));
+        assert!(begin.elapsed() < schedule_interval / 100);
+    }
+
+    fn build_file_handles(num: usize) -> Vec<FileHandle> {
+        let (tx, _rx) = mpsc::unbounded_channel();
+        (0..num)
+            .map(|id| {
+                let schema = build_schema();
+                let file_meta = FileMeta {
+                    id: id as u64,
+                    meta: SstMetaData {
+                        min_key: Bytes::from_static(b"100"),
+                        max_key: Bytes::from_static(b"200"),
+                        time_range: TimeRange::empty(),
+                        max_sequence: 200,
+                        schema_fingerprint: schema.fingerprint(),
+                        schema,
+                        size: 10,
+                        row_num: 2,
+                        storage_format_opts: Default::default(),
+                        bloom_filter: Default::default(),
+                        row_group_time_ranges: Vec::new(),
+                    },
+                };
+                let queue = FilePurgeQueue::new(1, 1.into(), tx.clone());
+                FileHandle::new(file_meta, queue)
+            })
+            .collect()
+    }
+
+    fn build_compaction_task(file_nums: &[usize]) -> CompactionTask {
+        CompactionTask {
+            compaction_inputs: file_nums
+                .iter()
+                .map(|num| CompactionInputFiles {
+                    level: 0,
+                    files: build_file_handles(*num),
+                    output_level: 0,
+                })
+                .collect(),
+            expired: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_observe_picked_task() {
+        let task = build_compaction_task(&[3, 2]);
+
+        // The histogram is shared by the tests running concurrently.
+        let histogram = &*COMPACTION_INPUT_FILE_NUM_HISTOGRAM;
+        let (count_before, sum_before) = (histogram.get_sample_count(), histogram.get_sample_sum());
+        observe_picked_task("test_observe_picked_task", TableId::from(1), &task);
+
+        assert!(histogram.get_sample_count() > count_before);
+        assert!(histogram.get_sample_sum() >= sum_before + 5.0);
+    }
+
+    #[test]
+    fn test_observe_pending_wait() {
+        // The histogram is shared by the tests running concurrently.
+        let histogram = &*COMPACTION_PENDING_WAIT_DURATION_HISTOGRAM;
+        let (count_before, sum_before) = (histogram.get_sample_count(), histogram.get_sample_sum());
+        let table_data = TableDataMocker::default().build();
+        let limit = OngoingTaskLimit::new(1);
+        limit.add_request(TableCompactionRequest::no_waiter(
+            Arc::new(table_data),
+            None,
+        ));
+
+        let delay = Duration::from_millis(50);
+        std::thread::sleep(delay);
+        let request = limit.drain_requests(1).pop().unwrap();
+        assert!(request.enqueue_time.is_none());
+
+        assert!(histogram.get_sample_count() > count_before);
+        assert!(histogram.get_sample_sum() >= sum_before + delay.as_secs_f64());
+    }
+
+    #[test]
+    fn test_drain_requests_by_deadline() {
+        let new_request = |seq, deadline| {
+            let table_data = TableDataMocker::default()
+                .table_id(table::new_table_id(2, seq))
+                .build();
+            TableCompactionRequest {
+                table_data: Arc::new(table_data),
+                compaction_notifier: None,
+                waiter: None,
+                enqueue_time: None,
+                deadline,
+            }
+        };
+        let drained_ids = |limit: &OngoingTaskLimit| {
+            limit
+                .drain_requests(limit.request_buf_len())
+                .into_iter()
+                .map(|request| request.table_data.id)
+                .collect::<Vec<_>>()
+        };
+        let table_ids = |seqs: &[u32]| {
+            seqs.iter()
+                .map(|seq| table::new_table_id(2, *seq))
+                .collect::<Vec<_>>()
+        };
+
+        let mut limit = OngoingTaskLimit::new(1);
+        limit.deadline_window = Duration::from_millis(200);
+        let deadline = Instant::now() + Duration::from_millis(500);
+        let add_requests = |limit: &OngoingTaskLimit| {
+            limit.add_request(new_request(0, None));
+            limit.add_request(new_request(1, None));
+            limit.add_request(new_request(2, Some(deadline)));
+            limit.add_request(new_request(3, None));
+        };
+
+        // The deadline is far away, so the requests are drained in FIFO order.
+        add_requests(&limit);
+        assert_eq!(table_ids(&[0, 1, 2, 3]), drained_ids(&limit));
+
+        // The deadline is approaching, so the request jumps ahead of the older ones.
+        add_requests(&limit);
+        std::thread::sleep(Duration::from_millis(400));
+        assert_eq!(table_ids(&[2, 0, 1, 3]), drained_ids(&limit));
+
+        // The request with an overdue deadline still comes first.
+        add_requests(&limit);
+        std::thread::sleep(Duration::from_millis(200));
+        let drained = limit.drain_requests(1);
+        assert_eq!(table_ids(&[2]), vec![drained[0].table_data.id]);
+        assert_eq!(table_ids(&[0, 1, 3]), drained_ids(&limit));
+    }
+
+    #[test]
+    fn test_memory_limited_rejection() {
+        // The estimated memory usage of the task is larger than the limit.
+        let limit = MemoryLimit::new(1);
+        let task = build_compaction_task(&[2]);
+
+        let rejected_before = COMPACTION_MEMORY_LIMITED_COUNTER.get();
+        assert!(try_apply_memory_usage_token(&limit, &task).is_none());
+        assert_eq!(rejected_before + 1, COMPACTION_MEMORY_LIMITED_COUNTER.get());
+        assert_eq!(0, limit.usage.load(Ordering::Relaxed));
+
+        // The task can be scheduled with enough memory.
+        let limit = MemoryLimit::new(1024);
+        let token = try_apply_memory_usage_token(&limit, &task);
+        assert!(token.is_some());
+        assert_eq!(rejected_before + 1, COMPACTION_MEMORY_LIMITED_COUNTER.get());
+    }
+
+    struct MockDiskSpaceProvider {
+        available: u64,
+    }
+
+    impl DiskSpaceProvider for MockDiskSpaceProvider {
+        fn available_space(&self) -> io::Result<u64> {
+            Ok(self.available)
+        }
+    }
+
+    #[test]
+    fn test_defer_for_insufficient_disk_space() {
+        // The estimated output size of the task is 20 bytes.
+        let task = build_compaction_task(&[2]);
+        let limit = OngoingTaskLimit::new(1);
+        let new_request = |seq| {
+            let table_data = TableDataMocker::default()
+                .table_id(table::new_table_id(2, seq))
+                .build();
+            let (tx, rx) = oneshot::channel();
+            let request = TableCompactionRequest {
+                table_data: Arc::new(table_data),
+                compaction_notifier: None,
+                waiter: Some(tx),
+                enqueue_time: None,
+                deadline: None,
+            };
+            (request, rx)
+        };
+
+        // Available space is below the estimated output size plus the margin.
+        let guard = DiskSpaceGuard {
+            provider: Arc::new(MockDiskSpaceProvider { available: 100 }),
+            margin: 90,
+        };
+        let deferred_before = COMPACTION_DISK_LIMITED_COUNTER.get();
+        let (request, mut waiter) = new_request(0);
+        assert!(defer_if_disk_insufficient(Some(&guard), &limit, &task, request).is_none());
+        assert_eq!(deferred_before + 1, COMPACTION_DISK_LIMITED_COUNTER.get());
+        assert_eq!(1, limit.request_buf_len());
+        // The waiter is still waiting for the deferred request.
+        assert!(matches!(
+            waiter.try_recv(),
+            Err(oneshot::error::TryRecvError::Empty)
+        ));
+        let pending = limit.drain_requests(1);
+        assert_eq!(table::new_table_id(2, 0), pending[0].table_data.id);
+
+        // Enough space for the task.
+        let guard = DiskSpaceGuard {
+            provider: Arc::new(MockDiskSpaceProvider { available: 110 }),
+            margin: 90,
+        };
+        let (request, _waiter) = new_request(1);
+        assert!(defer_if_disk_insufficient(Some(&guard), &limit, &task, request).is_some());
+        assert!(!limit.has_pending_requests());
+
+        // No guard.
+        let (request, _waiter) = new_request(2);
+        assert!(defer_if_disk_insufficient(None, &limit, &task, request).is_some());
+        assert!(!limit.has_pending_requests());
+        assert_eq!(deferred_before + 1, COMPACTION_DISK_LIMITED_COUNTER.get());
+    }
+
+    /// The throttle denies the first `num_denials` attempts.
+    struct DenyingThrottle {
+        num_denials: AtomicUsize,
+        num_attempts: AtomicUsize,
+    }
+
+    impl CompactionThrottle for DenyingThrottle {
+        fn acquire(&self, _task: &CompactionTask) -> ThrottleDecision {
+            let attempt = self.num_attempts.fetch_add(1, Ordering::SeqCst);
+            if attempt < self.num_denials.load(Ordering::SeqCst) {
+                ThrottleDecision::Wait
+            } else {
+                ThrottleDecision::Permit(Box::new(attempt))
+            }
+        }
+    }
+
+    #[test]
+    fn test_defer_if_throttled() {
+        let task = build_compaction_task(&[2]);
+        let limit = OngoingTaskLimit::new(1);
+        let throttle = DenyingThrottle {
+            num_denials: AtomicUsize::new(1),
+            num_attempts: AtomicUsize::new(0),
+        };
+        let table_data = Arc::new(TableDataMocker::default().build());
+        let (tx, mut waiter) = oneshot::channel();
+        let request = TableCompactionRequest {
+            table_data: table_data.clone(),
+            compaction_notifier: None,
+            waiter: Some(tx),
+            enqueue_time: None,
+            deadline: None,
+        };
+
+        // The first attempt is denied and the request is deferred.
+        let throttled_before = COMPACTION_THROTTLED_COUNTER.get();
+        assert!(defer_if_throttled(&throttle, &limit, &task, request).is_none());
+        assert_eq!(throttled_before + 1, COMPACTION_THROTTLED_COUNTER.get());
+        assert_eq!(1, limit.request_buf_len());
+        assert!(matches!(
+            waiter.try_recv(),
+            Err(oneshot::error::TryRecvError::Empty)
+        ));
+
+        // The deferred request is allowed by the second attempt.
+        let request = limit.drain_requests(1).pop().unwrap();
+        let (request, _permit) = defer_if_throttled(&throttle, &limit, &task, request).unwrap();
+        assert_eq!(table_data.id, request.table_data.id);
+        assert!(!limit.has_pending_requests());
+        assert_eq!(2, throttle.num_attempts.load(Ordering::SeqCst));
+
+        // The noop throttle permits all the tasks.
+        assert!(matches!(
+            NoopCompactionThrottle.acquire(&task),
+            ThrottleDecision::Permit(_)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_for_each_with_concurrency_limit() {
+        let max_concurrency = 3;
+        let running = Arc::new(AtomicUsize::new(0));
+        let max_running = Arc::new(AtomicUsize::new(0));
+        let finished = Arc::new(AtomicUsize::new(0));
+
+        for_each_with_concurrency_limit(0..10, max_concurrency, |_| {
+            let running = running.clone();
+            let max_running = max_running.clone();
+            let finished = finished.clone();
+            async move {
+                let current = running.fetch_add(1, Ordering::SeqCst) + 1;
+                max_running.fetch_max(current, Ordering::SeqCst);
+                // Give other tasks a chance to run.
+                time::sleep(Duration::from_millis(10)).await;
+                running.fetch_sub(1, Ordering::SeqCst);
+                finished.fetch_add(1, Ordering::SeqCst);
+            }
+        })
+        .await;
+
+        assert_eq!(10, finished.load(Ordering::SeqCst));
+        assert_eq!(max_concurrency, max_running.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn test_observe_flush_duration() {
+        let table_name = "test_observe_flush_duration";
+        let histogram = SCHEDULED_FLUSH_DURATION_HISTOGRAM.with_label_values(&[table_name]);
+        assert_eq!(0, histogram.get_sample_count());
+
+        let res = observe_flush_duration(table_name, async {
+            time::sleep(Duration::from_millis(10)).await;
+            Ok::<_, ()>(())
+        })
+        .await;
+        assert!(res.is_ok());
+        assert_eq!(1, histogram.get_sample_count());
+        assert!(histogram.get_sample_sum() >= 0.01);
+    }
+
+    #[test]
+    fn test_update_max_ongoing_tasks() {
+        let limit = OngoingTaskLimit::new(1);
+        limit.start_task();
+        assert_eq!(0, limit.num_schedulable_tasks());
+
+        // More tasks can be scheduled after the limit is raised.
+        limit.set_max_ongoing_tasks(3);
+        assert_eq!(2, limit.num_schedulable_tasks());
+        limit.start_task();
+        limit.start_task();
+        assert_eq!(0, limit.num_schedulable_tasks());
+
+        // The ongoing tasks are not affected if the limit is lowered.
+        limit.set_max_ongoing_tasks(1);
+        assert_eq!(3, limit.ongoing_tasks());
+        assert_eq!(0, limit.num_schedulable_tasks());
+        limit.finish_task();
+        limit.finish_task();
+        assert_eq!(0, limit.num_schedulable_tasks());
+        limit.finish_task();
+        assert_eq!(1, limit.num_schedulable_tasks());
+    }
+
+    #[test]
+    fn test_cancel_pending_requests() {
+        let limit = OngoingTaskLimit::new(1);
+        let mut waiters = Vec::new();
+        for seq in 0..3 {
+            let table_data = TableDataMocker::default()
+                .table_id(table::new_table_id(2, seq))
+                .build();
+            let (tx, rx) = oneshot::channel();
+            limit.add_request(TableCompactionRequest {
+                table_data: Arc::new(table_data),
+                compaction_notifier: None,
+                waiter: Some(tx),
+                enqueue_time: None,
+                deadline: None,
+            });
+            waiters.push(rx);
+        }
+        assert_eq!(3, limit.request_buf_len());
+
+        let canceled = limit.cancel_requests();
+        let expect_canceled: Vec<_> = (0..3).map(|seq| table::new_table_id(2, seq)).collect();
+        assert_eq!(expect_canceled, canceled);
+        assert!(!limit.has_pending_requests());
+        for mut waiter in waiters {
+            assert!(matches!(waiter.try_recv(), Ok(Err(WaitError::Canceled))));
+        }
+
+        assert!(limit.cancel_requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_add_request_with_grace_period() {
+        let new_request = |seq| {
+            let table_data = TableDataMocker::default()
+                .table_id(table::new_table_id(2, seq))
+                .build();
+            TableCompactionRequest {
+                table_data: Arc::new(table_data),
+                compaction_notifier: None,
+                waiter: None,
+                enqueue_time: None,
+                deadline: None,
+            }
+        };
+        let pending_ids = |limit: &OngoingTaskLimit| {
+            limit
+                .drain_requests(limit.request_buf_len())
+                .into_iter()
+                .map(|request| request.table_data.id)
+                .collect::<Vec<_>>()
+        };
+        let grace_period = Duration::from_secs(60);
+
+        // No task finishes in the grace period, so the oldest request is dropped.
+        let clock = ManualClock::default();
+        let shutdown = Notify::new();
+        let mut limit = OngoingTaskLimit::new(1);
+        limit.max_pending_requests = 2;
+        limit.start_task();
+        assert_eq!(0, limit.add_request(new_request(0)));
+        assert_eq!(0, limit.add_request(new_request(1)));
+        let add_request =
+            limit.add_request_with_grace_period(new_request(2), &clock, &shutdown, grace_period);
+        let advance_clock = async {
+            time::sleep(Duration::from_millis(10)).await;
+            clock.advance(grace_period);
+        };
+        let (schedulable, _) = tokio::join!(add_request, advance_clock);
+        assert!(schedulable.is_empty());
+        let expect_ids: Vec<_> = (1..3).map(|seq| table::new_table_id(2, seq)).collect();
+        assert_eq!(expect_ids, pending_ids(&limit));
+
+        // The ongoing task finishes in the grace period, so the oldest request is
+        // scheduled instead of being dropped.
+        assert_eq!(0, limit.add_request(new_request(0)));
+        assert_eq!(0, limit.add_request(new_request(1)));
+        let add_request =
+            limit.add_request_with_grace_period(new_request(2), &clock, &shutdown, grace_period);
+        let finish_task = async {
+            time::sleep(Duration::from_millis(10)).await;
+            limit.finish_task();
+        };
+        let (schedulable, _) = tokio::join!(add_request, finish_task);
+        let schedulable_ids: Vec<_> = schedulable
+            .iter()
+            .map(|request| request.table_data.id)
+            .collect();
+        assert_eq!(vec![table::new_table_id(2, 0)], schedulable_ids);
+        assert_eq!(expect_ids, pending_ids(&limit));
+
+        // Never wait if the grace period is zero.
+        limit.start_task();
+        assert_eq!(0, limit.add_request(new_request(0)));
+        assert_eq!(0, limit.add_request(new_request(1)));
+        let schedulable = limit
+            .add_request_with_grace_period(new_request(2), &clock, &shutdown, Duration::ZERO)
+            .await;
+        assert!(schedulable.is_empty());
+        assert_eq!(expect_ids, pending_ids(&limit));
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_during_grace_period() {
+        let new_request = |seq| {
+            let table_data = TableDataMocker::default()
+                .table_id(table::new_table_id(2, seq))
+                .build();
+            TableCompactionRequest::no_waiter(Arc::new(table_data), None)
+        };
+        let clock = ManualClock::default();
+        let shutdown = Notify::new();
+        let mut limit = OngoingTaskLimit::new(1);
+        limit.max_pending_requests = 1;
+        limit.start_task();
+        assert_eq!(0, limit.add_request(new_request(0)));
+
+        // The clock never advances, so the waiting only ends by the shutdown.
+        let add_request = limit.add_request_with_grace_period(
+            new_request(1),
+            &clock,
+            &shutdown,
+            MAX_PENDING_REQUEST_GRACE_PERIOD,
+        );
+        let stop = async {
+            time::sleep(Duration::from_millis(10)).await;
+            shutdown.notify_one();
+        };
+        let (schedulable, _) = time::timeout(Duration::from_secs(5), async {
+            tokio::join!(add_request, stop)
+        })
+        .await
+        .expect("grace period should be aborted by shutdown");
+        assert!(schedulable.is_empty());
+        assert_eq!(1, limit.request_buf_len());
+    }
+
+    #[test]
+    fn test_update_memory_limit() {
+        let limit = MemoryLimit::new(100);
+        let _token = limit.try_apply_token(80).unwrap();
+        assert!(limit.try_apply_token(40).is_none());
+
+        // The limit is shared with the clones, e.g. the one held by the scheduler.
+        let shared_limit = limit.clone();
+        shared_limit.set_limit(200);
+        assert!(limit.try_apply_token(40).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_next_schedule_time() {
+        let clock = ManualClock::default();
+        let (_tx, rx) = mpsc::channel(4);
+        let schedule_interval = Duration::from_secs(10);
+        let next_schedule_time = Arc::new(AtomicU64::new(0));
+        let mut events = ScheduleEvents {
+            receiver: rx,
+            clock: Arc::new(clock.clone()),
+            schedule_interval: Arc::new(RwLock::new(schedule_interval)),
+            next_schedule_time: next_schedule_time.clone(),
+            shutdown: Arc::new(Notify::new()),
+        };
+        assert_eq!(0, next_schedule_time.load(Ordering::Relaxed));
+
+        {
+            let next_event = events.next_event();
+            tokio::pin!(next_event);
+            assert!(futures::poll!(&mut next_event).is_pending());
+            assert_eq!(
+                schedule_interval.as_millis_u64(),
+                next_schedule_time.load(Ordering::Relaxed)
+            );
+            clock.advance(schedule_interval);
+            assert!(matches!(next_event.await, ScheduleEvent::Periodical));
+        }
+
+        // Wait for the next periodical schedule after a cycle.
+        let next_event = events.next_event();
+        tokio::pin!(next_event);
+        assert!(futures::poll!(&mut next_event).is_pending());
+        assert_eq!(
+            clock.now_millis() + schedule_interval.as_millis_u64(),
+            next_schedule_time.load(Ordering::Relaxed)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_update_schedule_interval() {
+        let clock = ManualClock::default();
+        let (_tx, rx) = mpsc::channel(4);
+        let schedule_interval = Arc::new(RwLock::new(Duration::from_secs(10)));
+        let mut events = ScheduleEvents {
+            receiver: rx,
+            clock: Arc::new(clock.clone()),
+            schedule_interval: schedule_interval.clone(),
+            next_schedule_time: Arc::new(AtomicU64::new(0)),
+            shutdown: Arc::new(Notify::new()),
+        };
+
+        *schedule_interval.write().unwrap() = Duration::from_secs(5);
+        let next_event = events.next_event();
+        tokio::pin!(next_event);
+        assert!(futures::poll!(&mut next_event).is_pending());
+        clock.advance(Duration::from_secs(5));
+        assert!(matches!(next_event.await, ScheduleEvent::Periodical));
+    }
 }
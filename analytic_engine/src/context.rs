@@ -6,7 +6,7 @@ use std::{fmt, sync::Arc};
 
 use table_engine::engine::EngineRuntimes;
 
-use crate::{sst::meta_cache::MetaCacheRef, Config};
+use crate::{compaction::scheduler::CompactionThrottleRef, sst::meta_cache::MetaCacheRef, Config};
 
 /// Context for instance open
 pub struct OpenContext {
@@ -18,6 +18,9 @@ pub struct OpenContext {
 
     /// Sst meta data cache.
     pub meta_cache: Option<MetaCacheRef>,
+
+    /// Throttle consulted before starting the compaction tasks.
+    pub compaction_throttle: CompactionThrottleRef,
 }
 
 impl fmt::Debug for OpenContext {
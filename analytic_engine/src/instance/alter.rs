@@ -13,9 +13,9 @@ use wal::manager::WriteContext;
 use crate::{
     instance::{
         engine::{
-            AlterDroppedTable, EncodePayloads, FlushTable, GetLogBatchEncoder, InvalidOptions,
-            InvalidPreVersion, InvalidSchemaVersion, OperateByWriteWorker, Result, WriteManifest,
-            WriteWal,
+            AlterDroppedTable, EncodePayloads, Error, FlushTable, GetLogBatchEncoder,
+            InvalidOptions, InvalidPreVersion, InvalidSchemaVersion, OperateByWriteWorker, Result,
+            WriteManifest, WriteWal,
         },
         flush_compaction::TableFlushOptions,
         write_worker,
@@ -66,13 +66,24 @@ impl Instance {
 
         // Send alter schema request to write worker, actual works done in
         // Self::process_alter_schema_command()
-        write_worker::process_command_in_write_worker(
+        let res = write_worker::process_command_in_write_worker(
             cmd.into_command(),
             space_table.table_data(),
             rx,
         )
-        .await
-        .context(OperateByWriteWorker {
+        .await;
+
+        // The conflict of the schema version is returned directly so the caller
+        // is able to distinguish it from other failures.
+        let res = match res {
+            Err(write_worker::Error::Channel { source }) => match source.downcast::<Error>() {
+                Ok(e) if matches!(*e, Error::InvalidPreVersion { .. }) => return Err(*e),
+                Ok(e) => Err(write_worker::Error::Channel { source: e }),
+                Err(source) => Err(write_worker::Error::Channel { source }),
+            },
+            res => res,
+        };
+        res.context(OperateByWriteWorker {
             space_id: space_table.space().id,
             table: &space_table.table_data().name,
             table_id: space_table.table_data().id,
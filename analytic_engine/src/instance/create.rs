@@ -17,8 +17,9 @@ use crate::{
     },
     meta::meta_update::{AddTableMeta, MetaUpdate, MetaUpdateRequest},
     space::SpaceRef,
+    sst::parquet::encoding,
     table::data::{TableData, TableDataRef},
-    table_options,
+    table_options::{self, StorageFormat},
 };
 
 impl Instance {
@@ -41,6 +42,18 @@ impl Instance {
         // Sanitize options before creating table.
         table_opts.sanitize();
 
+        // Reject the schema which can't be encoded in hybrid format in advance,
+        // instead of failing at the first flush.
+        if table_opts.storage_format == StorageFormat::Hybrid {
+            encoding::validate_hybrid_schema(&request.table_schema)
+                .map_err(|e| Box::new(e) as _)
+                .context(InvalidOptions {
+                    space_id: space.id,
+                    table: &request.table_name,
+                    table_id: request.table_id,
+                })?;
+        }
+
         if let Some(table_data) = space.find_table_by_id(request.table_id) {
             return Ok(table_data);
         }
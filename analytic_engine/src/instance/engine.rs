@@ -9,13 +9,15 @@ use common_util::define_result;
 use snafu::{Backtrace, OptionExt, Snafu};
 use table_engine::{
     engine::{CloseTableRequest, CreateTableRequest, DropTableRequest, OpenTableRequest},
-    table::TableId,
+    table::{SchemaId, TableId},
 };
 use wal::manager::WalLocation;
 
 use crate::{
+    engine,
     instance::{write_worker::WriteGroup, Instance},
     space::{Space, SpaceAndTable, SpaceContext, SpaceId, SpaceRef},
+    table::data::TableDataRef,
 };
 
 #[derive(Debug, Snafu)]
@@ -331,6 +333,21 @@ impl Instance {
         Ok(space_table)
     }
 
+    /// Find the table by the `schema_id` in the table engine and its name
+    ///
+    /// Return None if the table is not found
+    pub async fn find_table_in_schema(
+        &self,
+        schema_id: SchemaId,
+        table: &str,
+    ) -> Result<Option<TableDataRef>> {
+        let space_table = self
+            .find_table(engine::build_space_id(schema_id), table)
+            .await?;
+
+        Ok(space_table.map(|space_table| space_table.table_data().clone()))
+    }
+
     /// Find the table under given space by its table name
     ///
     /// Return None if space or table is not found
@@ -20,7 +20,10 @@ use futures::{
 };
 use log::{debug, error, info};
 use snafu::{Backtrace, OptionExt, ResultExt, Snafu};
-use table_engine::{predicate::Predicate, table::Result as TableResult};
+use table_engine::{
+    predicate::Predicate,
+    table::{CompactionSummary, Result as TableResult},
+};
 use tokio::sync::oneshot;
 use wal::manager::WalLocation;
 
@@ -142,6 +145,26 @@ pub enum Error {
 
 define_result!(Error);
 
+#[cfg(test)]
+lazy_static::lazy_static! {
+    /// Tables whose compactions are forced to fail.
+    static ref COMPACTION_FAILURE_TABLES: std::sync::Mutex<std::collections::HashSet<table_engine::table::TableId>> =
+        Default::default();
+}
+
+/// Force the compactions of the table to fail until
+/// [clear_compaction_failure] is called, only used to test the error handling
+/// of the compaction.
+#[cfg(test)]
+pub(crate) fn inject_compaction_failure(table_id: table_engine::table::TableId) {
+    COMPACTION_FAILURE_TABLES.lock().unwrap().insert(table_id);
+}
+
+#[cfg(test)]
+pub(crate) fn clear_compaction_failure(table_id: table_engine::table::TableId) {
+    COMPACTION_FAILURE_TABLES.lock().unwrap().remove(&table_id);
+}
+
 /// Options to flush single table.
 #[derive(Debug)]
 pub struct TableFlushOptions {
@@ -223,7 +246,10 @@ impl Instance {
     }
 
     /// Compact the table manually.
-    pub async fn manual_compact_table(&self, space_table: &SpaceAndTable) -> Result<()> {
+    pub async fn manual_compact_table(
+        &self,
+        space_table: &SpaceAndTable,
+    ) -> Result<CompactionSummary> {
         info!("Instance compact table, space_table:{:?}", space_table);
 
         // Create a oneshot channel to send/receive result from write worker.
@@ -610,6 +636,15 @@ impl Instance {
             sst_type: table_data.sst_type,
             num_rows_per_row_group: table_data.table_options().num_rows_per_row_group,
             compression: table_data.table_options().compression,
+            hybrid_encode_fallback: self.space_store.hybrid_encode_fallback,
+            max_row_group_bytes: self.space_store.max_row_group_bytes,
+            composite_bloom_filter_columns: self.space_store.composite_bloom_filter_columns.clone(),
+            hybrid_uncollapsed_columns: table_data
+                .table_options()
+                .hybrid_uncollapsed_columns
+                .clone(),
+            sort_dedup_by_key: false,
+            stats_sidecar: self.space_store.stats_sidecar,
         };
 
         for time_range in &time_ranges {
@@ -619,18 +654,21 @@ impl Instance {
             let sst_file_path = table_data.set_sst_file_path(file_id);
 
             // TODO: min_key max_key set in sst_builder build
+            let schema = table_data.schema();
             let mut sst_meta = SstMetaData {
                 min_key: min_key.clone(),
                 max_key: max_key.clone(),
                 time_range: *time_range,
                 max_sequence,
-                schema: table_data.schema(),
+                schema_fingerprint: schema.fingerprint(),
+                schema,
                 size: 0,
                 row_num: 0,
                 storage_format_opts: StorageFormatOptions::new(
                     table_data.table_options().storage_format,
                 ),
                 bloom_filter: Default::default(),
+                row_group_time_ranges: Vec::new(),
             };
 
             let store = self.space_store.clone();
@@ -666,6 +704,7 @@ impl Instance {
                 // update sst metadata by built info.
                 sst_meta.row_num = sst_info.row_num as u64;
                 sst_meta.size = sst_info.file_size as u64;
+                sst_meta.storage_format_opts.format = sst_info.storage_format;
                 Ok(sst_meta)
             });
 
@@ -727,16 +766,19 @@ impl Instance {
             }
         };
         let max_sequence = memtable_state.last_sequence();
+        let schema = table_data.schema();
         let mut sst_meta = SstMetaData {
             min_key,
             max_key,
             time_range: memtable_state.time_range,
             max_sequence,
-            schema: table_data.schema(),
+            schema_fingerprint: schema.fingerprint(),
+            schema,
             size: 0,
             row_num: 0,
             storage_format_opts: StorageFormatOptions::new(table_data.storage_format()),
             bloom_filter: Default::default(),
+            row_group_time_ranges: Vec::new(),
         };
 
         // Alloc file id for next sst file
@@ -747,6 +789,15 @@ impl Instance {
             sst_type: table_data.sst_type,
             num_rows_per_row_group: table_data.table_options().num_rows_per_row_group,
             compression: table_data.table_options().compression,
+            hybrid_encode_fallback: self.space_store.hybrid_encode_fallback,
+            max_row_group_bytes: self.space_store.max_row_group_bytes,
+            composite_bloom_filter_columns: self.space_store.composite_bloom_filter_columns.clone(),
+            hybrid_uncollapsed_columns: table_data
+                .table_options()
+                .hybrid_uncollapsed_columns
+                .clone(),
+            sort_dedup_by_key: false,
+            stats_sidecar: self.space_store.stats_sidecar,
         };
         let mut builder = self
             .space_store
@@ -776,6 +827,7 @@ impl Instance {
         // update sst metadata by built info.
         sst_meta.row_num = sst_info.row_num as u64;
         sst_meta.size = sst_info.file_size as u64;
+        sst_meta.storage_format_opts.format = sst_info.storage_format;
 
         Ok(Some(FileMeta {
             id: file_id,
@@ -799,11 +851,23 @@ impl SpaceStore {
         table_data: &TableData,
         request_id: RequestId,
         task: &CompactionTask,
-    ) -> Result<()> {
+    ) -> Result<CompactionSummary> {
         debug!(
             "Begin compact table, table_name:{}, id:{}, task:{:?}",
             table_data.name, table_data.id, task
         );
+        #[cfg(test)]
+        if COMPACTION_FAILURE_TABLES
+            .lock()
+            .unwrap()
+            .contains(&table_data.id)
+        {
+            return Other {
+                msg: format!("Injected compaction failure, table_id:{}", table_data.id),
+            }
+            .fail();
+        }
+
         let mut edit_meta = VersionEditMeta {
             space_id: table_data.space_id,
             table_id: table_data.id,
@@ -815,7 +879,7 @@ impl SpaceStore {
 
         if task.expired.is_empty() && task.compaction_inputs.is_empty() {
             // Nothing to compact.
-            return Ok(());
+            return Ok(CompactionSummary::default());
         }
 
         for files in &task.expired {
@@ -841,6 +905,23 @@ impl SpaceStore {
             .await?;
         }
 
+        let summary = CompactionSummary {
+            num_input_files: edit_meta.files_to_delete.len(),
+            input_size: task
+                .compaction_inputs
+                .iter()
+                .flat_map(|input| &input.files)
+                .chain(task.expired.iter().flat_map(|expired| &expired.files))
+                .map(|file| file.size())
+                .sum(),
+            num_output_files: edit_meta.files_to_add.len(),
+            output_size: edit_meta
+                .files_to_add
+                .iter()
+                .map(|add_file| add_file.file.meta.size)
+                .sum(),
+        };
+
         let meta_update = MetaUpdate::VersionEdit(edit_meta.clone());
         self.manifest
             .store_update(MetaUpdateRequest::new(
@@ -854,7 +935,7 @@ impl SpaceStore {
         let edit = edit_meta.into_version_edit();
         table_data.current_version().apply_edit(edit);
 
-        Ok(())
+        Ok(summary)
     }
 
     pub(crate) async fn compact_input_files(
@@ -920,6 +1001,7 @@ impl SpaceStore {
                 runtime: runtime.clone(),
                 background_read_parallelism: 1,
                 num_rows_per_row_group: table_options.num_rows_per_row_group,
+                decode_memory_limit: self.decode_memory_limit.clone(),
             };
             let mut builder = MergeBuilder::new(MergeConfig {
                 request_id,
@@ -963,6 +1045,12 @@ impl SpaceStore {
             sst_type: table_data.sst_type,
             num_rows_per_row_group: table_options.num_rows_per_row_group,
             compression: table_options.compression,
+            hybrid_encode_fallback: self.hybrid_encode_fallback,
+            max_row_group_bytes: self.max_row_group_bytes,
+            composite_bloom_filter_columns: self.composite_bloom_filter_columns.clone(),
+            hybrid_uncollapsed_columns: table_options.hybrid_uncollapsed_columns.clone(),
+            sort_dedup_by_key: table_options.need_dedup(),
+            stats_sidecar: self.stats_sidecar,
         };
         let mut sst_builder = self
             .sst_factory
@@ -982,6 +1070,7 @@ impl SpaceStore {
         // update sst metadata by built info.
         sst_meta.row_num = sst_info.row_num as u64;
         sst_meta.size = sst_info.file_size as u64;
+        sst_meta.storage_format_opts.format = sst_info.storage_format;
 
         table_data
             .metrics
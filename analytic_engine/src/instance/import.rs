@@ -0,0 +1,187 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+//! Import externally generated sst into the table.
+
+use common_types::{bytes::Bytes, schema::Schema};
+use common_util::define_result;
+use log::info;
+use object_store::ObjectStoreError;
+use parquet::{errors::ParquetError, file::footer};
+use snafu::{ensure, Backtrace, ResultExt, Snafu};
+
+use crate::{
+    instance::Instance,
+    meta::meta_update::{MetaUpdate, MetaUpdateRequest, VersionEditMeta},
+    space::SpaceAndTable,
+    sst::{
+        file::{FileMeta, SstMetaData},
+        manager::FileId,
+        meta_cache::{self, MetaData},
+    },
+    table::version_edit::{AddFile, VersionEdit},
+    table_options::StorageFormat,
+};
+
+#[derive(Debug, Snafu)]
+#[snafu(visibility = "pub")]
+pub enum Error {
+    #[snafu(display("Failed to parse parquet meta data of the sst, err:{}", source))]
+    ParseParquetMetaData { source: ParquetError },
+
+    #[snafu(display("Failed to decode meta data of the sst, err:{}", source))]
+    DecodeSstMetaData { source: meta_cache::Error },
+
+    #[snafu(display(
+        "Schema of the sst mismatches the table, table:{}, table_schema:{:?}, sst_schema:{:?}.\nBacktrace:\n{}",
+        table,
+        table_schema,
+        sst_schema,
+        backtrace
+    ))]
+    SchemaMismatch {
+        table: String,
+        table_schema: Schema,
+        sst_schema: Schema,
+        backtrace: Backtrace,
+    },
+
+    #[snafu(display(
+        "Storage format of the sst mismatches the table, table:{}, table_format:{:?}, sst_format:{:?}.\nBacktrace:\n{}",
+        table,
+        table_format,
+        sst_format,
+        backtrace
+    ))]
+    StorageFormatMismatch {
+        table: String,
+        table_format: StorageFormat,
+        sst_format: StorageFormat,
+        backtrace: Backtrace,
+    },
+
+    #[snafu(display("Failed to put sst into object store, path:{}, err:{}", path, source))]
+    PutSst {
+        path: String,
+        source: ObjectStoreError,
+    },
+
+    #[snafu(display("Failed to store version edit, err:{}", source))]
+    StoreVersionEdit {
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+}
+
+define_result!(Error);
+
+impl Instance {
+    /// Import the encoded sst into the table.
+    ///
+    /// The sst is validated against the table and then persisted to the object
+    /// store as is, so it must be encoded with the same schema and storage
+    /// format as the table. The imported sst is always added to level 0 and
+    /// the id of the new sst file is returned.
+    pub async fn import_sst(&self, space_table: &SpaceAndTable, sst: Bytes) -> Result<FileId> {
+        let table_data = space_table.table_data();
+        let table_options = table_data.table_options();
+        let sst_meta = decode_sst_meta(&sst)?;
+        validate_sst_meta(
+            &table_data.name,
+            &table_data.schema(),
+            table_options.storage_format,
+            &sst_meta,
+        )?;
+
+        let file_id = table_data.alloc_file_id();
+        let sst_file_path = table_data.set_sst_file_path(file_id);
+        self.space_store
+            .store_picker()
+            .default_store()
+            .put(&sst_file_path, sst)
+            .await
+            .with_context(|| PutSst {
+                path: sst_file_path.to_string(),
+            })?;
+
+        info!(
+            "Instance import sst, table:{}, table_id:{}, path:{}, sst_meta:{:?}",
+            table_data.name, table_data.id, sst_file_path, sst_meta
+        );
+
+        let files_to_add = vec![AddFile {
+            level: 0,
+            file: FileMeta {
+                id: file_id,
+                meta: sst_meta,
+            },
+        }];
+        let flushed_sequence = table_data.current_version().flushed_sequence();
+        let edit_meta = VersionEditMeta {
+            space_id: table_data.space_id,
+            table_id: table_data.id,
+            flushed_sequence,
+            files_to_add: files_to_add.clone(),
+            files_to_delete: vec![],
+        };
+        self.space_store
+            .manifest
+            .store_update(MetaUpdateRequest::new(
+                table_data.wal_location(),
+                MetaUpdate::VersionEdit(edit_meta),
+            ))
+            .await
+            .context(StoreVersionEdit)?;
+
+        let edit = VersionEdit {
+            flushed_sequence,
+            mems_to_remove: vec![],
+            files_to_add,
+            files_to_delete: vec![],
+        };
+        table_data.current_version().apply_edit(edit);
+
+        Ok(file_id)
+    }
+}
+
+/// Decode the [SstMetaData] from the footer of the encoded sst.
+fn decode_sst_meta(sst: &Bytes) -> Result<SstMetaData> {
+    let parquet_meta_data = footer::parse_metadata(sst).context(ParseParquetMetaData)?;
+    let meta_data =
+        MetaData::try_new(&parquet_meta_data, sst.len(), false).context(DecodeSstMetaData)?;
+
+    let mut sst_meta = meta_data.custom().as_ref().clone();
+    sst_meta.row_num = parquet_meta_data.file_metadata().num_rows() as u64;
+
+    Ok(sst_meta)
+}
+
+/// Ensure the sst can be read as a part of the table.
+fn validate_sst_meta(
+    table: &str,
+    table_schema: &Schema,
+    table_format: StorageFormat,
+    sst_meta: &SstMetaData,
+) -> Result<()> {
+    let sst_schema = &sst_meta.schema;
+    ensure!(
+        sst_meta.schema_fingerprint == table_schema.fingerprint()
+            && sst_schema.version() == table_schema.version(),
+        SchemaMismatch {
+            table,
+            table_schema: table_schema.clone(),
+            sst_schema: sst_schema.clone(),
+        }
+    );
+
+    let sst_format = sst_meta.storage_format_opts.format;
+    ensure!(
+        sst_format == table_format,
+        StorageFormatMismatch {
+            table,
+            table_format,
+            sst_format,
+        }
+    );
+
+    Ok(())
+}
@@ -11,6 +11,7 @@ mod create;
 mod drop;
 pub mod engine;
 pub mod flush_compaction;
+pub(crate) mod import;
 pub(crate) mod mem_collector;
 pub mod open;
 mod read;
@@ -25,12 +26,13 @@ use std::{
 use common_util::{define_result, runtime::Runtime};
 use log::info;
 use mem_collector::MemUsageCollector;
+use object_store::ObjectStoreRef;
 use snafu::{ResultExt, Snafu};
 use table_engine::{engine::EngineRuntimes, remote::RemoteEngineRef};
 use wal::manager::WalManagerRef;
 
 use crate::{
-    compaction::scheduler::CompactionSchedulerRef,
+    compaction::scheduler::{CompactionSchedulerRef, SchedulerStats},
     meta::ManifestRef,
     row_iter::IterOptions,
     space::{SpaceId, SpaceRef},
@@ -38,6 +40,7 @@ use crate::{
         factory::{FactoryRef as SstFactoryRef, ObjectStorePickerRef},
         file::FilePurger,
         meta_cache::MetaCacheRef,
+        parquet::encoding::DecodeMemoryLimit,
     },
     table::data::TableDataRef,
     wal_synchronizer::WalSynchronizer,
@@ -108,6 +111,16 @@ pub struct SpaceStore {
     sst_factory: SstFactoryRef,
 
     meta_cache: Option<MetaCacheRef>,
+    /// Encode sst in columnar format if encoding in hybrid format fails.
+    hybrid_encode_fallback: bool,
+    /// The max estimated bytes of the rows in one row group.
+    max_row_group_bytes: Option<usize>,
+    /// Column groups to build the composite bloom filters on.
+    composite_bloom_filter_columns: Vec<Vec<String>>,
+    /// Write the stats sidecar next to each sst.
+    stats_sidecar: bool,
+    /// Memory limit shared by the sst readers to decode the record batches.
+    decode_memory_limit: Option<DecodeMemoryLimit>,
 }
 
 impl Drop for SpaceStore {
@@ -198,6 +211,16 @@ impl Instance {
             .await
             .context(StopScheduler)
     }
+
+    /// Get the statistics of the compaction scheduler.
+    pub fn compaction_scheduler_stats(&self) -> SchedulerStats {
+        self.compaction_scheduler.stats()
+    }
+
+    /// Get the default object store persisting the ssts.
+    pub fn default_store(&self) -> &ObjectStoreRef {
+        self.space_store.store_picker().default_store()
+    }
 }
 
 // TODO(yingwen): Instance builder
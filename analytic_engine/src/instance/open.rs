@@ -18,7 +18,7 @@ use wal::{
 };
 
 use crate::{
-    compaction::scheduler::SchedulerImpl,
+    compaction::scheduler::{DiskSpaceProviderRef, LocalDiskSpaceProvider, SchedulerImpl},
     context::OpenContext,
     instance::{
         engine::{
@@ -38,7 +38,9 @@ use crate::{
     sst::{
         factory::{FactoryRef as SstFactoryRef, ObjectStorePickerRef},
         file::FilePurger,
+        parquet::encoding::DecodeMemoryLimit,
     },
+    storage_options::ObjectStoreOptions,
     table::data::{TableData, TableDataRef},
     wal_synchronizer::{WalSynchronizer, WalSynchronizerConfig},
 };
@@ -60,14 +62,35 @@ impl Instance {
             store_picker: store_picker.clone(),
             sst_factory,
             meta_cache: ctx.meta_cache.clone(),
+            hybrid_encode_fallback: ctx.config.sst_hybrid_encode_fallback,
+            max_row_group_bytes: ctx
+                .config
+                .sst_max_row_group_size
+                .map(|size| size.as_bytes() as usize),
+            composite_bloom_filter_columns: ctx.config.sst_composite_bloom_filters.clone(),
+            stats_sidecar: ctx.config.sst_stats_sidecar,
+            decode_memory_limit: ctx
+                .config
+                .sst_decode_memory_limit
+                .map(|size| DecodeMemoryLimit::new(size.as_bytes() as usize)),
         });
 
         let scheduler_config = ctx.config.compaction_config.clone();
         let bg_runtime = ctx.runtimes.bg_runtime.clone();
+        // Only the local disk space is checked before compaction.
+        let disk_space_provider: Option<DiskSpaceProviderRef> =
+            match &ctx.config.storage.object_store {
+                ObjectStoreOptions::Local(opts) => {
+                    Some(Arc::new(LocalDiskSpaceProvider::new(&opts.data_path)))
+                }
+                ObjectStoreOptions::Aliyun(_) => None,
+            };
         let compaction_scheduler = Arc::new(SchedulerImpl::new(
             space_store.clone(),
             bg_runtime.clone(),
             scheduler_config,
+            disk_space_provider,
+            ctx.compaction_throttle.clone(),
         ));
 
         let file_purger = FilePurger::start(&bg_runtime, store_picker.default_store().clone());
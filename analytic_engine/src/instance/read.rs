@@ -162,6 +162,7 @@ impl Instance {
             runtime: self.read_runtime().clone(),
             background_read_parallelism: iter_options.sst_background_read_parallelism,
             num_rows_per_row_group: table_options.num_rows_per_row_group,
+            decode_memory_limit: self.space_store.decode_memory_limit.clone(),
         };
 
         let time_range = request.predicate.time_range();
@@ -225,6 +226,7 @@ impl Instance {
             runtime: self.read_runtime().clone(),
             background_read_parallelism: iter_options.sst_background_read_parallelism,
             num_rows_per_row_group: table_options.num_rows_per_row_group,
+            decode_memory_limit: self.space_store.decode_memory_limit.clone(),
         };
 
         let time_range = request.predicate.time_range();
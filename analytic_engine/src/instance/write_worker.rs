@@ -23,7 +23,8 @@ use snafu::{Backtrace, ResultExt, Snafu};
 use table_engine::{
     engine::{CloseTableRequest, DropTableRequest},
     table::{
-        AlterSchemaRequest, Error as TableError, Result as TableResult, TableId, WriteRequest,
+        AlterSchemaRequest, CompactionSummary, Error as TableError, Result as TableResult, TableId,
+        WriteRequest,
     },
 };
 use tokio::sync::{mpsc, oneshot, watch, watch::Ref, Mutex, Notify};
@@ -450,7 +451,7 @@ impl FlushTableCommand {
 /// Compact table request.
 pub struct CompactTableCommand {
     pub table_data: TableDataRef,
-    pub waiter: Option<oneshot::Sender<WaitResult<()>>>,
+    pub waiter: Option<oneshot::Sender<WaitResult<CompactionSummary>>>,
     pub tx: oneshot::Sender<flush_compaction::Result<()>>,
 }
 
@@ -969,6 +970,8 @@ impl WriteWorker {
             table_data,
             compaction_notifier: Some(self.local.compaction_notifier()),
             waiter,
+            enqueue_time: None,
+            deadline: None,
         };
 
         self.instance.schedule_table_compaction(request).await;
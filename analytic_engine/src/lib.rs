@@ -23,7 +23,7 @@ mod wal_synchronizer;
 #[cfg(any(test, feature = "test"))]
 pub mod tests;
 
-use common_util::config::ReadableDuration;
+use common_util::config::{ReadableDuration, ReadableSize};
 use message_queue::kafka::config::Config as KafkaConfig;
 use meta::details::Options as ManifestOptions;
 use serde::Serialize;
@@ -35,7 +35,17 @@ use wal::{
     table_kv_impl::model::NamespaceConfig,
 };
 
-pub use crate::{compaction::scheduler::SchedulerConfig, table_options::TableOptions};
+pub use crate::{
+    compaction::{
+        scheduler::{
+            CompactionThrottle, CompactionThrottleRef, NoopCompactionThrottle, SchedulerConfig,
+            ThrottleDecision, ThrottlePermit,
+        },
+        CompactionTask,
+    },
+    instance::{Instance, InstanceRef},
+    table_options::TableOptions,
+};
 
 /// Config of analytic engine
 #[derive(Debug, Clone, Deserialize)]
@@ -80,6 +90,23 @@ pub struct Config {
     pub scan_batch_size: usize,
     /// Sst background reading parallelism
     pub sst_background_read_parallelism: usize,
+    /// Encode sst in columnar format if encoding in hybrid format fails, so
+    /// the data can still be persisted.
+    pub sst_hybrid_encode_fallback: bool,
+    /// The max estimated size of the rows in one row group of the sst, no
+    /// limit if not set.
+    pub sst_max_row_group_size: Option<ReadableSize>,
+    /// The max memory shared by all the reads of the ssts to stretch the
+    /// decoded record batches of the hybrid format, no limit if not set.
+    pub sst_decode_memory_limit: Option<ReadableSize>,
+    /// Groups of the column names to build the composite bloom filters on,
+    /// e.g. `[["region", "host"]]`. The groups not fully contained by the
+    /// table schema are ignored.
+    pub sst_composite_bloom_filters: Vec<Vec<String>>,
+    /// Write a json sidecar summarizing the meta data next to each sst, e.g.
+    /// the time range and the row number, for the external catalogs to index
+    /// the ssts.
+    pub sst_stats_sidecar: bool,
 
     /// Wal storage config
     ///
@@ -114,6 +141,11 @@ impl Default for Config {
             db_write_buffer_size: 0,
             scan_batch_size: 500,
             sst_background_read_parallelism: 8,
+            sst_hybrid_encode_fallback: false,
+            sst_max_row_group_size: None,
+            sst_decode_memory_limit: None,
+            sst_composite_bloom_filters: Vec::new(),
+            sst_stats_sidecar: false,
             wal_storage: WalStorageConfig::RocksDB,
             remote_engine_client: remote_engine_client::config::Config::default(),
         }
@@ -32,6 +32,7 @@ use wal::{
 };
 
 use crate::{
+    compaction::scheduler::NoopCompactionThrottle,
     context::OpenContext,
     engine::TableEngineImpl,
     instance::{Instance, InstanceRef},
@@ -145,11 +146,22 @@ pub trait EngineBuilder: Send + Sync + Default {
         context: EngineBuildContext,
         engine_runtimes: Arc<EngineRuntimes>,
     ) -> Result<TableEngineRef> {
+        let instance = self.build_instance(context, engine_runtimes).await?;
+        Ok(Arc::new(TableEngineImpl::new(instance)))
+    }
+
+    /// Build the instance of the analytic engine, which gives access to the
+    /// internal states of the tables, e.g. for the tools.
+    async fn build_instance(
+        &self,
+        context: EngineBuildContext,
+        engine_runtimes: Arc<EngineRuntimes>,
+    ) -> Result<InstanceRef> {
         let (wal, manifest) = self
             .open_wal_and_manifest(context.config.clone(), engine_runtimes.clone())
             .await?;
         let opened_storages = open_storage(context.config.storage.clone()).await?;
-        let instance = open_instance(
+        open_instance(
             context.config.clone(),
             engine_runtimes,
             wal,
@@ -157,8 +169,7 @@ pub trait EngineBuilder: Send + Sync + Default {
             Arc::new(opened_storages),
             context.router,
         )
-        .await?;
-        Ok(Arc::new(TableEngineImpl::new(instance)))
+        .await
     }
 
     async fn open_wal_and_manifest(
@@ -407,6 +418,7 @@ async fn open_instance(
         config,
         runtimes: engine_runtimes,
         meta_cache,
+        compaction_throttle: Arc::new(NoopCompactionThrottle),
     };
 
     let instance = Instance::open(
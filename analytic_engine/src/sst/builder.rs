@@ -6,7 +6,7 @@ use async_trait::async_trait;
 use common_types::{record_batch::RecordBatchWithKey, request_id::RequestId};
 use futures::Stream;
 
-use crate::sst::file::SstMetaData;
+use crate::{sst::file::SstMetaData, table_options::StorageFormat};
 
 pub mod error {
     use common_util::define_result;
@@ -40,6 +40,26 @@ pub mod error {
             source: Box<dyn std::error::Error + Send + Sync>,
         },
 
+        #[snafu(display("Failed to sort record batch by key, err:{}", source))]
+        SortRecordBatch {
+            source: common_types::record_batch::Error,
+        },
+
+        #[snafu(display(
+            "Failed to build bloom filter, column:{}, msg:{}.\nBacktrace:\n{}",
+            column,
+            msg,
+            backtrace
+        ))]
+        BuildBloomFilter {
+            column: String,
+            msg: String,
+            backtrace: Backtrace,
+        },
+
+        #[snafu(display("Failed to encode stats sidecar, err:{}", source))]
+        EncodeStatsSidecar { source: serde_json::Error },
+
         #[snafu(display("Failed to read data, err:{}", source))]
         ReadData {
             source: Box<dyn std::error::Error + Send + Sync>,
@@ -60,6 +80,9 @@ pub type RecordBatchStream = Box<dyn Stream<Item = RecordBatchStreamItem> + Send
 pub struct SstInfo {
     pub file_size: usize,
     pub row_num: usize,
+    /// The storage format actually used by the sst, which may differ from the
+    /// one in the given meta data if the encoding falls back.
+    pub storage_format: StorageFormat,
 }
 
 /// The builder for sst.
@@ -13,7 +13,10 @@ use crate::{
     sst::{
         builder::SstBuilder,
         meta_cache::MetaCacheRef,
-        parquet::{builder::ParquetSstBuilder, AsyncParquetReader, ThreadedReader},
+        parquet::{
+            builder::ParquetSstBuilder, encoding::DecodeMemoryLimit, AsyncParquetReader,
+            ThreadedReader,
+        },
         reader::SstReader,
     },
     table_options::Compression,
@@ -87,6 +90,9 @@ pub struct SstReaderOptions {
 
     /// The suggested parallelism while reading sst
     pub background_read_parallelism: usize,
+
+    /// The memory limit to decode the record batches, no limit if not set
+    pub decode_memory_limit: Option<DecodeMemoryLimit>,
 }
 
 #[derive(Debug, Clone)]
@@ -94,6 +100,26 @@ pub struct SstBuilderOptions {
     pub sst_type: SstType,
     pub num_rows_per_row_group: usize,
     pub compression: Compression,
+    /// Encode in columnar format instead if encoding in hybrid format fails.
+    pub hybrid_encode_fallback: bool,
+    /// The max estimated bytes of the rows in one row group, a row group is
+    /// ended once either it or `num_rows_per_row_group` is reached.
+    pub max_row_group_bytes: Option<usize>,
+    /// Names of the columns to build the composite bloom filters on, one
+    /// filter is built for each group of the columns existing in the schema.
+    pub composite_bloom_filter_columns: Vec<Vec<String>>,
+    /// Names of the columns not collapsed into lists in the hybrid format.
+    pub hybrid_uncollapsed_columns: Vec<String>,
+    /// Sort the rows by the primary key and drop the duplicate keys before
+    /// encoding, only the first row of the same key is kept.
+    ///
+    /// The rows of the same key are expected to be ordered by the sequence
+    /// in descending order, just as the output of the merge iterator, so the
+    /// one with the highest sequence is kept.
+    pub sort_dedup_by_key: bool,
+    /// Write a json sidecar holding the summary of the sst meta data next to
+    /// the sst.
+    pub stats_sidecar: bool,
 }
 
 #[derive(Debug, Default)]
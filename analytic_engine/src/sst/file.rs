@@ -18,6 +18,7 @@ use std::{
 
 use common_types::{
     bytes::Bytes,
+    datum::Datum,
     schema::Schema,
     time::{TimeRange, Timestamp},
     SequenceNumber,
@@ -27,9 +28,9 @@ use common_util::{
     metric::Meter,
     runtime::{JoinHandle, Runtime},
 };
-use ethbloom::Bloom;
+use ethbloom::{Bloom, Input};
 use log::{debug, error, info};
-use object_store::ObjectStoreRef;
+use object_store::{ObjectStoreError, ObjectStoreRef};
 use proto::{common as common_pb, sst as sst_pb};
 use snafu::{Backtrace, OptionExt, ResultExt, Snafu};
 use table_engine::table::TableId;
@@ -40,7 +41,7 @@ use tokio::sync::{
 
 use crate::{
     space::SpaceId,
-    sst::manager::FileId,
+    sst::{manager::FileId, sidecar},
     table::sst_util,
     table_options::{StorageFormat, StorageFormatOptions},
 };
@@ -433,23 +434,139 @@ impl FileMeta {
     }
 }
 
+/// Bloom filter over the concatenated values of multiple columns, which prunes
+/// better than the filters of the single columns for the queries filtering on
+/// these columns together.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct CompositeBloomFilter {
+    /// Indexes of the columns in the schema of the sst.
+    column_indexes: Vec<usize>,
+    /// Filter of each row group.
+    filters: Vec<Bloom>,
+}
+
+impl CompositeBloomFilter {
+    pub fn new(column_indexes: Vec<usize>, filters: Vec<Bloom>) -> Self {
+        Self {
+            column_indexes,
+            filters,
+        }
+    }
+
+    #[inline]
+    pub fn column_indexes(&self) -> &[usize] {
+        &self.column_indexes
+    }
+
+    #[inline]
+    pub fn filters(&self) -> &[Bloom] {
+        &self.filters
+    }
+
+    /// Encode the `datums` of the columns into the key accrued to the filter.
+    ///
+    /// Every datum is prefixed by its length so different combinations of the
+    /// values never produce the same key.
+    pub fn composite_key<'a>(datums: impl IntoIterator<Item = &'a Datum>) -> Vec<u8> {
+        let mut key = Vec::new();
+        for datum in datums {
+            let bytes = datum.to_bytes();
+            key.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+            key.extend_from_slice(&bytes);
+        }
+
+        key
+    }
+
+    /// Returns false if the combination of the `datums` definitely doesn't
+    /// exist in the row group at `row_group_idx`.
+    ///
+    /// The `datums` must be in the same order as the `column_indexes`, and
+    /// true is returned if the row group has no filter.
+    pub fn may_contain(&self, row_group_idx: usize, datums: &[Datum]) -> bool {
+        if datums.len() != self.column_indexes.len() {
+            return true;
+        }
+
+        match self.filters.get(row_group_idx) {
+            Some(filter) => {
+                let key = Self::composite_key(datums);
+                filter.contains_input(Input::Raw(&key))
+            }
+            None => true,
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Default)]
 pub struct BloomFilter {
     // Two level vector means
     // 1. row group
     // 2. column
     filters: Vec<Vec<Bloom>>,
+    composite_filters: Vec<CompositeBloomFilter>,
+    /// Indexes of the columns failed to build the filters, whose filters are
+    /// saturated and never filter out any row group.
+    missing_columns: Vec<usize>,
 }
 
 impl BloomFilter {
     pub fn new(filters: Vec<Vec<Bloom>>) -> Self {
-        Self { filters }
+        Self {
+            filters,
+            composite_filters: Vec::new(),
+            missing_columns: Vec::new(),
+        }
+    }
+
+    /// The filter regarded to contain any value, used for the columns whose
+    /// filters fail to build.
+    pub fn saturated_filter() -> Bloom {
+        Bloom::from([0xff; 256])
+    }
+
+    pub fn with_composite_filters(mut self, composite_filters: Vec<CompositeBloomFilter>) -> Self {
+        self.composite_filters = composite_filters;
+        self
+    }
+
+    pub fn with_missing_columns(mut self, missing_columns: Vec<usize>) -> Self {
+        self.missing_columns = missing_columns;
+        self
     }
 
     #[inline]
     pub fn filters(&self) -> &[Vec<Bloom>] {
         &self.filters
     }
+
+    #[inline]
+    pub fn composite_filters(&self) -> &[CompositeBloomFilter] {
+        &self.composite_filters
+    }
+
+    #[inline]
+    pub fn missing_columns(&self) -> &[usize] {
+        &self.missing_columns
+    }
+
+    /// Find the composite filter built on exactly the columns at
+    /// `column_indexes`.
+    pub fn composite_filter(&self, column_indexes: &[usize]) -> Option<&CompositeBloomFilter> {
+        self.composite_filters
+            .iter()
+            .find(|filter| filter.column_indexes == column_indexes)
+    }
+}
+
+fn decode_bloom(encoded_bytes: Vec<u8>) -> Result<Bloom> {
+    let size = encoded_bytes.len();
+    let bs: [u8; 256] = encoded_bytes
+        .try_into()
+        .ok()
+        .context(InvalidBloomFilterSize { size })?;
+
+    Ok(Bloom::from(bs))
 }
 
 impl From<BloomFilter> for sst_pb::SstBloomFilter {
@@ -465,8 +582,36 @@ impl From<BloomFilter> for sst_pb::SstBloomFilter {
                 sst_pb::sst_bloom_filter::RowGroupFilter { column_filters }
             })
             .collect::<Vec<_>>();
+        let composite_filters = bloom_filter
+            .composite_filters
+            .iter()
+            .map(
+                |composite_filter| sst_pb::sst_bloom_filter::CompositeFilter {
+                    column_indexes: composite_filter
+                        .column_indexes
+                        .iter()
+                        .map(|idx| *idx as u32)
+                        .collect(),
+                    row_group_filters: composite_filter
+                        .filters
+                        .iter()
+                        .map(|filter| filter.data().to_vec())
+                        .collect(),
+                },
+            )
+            .collect::<Vec<_>>();
 
-        sst_pb::SstBloomFilter { row_group_filters }
+        let missing_columns = bloom_filter
+            .missing_columns
+            .iter()
+            .map(|idx| *idx as u32)
+            .collect();
+
+        sst_pb::SstBloomFilter {
+            row_group_filters,
+            composite_filters,
+            missing_columns,
+        }
     }
 }
 
@@ -481,20 +626,40 @@ impl TryFrom<sst_pb::SstBloomFilter> for BloomFilter {
                 row_group_filter
                     .column_filters
                     .into_iter()
-                    .map(|encoded_bytes| {
-                        let size = encoded_bytes.len();
-                        let bs: [u8; 256] = encoded_bytes
-                            .try_into()
-                            .ok()
-                            .context(InvalidBloomFilterSize { size })?;
-
-                        Ok(Bloom::from(bs))
-                    })
+                    .map(decode_bloom)
                     .collect::<Result<Vec<_>>>()
             })
             .collect::<Result<Vec<_>>>()?;
+        let composite_filters = src
+            .composite_filters
+            .into_iter()
+            .map(|composite_filter| {
+                let filters = composite_filter
+                    .row_group_filters
+                    .into_iter()
+                    .map(decode_bloom)
+                    .collect::<Result<Vec<_>>>()?;
+                let column_indexes = composite_filter
+                    .column_indexes
+                    .into_iter()
+                    .map(|idx| idx as usize)
+                    .collect();
+
+                Ok(CompositeBloomFilter::new(column_indexes, filters))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let missing_columns = src
+            .missing_columns
+            .into_iter()
+            .map(|idx| idx as usize)
+            .collect();
 
-        Ok(BloomFilter { filters })
+        Ok(BloomFilter {
+            filters,
+            composite_filters,
+            missing_columns,
+        })
     }
 }
 
@@ -508,12 +673,19 @@ pub struct SstMetaData {
     /// Max sequence number in the sst
     pub max_sequence: SequenceNumber,
     pub schema: Schema,
+    /// Fingerprint of the `schema`, which is not persisted but computed from
+    /// the `schema`.
+    pub schema_fingerprint: u64,
     /// file size in bytes
     pub size: u64,
     // total row number
     pub row_num: u64,
     pub storage_format_opts: StorageFormatOptions,
     pub bloom_filter: Option<BloomFilter>,
+    /// Time ranges of the row groups, which may be tighter than the
+    /// `time_range` of the whole sst. Empty if the sst is written without
+    /// them.
+    pub row_group_time_ranges: Vec<TimeRange>,
 }
 
 pub type SstMetaDataRef = Arc<SstMetaData>;
@@ -522,6 +694,33 @@ impl SstMetaData {
     pub fn storage_format(&self) -> StorageFormat {
         self.storage_format_opts.format
     }
+
+    /// Returns true if the sequences of all the rows in the sst are less than
+    /// `low_watermark`, so the sst can be skipped by the reads only interested
+    /// in the rows since the `low_watermark`.
+    #[inline]
+    pub fn is_older_than(&self, low_watermark: SequenceNumber) -> bool {
+        self.max_sequence < low_watermark
+    }
+}
+
+/// Version of the [SstMetaData] written into the new ssts.
+///
+/// The ssts written before the version is introduced are of version 0, and
+/// their meta data may lack the storage format options.
+pub const SST_META_DATA_VERSION: u32 = 1;
+
+/// Fill the fields absent in the meta data of the older versions with the
+/// defaults derived from the version, so the meta data can be converted as
+/// the current version.
+fn migrate_sst_meta_data_pb(mut src: sst_pb::SstMetaData) -> sst_pb::SstMetaData {
+    if src.version == 0 && src.storage_format_opts.is_none() {
+        // Only the columnar format is supported before the storage format options
+        // are introduced.
+        src.storage_format_opts = Some(StorageFormatOptions::default().into());
+    }
+
+    src
 }
 
 impl From<SstMetaData> for sst_pb::SstMetaData {
@@ -536,6 +735,12 @@ impl From<SstMetaData> for sst_pb::SstMetaData {
             row_num: src.row_num,
             storage_format_opts: Some(src.storage_format_opts.into()),
             bloom_filter: src.bloom_filter.map(|v| v.into()),
+            row_group_time_ranges: src
+                .row_group_time_ranges
+                .into_iter()
+                .map(|v| v.into())
+                .collect(),
+            version: SST_META_DATA_VERSION,
         }
     }
 }
@@ -544,6 +749,7 @@ impl TryFrom<sst_pb::SstMetaData> for SstMetaData {
     type Error = Error;
 
     fn try_from(src: sst_pb::SstMetaData) -> Result<Self> {
+        let src = migrate_sst_meta_data_pb(src);
         let time_range = {
             let time_range = src.time_range.context(TimeRangeNotFound)?;
             TimeRange::try_from(time_range).context(ConvertTimeRange)?
@@ -557,17 +763,25 @@ impl TryFrom<sst_pb::SstMetaData> for SstMetaData {
                 .context(StorageFormatOptionsNotFound)?,
         );
         let bloom_filter = src.bloom_filter.map(BloomFilter::try_from).transpose()?;
+        let row_group_time_ranges = src
+            .row_group_time_ranges
+            .into_iter()
+            .map(TimeRange::try_from)
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .context(ConvertTimeRange)?;
 
         Ok(Self {
             min_key: src.min_key.into(),
             max_key: src.max_key.into(),
             time_range,
             max_sequence: src.max_sequence,
+            schema_fingerprint: schema.fingerprint(),
             schema,
             size: src.size,
             row_num: src.row_num,
             storage_format_opts,
             bloom_filter,
+            row_group_time_ranges,
         })
     }
 }
@@ -708,6 +922,16 @@ impl FilePurger {
                             e
                         );
                     }
+
+                    // The stats sidecar only exists if it is enabled when the sst is written.
+                    let sidecar_path = sidecar::stats_sidecar_path(&sst_file_path);
+                    match store.delete(&sidecar_path).await {
+                        Ok(()) | Err(ObjectStoreError::NotFound { .. }) => {}
+                        Err(e) => error!(
+                            "File purger failed to delete stats sidecar, path:{}, err:{}",
+                            sidecar_path, e
+                        ),
+                    }
                 }
                 Request::Exit => break,
             }
@@ -745,6 +969,7 @@ pub fn merge_sst_meta(files: &[FileHandle], schema: Schema) -> SstMetaData {
         max_key,
         time_range: TimeRange::new(time_range_start, time_range_end).unwrap(),
         max_sequence,
+        schema_fingerprint: schema.fingerprint(),
         schema,
         // we don't know file size and total row number yet
         size: 0,
@@ -752,6 +977,7 @@ pub fn merge_sst_meta(files: &[FileHandle], schema: Schema) -> SstMetaData {
         storage_format_opts: StorageFormatOptions::new(storage_format),
         // bloom filter is rebuilt when write sst, so use default here
         bloom_filter: Default::default(),
+        row_group_time_ranges: Vec::new(),
     }
 }
 
@@ -805,11 +1031,76 @@ pub mod tests {
                 time_range: self.time_range,
                 max_sequence: self.max_sequence,
                 schema: self.schema.clone(),
+                schema_fingerprint: self.schema.fingerprint(),
                 row_num: 0,
                 size: 0,
                 storage_format_opts: Default::default(),
                 bloom_filter: Default::default(),
+                row_group_time_ranges: Vec::new(),
             }
         }
     }
+
+    #[test]
+    fn test_composite_bloom_filter_pb() {
+        let mut bloom = Bloom::default();
+        let datums = [
+            Datum::String("region0".into()),
+            Datum::String("host0".into()),
+        ];
+        bloom.accrue(Input::Raw(&CompositeBloomFilter::composite_key(&datums)));
+        let composite_filter = CompositeBloomFilter::new(vec![2, 3], vec![bloom]);
+        let bloom_filter = BloomFilter::new(vec![vec![Bloom::default(); 4]])
+            .with_composite_filters(vec![composite_filter]);
+
+        let pb_filter = sst_pb::SstBloomFilter::from(bloom_filter.clone());
+        let decoded = BloomFilter::try_from(pb_filter).unwrap();
+        assert_eq!(bloom_filter, decoded);
+
+        let composite_filter = decoded.composite_filter(&[2, 3]).unwrap();
+        assert!(composite_filter.may_contain(0, &datums));
+        assert!(decoded.composite_filter(&[3, 2]).is_none());
+    }
+
+    #[test]
+    fn test_migrate_sst_meta_data() {
+        use prost::Message;
+
+        let schema = common_types::tests::build_schema();
+        let meta_data = SstMetaDataMocker::new(schema.clone())
+            .time_range(TimeRange::new_unchecked(
+                Timestamp::new(100),
+                Timestamp::new(200),
+            ))
+            .build();
+        let meta_data_pb = sst_pb::SstMetaData::from(meta_data.clone());
+        assert_eq!(SST_META_DATA_VERSION, meta_data_pb.version);
+
+        // The meta data written before the version and the storage format options
+        // are introduced.
+        let legacy_pb = sst_pb::SstMetaData {
+            version: 0,
+            storage_format_opts: None,
+            row_group_time_ranges: Vec::new(),
+            ..meta_data_pb.clone()
+        };
+        let fixture = legacy_pb.encode_to_vec();
+        let decoded_pb = sst_pb::SstMetaData::decode(fixture.as_slice()).unwrap();
+        assert_eq!(0, decoded_pb.version);
+        let decoded = SstMetaData::try_from(decoded_pb).unwrap();
+        assert_eq!(StorageFormatOptions::default(), decoded.storage_format_opts);
+        assert_eq!(StorageFormat::Columnar, decoded.storage_format());
+        assert!(decoded.row_group_time_ranges.is_empty());
+        assert_eq!(meta_data, decoded);
+
+        // The storage format options are required by the current version.
+        let corrupted_pb = sst_pb::SstMetaData {
+            storage_format_opts: None,
+            ..meta_data_pb
+        };
+        assert!(matches!(
+            SstMetaData::try_from(corrupted_pb),
+            Err(Error::StorageFormatOptionsNotFound { .. })
+        ));
+    }
 }
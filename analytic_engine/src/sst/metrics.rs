@@ -1,9 +1,25 @@
 // Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
 
 use lazy_static::lazy_static;
-use prometheus::{exponential_buckets, register_histogram, Histogram};
+use prometheus::{
+    exponential_buckets, register_histogram, register_int_counter_vec, Histogram, IntCounterVec,
+};
 
 lazy_static! {
+    // Counters:
+    pub static ref SST_ENCODE_ROWS_COUNTER: IntCounterVec = register_int_counter_vec!(
+        "sst_encode_rows",
+        "Number of rows encoded into the sst files",
+        &["storage_format"]
+    )
+    .unwrap();
+    pub static ref SST_ENCODE_BYTES_COUNTER: IntCounterVec = register_int_counter_vec!(
+        "sst_encode_bytes",
+        "Number of bytes of the encoded sst files",
+        &["storage_format"]
+    )
+    .unwrap();
+
     // Histogram:
     // Buckets: 100B,200B,400B,...,2KB
     pub static ref SST_GET_RANGE_HISTOGRAM: Histogram = register_histogram!(
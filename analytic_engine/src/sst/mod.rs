@@ -10,3 +10,4 @@ pub mod meta_cache;
 pub mod metrics;
 pub mod parquet;
 pub mod reader;
+pub mod sidecar;
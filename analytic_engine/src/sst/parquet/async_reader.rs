@@ -38,7 +38,10 @@ use crate::{
         file::{BloomFilter, SstMetaData},
         meta_cache::{MetaCacheRef, MetaData},
         metrics,
-        parquet::{encoding::ParquetDecoder, row_group_filter::RowGroupFilter},
+        parquet::{
+            encoding::{DecodeMemoryLimit, DecodeMemoryUsageToken, ParquetDecoder},
+            row_group_filter::RowGroupFilter,
+        },
         reader::{error::*, Result, SstReader},
     },
     table_options::StorageFormatOptions,
@@ -46,6 +49,11 @@ use crate::{
 
 type SendableRecordBatchStream = Pin<Box<dyn Stream<Item = Result<ArrowRecordBatch>> + Send>>;
 
+/// The decoded record batch with the token of the memory applied to decode it.
+type DecodedBatch = (RecordBatchWithKey, Option<DecodeMemoryUsageToken>);
+
+type DecodedBatchStream = Box<dyn Stream<Item = Result<DecodedBatch>> + Send + Unpin>;
+
 pub struct Reader<'a> {
     /// The path where the data is persisted.
     path: &'a Path,
@@ -64,6 +72,7 @@ pub struct Reader<'a> {
 
     /// Options for `read_parallelly`
     parallelism_options: ParallelismOptions,
+    decode_memory_limit: Option<DecodeMemoryLimit>,
 }
 
 impl<'a> Reader<'a> {
@@ -88,13 +97,14 @@ impl<'a> Reader<'a> {
             meta_data: None,
             row_projector: None,
             parallelism_options,
+            decode_memory_limit: options.decode_memory_limit.clone(),
         }
     }
 
     async fn maybe_read_parallelly(
         &mut self,
         read_parallelism: usize,
-    ) -> Result<Vec<Box<dyn Stream<Item = Result<RecordBatchWithKey>> + Send + Unpin>>> {
+    ) -> Result<Vec<DecodedBatchStream>> {
         assert!(read_parallelism > 0);
         let read_parallelism = if self.parallelism_options.enable_read_parallelly {
             read_parallelism
@@ -128,6 +138,7 @@ impl<'a> Reader<'a> {
                     stream,
                     row_projector.clone(),
                     storage_format_opts.clone(),
+                    self.decode_memory_limit.clone(),
                 )) as _
             })
             .collect();
@@ -427,6 +438,7 @@ struct RecordBatchProjector {
     stream: SendableRecordBatchStream,
     row_projector: ArrowRecordBatchProjector,
     storage_format_opts: StorageFormatOptions,
+    decode_memory_limit: Option<DecodeMemoryLimit>,
 
     row_num: usize,
     start_time: Instant,
@@ -438,12 +450,14 @@ impl RecordBatchProjector {
         stream: SendableRecordBatchStream,
         row_projector: ArrowRecordBatchProjector,
         storage_format_opts: StorageFormatOptions,
+        decode_memory_limit: Option<DecodeMemoryLimit>,
     ) -> Self {
         Self {
             path,
             stream,
             row_projector,
             storage_format_opts,
+            decode_memory_limit,
             row_num: 0,
             start_time: Instant::now(),
         }
@@ -462,7 +476,7 @@ impl Drop for RecordBatchProjector {
 }
 
 impl Stream for RecordBatchProjector {
-    type Item = Result<RecordBatchWithKey>;
+    type Item = Result<DecodedBatch>;
 
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
         let projector = self.get_mut();
@@ -475,18 +489,24 @@ impl Stream for RecordBatchProjector {
                 {
                     Err(e) => Poll::Ready(Some(Err(e))),
                     Ok(record_batch) => {
-                        let parquet_decoder =
-                            ParquetDecoder::new(projector.storage_format_opts.clone());
-                        let record_batch = parquet_decoder
-                            .decode_record_batch(record_batch)
+                        let parquet_decoder = ParquetDecoder::try_new_with_memory_limit(
+                            projector.storage_format_opts.clone(),
+                            projector.decode_memory_limit.clone(),
+                        )
+                        .map_err(|e| Box::new(e) as _)
+                        .context(DecodeRecordBatch)?;
+                        let decoded = parquet_decoder
+                            .decode_record_batch_with_token(record_batch)
                             .map_err(|e| Box::new(e) as _)
                             .context(DecodeRecordBatch)?;
 
-                        projector.row_num += record_batch.num_rows();
+                        projector.row_num += decoded.record_batch.num_rows();
 
+                        let memory_token = decoded.memory_token;
                         let projected_batch = projector
                             .row_projector
-                            .project_to_record_batch_with_key(record_batch)
+                            .project_to_record_batch_with_key(decoded.record_batch)
+                            .map(|batch| (batch, memory_token))
                             .map_err(|e| Box::new(e) as _)
                             .context(DecodeRecordBatch {});
 
@@ -519,17 +539,60 @@ impl<'a> SstReader for Reader<'a> {
         assert_eq!(streams.len(), 1);
         let stream = streams.pop().expect("impossible to fetch no stream");
 
-        Ok(stream)
+        Ok(Box::new(MemoryTokenHolder::new(stream)) as _)
+    }
+}
+
+/// Hold the memory token of the last record batch polled from the `stream`,
+/// which is released once the next record batch is polled or the stream is
+/// dropped, that is, after the consumer is done with the record batch.
+struct MemoryTokenHolder<S> {
+    stream: S,
+    memory_token: Option<DecodeMemoryUsageToken>,
+}
+
+impl<S> MemoryTokenHolder<S> {
+    fn new(stream: S) -> Self {
+        Self {
+            stream,
+            memory_token: None,
+        }
+    }
+}
+
+impl<S> Stream for MemoryTokenHolder<S>
+where
+    S: Stream<Item = Result<DecodedBatch>> + Unpin,
+{
+    type Item = Result<RecordBatchWithKey>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        // The last record batch is released by the consumer.
+        self.memory_token = None;
+
+        match self.stream.poll_next_unpin(cx) {
+            Poll::Ready(Some(Ok((record_batch, memory_token)))) => {
+                self.memory_token = memory_token;
+                Poll::Ready(Some(Ok(record_batch)))
+            }
+            Poll::Ready(Some(Err(e))) => Poll::Ready(Some(Err(e))),
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.stream.size_hint()
     }
 }
 
 struct RecordBatchReceiver {
-    rx_group: Vec<Receiver<Result<RecordBatchWithKey>>>,
+    rx_group: Vec<Receiver<Result<DecodedBatch>>>,
     cur_rx_idx: usize,
 }
 
 impl Stream for RecordBatchReceiver {
-    type Item = Result<RecordBatchWithKey>;
+    type Item = Result<DecodedBatch>;
 
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
         if self.rx_group.is_empty() {
@@ -600,8 +663,8 @@ impl<'a> ThreadedReader<'a> {
 
     fn read_record_batches_from_sub_reader(
         &mut self,
-        mut reader: Box<dyn Stream<Item = Result<RecordBatchWithKey>> + Send + Unpin>,
-        tx: Sender<Result<RecordBatchWithKey>>,
+        mut reader: DecodedBatchStream,
+        tx: Sender<Result<DecodedBatch>>,
     ) {
         self.runtime.spawn(async move {
             while let Some(batch) = reader.next().await {
@@ -628,10 +691,10 @@ impl<'a> SstReader for ThreadedReader<'a> {
             .maybe_read_parallelly(self.read_parallelism)
             .await?;
         if sub_readers.is_empty() {
-            return Ok(Box::new(RecordBatchReceiver {
+            return Ok(Box::new(MemoryTokenHolder::new(RecordBatchReceiver {
                 rx_group: Vec::new(),
                 cur_rx_idx: 0,
-            }) as _);
+            })) as _);
         }
 
         let read_parallelism = sub_readers.len();
@@ -643,7 +706,7 @@ impl<'a> SstReader for ThreadedReader<'a> {
         let channel_cap_per_sub_reader = self.channel_cap / self.read_parallelism + 1;
         let (tx_group, rx_group): (Vec<_>, Vec<_>) = (0..read_parallelism)
             .into_iter()
-            .map(|_| mpsc::channel::<Result<RecordBatchWithKey>>(channel_cap_per_sub_reader))
+            .map(|_| mpsc::channel::<Result<DecodedBatch>>(channel_cap_per_sub_reader))
             .unzip();
 
         // Start the background readings.
@@ -651,10 +714,12 @@ impl<'a> SstReader for ThreadedReader<'a> {
             self.read_record_batches_from_sub_reader(sub_reader, tx);
         }
 
-        Ok(Box::new(RecordBatchReceiver {
+        // The tokens are sent with the record batches through the channels, so
+        // the memory of the buffered record batches is still held.
+        Ok(Box::new(MemoryTokenHolder::new(RecordBatchReceiver {
             rx_group,
             cur_rx_idx: 0,
-        }) as _)
+        })) as _)
     }
 }
 
@@ -666,10 +731,12 @@ mod tests {
         time::Duration,
     };
 
-    use futures::{Stream, StreamExt};
+    use common_types::tests::{build_record_batch_with_key_by_rows, build_row};
+    use futures::{stream, Stream, StreamExt};
     use tokio::sync::mpsc::{self, Receiver, Sender};
 
-    use super::ParallelismOptions;
+    use super::{DecodedBatch, MemoryTokenHolder, ParallelismOptions};
+    use crate::sst::{parquet::encoding::DecodeMemoryLimit, reader::Result};
 
     struct MockReceivers {
         rx_group: Vec<Receiver<u32>>,
@@ -771,6 +838,42 @@ mod tests {
         assert_eq!(actual, expected);
     }
 
+    #[tokio::test]
+    async fn test_memory_token_holder() {
+        let memory_limit = DecodeMemoryLimit::new(100);
+        let decoded_batches: Vec<Result<DecodedBatch>> = [10, 20]
+            .into_iter()
+            .map(|bytes| {
+                let record_batch =
+                    build_record_batch_with_key_by_rows(vec![build_row(b"key", 1, 1.0, "v")]);
+                let memory_token = memory_limit.try_apply_token(bytes).unwrap();
+                Ok((record_batch, Some(memory_token)))
+            })
+            .collect();
+        assert_eq!(30, memory_limit.usage());
+
+        let mut stream = MemoryTokenHolder::new(stream::iter(decoded_batches));
+        // The token of the polled record batch is held until the next poll.
+        stream.next().await.unwrap().unwrap();
+        assert_eq!(30, memory_limit.usage());
+        stream.next().await.unwrap().unwrap();
+        assert_eq!(20, memory_limit.usage());
+        assert!(stream.next().await.is_none());
+        assert_eq!(0, memory_limit.usage());
+
+        // The token is released once the stream is dropped.
+        let memory_token = memory_limit.try_apply_token(10).unwrap();
+        let record_batch =
+            build_record_batch_with_key_by_rows(vec![build_row(b"key", 1, 1.0, "v")]);
+        let decoded_batches: Vec<Result<DecodedBatch>> =
+            vec![Ok((record_batch, Some(memory_token)))];
+        let mut stream = MemoryTokenHolder::new(stream::iter(decoded_batches));
+        stream.next().await.unwrap().unwrap();
+        assert_eq!(10, memory_limit.usage());
+        drop(stream);
+        assert_eq!(0, memory_limit.usage());
+    }
+
     #[test]
     fn test_parallelism_options() {
         // `read_batch_row_num` < num_rows_per_row_group`
@@ -2,25 +2,37 @@
 
 //! Sst builder implementation based on parquet.
 
-use std::sync::{
-    atomic::{AtomicUsize, Ordering},
-    Arc,
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
 };
 
+use arrow::{array::Array, record_batch::RecordBatch as ArrowRecordBatch};
 use async_trait::async_trait;
-use common_types::{record_batch::RecordBatchWithKey, request_id::RequestId};
+use common_types::{
+    record_batch::{RecordBatchWithKey, RecordBatchWithKeyBuilder},
+    request_id::RequestId,
+    row::RowViewOnBatch,
+};
 use datafusion::parquet::basic::Compression;
 use ethbloom::{Bloom, Input};
-use futures::StreamExt;
-use log::debug;
+use futures::{stream, StreamExt};
+use log::{debug, warn};
 use object_store::{ObjectStoreRef, Path};
 use snafu::ResultExt;
 
-use crate::sst::{
-    builder::{RecordBatchStream, SstBuilder, *},
-    factory::{ObjectStorePickerRef, SstBuilderOptions},
-    file::{BloomFilter, SstMetaData},
-    parquet::encoding::ParquetEncoder,
+use crate::{
+    sst::{
+        builder::{RecordBatchStream, SstBuilder, *},
+        factory::{ObjectStorePickerRef, SstBuilderOptions},
+        file::{BloomFilter, CompositeBloomFilter, SstMetaData},
+        parquet::encoding::{self, ColumnEncoding, ParquetEncoder, ParquetEncoderOptions},
+        sidecar::{self, SstStats},
+    },
+    table_options::{StorageFormat, StorageFormatOptions},
 };
 
 /// The implementation of sst based on parquet and object storage.
@@ -33,6 +45,18 @@ pub struct ParquetSstBuilder<'a> {
     /// Max row group size.
     num_rows_per_row_group: usize,
     compression: Compression,
+    /// Whether to encode in columnar format if encoding in hybrid format fails.
+    hybrid_encode_fallback: bool,
+    /// Max estimated bytes of the rows in one row group.
+    max_row_group_bytes: Option<usize>,
+    /// Column groups to build the composite bloom filters on.
+    composite_bloom_filter_columns: Vec<Vec<String>>,
+    /// Columns not collapsed in the hybrid format.
+    hybrid_uncollapsed_columns: Vec<String>,
+    /// Whether to sort and dedup the rows by the primary key before encoding.
+    sort_dedup_by_key: bool,
+    /// Whether to write the stats sidecar next to the sst.
+    stats_sidecar: bool,
 }
 
 impl<'a> ParquetSstBuilder<'a> {
@@ -47,6 +71,12 @@ impl<'a> ParquetSstBuilder<'a> {
             store,
             num_rows_per_row_group: options.num_rows_per_row_group,
             compression: options.compression.into(),
+            hybrid_encode_fallback: options.hybrid_encode_fallback,
+            max_row_group_bytes: options.max_row_group_bytes,
+            composite_bloom_filter_columns: options.composite_bloom_filter_columns.clone(),
+            hybrid_uncollapsed_columns: options.hybrid_uncollapsed_columns.clone(),
+            sort_dedup_by_key: options.sort_dedup_by_key,
+            stats_sidecar: options.stats_sidecar,
         }
     }
 }
@@ -58,18 +88,96 @@ struct RecordBytesReader {
     record_stream: RecordBatchStream,
     num_rows_per_row_group: usize,
     compression: Compression,
+    hybrid_encode_fallback: bool,
+    max_row_group_bytes: Option<usize>,
+    composite_bloom_filter_columns: Vec<Vec<String>>,
+    hybrid_uncollapsed_columns: Vec<String>,
+    sort_dedup_by_key: bool,
     meta_data: SstMetaData,
     total_row_num: Arc<AtomicUsize>,
-    // Record batch partitioned by exactly given `num_rows_per_row_group`
+    // Record batch partitioned by given `num_rows_per_row_group` and
+    // `max_row_group_bytes`
     // There may be more than one `RecordBatchWithKey` inside each partition
     partitioned_record_batch: Vec<Vec<RecordBatchWithKey>>,
 }
 
+#[cfg(test)]
+lazy_static::lazy_static! {
+    /// Columns whose bloom filters are forced to fail to build.
+    static ref BLOOM_FILTER_FAILURE_COLUMNS: std::sync::Mutex<std::collections::HashSet<String>> =
+        Default::default();
+}
+
+/// Estimate the bytes of one row in the `record_batch` by its memory size.
+fn estimate_row_bytes(record_batch: &RecordBatchWithKey) -> usize {
+    let batch_bytes: usize = record_batch
+        .as_arrow_record_batch()
+        .columns()
+        .iter()
+        .map(|column| column.get_array_memory_size())
+        .sum();
+    let num_rows = record_batch.num_rows().max(1);
+
+    (batch_bytes + num_rows - 1) / num_rows
+}
+
 impl RecordBytesReader {
-    // Partition record batch stream into batch vector with exactly given
-    // `num_rows_per_row_group`
+    /// Sort the rows from `self.record_stream` by the primary key and drop the
+    /// duplicate keys, the first row of the same key is kept.
+    ///
+    /// All the rows are collected into memory, and the `self.record_stream`
+    /// is replaced by the stream of the sorted rows.
+    async fn sort_dedup_record_batch(&mut self) -> Result<()> {
+        let mut record_batches = Vec::new();
+        while let Some(record_batch) = self.record_stream.next().await {
+            let record_batch = record_batch.context(PollRecordBatch)?;
+            if !record_batch.is_empty() {
+                record_batches.push(record_batch);
+            }
+        }
+        if record_batches.is_empty() {
+            return Ok(());
+        }
+
+        let schema_with_key = record_batches[0].schema_with_key().clone();
+        let mut row_views: Vec<_> = record_batches
+            .iter()
+            .flat_map(|record_batch| {
+                (0..record_batch.num_rows()).map(move |row_idx| RowViewOnBatch {
+                    record_batch,
+                    row_idx,
+                })
+            })
+            .collect();
+        // The sort is stable, so the rows of the same key keep the order in the
+        // stream.
+        row_views.sort_by(|lhs, rhs| schema_with_key.compare_row(lhs, rhs));
+        let total_rows = row_views.len();
+        row_views.dedup_by(|curr, prev| schema_with_key.compare_row(prev, curr).is_eq());
+        debug!(
+            "Sort and dedup record batches, request_id:{}, total_rows:{}, selected_rows:{}",
+            self.request_id,
+            total_rows,
+            row_views.len()
+        );
+
+        let mut builder =
+            RecordBatchWithKeyBuilder::with_capacity(schema_with_key, row_views.len());
+        for row_view in &row_views {
+            builder.append_row_view(row_view).context(SortRecordBatch)?;
+        }
+        let record_batch = builder.build().context(SortRecordBatch)?;
+        self.record_stream = Box::new(stream::iter(vec![Ok(record_batch)]));
+
+        Ok(())
+    }
+
+    // Partition record batch stream into batch vector with given
+    // `num_rows_per_row_group` and `max_row_group_bytes`
     async fn partition_record_batch(&mut self) -> Result<()> {
-        let mut prev_record_batch: Option<RecordBatchWithKey> = None;
+        // The record batch and the estimated bytes of its rows, the estimation is
+        // done before slicing as the slices share the memory of the whole batch.
+        let mut prev_record_batch: Option<(RecordBatchWithKey, usize)> = None;
 
         loop {
             let row_group = self.fetch_next_row_group(&mut prev_record_batch).await?;
@@ -85,32 +193,48 @@ impl RecordBytesReader {
     /// Fetch an integral row group from the `self.record_stream`.
     ///
     /// Except the last one, every row group is ensured to contains exactly
-    /// `self.num_rows_per_row_group`. As for the last one, it will cover all
-    /// the left rows.
+    /// `self.num_rows_per_row_group`, unless the estimated bytes of its rows
+    /// reach `self.max_row_group_bytes` first. As for the last one, it will
+    /// cover all the left rows.
     async fn fetch_next_row_group(
         &mut self,
-        prev_record_batch: &mut Option<RecordBatchWithKey>,
+        prev_record_batch: &mut Option<(RecordBatchWithKey, usize)>,
     ) -> Result<Vec<RecordBatchWithKey>> {
         let mut curr_row_group = vec![];
         // Used to record the number of remaining rows to fill `curr_row_group`.
         let mut remaining = self.num_rows_per_row_group;
+        // Used to record the remaining estimated bytes to fill `curr_row_group`.
+        let mut remaining_bytes = self.max_row_group_bytes.unwrap_or(usize::MAX);
 
         // Keep filling `curr_row_group` until `remaining` is zero.
         while remaining > 0 {
             // Use the `prev_record_batch` to fill `curr_row_group` if possible.
-            if let Some(v) = prev_record_batch {
+            if let Some((v, row_bytes)) = prev_record_batch {
+                // At least one row is filled even if the row is too large, otherwise no
+                // progress can be made.
+                let mut num_rows = remaining.min(remaining_bytes / (*row_bytes).max(1));
+                if curr_row_group.is_empty() {
+                    num_rows = num_rows.max(1);
+                }
+                if num_rows == 0 {
+                    // The `curr_row_group` is full in bytes.
+                    break;
+                }
+
                 let total_rows = v.num_rows();
-                if total_rows <= remaining {
+                if total_rows <= num_rows {
                     // The whole record batch is part of the `curr_row_group`, and let's feed it
                     // into `curr_row_group`.
-                    curr_row_group.push(prev_record_batch.take().unwrap());
+                    remaining_bytes = remaining_bytes.saturating_sub(total_rows * *row_bytes);
+                    curr_row_group.push(prev_record_batch.take().unwrap().0);
                     remaining -= total_rows;
                 } else {
-                    // Only first `remaining` rows of the record batch belongs to `curr_row_group`,
+                    // Only first `num_rows` rows of the record batch belongs to `curr_row_group`,
                     // the rest should be put to `prev_record_batch` for next row group.
-                    curr_row_group.push(v.slice(0, remaining));
-                    *v = v.slice(remaining, total_rows - remaining);
-                    remaining = 0;
+                    curr_row_group.push(v.slice(0, num_rows));
+                    *v = v.slice(num_rows, total_rows - num_rows);
+                    // The `curr_row_group` is full in either rows or bytes.
+                    break;
                 }
 
                 continue;
@@ -128,7 +252,8 @@ impl RecordBytesReader {
 
                     // Updated the exhausted `prev_record_batch`, and let next loop to continue to
                     // fill `curr_row_group`.
-                    prev_record_batch.replace(v);
+                    let row_bytes = estimate_row_bytes(&v);
+                    prev_record_batch.replace((v, row_bytes));
                 }
                 None => break,
             };
@@ -137,65 +262,233 @@ impl RecordBytesReader {
         Ok(curr_row_group)
     }
 
+    /// Build the bloom filters of all the columns.
+    ///
+    /// Building the filters is best-effort: the column failed to build its
+    /// filters gets the saturated filters instead and is recorded in the
+    /// missing columns of the [BloomFilter], so the sst can still be written.
     fn build_bloom_filter(&self) -> BloomFilter {
+        let num_row_groups = self.partitioned_record_batch.len();
+        let num_columns = self.meta_data.schema.num_columns();
+        let mut filters = vec![Vec::with_capacity(num_columns); num_row_groups];
+        let mut missing_columns = Vec::new();
+        for col_idx in 0..num_columns {
+            let column_filters = match self.build_column_bloom_filters(col_idx) {
+                Ok(v) => v,
+                Err(e) => {
+                    warn!(
+                        "Failed to build bloom filter, the column is left without filter, request_id:{}, column:{}, err:{}",
+                        self.request_id,
+                        self.meta_data.schema.column(col_idx).name,
+                        e
+                    );
+                    missing_columns.push(col_idx);
+                    vec![BloomFilter::saturated_filter(); num_row_groups]
+                }
+            };
+
+            for (row_group_filters, filter) in filters.iter_mut().zip(column_filters) {
+                row_group_filters.push(filter);
+            }
+        }
+        let composite_filters = self
+            .composite_bloom_filter_column_indexes()
+            .into_iter()
+            .map(|column_indexes| self.build_composite_bloom_filter(column_indexes))
+            .collect();
+
+        BloomFilter::new(filters)
+            .with_composite_filters(composite_filters)
+            .with_missing_columns(missing_columns)
+    }
+
+    /// Build the filters of the column at `col_idx` for every row group.
+    fn build_column_bloom_filters(&self, col_idx: usize) -> Result<Vec<Bloom>> {
+        #[cfg(test)]
+        {
+            let column_name = &self.meta_data.schema.column(col_idx).name;
+            if BLOOM_FILTER_FAILURE_COLUMNS
+                .lock()
+                .unwrap()
+                .contains(column_name)
+            {
+                return BuildBloomFilter {
+                    column: column_name.clone(),
+                    msg: "injected failure",
+                }
+                .fail();
+            }
+        }
+
         let filters = self
             .partitioned_record_batch
             .iter()
             .map(|row_group_batch| {
-                let mut row_group_filters =
-                    vec![Bloom::default(); row_group_batch[0].num_columns()];
+                let mut row_group_filter = Bloom::default();
+                for partial_batch in row_group_batch {
+                    let column = partial_batch.column(col_idx);
+                    for row in 0..column.num_rows() {
+                        let datum = column.datum(row);
+                        let bytes = datum.to_bytes();
+                        row_group_filter.accrue(Input::Raw(&bytes));
+                    }
+                }
+
+                row_group_filter
+            })
+            .collect();
+
+        Ok(filters)
+    }
+
+    /// Resolve the column groups of the composite bloom filters into the
+    /// column indexes, the groups with missing columns are ignored.
+    fn composite_bloom_filter_column_indexes(&self) -> Vec<Vec<usize>> {
+        let schema = &self.meta_data.schema;
+        self.composite_bloom_filter_columns
+            .iter()
+            .filter_map(|column_names| {
+                let column_indexes = column_names
+                    .iter()
+                    .map(|name| schema.index_of(name))
+                    .collect::<Option<Vec<_>>>();
+                if column_indexes.is_none() {
+                    debug!(
+                        "Composite bloom filter is ignored for columns not found, request_id:{}, columns:{:?}",
+                        self.request_id, column_names
+                    );
+                }
+                column_indexes
+            })
+            .collect()
+    }
 
+    fn build_composite_bloom_filter(&self, column_indexes: Vec<usize>) -> CompositeBloomFilter {
+        let filters = self
+            .partitioned_record_batch
+            .iter()
+            .map(|row_group_batch| {
+                let mut row_group_filter = Bloom::default();
                 for partial_batch in row_group_batch {
-                    for (col_idx, column) in partial_batch.columns().iter().enumerate() {
-                        for row in 0..column.num_rows() {
-                            let datum = column.datum(row);
-                            let bytes = datum.to_bytes();
-                            row_group_filters[col_idx].accrue(Input::Raw(&bytes));
-                        }
+                    let columns = partial_batch.columns();
+                    for row in 0..partial_batch.num_rows() {
+                        let datums = column_indexes
+                            .iter()
+                            .map(|col_idx| columns[*col_idx].datum(row))
+                            .collect::<Vec<_>>();
+                        let key = CompositeBloomFilter::composite_key(&datums);
+                        row_group_filter.accrue(Input::Raw(&key));
                     }
                 }
 
-                row_group_filters
+                row_group_filter
             })
-            .collect::<Vec<_>>();
+            .collect();
 
-        BloomFilter::new(filters)
+        CompositeBloomFilter::new(column_indexes, filters)
     }
 
-    async fn read_all(mut self) -> Result<Vec<u8>> {
+    /// Encode all the record batches and return the encoded bytes and the
+    /// storage format actually used.
+    ///
+    /// If `hybrid_encode_fallback` is set and encoding in the hybrid format
+    /// fails, the same record batches will be encoded in the columnar format
+    /// instead.
+    async fn read_all(mut self) -> Result<(Vec<u8>, StorageFormat)> {
+        if self.sort_dedup_by_key {
+            self.sort_dedup_record_batch().await?;
+        }
         self.partition_record_batch().await?;
         let filter = self.build_bloom_filter();
         self.meta_data.bloom_filter = Some(filter);
 
+        let row_groups: Vec<Vec<ArrowRecordBatch>> =
+            std::mem::take(&mut self.partitioned_record_batch)
+                .into_iter()
+                .map(|record_batches| {
+                    record_batches
+                        .into_iter()
+                        .map(|batch| batch.into_record_batch().into_arrow_record_batch())
+                        .collect()
+                })
+                .collect();
+        self.meta_data.row_group_time_ranges =
+            encoding::compute_row_group_time_ranges(&self.meta_data.schema, &row_groups)
+                .map_err(|e| Box::new(e) as _)
+                .context(EncodeRecordBatch)?;
+
+        let storage_format = self.meta_data.storage_format();
+        let meta_data = self.meta_data.clone();
+        let encode_res = self.encode_row_groups(meta_data, &row_groups);
+        let (bytes, row_num, storage_format) = match encode_res {
+            Ok((bytes, row_num)) => (bytes, row_num, storage_format),
+            Err(e) if self.hybrid_encode_fallback && storage_format == StorageFormat::Hybrid => {
+                warn!(
+                    "Failed to encode in hybrid format, fallback to columnar format, request_id:{}, err:{}",
+                    self.request_id, e
+                );
+
+                let mut meta_data = self.meta_data.clone();
+                meta_data.storage_format_opts = StorageFormatOptions::new(StorageFormat::Columnar);
+                let (bytes, row_num) = self.encode_row_groups(meta_data, &row_groups)?;
+                (bytes, row_num, StorageFormat::Columnar)
+            }
+            Err(e) => return Err(e),
+        };
+        self.total_row_num.fetch_add(row_num, Ordering::Relaxed);
+
+        Ok((bytes, storage_format))
+    }
+
+    /// Encode the `row_groups` with the storage format specified in
+    /// `meta_data`, and return the encoded bytes and the number of rows.
+    fn encode_row_groups(
+        &self,
+        meta_data: SstMetaData,
+        row_groups: &[Vec<ArrowRecordBatch>],
+    ) -> Result<(Vec<u8>, usize)> {
+        let column_encodings: HashMap<_, _> = self
+            .hybrid_uncollapsed_columns
+            .iter()
+            .map(|name| {
+                let encoding = ColumnEncoding {
+                    collapsible: Some(false),
+                    ..Default::default()
+                };
+                (name.clone(), encoding)
+            })
+            .collect();
         let mut parquet_encoder = ParquetEncoder::try_new(
-            self.num_rows_per_row_group,
-            self.compression,
-            self.meta_data,
+            ParquetEncoderOptions {
+                num_rows_per_row_group: self.num_rows_per_row_group,
+                compression: self.compression,
+                column_encodings,
+                ..Default::default()
+            },
+            meta_data,
         )
         .map_err(|e| Box::new(e) as _)
         .context(EncodeRecordBatch)?;
 
-        // process record batch stream
-        let mut arrow_record_batch_vec = Vec::new();
-        for record_batches in self.partitioned_record_batch {
-            for batch in record_batches {
-                arrow_record_batch_vec.push(batch.into_record_batch().into_arrow_record_batch());
-            }
-
-            let buf_len = arrow_record_batch_vec.len();
+        let mut total_row_num = 0;
+        for arrow_record_batch_vec in row_groups {
             let row_num = parquet_encoder
-                .encode_record_batch(arrow_record_batch_vec)
+                .encode_record_batch(arrow_record_batch_vec.clone())
                 .map_err(|e| Box::new(e) as _)
                 .context(EncodeRecordBatch)?;
-            self.total_row_num.fetch_add(row_num, Ordering::Relaxed);
-            arrow_record_batch_vec = Vec::with_capacity(buf_len);
+            total_row_num += row_num;
         }
 
-        let bytes = parquet_encoder
-            .close()
+        let (bytes, summary) = parquet_encoder
+            .close_with_summary()
             .map_err(|e| Box::new(e) as _)
             .context(EncodeRecordBatch)?;
-        Ok(bytes)
+        debug!(
+            "Encode parquet file, request_id:{}, summary:{:?}",
+            self.request_id, summary
+        );
+
+        Ok((bytes, total_row_num))
     }
 }
 
@@ -218,22 +511,37 @@ impl<'a> SstBuilder for ParquetSstBuilder<'a> {
             record_stream,
             num_rows_per_row_group: self.num_rows_per_row_group,
             compression: self.compression,
+            hybrid_encode_fallback: self.hybrid_encode_fallback,
+            max_row_group_bytes: self.max_row_group_bytes,
+            composite_bloom_filter_columns: self.composite_bloom_filter_columns.clone(),
+            hybrid_uncollapsed_columns: self.hybrid_uncollapsed_columns.clone(),
+            sort_dedup_by_key: self.sort_dedup_by_key,
             total_row_num: total_row_num.clone(),
             // TODO(xikai): should we avoid this clone?
             meta_data: meta.to_owned(),
             partitioned_record_batch: Default::default(),
         };
-        let bytes = reader.read_all().await?;
+        let (bytes, storage_format) = reader.read_all().await?;
         self.store
             .put(self.path, bytes.into())
             .await
             .context(Storage)?;
 
         let file_head = self.store.head(self.path).await.context(Storage)?;
+        let row_num = total_row_num.load(Ordering::Relaxed);
+        if self.stats_sidecar {
+            let stats = SstStats::new(meta, row_num as u64, file_head.size as u64);
+            let bytes = serde_json::to_vec(&stats).context(EncodeStatsSidecar)?;
+            self.store
+                .put(&sidecar::stats_sidecar_path(self.path), bytes.into())
+                .await
+                .context(Storage)?;
+        }
 
         Ok(SstInfo {
             file_size: file_head.size,
-            row_num: total_row_num.load(Ordering::Relaxed),
+            row_num,
+            storage_format,
         })
     }
 }
@@ -245,7 +553,12 @@ mod tests {
 
     use common_types::{
         bytes::Bytes,
+        column_schema,
+        datum::{Datum, DatumKind},
         projected_schema::ProjectedSchema,
+        row::Row,
+        schema::{self, Schema},
+        string::StringBytes,
         tests::{build_row, build_schema},
         time::{TimeRange, Timestamp},
     };
@@ -253,6 +566,7 @@ mod tests {
         runtime::{self, Runtime},
         tests::init_log_for_test,
     };
+    use datafusion::parquet::file::footer;
     use futures::stream;
     use object_store::LocalFileSystem;
     use table_engine::predicate::Predicate;
@@ -265,6 +579,7 @@ mod tests {
             factory::{
                 Factory, FactoryImpl, ReadFrequency, SstBuilderOptions, SstReaderOptions, SstType,
             },
+            file::tests::SstMetaDataMocker,
             parquet::AsyncParquetReader,
             reader::{tests::check_stream, SstReader},
         },
@@ -294,6 +609,12 @@ mod tests {
                 sst_type: SstType::Parquet,
                 num_rows_per_row_group,
                 compression: table_options::Compression::Uncompressed,
+                hybrid_encode_fallback: false,
+                max_row_group_bytes: None,
+                composite_bloom_filter_columns: Vec::new(),
+                hybrid_uncollapsed_columns: Vec::new(),
+                sort_dedup_by_key: false,
+                stats_sidecar: false,
             };
 
             let dir = tempdir().unwrap();
@@ -307,13 +628,15 @@ mod tests {
             let sst_meta = SstMetaData {
                 min_key: Bytes::from_static(b"100"),
                 max_key: Bytes::from_static(b"200"),
-                time_range: TimeRange::new_unchecked(Timestamp::new(1), Timestamp::new(2)),
+                time_range: TimeRange::new_unchecked(Timestamp::new(100), Timestamp::new(105)),
                 max_sequence: 200,
                 schema: schema.clone(),
+                schema_fingerprint: schema.fingerprint(),
                 size: 10,
                 row_num: 2,
                 storage_format_opts: Default::default(),
                 bloom_filter: Default::default(),
+                row_group_time_ranges: Vec::new(),
             };
 
             let mut counter = 5;
@@ -355,6 +678,7 @@ mod tests {
                 runtime: runtime.clone(),
                 num_rows_per_row_group: 5,
                 background_read_parallelism: 1,
+                decode_memory_limit: None,
             };
 
             let mut reader: Box<dyn SstReader + Send> = {
@@ -370,6 +694,16 @@ mod tests {
                 // bloom filter is built insider sst writer, so overwrite to default for
                 // comparsion
                 sst_meta_readback.bloom_filter = Default::default();
+                // The time range of every row group is tighter than and contained within the
+                // time range of the whole sst.
+                let row_group_time_ranges =
+                    std::mem::take(&mut sst_meta_readback.row_group_time_ranges);
+                assert_eq!(expected_num_rows.len(), row_group_time_ranges.len());
+                for time_range in &row_group_time_ranges {
+                    assert_ne!(&sst_meta.time_range, time_range);
+                    assert!(sst_meta.time_range.inclusive_start() <= time_range.inclusive_start());
+                    assert!(time_range.exclusive_end() <= sst_meta.time_range.exclusive_end());
+                }
                 assert_eq!(&sst_meta_readback, &sst_meta);
                 assert_eq!(
                     expected_num_rows,
@@ -395,6 +729,277 @@ mod tests {
         });
     }
 
+    #[test]
+    fn test_hybrid_encode_fallback() {
+        init_log_for_test();
+
+        let runtime = Arc::new(runtime::Builder::default().build().unwrap());
+        runtime.block_on(async {
+            let dir = tempdir().unwrap();
+            let store: ObjectStoreRef =
+                Arc::new(LocalFileSystem::new_with_prefix(dir.path()).unwrap());
+            let store_picker: ObjectStorePickerRef = Arc::new(store);
+            let sst_file_path = Path::from("data.par");
+
+            // The schema without tsid can't be encoded in hybrid format.
+            let schema = build_schema();
+            let sst_meta = SstMetaData {
+                min_key: Bytes::from_static(b"a"),
+                max_key: Bytes::from_static(b"b"),
+                time_range: TimeRange::new_unchecked(Timestamp::new(1), Timestamp::new(2)),
+                max_sequence: 200,
+                schema: schema.clone(),
+                schema_fingerprint: schema.fingerprint(),
+                size: 10,
+                row_num: 2,
+                storage_format_opts: StorageFormatOptions::new(StorageFormat::Hybrid),
+                bloom_filter: Default::default(),
+                row_group_time_ranges: Vec::new(),
+            };
+            let build_record_batch_stream = || {
+                let rows = vec![
+                    build_row(b"a", 100, 10.0, "v4"),
+                    build_row(b"b", 101, 10.0, "v4"),
+                ];
+                let batch = build_record_batch_with_key(schema.clone(), rows);
+                Box::new(stream::iter(vec![Ok(batch)])) as RecordBatchStream
+            };
+
+            let mut sst_builder_options = SstBuilderOptions {
+                sst_type: SstType::Parquet,
+                num_rows_per_row_group: 10,
+                compression: table_options::Compression::Uncompressed,
+                hybrid_encode_fallback: false,
+                max_row_group_bytes: None,
+                composite_bloom_filter_columns: Vec::new(),
+                hybrid_uncollapsed_columns: Vec::new(),
+                sort_dedup_by_key: false,
+                stats_sidecar: false,
+            };
+            let mut builder =
+                ParquetSstBuilder::new(&sst_file_path, &store_picker, &sst_builder_options);
+            assert!(builder
+                .build(RequestId::next_id(), &sst_meta, build_record_batch_stream())
+                .await
+                .is_err());
+
+            sst_builder_options.hybrid_encode_fallback = true;
+            let mut builder =
+                ParquetSstBuilder::new(&sst_file_path, &store_picker, &sst_builder_options);
+            let sst_info = builder
+                .build(RequestId::next_id(), &sst_meta, build_record_batch_stream())
+                .await
+                .unwrap();
+            assert_eq!(2, sst_info.row_num);
+            assert_eq!(StorageFormat::Columnar, sst_info.storage_format);
+
+            let sst_reader_options = SstReaderOptions {
+                read_batch_row_num: 5,
+                reverse: false,
+                frequency: ReadFrequency::Frequent,
+                projected_schema: ProjectedSchema::no_projection(schema.clone()),
+                predicate: Arc::new(Predicate::empty()),
+                meta_cache: None,
+                runtime: runtime.clone(),
+                num_rows_per_row_group: 10,
+                background_read_parallelism: 1,
+                decode_memory_limit: None,
+            };
+            let mut reader =
+                AsyncParquetReader::new(&sst_file_path, &store_picker, &sst_reader_options);
+            assert_eq!(
+                StorageFormat::Columnar,
+                reader.meta_data().await.unwrap().storage_format()
+            );
+
+            let mut stream = reader.read().await.unwrap();
+            let expect_rows = vec![
+                build_row(b"a", 100, 10.0, "v4"),
+                build_row(b"b", 101, 10.0, "v4"),
+            ];
+            check_stream(&mut stream, expect_rows).await;
+        });
+    }
+
+    /// Build a schema with `num_fields` string fields besides the key columns.
+    fn build_wide_schema(num_fields: usize) -> Schema {
+        let mut builder = schema::Builder::new()
+            .auto_increment_column_id(true)
+            .add_key_column(
+                column_schema::Builder::new("key".to_string(), DatumKind::Varbinary)
+                    .build()
+                    .unwrap(),
+            )
+            .unwrap()
+            .add_key_column(
+                column_schema::Builder::new("timestamp".to_string(), DatumKind::Timestamp)
+                    .build()
+                    .unwrap(),
+            )
+            .unwrap();
+        for i in 0..num_fields {
+            builder = builder
+                .add_normal_column(
+                    column_schema::Builder::new(format!("field{}", i), DatumKind::String)
+                        .build()
+                        .unwrap(),
+                )
+                .unwrap();
+        }
+
+        builder.build().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_max_row_group_bytes() {
+        let num_fields = 32;
+        let num_rows = 1000;
+        let max_row_group_bytes = 1024 * 1024;
+        let schema = build_wide_schema(num_fields);
+
+        let rows = (0..num_rows)
+            .map(|row_idx| {
+                let mut datums = vec![
+                    Datum::Varbinary(Bytes::from(format!("key{:04}", row_idx))),
+                    Datum::Timestamp(Timestamp::new(100 + row_idx as i64)),
+                ];
+                for field_idx in 0..num_fields {
+                    let value = format!("{:0>100}", row_idx * num_fields + field_idx);
+                    datums.push(Datum::String(StringBytes::from(value.as_str())));
+                }
+                Row::from_datums(datums)
+            })
+            .collect::<Vec<_>>();
+        let batch = build_record_batch_with_key(schema.clone(), rows);
+        // The whole batch is larger than the cap.
+        assert!(estimate_row_bytes(&batch) * num_rows > max_row_group_bytes);
+
+        let dir = tempdir().unwrap();
+        let store: ObjectStoreRef = Arc::new(LocalFileSystem::new_with_prefix(dir.path()).unwrap());
+        let store_picker: ObjectStorePickerRef = Arc::new(store.clone());
+        let sst_file_path = Path::from("data.par");
+        let sst_builder_options = SstBuilderOptions {
+            sst_type: SstType::Parquet,
+            // Large enough to hold all the rows.
+            num_rows_per_row_group: 10000,
+            compression: table_options::Compression::Uncompressed,
+            hybrid_encode_fallback: false,
+            max_row_group_bytes: Some(max_row_group_bytes),
+            composite_bloom_filter_columns: Vec::new(),
+            hybrid_uncollapsed_columns: Vec::new(),
+            sort_dedup_by_key: false,
+            stats_sidecar: false,
+        };
+        let sst_meta = SstMetaData {
+            min_key: Bytes::from_static(b"key0000"),
+            max_key: Bytes::from(format!("key{:04}", num_rows - 1)),
+            time_range: TimeRange::new_unchecked(
+                Timestamp::new(100),
+                Timestamp::new(100 + num_rows as i64),
+            ),
+            max_sequence: 1,
+            schema: schema.clone(),
+            schema_fingerprint: schema.fingerprint(),
+            size: 0,
+            row_num: num_rows as u64,
+            storage_format_opts: Default::default(),
+            bloom_filter: Default::default(),
+            row_group_time_ranges: Vec::new(),
+        };
+
+        let mut builder =
+            ParquetSstBuilder::new(&sst_file_path, &store_picker, &sst_builder_options);
+        let sst_info = builder
+            .build(
+                RequestId::next_id(),
+                &sst_meta,
+                Box::new(stream::iter(vec![Ok(batch)])),
+            )
+            .await
+            .unwrap();
+        assert_eq!(num_rows, sst_info.row_num);
+
+        let bytes = store
+            .get(&sst_file_path)
+            .await
+            .unwrap()
+            .bytes()
+            .await
+            .unwrap();
+        let parquet_meta_data = footer::parse_metadata(&bytes).unwrap();
+        assert!(parquet_meta_data.num_row_groups() > 1);
+        let mut total_rows = 0;
+        for row_group in parquet_meta_data.row_groups() {
+            assert!(row_group.total_byte_size() as usize <= max_row_group_bytes);
+            total_rows += row_group.num_rows() as usize;
+        }
+        assert_eq!(num_rows, total_rows);
+    }
+
+    #[tokio::test]
+    async fn test_write_stats_sidecar() {
+        let schema = build_schema();
+        let rows = vec![
+            build_row(b"a", 100, 10.0, "v1"),
+            build_row(b"b", 101, 10.0, "v2"),
+            build_row(b"c", 102, 10.0, "v3"),
+        ];
+        let batch = build_record_batch_with_key(schema.clone(), rows);
+
+        let dir = tempdir().unwrap();
+        let store: ObjectStoreRef = Arc::new(LocalFileSystem::new_with_prefix(dir.path()).unwrap());
+        let store_picker: ObjectStorePickerRef = Arc::new(store.clone());
+        let sst_file_path = Path::from("data.par");
+        let sst_builder_options = SstBuilderOptions {
+            sst_type: SstType::Parquet,
+            num_rows_per_row_group: 10,
+            compression: table_options::Compression::Uncompressed,
+            hybrid_encode_fallback: false,
+            max_row_group_bytes: None,
+            composite_bloom_filter_columns: Vec::new(),
+            hybrid_uncollapsed_columns: Vec::new(),
+            sort_dedup_by_key: false,
+            stats_sidecar: true,
+        };
+        let mut sst_meta = SstMetaDataMocker::new(schema)
+            .time_range(TimeRange::new_unchecked(
+                Timestamp::new(100),
+                Timestamp::new(103),
+            ))
+            .build();
+        sst_meta.min_key = Bytes::from_static(b"a");
+        sst_meta.max_key = Bytes::from_static(b"c");
+
+        let mut builder =
+            ParquetSstBuilder::new(&sst_file_path, &store_picker, &sst_builder_options);
+        let sst_info = builder
+            .build(
+                RequestId::next_id(),
+                &sst_meta,
+                Box::new(stream::iter(vec![Ok(batch)])),
+            )
+            .await
+            .unwrap();
+
+        let bytes = store
+            .get(&sidecar::stats_sidecar_path(&sst_file_path))
+            .await
+            .unwrap()
+            .bytes()
+            .await
+            .unwrap();
+        let stats: SstStats = serde_json::from_slice(&bytes).unwrap();
+        let expect = SstStats {
+            time_range_start: 100,
+            time_range_end: 103,
+            min_key: "61".to_string(),
+            max_key: "63".to_string(),
+            row_num: 3,
+            size: sst_info.file_size as u64,
+        };
+        assert_eq!(expect, stats);
+    }
+
     #[tokio::test]
     async fn test_partition_record_batch() {
         // rows per group: 10
@@ -447,16 +1052,23 @@ mod tests {
             record_stream: record_batch_stream,
             num_rows_per_row_group,
             compression: Compression::UNCOMPRESSED,
+            hybrid_encode_fallback: false,
+            max_row_group_bytes: None,
+            composite_bloom_filter_columns: Vec::new(),
+            hybrid_uncollapsed_columns: Vec::new(),
+            sort_dedup_by_key: false,
             meta_data: SstMetaData {
                 min_key: Default::default(),
                 max_key: Default::default(),
                 time_range: Default::default(),
                 max_sequence: 1,
+                schema_fingerprint: schema.fingerprint(),
                 schema,
                 size: 0,
                 row_num: 0,
                 storage_format_opts: Default::default(),
                 bloom_filter: Default::default(),
+                row_group_time_ranges: Vec::new(),
             },
             total_row_num: Arc::new(AtomicUsize::new(0)),
             partitioned_record_batch: Vec::new(),
@@ -472,4 +1084,244 @@ mod tests {
             assert_eq!(expected_row_num, actual);
         }
     }
+
+    #[tokio::test]
+    async fn test_sort_dedup_record_batch() {
+        let schema = build_schema();
+        // Rows of the same key are ordered by the sequence in descending order,
+        // so the first one should be kept.
+        let batches = vec![
+            vec![
+                build_row(b"c", 100, 1.0, "v1"),
+                build_row(b"a", 101, 1.0, "v1"),
+                build_row(b"b", 100, 1.0, "v1"),
+            ],
+            vec![
+                build_row(b"a", 101, 2.0, "v2"),
+                build_row(b"b", 99, 2.0, "v2"),
+                build_row(b"c", 100, 2.0, "v2"),
+            ],
+            vec![build_row(b"a", 100, 3.0, "v3")],
+        ];
+        let record_batch_stream = Box::new(stream::iter(
+            batches
+                .into_iter()
+                .map(|rows| Ok(build_record_batch_with_key(schema.clone(), rows)))
+                .collect::<Vec<_>>(),
+        ));
+
+        let mut reader = RecordBytesReader {
+            request_id: RequestId::next_id(),
+            record_stream: record_batch_stream,
+            num_rows_per_row_group: 2,
+            compression: Compression::UNCOMPRESSED,
+            hybrid_encode_fallback: false,
+            max_row_group_bytes: None,
+            composite_bloom_filter_columns: Vec::new(),
+            hybrid_uncollapsed_columns: Vec::new(),
+            sort_dedup_by_key: true,
+            meta_data: SstMetaData {
+                min_key: Default::default(),
+                max_key: Default::default(),
+                time_range: Default::default(),
+                max_sequence: 1,
+                schema_fingerprint: schema.fingerprint(),
+                schema,
+                size: 0,
+                row_num: 0,
+                storage_format_opts: Default::default(),
+                bloom_filter: Default::default(),
+                row_group_time_ranges: Vec::new(),
+            },
+            total_row_num: Arc::new(AtomicUsize::new(0)),
+            partitioned_record_batch: Vec::new(),
+        };
+
+        reader.sort_dedup_record_batch().await.unwrap();
+        reader.partition_record_batch().await.unwrap();
+
+        let rows: Vec<_> = reader
+            .partitioned_record_batch
+            .iter()
+            .flatten()
+            .flat_map(|batch| (0..batch.num_rows()).map(|i| batch.clone_row_at(i)))
+            .collect();
+        let expected = vec![
+            build_row(b"a", 100, 3.0, "v3"),
+            build_row(b"a", 101, 1.0, "v1"),
+            build_row(b"b", 99, 2.0, "v2"),
+            build_row(b"b", 100, 1.0, "v1"),
+            build_row(b"c", 100, 1.0, "v1"),
+        ];
+        assert_eq!(expected, rows);
+        assert_eq!(3, reader.partitioned_record_batch.len());
+    }
+
+    #[tokio::test]
+    async fn test_composite_bloom_filter() {
+        let schema = build_schema();
+        let rows = vec![
+            build_row(b"region0", 100, 10.0, "host0"),
+            build_row(b"region0", 101, 10.0, "host1"),
+            build_row(b"region1", 102, 10.0, "host2"),
+        ];
+        let batch = build_record_batch_with_key(schema.clone(), rows);
+
+        let mut reader = RecordBytesReader {
+            request_id: RequestId::next_id(),
+            record_stream: Box::new(stream::iter(vec![Ok(batch)])),
+            num_rows_per_row_group: 10,
+            compression: Compression::UNCOMPRESSED,
+            hybrid_encode_fallback: false,
+            max_row_group_bytes: None,
+            composite_bloom_filter_columns: vec![
+                vec!["key1".to_string(), "field2".to_string()],
+                vec!["key1".to_string(), "not_exist".to_string()],
+            ],
+            hybrid_uncollapsed_columns: Vec::new(),
+            sort_dedup_by_key: false,
+            meta_data: SstMetaDataMocker::new(schema.clone()).build(),
+            total_row_num: Arc::new(AtomicUsize::new(0)),
+            partitioned_record_batch: Vec::new(),
+        };
+        reader.partition_record_batch().await.unwrap();
+        let bloom_filter = reader.build_bloom_filter();
+
+        // The group with missing columns is ignored.
+        assert_eq!(1, bloom_filter.composite_filters().len());
+        let column_indexes = vec![
+            schema.index_of("key1").unwrap(),
+            schema.index_of("field2").unwrap(),
+        ];
+        let composite_filter = bloom_filter.composite_filter(&column_indexes).unwrap();
+        assert_eq!(1, composite_filter.filters().len());
+
+        let probe = |region: &str, host: &str| {
+            let datums = [
+                Datum::Varbinary(Bytes::copy_from_slice(region.as_bytes())),
+                Datum::String(StringBytes::from(host)),
+            ];
+            composite_filter.may_contain(0, &datums)
+        };
+        assert!(probe("region0", "host0"));
+        assert!(probe("region0", "host1"));
+        assert!(probe("region1", "host2"));
+        // Each value exists but the combination doesn't.
+        assert!(!probe("region1", "host0"));
+        assert!(!probe("region0", "host2"));
+        // The concatenation of the values is the same as an existing one.
+        assert!(!probe("region0h", "ost0"));
+        // Row group without the filter.
+        assert!(composite_filter.may_contain(1, &[Datum::Null, Datum::Null]));
+    }
+
+    #[test]
+    fn test_bloom_filter_build_failure() {
+        let runtime = Arc::new(runtime::Builder::default().build().unwrap());
+        runtime.block_on(async {
+            let failed_column = "bloom_failure_field";
+            let schema = schema::Builder::new()
+                .auto_increment_column_id(true)
+                .add_key_column(
+                    column_schema::Builder::new("key".to_string(), DatumKind::Varbinary)
+                        .build()
+                        .unwrap(),
+                )
+                .unwrap()
+                .add_key_column(
+                    column_schema::Builder::new("timestamp".to_string(), DatumKind::Timestamp)
+                        .build()
+                        .unwrap(),
+                )
+                .unwrap()
+                .add_normal_column(
+                    column_schema::Builder::new(failed_column.to_string(), DatumKind::String)
+                        .build()
+                        .unwrap(),
+                )
+                .unwrap()
+                .build()
+                .unwrap();
+            let new_row = |key: &'static [u8], ts: i64, value: &str| {
+                Row::from_datums(vec![
+                    Datum::Varbinary(Bytes::from_static(key)),
+                    Datum::Timestamp(Timestamp::new(ts)),
+                    Datum::String(StringBytes::from(value)),
+                ])
+            };
+            let rows = vec![new_row(b"a", 100, "v1"), new_row(b"b", 101, "v2")];
+            let batch = build_record_batch_with_key(schema.clone(), rows.clone());
+
+            let dir = tempdir().unwrap();
+            let store: ObjectStoreRef =
+                Arc::new(LocalFileSystem::new_with_prefix(dir.path()).unwrap());
+            let store_picker: ObjectStorePickerRef = Arc::new(store);
+            let sst_file_path = Path::from("data.par");
+            let sst_builder_options = SstBuilderOptions {
+                sst_type: SstType::Parquet,
+                num_rows_per_row_group: 10,
+                compression: table_options::Compression::Uncompressed,
+                hybrid_encode_fallback: false,
+                max_row_group_bytes: None,
+                composite_bloom_filter_columns: Vec::new(),
+                hybrid_uncollapsed_columns: Vec::new(),
+                sort_dedup_by_key: false,
+                stats_sidecar: false,
+            };
+            let sst_meta = SstMetaDataMocker::new(schema.clone()).build();
+
+            BLOOM_FILTER_FAILURE_COLUMNS
+                .lock()
+                .unwrap()
+                .insert(failed_column.to_string());
+            let mut builder =
+                ParquetSstBuilder::new(&sst_file_path, &store_picker, &sst_builder_options);
+            let res = builder
+                .build(
+                    RequestId::next_id(),
+                    &sst_meta,
+                    Box::new(stream::iter(vec![Ok(batch)])),
+                )
+                .await;
+            BLOOM_FILTER_FAILURE_COLUMNS
+                .lock()
+                .unwrap()
+                .remove(failed_column);
+            let sst_info = res.unwrap();
+            assert_eq!(2, sst_info.row_num);
+
+            let sst_reader_options = SstReaderOptions {
+                read_batch_row_num: 5,
+                reverse: false,
+                frequency: ReadFrequency::Frequent,
+                projected_schema: ProjectedSchema::no_projection(schema.clone()),
+                predicate: Arc::new(Predicate::empty()),
+                meta_cache: None,
+                runtime: runtime.clone(),
+                num_rows_per_row_group: 10,
+                background_read_parallelism: 1,
+                decode_memory_limit: None,
+            };
+            let mut reader =
+                AsyncParquetReader::new(&sst_file_path, &store_picker, &sst_reader_options);
+            let bloom_filter = reader
+                .meta_data()
+                .await
+                .unwrap()
+                .bloom_filter
+                .clone()
+                .unwrap();
+            let failed_idx = schema.index_of(failed_column).unwrap();
+            assert_eq!(&[failed_idx], bloom_filter.missing_columns());
+            // The column without filter never filters out the row group.
+            let filter = &bloom_filter.filters()[0][failed_idx];
+            assert!(filter.contains_input(Input::Raw(b"not_exist")));
+            // The filters of other columns are still built.
+            let key_filter = &bloom_filter.filters()[0][schema.index_of("key").unwrap()];
+            assert!(!key_filter.contains_input(Input::Raw(b"not_exist")));
+
+            let mut stream = reader.read().await.unwrap();
+            check_stream(&mut stream, rows).await;
+        });
+    }
 }
@@ -1,26 +1,43 @@
 // Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
 
-use std::{convert::TryFrom, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    convert::TryFrom,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
 
 use arrow::{
-    array::{Array, ArrayData, ArrayRef},
+    array::{
+        Array, ArrayData, ArrayRef, StringArray, TimestampMicrosecondArray,
+        TimestampMillisecondArray, UInt64Array,
+    },
     buffer::MutableBuffer,
     compute,
+    datatypes::TimeUnit,
     record_batch::RecordBatch as ArrowRecordBatch,
     util::bit_util,
 };
 use common_types::{
     bytes::{BytesMut, SafeBufMut},
     datum::DatumKind,
-    schema::{ArrowSchema, ArrowSchemaRef, DataType, Field},
+    schema::{ArrowSchema, ArrowSchemaRef, DataType, Field, Schema},
+    time::{TimeRange, Timestamp},
 };
 use common_util::define_result;
-use log::trace;
+use log::{trace, warn};
 use parquet::{
-    arrow::ArrowWriter,
-    basic::Compression,
-    file::{metadata::KeyValue, properties::WriterProperties},
+    arrow::{self as parquet_arrow, ArrowWriter},
+    basic::{Compression, Encoding},
+    file::{
+        footer,
+        metadata::{KeyValue, ParquetMetaData},
+        properties::{EnabledStatistics, WriterProperties},
+    },
 };
+use prometheus::IntCounterVec;
 use prost::Message;
 use proto::sst::SstMetaData as SstMetaDataPb;
 use snafu::{ensure, Backtrace, OptionExt, ResultExt, Snafu};
@@ -28,9 +45,10 @@ use snafu::{ensure, Backtrace, OptionExt, ResultExt, Snafu};
 use crate::{
     sst::{
         file::SstMetaData,
+        metrics::{SST_ENCODE_BYTES_COUNTER, SST_ENCODE_ROWS_COUNTER},
         parquet::hybrid::{self, IndexedType},
     },
-    table_options::{StorageFormat, StorageFormatOptions},
+    table_options::{StorageFormat, StorageFormatOptions, DEFAULT_NUM_ROW_PER_ROW_GROUP},
 };
 
 // TODO: Only support i32 offset now, consider i64 here?
@@ -115,6 +133,19 @@ pub enum Error {
         backtrace: Backtrace,
     },
 
+    #[snafu(display("Failed to compress meta value, err:{}", source))]
+    CompressMetaValue { source: std::io::Error },
+
+    #[snafu(display(
+        "Failed to decompress meta value, base64 of meta value:{}, err:{}",
+        meta_value,
+        source
+    ))]
+    DecompressMetaValue {
+        meta_value: String,
+        source: std::io::Error,
+    },
+
     #[snafu(display("Failed to convert sst meta data from protobuf, err:{}", source))]
     ConvertSstMetaData { source: crate::sst::file::Error },
 
@@ -156,29 +187,187 @@ pub enum Error {
         type_name: String,
         backtrace: Backtrace,
     },
+
+    #[snafu(display(
+        "Decode memory limit exceeded, applied:{}, usage:{}, limit:{}.\nBacktrace:\n{}",
+        applied,
+        usage,
+        limit,
+        backtrace
+    ))]
+    DecodeMemoryLimitExceeded {
+        applied: usize,
+        usage: usize,
+        limit: usize,
+        backtrace: Backtrace,
+    },
+
+    #[snafu(display(
+        "Row num in the meta data mismatches the encoded rows, row_num:{}, encoded_rows:{}.\nBacktrace:\n{}",
+        row_num,
+        encoded_rows,
+        backtrace
+    ))]
+    RowNumMismatch {
+        row_num: u64,
+        encoded_rows: u64,
+        backtrace: Backtrace,
+    },
+
+    #[snafu(display(
+        "Storage format is not supported, format:{:?}.\nBacktrace:\n{}",
+        format,
+        backtrace
+    ))]
+    UnsupportedStorageFormat {
+        format: StorageFormat,
+        backtrace: Backtrace,
+    },
+
+    #[snafu(display(
+        "Record batch is incompatible with the schema of encoder, msg:{}.\nBacktrace:\n{}",
+        msg,
+        backtrace
+    ))]
+    IncompatibleRecordBatch { msg: String, backtrace: Backtrace },
+
+    #[snafu(display(
+        "Column name is duplicated in the schema, name:{}.\nBacktrace:\n{}",
+        name,
+        backtrace
+    ))]
+    DuplicateColumnName { name: String, backtrace: Backtrace },
+
+    #[snafu(display(
+        "Only string column can be stored uncollapsed in hybrid format, name:{}, type:{}.\nBacktrace:\n{}",
+        name,
+        type_name,
+        backtrace
+    ))]
+    InvalidUncollapsedColumn {
+        name: String,
+        type_name: String,
+        backtrace: Backtrace,
+    },
+
+    #[snafu(display(
+        "Values of uncollapsed column must be non-null and the same in a time series, name:{}, tsid:{}.\nBacktrace:\n{}",
+        name,
+        tsid,
+        backtrace
+    ))]
+    InvalidUncollapsedValue {
+        name: String,
+        tsid: u64,
+        backtrace: Backtrace,
+    },
+
+    #[snafu(display(
+        "Timestamp type of the record batch mismatches the encoder, name:{}, expect:{:?}, given:{:?}.\nBacktrace:\n{}",
+        name,
+        expect,
+        given,
+        backtrace
+    ))]
+    TimestampTypeMismatch {
+        name: String,
+        expect: DataType,
+        given: DataType,
+        backtrace: Backtrace,
+    },
+
+    #[snafu(display(
+        "Unsupported type of timestamp column, only millisecond and microsecond timestamp are supported, name:{}, type:{:?}.\nBacktrace:\n{}",
+        name,
+        data_type,
+        backtrace
+    ))]
+    UnsupportedTimestampType {
+        name: String,
+        data_type: DataType,
+        backtrace: Backtrace,
+    },
+
+    #[snafu(display(
+        "Unsafe type coercion of column, name:{}, from:{}, to:{}.\nBacktrace:\n{}",
+        name,
+        from,
+        to,
+        backtrace
+    ))]
+    UnsafeTypeCoercion {
+        name: String,
+        from: DataType,
+        to: DataType,
+        backtrace: Backtrace,
+    },
 }
 
 define_result!(Error);
 
 pub const META_KEY: &str = "meta";
 pub const META_VALUE_HEADER: u8 = 0;
-
-/// Encode the sst meta data into binary key value pair.
+/// Header of the meta value whose protobuf bytes are compressed by zstd.
+pub const COMPRESSED_META_VALUE_HEADER: u8 = 1;
+/// The protobuf bytes of the meta data are compressed if the length reaches
+/// this threshold.
+pub const META_COMPRESSION_THRESHOLD: usize = 16 * 1024;
+
+/// Encode the sst meta data into binary key value pair, and the meta data is
+/// compressed if it is larger than [META_COMPRESSION_THRESHOLD].
 pub fn encode_sst_meta_data(meta_data: SstMetaData) -> Result<KeyValue> {
-    let meta_data_pb = SstMetaDataPb::from(meta_data);
+    encode_sst_meta_data_with_threshold(meta_data, META_COMPRESSION_THRESHOLD)
+}
 
-    let mut buf = BytesMut::with_capacity(meta_data_pb.encoded_len() as usize + 1);
-    buf.try_put_u8(META_VALUE_HEADER)
-        .expect("Should write header into the buffer successfully");
+/// Encode the sst meta data into binary key value pair, and the protobuf bytes
+/// are compressed by zstd if the length reaches `compression_threshold`.
+pub fn encode_sst_meta_data_with_threshold(
+    meta_data: SstMetaData,
+    compression_threshold: usize,
+) -> Result<KeyValue> {
+    let buf = encode_meta_value(meta_data, compression_threshold)?;
 
-    // encode the sst meta data into protobuf binary
-    meta_data_pb.encode(&mut buf).context(EncodeIntoPb)?;
     Ok(KeyValue {
         key: META_KEY.to_string(),
         value: Some(base64::encode(buf.as_ref())),
     })
 }
 
+/// Encode the sst meta data into raw bytes without base64, which is only used
+/// to transfer the meta data internally.
+///
+/// The raw bytes share the same header with the value of the key value pair
+/// and can be decoded by [decode_sst_meta_data_raw].
+pub fn encode_sst_meta_data_raw(meta_data: SstMetaData) -> Result<Vec<u8>> {
+    let buf = encode_meta_value(meta_data, META_COMPRESSION_THRESHOLD)?;
+
+    Ok(buf.to_vec())
+}
+
+/// Encode the sst meta data into the header and the (maybe compressed)
+/// protobuf bytes.
+fn encode_meta_value(meta_data: SstMetaData, compression_threshold: usize) -> Result<BytesMut> {
+    let meta_data_pb = SstMetaDataPb::from(meta_data);
+    let encoded_len = meta_data_pb.encoded_len();
+
+    let mut buf = BytesMut::with_capacity(encoded_len + 1);
+    if encoded_len < compression_threshold {
+        buf.try_put_u8(META_VALUE_HEADER)
+            .expect("Should write header into the buffer successfully");
+        // encode the sst meta data into protobuf binary
+        meta_data_pb.encode(&mut buf).context(EncodeIntoPb)?;
+    } else {
+        buf.try_put_u8(COMPRESSED_META_VALUE_HEADER)
+            .expect("Should write header into the buffer successfully");
+        let pb_bytes = meta_data_pb.encode_to_vec();
+        let compressed = zstd::encode_all(pb_bytes.as_slice(), zstd::DEFAULT_COMPRESSION_LEVEL)
+            .context(CompressMetaValue)?;
+        buf.extend_from_slice(&compressed);
+    }
+
+    Ok(buf)
+}
+
 /// Decode the sst meta data from the binary key value pair.
 pub fn decode_sst_meta_data(kv: &KeyValue) -> Result<SstMetaData> {
     ensure!(
@@ -197,19 +386,147 @@ pub fn decode_sst_meta_data(kv: &KeyValue) -> Result<SstMetaData> {
 
     let raw_bytes = base64::decode(meta_value).context(DecodeBase64MetaValue { meta_value })?;
 
-    ensure!(!raw_bytes.is_empty(), InvalidMetaValueLen { meta_value });
+    decode_meta_value(&raw_bytes, || meta_value.clone())
+}
 
-    ensure!(
-        raw_bytes[0] == META_VALUE_HEADER,
-        InvalidMetaValueHeader { meta_value }
-    );
+/// Decode the sst meta data from the raw bytes encoded by
+/// [encode_sst_meta_data_raw].
+pub fn decode_sst_meta_data_raw(raw_bytes: &[u8]) -> Result<SstMetaData> {
+    decode_meta_value(raw_bytes, || base64::encode(raw_bytes))
+}
 
-    let meta_data_pb: SstMetaDataPb =
-        Message::decode(&raw_bytes[1..]).context(DecodeFromPb { meta_value })?;
+/// Decode the sst meta data from the header and the (maybe compressed)
+/// protobuf bytes, and the `meta_value` is only called to build the error.
+fn decode_meta_value<F>(raw_bytes: &[u8], meta_value: F) -> Result<SstMetaData>
+where
+    F: Fn() -> String,
+{
+    if raw_bytes.is_empty() {
+        return InvalidMetaValueLen {
+            meta_value: meta_value(),
+        }
+        .fail();
+    }
+
+    let meta_data_pb: SstMetaDataPb = match raw_bytes[0] {
+        META_VALUE_HEADER => Message::decode(&raw_bytes[1..]).with_context(|| DecodeFromPb {
+            meta_value: meta_value(),
+        })?,
+        COMPRESSED_META_VALUE_HEADER => {
+            let pb_bytes =
+                zstd::decode_all(&raw_bytes[1..]).with_context(|| DecompressMetaValue {
+                    meta_value: meta_value(),
+                })?;
+            Message::decode(pb_bytes.as_slice()).with_context(|| DecodeFromPb {
+                meta_value: meta_value(),
+            })?
+        }
+        _ => {
+            return InvalidMetaValueHeader {
+                meta_value: meta_value(),
+            }
+            .fail()
+        }
+    };
 
     SstMetaData::try_from(meta_data_pb).context(ConvertSstMetaData)
 }
 
+/// Encoding options of a single column, which override the options for the
+/// whole sst.
+///
+/// Options can be combined, e.g. dictionary encoding layered under zstd
+/// compression for the high-cardinality-but-repetitive columns:
+///
+/// ```
+/// use std::collections::HashMap;
+///
+/// use analytic_engine::sst::parquet::encoding::ColumnEncoding;
+/// use parquet::basic::Compression;
+///
+/// let mut column_encodings = HashMap::new();
+/// column_encodings.insert(
+///     "host".to_string(),
+///     ColumnEncoding {
+///         dictionary_enabled: Some(true),
+///         compression: Some(Compression::ZSTD),
+///         ..Default::default()
+///     },
+/// );
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ColumnEncoding {
+    /// Whether to enable the dictionary encoding, and the default of parquet is
+    /// used if not set.
+    pub dictionary_enabled: Option<bool>,
+    /// Compression of the column, and the compression of the sst is used if not
+    /// set.
+    pub compression: Option<Compression>,
+    /// Encoding of the column, which is only used when the dictionary encoding
+    /// is disabled or falls back.
+    pub encoding: Option<Encoding>,
+    /// Whether to collapse the column into a list in the hybrid format, and
+    /// the schema decides it if not set. A string column whose values are the
+    /// same in every time series can be set to false, then it is stored once
+    /// per time series instead of being repeated in the list.
+    pub collapsible: Option<bool>,
+}
+
+/// Build the [WriterProperties] for writing the `arrow_schema`, and the
+/// `column_encodings` are applied to all the leaf columns of the columns with
+/// the same name.
+fn build_write_props(
+    arrow_schema: &ArrowSchema,
+    num_rows_per_row_group: usize,
+    compression: Compression,
+    statistics_enabled: EnabledStatistics,
+    column_encodings: &HashMap<String, ColumnEncoding>,
+    write_meta_data: bool,
+    meta_data: SstMetaData,
+) -> Result<WriterProperties> {
+    let kv_metas = if write_meta_data {
+        Some(vec![encode_sst_meta_data(meta_data)?])
+    } else {
+        None
+    };
+    let mut builder = WriterProperties::builder()
+        .set_key_value_metadata(kv_metas)
+        .set_max_row_group_size(num_rows_per_row_group)
+        .set_compression(compression)
+        .set_statistics_enabled(statistics_enabled);
+    if column_encodings.is_empty() {
+        return Ok(builder.build());
+    }
+
+    let schema_desc = parquet_arrow::arrow_to_parquet_schema(arrow_schema)
+        .map_err(|e| Box::new(e) as _)
+        .context(EncodeRecordBatch)?;
+    for column_desc in schema_desc.columns() {
+        let column_path = column_desc.path();
+        let encoding = match column_path
+            .parts()
+            .first()
+            .and_then(|name| column_encodings.get(name))
+        {
+            Some(v) => v,
+            None => continue,
+        };
+
+        if let Some(dictionary_enabled) = encoding.dictionary_enabled {
+            builder =
+                builder.set_column_dictionary_enabled(column_path.clone(), dictionary_enabled);
+        }
+        if let Some(compression) = encoding.compression {
+            builder = builder.set_column_compression(column_path.clone(), compression);
+        }
+        if let Some(encoding) = encoding.encoding {
+            builder = builder.set_column_encoding(column_path.clone(), encoding);
+        }
+    }
+
+    Ok(builder.build())
+}
+
 /// RecordEncoder is used for encoding ArrowBatch.
 ///
 /// TODO: allow pre-allocate buffer
@@ -233,15 +550,23 @@ impl ColumnarRecordEncoder {
     fn try_new(
         num_rows_per_row_group: usize,
         compression: Compression,
+        statistics_enabled: EnabledStatistics,
+        column_encodings: &HashMap<String, ColumnEncoding>,
+        write_meta_data: bool,
+        arrow_schema: ArrowSchemaRef,
         meta_data: SstMetaData,
     ) -> Result<Self> {
-        let arrow_schema = meta_data.schema.to_arrow_schema_ref();
-
-        let write_props = WriterProperties::builder()
-            .set_key_value_metadata(Some(vec![encode_sst_meta_data(meta_data)?]))
-            .set_max_row_group_size(num_rows_per_row_group)
-            .set_compression(compression)
-            .build();
+        validate_column_names(&meta_data.schema)?;
+
+        let write_props = build_write_props(
+            &arrow_schema,
+            num_rows_per_row_group,
+            compression,
+            statistics_enabled,
+            column_encodings,
+            write_meta_data,
+            meta_data,
+        )?;
 
         let arrow_writer =
             ArrowWriter::try_new(Vec::new(), arrow_schema.clone(), Some(write_props))
@@ -259,6 +584,10 @@ impl RecordEncoder for ColumnarRecordEncoder {
     fn encode(&mut self, arrow_record_batch_vec: Vec<ArrowRecordBatch>) -> Result<usize> {
         assert!(self.arrow_writer.is_some());
 
+        let arrow_record_batch_vec = arrow_record_batch_vec
+            .into_iter()
+            .map(|batch| align_record_batch(&self.arrow_schema, batch))
+            .collect::<Result<Vec<_>>>()?;
         let record_batch = compute::concat_batches(&self.arrow_schema, &arrow_record_batch_vec)
             .map_err(|e| Box::new(e) as _)
             .context(EncodeRecordBatch)?;
@@ -270,6 +599,15 @@ impl RecordEncoder for ColumnarRecordEncoder {
             .map_err(|e| Box::new(e) as _)
             .context(EncodeRecordBatch)?;
 
+        // The caller may end the row group before it reaches
+        // `num_rows_per_row_group`, e.g. the row group is too large in bytes.
+        self.arrow_writer
+            .as_mut()
+            .unwrap()
+            .flush()
+            .map_err(|e| Box::new(e) as _)
+            .context(EncodeRecordBatch)?;
+
         Ok(record_batch.num_rows())
     }
 
@@ -286,6 +624,203 @@ impl RecordEncoder for ColumnarRecordEncoder {
     }
 }
 
+/// Align the `record_batch` to the `arrow_schema` so that it can be
+/// concatenated with other batches, which is required as the batches may be
+/// built with a slightly different schema, e.g. the nullability of a column is
+/// changed by altering the table.
+///
+/// The columns of different data types are casted, and error is returned if
+/// the columns mismatch or the cast is not supported.
+fn align_record_batch(
+    arrow_schema: &ArrowSchemaRef,
+    record_batch: ArrowRecordBatch,
+) -> Result<ArrowRecordBatch> {
+    if record_batch.schema() == *arrow_schema {
+        return Ok(record_batch);
+    }
+
+    ensure!(
+        record_batch.num_columns() == arrow_schema.fields().len(),
+        IncompatibleRecordBatch {
+            msg: format!(
+                "columns number mismatch, expect:{}, given:{}",
+                arrow_schema.fields().len(),
+                record_batch.num_columns()
+            ),
+        }
+    );
+
+    let columns = arrow_schema
+        .fields()
+        .iter()
+        .zip(record_batch.columns())
+        .map(|(field, column)| {
+            if field.data_type() == column.data_type() {
+                return Ok(column.clone());
+            }
+
+            ensure!(
+                compute::can_cast_types(column.data_type(), field.data_type()),
+                IncompatibleRecordBatch {
+                    msg: format!(
+                        "unsupported cast of column:{}, from:{}, to:{}",
+                        field.name(),
+                        column.data_type(),
+                        field.data_type()
+                    ),
+                }
+            );
+            compute::cast(column, field.data_type())
+                .map_err(|e| Box::new(e) as _)
+                .context(EncodeRecordBatch)
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    // The nullability is checked when building the record batch.
+    ArrowRecordBatch::try_new(arrow_schema.clone(), columns)
+        .map_err(|e| Box::new(e) as _)
+        .context(EncodeRecordBatch)
+}
+
+/// Compute the time range of every row group, which consists of the record
+/// batches in `row_groups` in the `schema`.
+///
+/// The time range of a row group without any timestamp is empty, and the
+/// timestamps in microseconds are rounded to milliseconds, so the time range
+/// still covers all of them.
+pub fn compute_row_group_time_ranges(
+    schema: &Schema,
+    row_groups: &[Vec<ArrowRecordBatch>],
+) -> Result<Vec<TimeRange>> {
+    let timestamp_idx = schema.timestamp_index();
+    let timestamp_name = &schema.column(timestamp_idx).name;
+    row_groups
+        .iter()
+        .map(|record_batches| {
+            let mut min = i64::MAX;
+            let mut max = i64::MIN;
+            for record_batch in record_batches {
+                let timestamps =
+                    timestamp_millis(timestamp_name, record_batch.column(timestamp_idx))?;
+                for ts in timestamps {
+                    min = min.min(ts);
+                    max = max.max(ts);
+                }
+            }
+            if min > max {
+                return Ok(TimeRange::empty());
+            }
+
+            let end = Timestamp::new(max)
+                .checked_add_i64(1)
+                .unwrap_or(Timestamp::MAX);
+            Ok(TimeRange::new_unchecked(Timestamp::new(min), end))
+        })
+        .collect()
+}
+
+/// Collect the non-null timestamps of the `array` in milliseconds, and the
+/// sub-millisecond part of the timestamps in microseconds is rounded down.
+fn timestamp_millis(name: &str, array: &ArrayRef) -> Result<Vec<i64>> {
+    match array.data_type() {
+        DataType::Timestamp(TimeUnit::Millisecond, _) => Ok(array
+            .as_any()
+            .downcast_ref::<TimestampMillisecondArray>()
+            .expect("checked by data type")
+            .iter()
+            .flatten()
+            .collect()),
+        DataType::Timestamp(TimeUnit::Microsecond, _) => Ok(array
+            .as_any()
+            .downcast_ref::<TimestampMicrosecondArray>()
+            .expect("checked by data type")
+            .iter()
+            .flatten()
+            .map(|ts| ts.div_euclid(1000))
+            .collect()),
+        data_type => UnsupportedTimestampType {
+            name,
+            data_type: data_type.clone(),
+        }
+        .fail(),
+    }
+}
+
+/// Build the arrow schema of the `schema` with the timestamp column in the
+/// `timestamp_unit`, and only millisecond and microsecond are supported.
+fn build_arrow_schema(schema: &Schema, timestamp_unit: TimeUnit) -> Result<ArrowSchemaRef> {
+    let timestamp_idx = schema.timestamp_index();
+    let timestamp_name = &schema.column(timestamp_idx).name;
+    let timestamp_type = DataType::Timestamp(timestamp_unit, None);
+    ensure!(
+        matches!(
+            timestamp_unit,
+            TimeUnit::Millisecond | TimeUnit::Microsecond
+        ),
+        UnsupportedTimestampType {
+            name: timestamp_name,
+            data_type: timestamp_type,
+        }
+    );
+
+    let arrow_schema = schema.to_arrow_schema_ref();
+    if arrow_schema.field(timestamp_idx).data_type() == &timestamp_type {
+        return Ok(arrow_schema);
+    }
+
+    let mut fields = arrow_schema.fields().clone();
+    let timestamp_field = &fields[timestamp_idx];
+    fields[timestamp_idx] = Field::new(
+        timestamp_field.name(),
+        timestamp_type,
+        timestamp_field.is_nullable(),
+    )
+    .with_metadata(timestamp_field.metadata().cloned());
+
+    Ok(Arc::new(ArrowSchema::new_with_metadata(
+        fields,
+        arrow_schema.metadata().clone(),
+    )))
+}
+
+/// Ensure the column names of the schema are unique, otherwise the columns
+/// can't be looked up by name when decoding the sst.
+fn validate_column_names(schema: &Schema) -> Result<()> {
+    let mut names = HashSet::with_capacity(schema.num_columns());
+    for col in schema.columns() {
+        ensure!(
+            names.insert(col.name.as_str()),
+            DuplicateColumnName { name: &col.name }
+        );
+    }
+
+    Ok(())
+}
+
+/// Check whether the `schema` can be encoded in the hybrid format, so the
+/// invalid schema can be rejected before any data is written.
+pub fn validate_hybrid_schema(schema: &Schema) -> Result<()> {
+    // TODO: What we really want here is a unique ID, tsid is one case
+    // Maybe support other cases later.
+    let tsid_idx = schema.index_of_tsid().context(TsidRequired)?;
+
+    for (idx, col) in schema.columns().iter().enumerate() {
+        if idx == tsid_idx || schema.is_collapsible_column(idx) {
+            continue;
+        }
+
+        // TODO: support non-string key columns
+        ensure!(
+            matches!(col.data_type, DatumKind::String),
+            StringKeyColumnRequired {
+                type_name: col.data_type.to_string(),
+            }
+        );
+    }
+
+    Ok(())
+}
+
 struct HybridRecordEncoder {
     // wrap in Option so ownership can be taken out behind `&mut self`
     arrow_writer: Option<ArrowWriter<Vec<u8>>>,
@@ -294,60 +829,96 @@ struct HybridRecordEncoder {
     non_collapsible_col_types: Vec<IndexedType>,
     // columns that can be collpased into list
     collapsible_col_types: Vec<IndexedType>,
+    // collapsible columns stored like the tags instead of lists
+    uncollapsed_cols_idx: Vec<usize>,
+    // sort rows within each tsid by this timestamp column if set
+    sort_timestamp_idx: Option<usize>,
 }
 
 impl HybridRecordEncoder {
     fn try_new(
         num_rows_per_row_group: usize,
         compression: Compression,
+        statistics_enabled: EnabledStatistics,
+        sort_by_timestamp: bool,
+        column_encodings: &HashMap<String, ColumnEncoding>,
+        write_meta_data: bool,
+        arrow_schema: ArrowSchemaRef,
         mut meta_data: SstMetaData,
     ) -> Result<Self> {
-        // TODO: What we really want here is a unique ID, tsid is one case
-        // Maybe support other cases later.
+        validate_column_names(&meta_data.schema)?;
+        validate_hybrid_schema(&meta_data.schema)?;
+
         let tsid_idx = meta_data.schema.index_of_tsid().context(TsidRequired)?;
         let tsid_type = IndexedType {
             idx: tsid_idx,
             data_type: meta_data.schema.column(tsid_idx).data_type,
         };
 
+        let mut storage_format_opts =
+            StorageFormatOptions::for_schema(&meta_data.schema, StorageFormat::Hybrid);
         let mut non_collapsible_col_types = Vec::new();
         let mut collapsible_col_types = Vec::new();
+        let mut uncollapsed_cols_idx = Vec::new();
         for (idx, col) in meta_data.schema.columns().iter().enumerate() {
             if idx == tsid_idx {
                 continue;
             }
 
-            if meta_data.schema.is_collapsible_column(idx) {
-                collapsible_col_types.push(IndexedType {
-                    idx,
-                    data_type: meta_data.schema.column(idx).data_type,
-                });
-                meta_data
-                    .storage_format_opts
-                    .collapsible_cols_idx
-                    .push(idx as u32);
-            } else {
-                // TODO: support non-string key columns
+            let collapsible = storage_format_opts
+                .collapsible_cols_idx
+                .contains(&(idx as u32));
+            let uncollapsed = collapsible
+                && column_encodings
+                    .get(&col.name)
+                    .and_then(|encoding| encoding.collapsible)
+                    == Some(false);
+            if uncollapsed {
+                // The uncollapsed columns are stored like the tags.
                 ensure!(
                     matches!(col.data_type, DatumKind::String),
-                    StringKeyColumnRequired {
+                    InvalidUncollapsedColumn {
+                        name: &col.name,
                         type_name: col.data_type.to_string(),
                     }
                 );
+                uncollapsed_cols_idx.push(idx);
+                non_collapsible_col_types.push(IndexedType {
+                    idx,
+                    data_type: col.data_type,
+                });
+            } else if collapsible {
+                collapsible_col_types.push(IndexedType {
+                    idx,
+                    data_type: col.data_type,
+                });
+            } else {
                 non_collapsible_col_types.push(IndexedType {
                     idx,
                     data_type: col.data_type,
                 });
             }
         }
+        storage_format_opts
+            .collapsible_cols_idx
+            .retain(|idx| !uncollapsed_cols_idx.contains(&(*idx as usize)));
+        meta_data.storage_format_opts = storage_format_opts;
+
+        let sort_timestamp_idx = sort_by_timestamp.then(|| meta_data.schema.timestamp_index());
+        let arrow_schema = hybrid::build_hybrid_arrow_schema(
+            &arrow_schema,
+            &meta_data.storage_format_opts.collapsible_cols_idx,
+        );
 
-        let arrow_schema = hybrid::build_hybrid_arrow_schema(&meta_data.schema);
-
-        let write_props = WriterProperties::builder()
-            .set_key_value_metadata(Some(vec![encode_sst_meta_data(meta_data)?]))
-            .set_max_row_group_size(num_rows_per_row_group)
-            .set_compression(compression)
-            .build();
+        let write_props = build_write_props(
+            &arrow_schema,
+            num_rows_per_row_group,
+            compression,
+            statistics_enabled,
+            column_encodings,
+            write_meta_data,
+            meta_data,
+        )?;
 
         let arrow_writer =
             ArrowWriter::try_new(Vec::new(), arrow_schema.clone(), Some(write_props))
@@ -359,18 +930,56 @@ impl HybridRecordEncoder {
             tsid_type,
             non_collapsible_col_types,
             collapsible_col_types,
+            uncollapsed_cols_idx,
+            sort_timestamp_idx,
         })
     }
+
+    /// Ensure the values of every uncollapsed column are non-null and the same
+    /// in every time series, as only one value is stored for a time series.
+    fn check_uncollapsed_values(&self, record_batches: &[ArrowRecordBatch]) -> Result<()> {
+        for col_idx in &self.uncollapsed_cols_idx {
+            let mut values_by_tsid = HashMap::new();
+            for record_batch in record_batches {
+                let tsids = record_batch
+                    .column(self.tsid_type.idx)
+                    .as_any()
+                    .downcast_ref::<UInt64Array>()
+                    .expect("checked when create table");
+                let values = record_batch
+                    .column(*col_idx)
+                    .as_any()
+                    .downcast_ref::<StringArray>()
+                    .expect("checked in HybridRecordEncoder::try_new");
+                for row_idx in 0..record_batch.num_rows() {
+                    let tsid = tsids.value(row_idx);
+                    let value = values.is_valid(row_idx).then(|| values.value(row_idx));
+                    let expect = *values_by_tsid.entry(tsid).or_insert(value);
+                    ensure!(
+                        value.is_some() && value == expect,
+                        InvalidUncollapsedValue {
+                            name: self.arrow_schema.field(*col_idx).name(),
+                            tsid,
+                        }
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
 }
 
 impl RecordEncoder for HybridRecordEncoder {
     fn encode(&mut self, arrow_record_batch_vec: Vec<ArrowRecordBatch>) -> Result<usize> {
         assert!(self.arrow_writer.is_some());
 
+        self.check_uncollapsed_values(&arrow_record_batch_vec)?;
         let record_batch = hybrid::convert_to_hybrid_record(
             &self.tsid_type,
             &self.non_collapsible_col_types,
             &self.collapsible_col_types,
+            self.sort_timestamp_idx,
             self.arrow_schema.clone(),
             arrow_record_batch_vec,
         )
@@ -409,34 +1018,157 @@ impl RecordEncoder for HybridRecordEncoder {
     }
 }
 
+/// Options to build the [ParquetEncoder].
+#[derive(Debug, Clone)]
+pub struct ParquetEncoderOptions {
+    pub num_rows_per_row_group: usize,
+    pub compression: Compression,
+    /// The level of the statistics written for columns, and
+    /// [EnabledStatistics::Page] is the default one of parquet.
+    pub statistics_enabled: EnabledStatistics,
+    /// Only takes effect on hybrid format, and rows within the same tsid will
+    /// be sorted by timestamp if it is set.
+    pub sort_by_timestamp: bool,
+    /// Overrides the encoding options of the columns with the given names,
+    /// see [ColumnEncoding].
+    pub column_encodings: HashMap<String, ColumnEncoding>,
+    /// If not set, the meta data won't be written into the key value meta
+    /// data and a plain parquet file is produced, which can be opened by the
+    /// external parquet tools. However, such sst can't be read by the sst
+    /// reader, and the [StorageFormatOptions] of it must be kept out of band
+    /// to build the [ParquetDecoder].
+    pub write_meta_data: bool,
+    /// Unit of the timestamp column written to the sst, which is kept when the
+    /// sst is decoded. Only millisecond and microsecond are supported, and the
+    /// timestamps of the input records must be in this unit.
+    pub timestamp_unit: TimeUnit,
+}
+
+impl Default for ParquetEncoderOptions {
+    fn default() -> Self {
+        Self {
+            num_rows_per_row_group: DEFAULT_NUM_ROW_PER_ROW_GROUP,
+            compression: Compression::ZSTD,
+            statistics_enabled: EnabledStatistics::Page,
+            sort_by_timestamp: false,
+            column_encodings: HashMap::new(),
+            write_meta_data: true,
+            timestamp_unit: TimeUnit::Millisecond,
+        }
+    }
+}
+
 pub struct ParquetEncoder {
     record_encoder: Box<dyn RecordEncoder + Send>,
+    /// The `row_num` in the meta data written to the sst.
+    row_num: u64,
+    /// Number of the input rows encoded so far, which may differ from the
+    /// rows in the encoded records, e.g. the rows are collapsed in hybrid
+    /// format.
+    encoded_rows: u64,
+    storage_format: StorageFormat,
+    /// The timestamp column in the schema, whose type is required for the
+    /// input records.
+    timestamp_idx: usize,
+    timestamp_name: String,
+    timestamp_type: DataType,
+    /// Counters of the encoded rows and bytes, labeled by the storage format.
+    encode_rows_counter: IntCounterVec,
+    encode_bytes_counter: IntCounterVec,
 }
 
 impl ParquetEncoder {
-    pub fn try_new(
-        num_rows_per_row_group: usize,
-        compression: Compression,
-        meta_data: SstMetaData,
-    ) -> Result<Self> {
+    /// Create a encoder for the storage format specified in `meta_data`.
+    pub fn try_new(options: ParquetEncoderOptions, meta_data: SstMetaData) -> Result<Self> {
+        let ParquetEncoderOptions {
+            num_rows_per_row_group,
+            compression,
+            statistics_enabled,
+            sort_by_timestamp,
+            column_encodings,
+            write_meta_data,
+            timestamp_unit,
+        } = options;
+        let column_encodings = &column_encodings;
+        let row_num = meta_data.row_num;
+        let storage_format = meta_data.storage_format();
+        let timestamp_idx = meta_data.schema.timestamp_index();
+        let timestamp_column = meta_data.schema.column(timestamp_idx);
+        let timestamp_name = timestamp_column.name.clone();
+        let arrow_schema = build_arrow_schema(&meta_data.schema, timestamp_unit)?;
+        let timestamp_type = arrow_schema.field(timestamp_idx).data_type().clone();
         let record_encoder: Box<dyn RecordEncoder + Send> = match meta_data.storage_format() {
             StorageFormat::Hybrid => Box::new(HybridRecordEncoder::try_new(
                 num_rows_per_row_group,
                 compression,
+                statistics_enabled,
+                sort_by_timestamp,
+                column_encodings,
+                write_meta_data,
+                arrow_schema,
                 meta_data,
             )?),
             StorageFormat::Columnar => Box::new(ColumnarRecordEncoder::try_new(
                 num_rows_per_row_group,
                 compression,
+                statistics_enabled,
+                column_encodings,
+                write_meta_data,
+                arrow_schema,
                 meta_data,
             )?),
+            StorageFormat::ColumnarDeltaTs => {
+                // The delta encoding is provided by parquet, and it is used only if the
+                // dictionary encoding is disabled.
+                let timestamp_column = meta_data.schema.column(meta_data.schema.timestamp_index());
+                let timestamp_encoding = column_encodings
+                    .get(&timestamp_column.name)
+                    .copied()
+                    .unwrap_or_default();
+                let mut column_encodings = column_encodings.clone();
+                column_encodings.insert(
+                    timestamp_column.name.clone(),
+                    ColumnEncoding {
+                        dictionary_enabled: Some(false),
+                        encoding: Some(Encoding::DELTA_BINARY_PACKED),
+                        ..timestamp_encoding
+                    },
+                );
+
+                Box::new(ColumnarRecordEncoder::try_new(
+                    num_rows_per_row_group,
+                    compression,
+                    statistics_enabled,
+                    &column_encodings,
+                    write_meta_data,
+                    arrow_schema,
+                    meta_data,
+                )?)
+            }
+            format @ StorageFormat::Unknown(_) => {
+                return UnsupportedStorageFormat { format }.fail();
+            }
         };
 
-        Ok(ParquetEncoder { record_encoder })
+        Ok(ParquetEncoder {
+            record_encoder,
+            row_num,
+            encoded_rows: 0,
+            storage_format,
+            timestamp_idx,
+            timestamp_name,
+            timestamp_type,
+            encode_rows_counter: SST_ENCODE_ROWS_COUNTER.clone(),
+            encode_bytes_counter: SST_ENCODE_BYTES_COUNTER.clone(),
+        })
     }
 
     /// Encode the record batch with [ArrowWriter] and the encoded contents is
     /// written to the buffer.
+    ///
+    /// The current row group is ended after the batches are encoded, and the
+    /// batches with more than `num_rows_per_row_group` rows are split into
+    /// multiple row groups.
     pub fn encode_record_batch(
         &mut self,
         arrow_record_batch_vec: Vec<ArrowRecordBatch>,
@@ -444,42 +1176,279 @@ impl ParquetEncoder {
         if arrow_record_batch_vec.is_empty() {
             return Ok(0);
         }
+        for record_batch in &arrow_record_batch_vec {
+            self.check_timestamp_type(record_batch)?;
+        }
+
+        let input_rows: usize = arrow_record_batch_vec
+            .iter()
+            .map(|batch| batch.num_rows())
+            .sum();
+        let num_rows = self.record_encoder.encode(arrow_record_batch_vec)?;
+        self.encoded_rows += input_rows as u64;
 
-        self.record_encoder.encode(arrow_record_batch_vec)
+        Ok(num_rows)
     }
 
-    pub fn close(mut self) -> Result<Vec<u8>> {
-        self.record_encoder.close()
+    /// Ensure the timestamp unit of the `record_batch` is the one of the
+    /// encoder, otherwise the timestamps in other units would be written
+    /// without conversion silently, as they share the same physical type.
+    fn check_timestamp_type(&self, record_batch: &ArrowRecordBatch) -> Result<()> {
+        let given = record_batch.column(self.timestamp_idx).data_type();
+        ensure!(
+            given == &self.timestamp_type,
+            TimestampTypeMismatch {
+                name: &self.timestamp_name,
+                expect: self.timestamp_type.clone(),
+                given: given.clone(),
+            }
+        );
+
+        Ok(())
     }
-}
 
-/// RecordDecoder is used for decoding ArrowRecordBatch based on
-/// `schema.StorageFormat`
-trait RecordDecoder {
-    fn decode(&self, arrow_record_batch: ArrowRecordBatch) -> Result<ArrowRecordBatch>;
-}
+    /// Check whether the `row_num` in the meta data matches the number of the
+    /// input rows encoded so far.
+    ///
+    /// The `row_num` is supplied by the caller and written into the sst, so a
+    /// mismatch leads to the wrong estimations based on it.
+    pub fn check_row_num(&self) -> Result<()> {
+        ensure!(
+            self.row_num == self.encoded_rows,
+            RowNumMismatch {
+                row_num: self.row_num,
+                encoded_rows: self.encoded_rows,
+            }
+        );
 
-struct ColumnarRecordDecoder {}
+        Ok(())
+    }
 
-impl RecordDecoder for ColumnarRecordDecoder {
-    fn decode(&self, arrow_record_batch: ArrowRecordBatch) -> Result<ArrowRecordBatch> {
-        Ok(arrow_record_batch)
+    /// Close the encoder and return the encoded bytes, a warning is logged if
+    /// the `row_num` in the meta data mismatches the encoded rows.
+    pub fn close(mut self) -> Result<Vec<u8>> {
+        if let Err(e) = self.check_row_num() {
+            warn!(
+                "Sst meta data is inconsistent with the encoded rows, err:{}",
+                e
+            );
+        }
+
+        let bytes = self.record_encoder.close()?;
+        let storage_format = self.storage_format.to_string();
+        self.encode_rows_counter
+            .with_label_values(&[&storage_format])
+            .inc_by(self.encoded_rows);
+        self.encode_bytes_counter
+            .with_label_values(&[&storage_format])
+            .inc_by(bytes.len() as u64);
+
+        Ok(bytes)
+    }
+
+    /// Close the encoder and return the encoded bytes together with the
+    /// [EncodeSummary] of them.
+    pub fn close_with_summary(self) -> Result<(Vec<u8>, EncodeSummary)> {
+        let bytes = self.close()?;
+        let parquet_metadata = decode_parquet_metadata(&bytes)?;
+        let summary = EncodeSummary::from(&parquet_metadata);
+
+        Ok((bytes, summary))
     }
 }
 
-struct HybridRecordDecoder {
-    storage_format_opts: StorageFormatOptions,
+/// Sizes of one column in the encoded sst, accumulated over all the row
+/// groups.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ColumnEncodeSummary {
+    /// Name of the top level column in the parquet schema.
+    pub name: String,
+    pub compressed_size: i64,
+    pub uncompressed_size: i64,
 }
 
-impl HybridRecordDecoder {
-    /// Convert `ListArray` fields to underlying data type
-    fn convert_schema(arrow_schema: ArrowSchemaRef) -> ArrowSchemaRef {
-        let new_fields: Vec<_> = arrow_schema
-            .fields()
+/// Summary of the encoded sst, which is built from the column chunk meta data
+/// written by the parquet writer.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EncodeSummary {
+    pub num_rows: i64,
+    pub num_row_groups: usize,
+    pub compressed_size: i64,
+    pub uncompressed_size: i64,
+    /// Sizes of every leaf column, in the order of the parquet schema.
+    pub columns: Vec<ColumnEncodeSummary>,
+}
+
+impl From<&ParquetMetaData> for EncodeSummary {
+    fn from(parquet_metadata: &ParquetMetaData) -> Self {
+        let file_metadata = parquet_metadata.file_metadata();
+        let mut columns: Vec<_> = file_metadata
+            .schema_descr()
+            .columns()
             .iter()
-            .map(|f| {
-                if let DataType::List(nested_field) = f.data_type() {
-                    Field::new(f.name(), nested_field.data_type().clone(), true)
+            .map(|column| ColumnEncodeSummary {
+                name: column.path().parts()[0].clone(),
+                ..Default::default()
+            })
+            .collect();
+
+        let mut summary = EncodeSummary {
+            num_rows: file_metadata.num_rows(),
+            num_row_groups: parquet_metadata.num_row_groups(),
+            ..Default::default()
+        };
+        for row_group in parquet_metadata.row_groups() {
+            summary.uncompressed_size += row_group.total_byte_size();
+            for (column_summary, column) in columns.iter_mut().zip(row_group.columns()) {
+                column_summary.compressed_size += column.compressed_size();
+                column_summary.uncompressed_size += column.uncompressed_size();
+                summary.compressed_size += column.compressed_size();
+            }
+        }
+        summary.columns = columns;
+
+        summary
+    }
+}
+
+/// Decode the parquet meta data from the footer of the encoded `bytes`.
+fn decode_parquet_metadata(bytes: &[u8]) -> Result<ParquetMetaData> {
+    let footer_start = bytes.len().saturating_sub(footer::FOOTER_SIZE);
+    let footer_bytes: &[u8; footer::FOOTER_SIZE] = bytes[footer_start..]
+        .try_into()
+        .map_err(|e| Box::new(e) as _)
+        .context(EncodeRecordBatch)?;
+    let metadata_len = footer::decode_footer(footer_bytes)
+        .map_err(|e| Box::new(e) as _)
+        .context(EncodeRecordBatch)?;
+    let metadata_start = footer_start.saturating_sub(metadata_len);
+
+    footer::decode_metadata(&bytes[metadata_start..footer_start])
+        .map_err(|e| Box::new(e) as _)
+        .context(EncodeRecordBatch)
+}
+
+/// Combined with [`DecodeMemoryUsageToken`], [`DecodeMemoryLimit`] imposes a
+/// limit on the memory used by decoding, which can be shared among decoders.
+#[derive(Clone, Debug)]
+pub struct DecodeMemoryLimit {
+    usage: Arc<AtomicUsize>,
+    limit: usize,
+}
+
+/// The token for the memory usage of decoding, which should not derive Clone.
+/// The applied memory will be subtracted from the global memory usage.
+#[derive(Debug)]
+pub struct DecodeMemoryUsageToken {
+    global_usage: Arc<AtomicUsize>,
+    applied_usage: usize,
+}
+
+impl Drop for DecodeMemoryUsageToken {
+    fn drop(&mut self) {
+        self.global_usage
+            .fetch_sub(self.applied_usage, Ordering::Relaxed);
+    }
+}
+
+impl DecodeMemoryLimit {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            usage: Arc::new(AtomicUsize::new(0)),
+            limit,
+        }
+    }
+
+    /// Try to apply a token, fails if the memory usage exceeds the limit after
+    /// applied.
+    pub fn try_apply_token(&self, bytes: usize) -> Result<DecodeMemoryUsageToken> {
+        let usage = self.usage.fetch_add(bytes, Ordering::Relaxed) + bytes;
+        let token = DecodeMemoryUsageToken {
+            global_usage: self.usage.clone(),
+            applied_usage: bytes,
+        };
+
+        ensure!(
+            usage <= self.limit,
+            DecodeMemoryLimitExceeded {
+                applied: bytes,
+                usage,
+                limit: self.limit,
+            }
+        );
+
+        Ok(token)
+    }
+
+    #[inline]
+    pub fn usage(&self) -> usize {
+        self.usage.load(Ordering::Relaxed)
+    }
+}
+
+/// The record batch decoded by [ParquetDecoder], and the token of the memory
+/// applied to decode it if any.
+///
+/// The token should be held as long as the record batch is in use, and the
+/// applied memory is released once the token is dropped.
+#[derive(Debug)]
+pub struct DecodedRecordBatch {
+    pub record_batch: ArrowRecordBatch,
+    pub memory_token: Option<DecodeMemoryUsageToken>,
+}
+
+/// RecordDecoder is used for decoding ArrowRecordBatch based on
+/// `schema.StorageFormat`
+trait RecordDecoder {
+    fn decode(&self, arrow_record_batch: ArrowRecordBatch) -> Result<DecodedRecordBatch>;
+
+    /// Schema of the record batch decoded from the record batch of
+    /// `arrow_schema`.
+    fn output_schema(&self, arrow_schema: &ArrowSchemaRef) -> ArrowSchemaRef;
+}
+
+struct ColumnarRecordDecoder {}
+
+impl RecordDecoder for ColumnarRecordDecoder {
+    fn decode(&self, arrow_record_batch: ArrowRecordBatch) -> Result<DecodedRecordBatch> {
+        Ok(DecodedRecordBatch {
+            record_batch: arrow_record_batch,
+            memory_token: None,
+        })
+    }
+
+    fn output_schema(&self, arrow_schema: &ArrowSchemaRef) -> ArrowSchemaRef {
+        arrow_schema.clone()
+    }
+}
+
+struct HybridRecordDecoder {
+    storage_format_opts: StorageFormatOptions,
+    memory_limit: Option<DecodeMemoryLimit>,
+}
+
+impl HybridRecordDecoder {
+    /// Estimate the memory needed to stretch the non-collapsed columns into
+    /// `values_num` rows.
+    fn estimate_stretch_memory(arrays: &[ArrayRef], values_num: usize) -> usize {
+        arrays
+            .iter()
+            .filter(|array_ref| !matches!(array_ref.data_type(), DataType::List(_)))
+            .map(|array_ref| {
+                let rows = array_ref.len().max(1);
+                array_ref.get_buffer_memory_size() / rows * values_num
+            })
+            .sum()
+    }
+
+    /// Convert `ListArray` fields to underlying data type
+    fn convert_schema(arrow_schema: ArrowSchemaRef) -> ArrowSchemaRef {
+        let new_fields: Vec<_> = arrow_schema
+            .fields()
+            .iter()
+            .map(|f| {
+                if let DataType::List(nested_field) = f.data_type() {
+                    Field::new(f.name(), nested_field.data_type().clone(), true)
                 } else {
                     f.clone()
                 }
@@ -499,6 +1468,10 @@ impl HybridRecordDecoder {
     /// `array_ref` is `a b c`, `value_offsets` is `[0, 3, 5, 6]`, then
     /// output array is `a a a b b c`
     ///
+    /// A group may be empty if two consecutive offsets are equal, and it
+    /// contributes nothing to the output array, e.g. `value_offsets` is
+    /// `[0, 3, 3, 4]` results in `a a a c`.
+    ///
     /// Note: caller should ensure offsets is not empty.
     fn stretch_variable_length_column(
         array_ref: &ArrayRef,
@@ -517,7 +1490,7 @@ impl HybridRecordDecoder {
             null_bitmap.map(|v| v.buffer_ref().as_slice())
         );
 
-        let i32_offsets = Self::get_array_offsets(offset_slices);
+        let i32_offsets = Self::get_array_offsets(offset_slices)?;
         let mut value_bytes = 0;
         for (idx, (current, prev)) in i32_offsets[1..].iter().zip(&i32_offsets).enumerate() {
             let value_len = current - prev;
@@ -537,6 +1510,10 @@ impl HybridRecordDecoder {
         for (idx, (current, prev)) in i32_offsets[1..].iter().zip(&i32_offsets).enumerate() {
             let value_len = current - prev;
             let value_num = value_offsets[idx + 1] - value_offsets[idx];
+            if value_num == 0 {
+                // Empty group, neither values nor null bits are produced.
+                continue;
+            }
 
             if let Some(bitmap) = null_bitmap {
                 if !bitmap.is_set(idx) {
@@ -581,7 +1558,7 @@ impl HybridRecordDecoder {
         value_size: usize,
         value_offsets: &[i32],
     ) -> Result<ArrayRef> {
-        assert!(!value_offsets.is_empty());
+        assert_eq!(array_ref.len() + 1, value_offsets.len());
 
         let values_num = *value_offsets.last().unwrap() as usize;
         let old_values_buffer = array_ref.data().buffers()[0].as_slice();
@@ -592,8 +1569,16 @@ impl HybridRecordDecoder {
         let null_slice = new_null_buffer.as_slice_mut();
         let mut length_so_far = 0;
 
-        for (idx, offset) in (0..old_values_buffer.len()).step_by(value_size).enumerate() {
+        // Iterate by the array length because the buffer may be longer than the
+        // values actually used.
+        for idx in 0..array_ref.len() {
             let value_num = (value_offsets[idx + 1] - value_offsets[idx]) as usize;
+            if value_num == 0 {
+                // Empty group, neither values nor null bits are produced.
+                continue;
+            }
+
+            let offset = idx * value_size;
             if let Some(bitmap) = old_null_bitmap {
                 if !bitmap.is_set(idx) {
                     for i in 0..value_num {
@@ -617,20 +1602,36 @@ impl HybridRecordDecoder {
     }
 
     /// Decode offset slices into Vec<i32>
-    fn get_array_offsets(offset_slices: &[u8]) -> Vec<i32> {
-        let mut i32_offsets = Vec::with_capacity(offset_slices.len() / OFFSET_SIZE);
-        for i in (0..offset_slices.len()).step_by(OFFSET_SIZE) {
-            let offset = i32::from_le_bytes(offset_slices[i..i + OFFSET_SIZE].try_into().unwrap());
-            i32_offsets.push(offset);
+    ///
+    /// Error is returned if the length of `offset_slices` is not a multiple of
+    /// [OFFSET_SIZE], which means the buffer is truncated or misaligned.
+    fn get_array_offsets(offset_slices: &[u8]) -> Result<Vec<i32>> {
+        if offset_slices.len() % OFFSET_SIZE != 0 {
+            let msg = format!(
+                "misaligned offsets buffer, len:{}, offset_size:{}",
+                offset_slices.len(),
+                OFFSET_SIZE
+            );
+            return Err(Box::<dyn std::error::Error + Send + Sync>::from(msg))
+                .context(DecodeRecordBatch);
         }
 
-        i32_offsets
+        let i32_offsets = offset_slices
+            .chunks_exact(OFFSET_SIZE)
+            .map(|chunk| {
+                // The length of the chunk is ensured by `chunks_exact`.
+                let bytes: [u8; OFFSET_SIZE] = chunk.try_into().unwrap();
+                i32::from_le_bytes(bytes)
+            })
+            .collect();
+
+        Ok(i32_offsets)
     }
 }
 
 impl RecordDecoder for HybridRecordDecoder {
     /// Decode records from hybrid to columnar format
-    fn decode(&self, arrow_record_batch: ArrowRecordBatch) -> Result<ArrowRecordBatch> {
+    fn decode(&self, arrow_record_batch: ArrowRecordBatch) -> Result<DecodedRecordBatch> {
         let new_arrow_schema = Self::convert_schema(arrow_record_batch.schema());
         let arrays = arrow_record_batch.columns();
 
@@ -638,12 +1639,22 @@ impl RecordDecoder for HybridRecordDecoder {
         // Find value offsets from the first col in collapsible_cols_idx.
         if let Some(idx) = self.storage_format_opts.collapsible_cols_idx.first() {
             let offset_slices = arrays[*idx as usize].data().buffers()[0].as_slice();
-            value_offsets = Some(Self::get_array_offsets(offset_slices));
+            value_offsets = Some(Self::get_array_offsets(offset_slices)?);
         } else {
             CollapsibleColsIdxEmpty.fail()?;
         }
 
         let value_offsets = value_offsets.unwrap();
+        // The token is returned with the stretched record batch, so the memory is
+        // released after the record batch is used up.
+        let memory_token = match &self.memory_limit {
+            Some(memory_limit) => {
+                let values_num = value_offsets.last().copied().unwrap_or(0) as usize;
+                let estimated_memory = Self::estimate_stretch_memory(arrays, values_num);
+                Some(memory_limit.try_apply_token(estimated_memory)?)
+            }
+            None => None,
+        };
         let arrays = arrays
             .iter()
             .map(|array_ref| {
@@ -656,7 +1667,18 @@ impl RecordDecoder for HybridRecordDecoder {
                     // future. So We should keep metadata about which columns
                     // are collapsed by hybrid storage format, to differentiate
                     // List column in original records
-                    DataType::List(_nested_field) => {
+                    DataType::List(nested_field) => {
+                        // Taking the child data of a nested list produces wrong results
+                        // silently, so reject it explicitly.
+                        if let DataType::List(_) = nested_field.data_type() {
+                            let msg = format!(
+                                "nested list is not supported by hybrid format, data_type:{:?}",
+                                data_type
+                            );
+                            return Err(Box::<dyn std::error::Error + Send + Sync>::from(msg))
+                                .context(DecodeRecordBatch);
+                        }
+
                         Ok(array_ref.data().child_data()[0].clone().into())
                     }
                     _ => {
@@ -674,39 +1696,232 @@ impl RecordDecoder for HybridRecordDecoder {
             })
             .collect::<Result<Vec<_>>>()?;
 
-        ArrowRecordBatch::try_new(new_arrow_schema, arrays)
+        let record_batch = ArrowRecordBatch::try_new(new_arrow_schema, arrays)
             .map_err(|e| Box::new(e) as _)
-            .context(EncodeRecordBatch)
+            .context(EncodeRecordBatch)?;
+
+        Ok(DecodedRecordBatch {
+            record_batch,
+            memory_token,
+        })
+    }
+
+    fn output_schema(&self, arrow_schema: &ArrowSchemaRef) -> ArrowSchemaRef {
+        Self::convert_schema(arrow_schema.clone())
     }
 }
 
 pub struct ParquetDecoder {
     record_decoder: Box<dyn RecordDecoder>,
+    /// The decoded record batches are coerced into the types of it if set.
+    target_schema: Option<ArrowSchemaRef>,
 }
 
 impl ParquetDecoder {
-    pub fn new(storage_format_opts: StorageFormatOptions) -> Self {
+    /// Create a decoder for the `storage_format_opts`, and error is returned if
+    /// the storage format is not understood by this build.
+    pub fn try_new(storage_format_opts: StorageFormatOptions) -> Result<Self> {
+        Self::try_new_with_memory_limit(storage_format_opts, None)
+    }
+
+    /// Create a decoder which consults the `memory_limit` before stretching a
+    /// hybrid record batch, and the decoding fails if the limit is exceeded.
+    pub fn try_new_with_memory_limit(
+        storage_format_opts: StorageFormatOptions,
+        memory_limit: Option<DecodeMemoryLimit>,
+    ) -> Result<Self> {
         let record_decoder: Box<dyn RecordDecoder> = match storage_format_opts.format {
             StorageFormat::Hybrid => Box::new(HybridRecordDecoder {
                 storage_format_opts,
+                memory_limit,
             }),
-            StorageFormat::Columnar => Box::new(ColumnarRecordDecoder {}),
+            // The delta encoded timestamp column is decoded by parquet.
+            StorageFormat::Columnar | StorageFormat::ColumnarDeltaTs => {
+                Box::new(ColumnarRecordDecoder {})
+            }
+            format @ StorageFormat::Unknown(_) => {
+                return UnsupportedStorageFormat { format }.fail();
+            }
         };
 
-        Self { record_decoder }
+        Ok(Self {
+            record_decoder,
+            target_schema: None,
+        })
+    }
+
+    /// Coerce the decoded columns into the types of the columns with the same
+    /// name in `target_schema`, e.g. the current schema of the table whose
+    /// column types are widened after the sst is written.
+    ///
+    /// Only the lossless coercion is allowed, e.g. from `Int32` to `Int64`,
+    /// and the decoding fails on narrowing.
+    pub fn with_target_schema(mut self, target_schema: ArrowSchemaRef) -> Self {
+        self.target_schema = Some(target_schema);
+        self
     }
 
+    /// Decode the record batch, and the memory applied from the memory limit
+    /// to decode it is released on return.
+    ///
+    /// Use [Self::decode_record_batch_with_token] to hold the memory as long
+    /// as the decoded record batch is in use.
     pub fn decode_record_batch(
         &self,
         arrow_record_batch: ArrowRecordBatch,
     ) -> Result<ArrowRecordBatch> {
-        self.record_decoder.decode(arrow_record_batch)
+        self.decode_record_batch_with_token(arrow_record_batch)
+            .map(|decoded| decoded.record_batch)
+    }
+
+    /// Decode the record batch and return it with the token of the memory
+    /// applied to decode it.
+    pub fn decode_record_batch_with_token(
+        &self,
+        arrow_record_batch: ArrowRecordBatch,
+    ) -> Result<DecodedRecordBatch> {
+        let DecodedRecordBatch {
+            record_batch,
+            memory_token,
+        } = self.record_decoder.decode(arrow_record_batch)?;
+        let record_batch = match &self.target_schema {
+            Some(target_schema) => coerce_record_batch(record_batch, target_schema)?,
+            None => record_batch,
+        };
+
+        Ok(DecodedRecordBatch {
+            record_batch,
+            memory_token,
+        })
+    }
+
+    /// Schema of the record batches returned by [Self::decode_record_batch]
+    /// for the record batches of `arrow_schema` read from the sst, that is,
+    /// the stretched schema for the hybrid format and `arrow_schema` itself
+    /// for the columnar format, with the column types coerced into the target
+    /// schema if any.
+    pub fn output_schema(&self, arrow_schema: &ArrowSchemaRef) -> ArrowSchemaRef {
+        let schema = self.record_decoder.output_schema(arrow_schema);
+        match &self.target_schema {
+            Some(target_schema) => coerce_schema(schema, target_schema),
+            None => schema,
+        }
+    }
+}
+
+/// Replace the types of the fields in `schema` with the types of the fields
+/// with the same name in `target_schema`.
+fn coerce_schema(schema: ArrowSchemaRef, target_schema: &ArrowSchema) -> ArrowSchemaRef {
+    let mut coerced = false;
+    let fields = schema
+        .fields()
+        .iter()
+        .map(|field| match target_schema.field_with_name(field.name()) {
+            Ok(target_field) if target_field.data_type() != field.data_type() => {
+                coerced = true;
+                Field::new(
+                    field.name(),
+                    target_field.data_type().clone(),
+                    field.is_nullable(),
+                )
+                .with_metadata(field.metadata().cloned())
+            }
+            _ => field.clone(),
+        })
+        .collect();
+
+    if !coerced {
+        return schema;
+    }
+
+    Arc::new(ArrowSchema::new_with_metadata(
+        fields,
+        schema.metadata().clone(),
+    ))
+}
+
+/// Cast the columns of `record_batch` into the types in `target_schema`, and
+/// the columns absent from `target_schema` are left unchanged.
+fn coerce_record_batch(
+    record_batch: ArrowRecordBatch,
+    target_schema: &ArrowSchema,
+) -> Result<ArrowRecordBatch> {
+    let schema = record_batch.schema();
+    let mut coerced = false;
+    let mut fields = Vec::with_capacity(schema.fields().len());
+    let mut columns = Vec::with_capacity(schema.fields().len());
+    for (field, column) in schema.fields().iter().zip(record_batch.columns()) {
+        let target_type = match target_schema.field_with_name(field.name()) {
+            Ok(target_field) if target_field.data_type() != field.data_type() => {
+                target_field.data_type()
+            }
+            _ => {
+                fields.push(field.clone());
+                columns.push(column.clone());
+                continue;
+            }
+        };
+
+        ensure!(
+            is_lossless_cast(field.data_type(), target_type),
+            UnsafeTypeCoercion {
+                name: field.name(),
+                from: field.data_type().clone(),
+                to: target_type.clone(),
+            }
+        );
+        let column = compute::cast(column, target_type)
+            .map_err(|e| Box::new(e) as _)
+            .context(DecodeRecordBatch)?;
+        let field = Field::new(field.name(), target_type.clone(), field.is_nullable())
+            .with_metadata(field.metadata().cloned());
+        fields.push(field);
+        columns.push(column);
+        coerced = true;
+    }
+
+    if !coerced {
+        return Ok(record_batch);
     }
+
+    let schema = Arc::new(ArrowSchema::new_with_metadata(
+        fields,
+        schema.metadata().clone(),
+    ));
+    ArrowRecordBatch::try_new(schema, columns)
+        .map_err(|e| Box::new(e) as _)
+        .context(DecodeRecordBatch)
+}
+
+/// Whether every value of the type `from` can be represented by the type `to`.
+fn is_lossless_cast(from: &DataType, to: &DataType) -> bool {
+    use DataType::*;
+
+    matches!(
+        (from, to),
+        (Int8, Int16 | Int32 | Int64 | Float32 | Float64)
+            | (Int16, Int32 | Int64 | Float32 | Float64)
+            | (Int32, Int64 | Float64)
+            | (
+                UInt8,
+                UInt16 | UInt32 | UInt64 | Int16 | Int32 | Int64 | Float32 | Float64
+            )
+            | (UInt16, UInt32 | UInt64 | Int32 | Int64 | Float32 | Float64)
+            | (UInt32, UInt64 | Int64 | Float64)
+            | (Float32, Float64)
+    )
 }
 
 #[cfg(test)]
 mod tests {
-    use arrow::array::{Int32Array, StringArray, TimestampMillisecondArray, UInt64Array};
+    use arrow::{
+        array::{
+            Int32Array, Int64Array, ListArray, StringArray, TimestampMicrosecondArray,
+            TimestampMillisecondArray, UInt64Array,
+        },
+        buffer::Buffer,
+        datatypes::Int32Type,
+    };
     use common_types::{
         bytes::Bytes,
         column_schema,
@@ -852,11 +2067,38 @@ mod tests {
         }
     }
 
-    fn collect_collapsible_cols_idx(schema: &Schema, collapsible_cols_idx: &mut Vec<u32>) {
-        for (idx, _col) in schema.columns().iter().enumerate() {
-            if schema.is_collapsible_column(idx) {
-                collapsible_cols_idx.push(idx as u32);
-            }
+    #[test]
+    fn stretch_column_with_empty_groups() {
+        // The second and the fourth groups are empty.
+        let value_offsets = vec![0, 2, 2, 3, 3, 5];
+        let expected_nulls = [false, false, true, false, false];
+
+        let input = int32_array(vec![Some(1), Some(2), None, Some(4), Some(5)]);
+        let actual = HybridRecordDecoder::stretch_fixed_length_column(
+            &input,
+            std::mem::size_of::<i32>(),
+            &value_offsets,
+        )
+        .unwrap();
+        let expected = int32_array(vec![Some(1), Some(1), None, Some(5), Some(5)]);
+        assert_eq!(
+            actual.as_any().downcast_ref::<Int32Array>().unwrap(),
+            expected.as_any().downcast_ref::<Int32Array>().unwrap(),
+        );
+        for (idx, is_null) in expected_nulls.iter().enumerate() {
+            assert_eq!(*is_null, actual.is_null(idx));
+        }
+
+        let input = string_array(vec![Some("a"), Some("b"), None, Some("d"), Some("e")]);
+        let actual =
+            HybridRecordDecoder::stretch_variable_length_column(&input, &value_offsets).unwrap();
+        let expected = string_array(vec![Some("a"), Some("a"), None, Some("e"), Some("e")]);
+        assert_eq!(
+            actual.as_any().downcast_ref::<StringArray>().unwrap(),
+            expected.as_any().downcast_ref::<StringArray>().unwrap(),
+        );
+        for (idx, is_null) in expected_nulls.iter().enumerate() {
+            assert_eq!(*is_null, actual.is_null(idx));
         }
     }
 
@@ -871,13 +2113,23 @@ mod tests {
             time_range: TimeRange::new_unchecked(Timestamp::new(100), Timestamp::new(101)),
             max_sequence: 200,
             schema: schema.clone(),
+            schema_fingerprint: schema.fingerprint(),
             size: 10,
             row_num: 4,
             storage_format_opts,
             bloom_filter: Default::default(),
+            row_group_time_ranges: Vec::new(),
         };
-        let mut encoder =
-            HybridRecordEncoder::try_new(100, Compression::ZSTD, meta_data.clone()).unwrap();
+        let mut encoder = HybridRecordEncoder::try_new(
+            100,
+            Compression::ZSTD,
+            EnabledStatistics::Page,
+            false,
+            &HashMap::new(),
+            true,
+            meta_data.clone(),
+        )
+        .unwrap();
 
         let columns = vec![
             Arc::new(UInt64Array::from(vec![1, 1, 2])) as ArrayRef,
@@ -932,15 +2184,14 @@ mod tests {
             .build()
             .unwrap();
         let hybrid_record_batch = reader.next().unwrap().unwrap();
-        collect_collapsible_cols_idx(
-            &meta_data.schema,
-            &mut meta_data.storage_format_opts.collapsible_cols_idx,
-        );
+        meta_data.storage_format_opts =
+            StorageFormatOptions::for_schema(&meta_data.schema, StorageFormat::Hybrid);
 
         let decoder = HybridRecordDecoder {
             storage_format_opts: meta_data.storage_format_opts,
+            memory_limit: None,
         };
-        let decoded_record_batch = decoder.decode(hybrid_record_batch).unwrap();
+        let decoded_record_batch = decoder.decode(hybrid_record_batch).unwrap().record_batch;
 
         // Note: decode record batch's schema doesn't have metadata
         // It's encoded in metadata of every fields
@@ -996,103 +2247,57 @@ mod tests {
     }
 
     #[test]
-    fn test_hybrid_flush() {
+    fn test_hybrid_encode_sort_by_timestamp() {
         let schema = build_schema();
-        let storage_format_opts = StorageFormatOptions::new(StorageFormat::Hybrid);
-
         let meta_data = SstMetaData {
             min_key: Bytes::from_static(b"100"),
             max_key: Bytes::from_static(b"200"),
-            time_range: TimeRange::new_unchecked(Timestamp::new(100), Timestamp::new(101)),
+            time_range: TimeRange::new_unchecked(Timestamp::new(100), Timestamp::new(103)),
             max_sequence: 200,
             schema: schema.clone(),
+            schema_fingerprint: schema.fingerprint(),
             size: 10,
-            row_num: 4,
-            storage_format_opts,
+            row_num: 7,
+            storage_format_opts: StorageFormatOptions::new(StorageFormat::Hybrid),
             bloom_filter: Default::default(),
+            row_group_time_ranges: Vec::new(),
         };
-        let mut encoder = HybridRecordEncoder::try_new(10, Compression::ZSTD, meta_data).unwrap();
+        let mut encoder = HybridRecordEncoder::try_new(
+            100,
+            Compression::ZSTD,
+            EnabledStatistics::Page,
+            true,
+            &HashMap::new(),
+            true,
+            meta_data,
+        )
+        .unwrap();
 
         let columns = vec![
             Arc::new(UInt64Array::from(vec![1, 1, 2])) as ArrayRef,
-            timestamp_array(vec![100, 101, 100]),
+            timestamp_array(vec![102, 101, 103]),
             string_array(vec![Some("host1"), Some("host1"), Some("host2")]),
             string_array(vec![Some("region1"), Some("region1"), Some("region2")]),
-            int32_array(vec![Some(1), Some(2), Some(11)]),
-            string_array(vec![
-                Some("string_value1"),
-                Some("string_value2"),
-                Some("string_value3"),
-            ]),
+            int32_array(vec![Some(2), Some(1), Some(13)]),
+            string_array(vec![Some("v102"), Some("v101"), Some("v103")]),
         ];
-
         let columns2 = vec![
-            Arc::new(UInt64Array::from(vec![1, 2, 1, 2])) as ArrayRef,
-            timestamp_array(vec![100, 101, 100, 101]),
+            Arc::new(UInt64Array::from(vec![1, 2, 2, 2])) as ArrayRef,
+            timestamp_array(vec![100, 100, 102, 101]),
             string_array(vec![
                 Some("host1"),
                 Some("host2"),
-                Some("host1"),
-                Some("host2"),
-            ]),
-            string_array(vec![
-                Some("region1"),
-                Some("region2"),
-                Some("region1"),
-                Some("region2"),
-            ]),
-            int32_array(vec![Some(1), Some(2), Some(11), Some(12)]),
-            string_array(vec![
-                Some("string_value1"),
-                Some("string_value2"),
-                Some("string_value3"),
-                Some("string_value4"),
-            ]),
-        ];
-
-        let columns3 = vec![
-            Arc::new(UInt64Array::from(vec![1, 2, 3, 4, 5, 6, 7, 8])) as ArrayRef,
-            timestamp_array(vec![100, 101, 100, 100, 101, 100, 102, 103]),
-            string_array(vec![
-                Some("host1"),
-                Some("host1"),
                 Some("host2"),
-                Some("host3"),
-                Some("host4"),
                 Some("host2"),
-                Some("host3"),
-                Some("host4"),
             ]),
             string_array(vec![
-                Some("region1"),
                 Some("region1"),
                 Some("region2"),
-                Some("region3"),
-                Some("region1"),
-                Some("region1"),
                 Some("region2"),
-                Some("region3"),
-            ]),
-            int32_array(vec![
-                Some(1),
-                Some(2),
-                Some(11),
-                Some(12),
-                Some(1),
-                Some(2),
-                Some(11),
-                Some(12),
-            ]),
-            string_array(vec![
-                Some("string_value1"),
-                Some("string_value2"),
-                Some("string_value3"),
-                Some("string_value4"),
-                Some("string_value1"),
-                Some("string_value2"),
-                Some("string_value3"),
-                Some("string_value4"),
+                Some("region2"),
             ]),
+            int32_array(vec![Some(0), Some(10), Some(12), Some(11)]),
+            string_array(vec![Some("v100"), Some("v100"), Some("v102"), Some("v101")]),
         ];
 
         let input_record_batch =
@@ -1104,14 +2309,1713 @@ mod tests {
             .unwrap();
         assert_eq!(2, row_nums);
 
-        let input_record_batch3 =
-            ArrowRecordBatch::try_new(schema.to_arrow_schema_ref(), columns3).unwrap();
-        let row_nums2 = encoder.encode(vec![input_record_batch3]).unwrap();
-        assert_eq!(8, row_nums2);
+        let encoded_bytes = encoder.close().unwrap();
+        let mut reader = ParquetRecordBatchReaderBuilder::try_new(Bytes::from(encoded_bytes))
+            .unwrap()
+            .build()
+            .unwrap();
+        let hybrid_record_batch = reader.next().unwrap().unwrap();
+        let timestamp_lists = hybrid_record_batch
+            .column(schema.timestamp_index())
+            .as_any()
+            .downcast_ref::<ListArray>()
+            .unwrap();
+        let value_lists = hybrid_record_batch
+            .column(4)
+            .as_any()
+            .downcast_ref::<ListArray>()
+            .unwrap();
 
-        let sst = encoder.close().unwrap();
-        let bytes = Bytes::from(sst);
-        let parquet_metadata = footer::parse_metadata(&bytes).unwrap();
-        assert_eq!(2, parquet_metadata.num_row_groups());
+        let expected = [
+            (vec![100, 101, 102], vec![0, 1, 2]),
+            (vec![100, 101, 102, 103], vec![10, 11, 12, 13]),
+        ];
+        assert_eq!(expected.len(), timestamp_lists.len());
+        for (group_idx, (expected_timestamps, expected_values)) in expected.iter().enumerate() {
+            let timestamps = timestamp_lists.value(group_idx);
+            let timestamps = timestamps
+                .as_any()
+                .downcast_ref::<TimestampMillisecondArray>()
+                .unwrap();
+            let timestamps = timestamps.values().to_vec();
+            assert!(timestamps.windows(2).all(|w| w[0] <= w[1]));
+            assert_eq!(expected_timestamps, &timestamps);
+
+            // Other collapsible columns should be reordered together with timestamp.
+            let values = value_lists.value(group_idx);
+            let values = values.as_any().downcast_ref::<Int32Array>().unwrap();
+            assert_eq!(expected_values, &values.values().to_vec());
+        }
+    }
+
+    #[test]
+    fn test_hybrid_encode_uncollapsed_column() {
+        let schema = build_schema();
+        let meta_data = SstMetaData {
+            min_key: Bytes::from_static(b"100"),
+            max_key: Bytes::from_static(b"200"),
+            time_range: TimeRange::new_unchecked(Timestamp::new(100), Timestamp::new(102)),
+            max_sequence: 200,
+            schema: schema.clone(),
+            schema_fingerprint: schema.fingerprint(),
+            size: 10,
+            row_num: 3,
+            storage_format_opts: StorageFormatOptions::new(StorageFormat::Hybrid),
+            bloom_filter: Default::default(),
+            row_group_time_ranges: Vec::new(),
+        };
+        let uncollapsed = ColumnEncoding {
+            collapsible: Some(false),
+            ..Default::default()
+        };
+        let column_encodings: HashMap<_, _> = [("string_value".to_string(), uncollapsed)]
+            .into_iter()
+            .collect();
+        let new_encoder = |column_encodings: &HashMap<String, ColumnEncoding>| {
+            HybridRecordEncoder::try_new(
+                100,
+                Compression::ZSTD,
+                EnabledStatistics::Page,
+                false,
+                column_encodings,
+                true,
+                meta_data.clone(),
+            )
+        };
+        let build_record_batch = |string_values| {
+            let columns = vec![
+                Arc::new(UInt64Array::from(vec![1, 1, 2])) as ArrayRef,
+                timestamp_array(vec![100, 101, 100]),
+                string_array(vec![Some("host1"), Some("host1"), Some("host2")]),
+                string_array(vec![Some("region1"), Some("region1"), Some("region2")]),
+                int32_array(vec![Some(1), Some(2), Some(3)]),
+                string_array(string_values),
+            ];
+            ArrowRecordBatch::try_new(schema.to_arrow_schema_ref(), columns).unwrap()
+        };
+
+        let input_record_batch =
+            build_record_batch(vec![Some("blob1"), Some("blob1"), Some("blob2")]);
+        let mut encoder = new_encoder(&column_encodings).unwrap();
+        assert_eq!(2, encoder.encode(vec![input_record_batch.clone()]).unwrap());
+        let encoded_bytes = Bytes::from(encoder.close().unwrap());
+
+        // The excluded column is stored as a regular column, while the other
+        // collapsible columns are still lists.
+        let parquet_meta_data = footer::parse_metadata(&encoded_bytes).unwrap();
+        let kv_meta = &parquet_meta_data
+            .file_metadata()
+            .key_value_metadata()
+            .unwrap()[0];
+        let sst_meta_data = decode_sst_meta_data(kv_meta).unwrap();
+        assert_eq!(
+            vec![1, 4],
+            sst_meta_data.storage_format_opts.collapsible_cols_idx
+        );
+        let mut reader = ParquetRecordBatchReaderBuilder::try_new(encoded_bytes)
+            .unwrap()
+            .build()
+            .unwrap();
+        let hybrid_record_batch = reader.next().unwrap().unwrap();
+        assert_eq!(&DataType::Utf8, hybrid_record_batch.column(5).data_type());
+        assert!(matches!(
+            hybrid_record_batch.column(4).data_type(),
+            DataType::List(_)
+        ));
+
+        let decoder = ParquetDecoder::try_new(sst_meta_data.storage_format_opts).unwrap();
+        let decoded = decoder.decode_record_batch(hybrid_record_batch).unwrap();
+        for (expect, column) in input_record_batch.columns().iter().zip(decoded.columns()) {
+            assert_eq!(expect.data(), column.data());
+        }
+
+        // The values differ in a time series.
+        let mut encoder = new_encoder(&column_encodings).unwrap();
+        let res = encoder.encode(vec![build_record_batch(vec![
+            Some("blob1"),
+            Some("blob2"),
+            Some("blob2"),
+        ])]);
+        assert!(matches!(
+            res,
+            Err(Error::InvalidUncollapsedValue { tsid: 1, .. })
+        ));
+
+        // Only the string columns can be excluded.
+        let column_encodings: HashMap<_, _> =
+            [("value".to_string(), uncollapsed)].into_iter().collect();
+        assert!(matches!(
+            new_encoder(&column_encodings),
+            Err(Error::InvalidUncollapsedColumn { .. })
+        ));
+    }
+
+    #[test]
+    fn test_storage_format_options_for_schema() {
+        let schema = build_schema();
+        let meta_data = SstMetaData {
+            min_key: Bytes::from_static(b"100"),
+            max_key: Bytes::from_static(b"200"),
+            time_range: TimeRange::new_unchecked(Timestamp::new(100), Timestamp::new(101)),
+            max_sequence: 200,
+            schema: schema.clone(),
+            schema_fingerprint: schema.fingerprint(),
+            size: 10,
+            row_num: 2,
+            storage_format_opts: StorageFormatOptions::new(StorageFormat::Hybrid),
+            bloom_filter: Default::default(),
+            row_group_time_ranges: Vec::new(),
+        };
+        let mut encoder = HybridRecordEncoder::try_new(
+            100,
+            Compression::ZSTD,
+            EnabledStatistics::Page,
+            false,
+            &HashMap::new(),
+            true,
+            meta_data,
+        )
+        .unwrap();
+        let input_record_batch = ArrowRecordBatch::try_new(
+            schema.to_arrow_schema_ref(),
+            vec![
+                Arc::new(UInt64Array::from(vec![1, 1])) as ArrayRef,
+                timestamp_array(vec![100, 101]),
+                string_array(vec![Some("host1"), Some("host1")]),
+                string_array(vec![Some("region1"), Some("region1")]),
+                int32_array(vec![Some(1), Some(2)]),
+                string_array(vec![Some("v1"), Some("v2")]),
+            ],
+        )
+        .unwrap();
+        encoder.encode(vec![input_record_batch]).unwrap();
+        let encoded_bytes = Bytes::from(encoder.close().unwrap());
+        let parquet_meta_data = footer::parse_metadata(&encoded_bytes).unwrap();
+        let kv_meta = &parquet_meta_data
+            .file_metadata()
+            .key_value_metadata()
+            .unwrap()[0];
+        let encoded_opts = decode_sst_meta_data(kv_meta).unwrap().storage_format_opts;
+
+        let storage_format_opts = StorageFormatOptions::for_schema(&schema, StorageFormat::Hybrid);
+        assert_eq!(vec![1, 4, 5], storage_format_opts.collapsible_cols_idx);
+        assert_eq!(encoded_opts, storage_format_opts);
+
+        // Only the hybrid format has collapsible columns.
+        for format in [StorageFormat::Columnar, StorageFormat::ColumnarDeltaTs] {
+            assert_eq!(
+                StorageFormatOptions::new(format),
+                StorageFormatOptions::for_schema(&schema, format)
+            );
+        }
+    }
+
+    #[test]
+    fn test_hybrid_decode_with_memory_limit() {
+        let schema = build_schema();
+        let mut meta_data = SstMetaData {
+            min_key: Bytes::from_static(b"100"),
+            max_key: Bytes::from_static(b"200"),
+            time_range: TimeRange::new_unchecked(Timestamp::new(100), Timestamp::new(101)),
+            max_sequence: 200,
+            schema: schema.clone(),
+            schema_fingerprint: schema.fingerprint(),
+            size: 10,
+            row_num: 3,
+            storage_format_opts: StorageFormatOptions::new(StorageFormat::Hybrid),
+            bloom_filter: Default::default(),
+            row_group_time_ranges: Vec::new(),
+        };
+        let mut encoder = HybridRecordEncoder::try_new(
+            100,
+            Compression::ZSTD,
+            EnabledStatistics::Page,
+            false,
+            &HashMap::new(),
+            true,
+            meta_data.clone(),
+        )
+        .unwrap();
+        let columns = vec![
+            Arc::new(UInt64Array::from(vec![1, 1, 2])) as ArrayRef,
+            timestamp_array(vec![100, 101, 100]),
+            string_array(vec![Some("host1"), Some("host1"), Some("host2")]),
+            string_array(vec![Some("region1"), Some("region1"), Some("region2")]),
+            int32_array(vec![Some(1), Some(2), Some(11)]),
+            string_array(vec![
+                Some("string_value1"),
+                Some("string_value2"),
+                Some("string_value3"),
+            ]),
+        ];
+        let input_record_batch =
+            ArrowRecordBatch::try_new(schema.to_arrow_schema_ref(), columns).unwrap();
+        encoder.encode(vec![input_record_batch]).unwrap();
+        let encoded_bytes = encoder.close().unwrap();
+        let mut reader = ParquetRecordBatchReaderBuilder::try_new(Bytes::from(encoded_bytes))
+            .unwrap()
+            .build()
+            .unwrap();
+        let hybrid_record_batch = reader.next().unwrap().unwrap();
+        meta_data.storage_format_opts =
+            StorageFormatOptions::for_schema(&meta_data.schema, StorageFormat::Hybrid);
+
+        let memory_limit = DecodeMemoryLimit::new(1024 * 1024);
+        let decoder = ParquetDecoder::try_new_with_memory_limit(
+            meta_data.storage_format_opts,
+            Some(memory_limit.clone()),
+        )
+        .unwrap();
+
+        // Exhaust the budget, and the decode should be rejected.
+        let token = memory_limit.try_apply_token(1024 * 1024).unwrap();
+        let res = decoder.decode_record_batch(hybrid_record_batch.clone());
+        assert!(matches!(res, Err(Error::DecodeMemoryLimitExceeded { .. })));
+        assert_eq!(1024 * 1024, memory_limit.usage());
+
+        // Release the budget, and the decode should succeed.
+        drop(token);
+        assert_eq!(0, memory_limit.usage());
+        let decoded = decoder
+            .decode_record_batch_with_token(hybrid_record_batch)
+            .unwrap();
+        assert_eq!(3, decoded.record_batch.num_rows());
+
+        // The memory is held until the token of the decoded record batch is dropped.
+        assert!(decoded.memory_token.is_some());
+        assert!(memory_limit.usage() > 0);
+        drop(decoded);
+        assert_eq!(0, memory_limit.usage());
+    }
+
+    #[test]
+    fn test_encode_with_statistics_disabled() {
+        let schema = build_schema();
+        let columns = vec![
+            Arc::new(UInt64Array::from(vec![1, 1, 2])) as ArrayRef,
+            timestamp_array(vec![100, 101, 100]),
+            string_array(vec![Some("host1"), Some("host1"), Some("host2")]),
+            string_array(vec![Some("region1"), Some("region1"), Some("region2")]),
+            int32_array(vec![Some(1), Some(2), Some(11)]),
+            string_array(vec![
+                Some("string_value1"),
+                Some("string_value2"),
+                Some("string_value3"),
+            ]),
+        ];
+        let input_record_batch =
+            ArrowRecordBatch::try_new(schema.to_arrow_schema_ref(), columns).unwrap();
+
+        for (statistics_enabled, expect_statistics) in [
+            (EnabledStatistics::Page, true),
+            (EnabledStatistics::None, false),
+        ] {
+            let meta_data = SstMetaData {
+                min_key: Bytes::from_static(b"100"),
+                max_key: Bytes::from_static(b"200"),
+                time_range: TimeRange::new_unchecked(Timestamp::new(100), Timestamp::new(101)),
+                max_sequence: 200,
+                schema: schema.clone(),
+                schema_fingerprint: schema.fingerprint(),
+                size: 10,
+                row_num: 3,
+                storage_format_opts: StorageFormatOptions::new(StorageFormat::Columnar),
+                bloom_filter: Default::default(),
+                row_group_time_ranges: Vec::new(),
+            };
+            let mut encoder = ParquetEncoder::try_new(
+                ParquetEncoderOptions {
+                    num_rows_per_row_group: 100,
+                    statistics_enabled,
+                    ..Default::default()
+                },
+                meta_data,
+            )
+            .unwrap();
+            encoder
+                .encode_record_batch(vec![input_record_batch.clone()])
+                .unwrap();
+
+            let bytes = Bytes::from(encoder.close().unwrap());
+            let parquet_metadata = footer::parse_metadata(&bytes).unwrap();
+            for row_group in parquet_metadata.row_groups() {
+                for column in row_group.columns() {
+                    assert_eq!(expect_statistics, column.statistics().is_some());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_encode_with_column_encodings() {
+        let schema = build_schema();
+        let columns = vec![
+            Arc::new(UInt64Array::from(vec![1, 1, 2, 2])) as ArrayRef,
+            timestamp_array(vec![100, 101, 100, 101]),
+            string_array(vec![
+                Some("host1"),
+                Some("host1"),
+                Some("host2"),
+                Some("host2"),
+            ]),
+            string_array(vec![
+                Some("region1"),
+                Some("region1"),
+                Some("region2"),
+                Some("region2"),
+            ]),
+            int32_array(vec![Some(1), Some(2), Some(11), Some(12)]),
+            string_array(vec![
+                Some("string_value1"),
+                Some("string_value1"),
+                Some("string_value1"),
+                Some("string_value1"),
+            ]),
+        ];
+        let input_record_batch =
+            ArrowRecordBatch::try_new(schema.to_arrow_schema_ref(), columns).unwrap();
+
+        let dict_zstd = ColumnEncoding {
+            dictionary_enabled: Some(true),
+            compression: Some(Compression::ZSTD),
+            encoding: None,
+            collapsible: None,
+        };
+        let plain = ColumnEncoding {
+            dictionary_enabled: Some(false),
+            compression: None,
+            encoding: None,
+            collapsible: None,
+        };
+        let column_encodings: HashMap<_, _> = [
+            ("host".to_string(), dict_zstd),
+            ("string_value".to_string(), dict_zstd),
+            ("value".to_string(), plain),
+        ]
+        .into_iter()
+        .collect();
+
+        for storage_format in [StorageFormat::Columnar, StorageFormat::Hybrid] {
+            let meta_data = SstMetaData {
+                min_key: Bytes::from_static(b"100"),
+                max_key: Bytes::from_static(b"200"),
+                time_range: TimeRange::new_unchecked(Timestamp::new(100), Timestamp::new(102)),
+                max_sequence: 200,
+                schema: schema.clone(),
+                schema_fingerprint: schema.fingerprint(),
+                size: 10,
+                row_num: 4,
+                storage_format_opts: StorageFormatOptions::new(storage_format),
+                bloom_filter: Default::default(),
+                row_group_time_ranges: Vec::new(),
+            };
+            let mut encoder = ParquetEncoder::try_new(
+                ParquetEncoderOptions {
+                    num_rows_per_row_group: 100,
+                    compression: Compression::UNCOMPRESSED,
+                    column_encodings: column_encodings.clone(),
+                    ..Default::default()
+                },
+                meta_data,
+            )
+            .unwrap();
+            encoder
+                .encode_record_batch(vec![input_record_batch.clone()])
+                .unwrap();
+
+            let bytes = Bytes::from(encoder.close().unwrap());
+            let parquet_metadata = footer::parse_metadata(&bytes).unwrap();
+            for row_group in parquet_metadata.row_groups() {
+                for column in row_group.columns() {
+                    let column_name = column.column_path().parts()[0].as_str();
+                    match column_encodings.get(column_name) {
+                        Some(encoding) if *encoding == dict_zstd => {
+                            assert!(column.dictionary_page_offset().is_some());
+                            assert_eq!(Compression::ZSTD, column.compression());
+                        }
+                        Some(_) => {
+                            assert!(column.dictionary_page_offset().is_none());
+                            assert_eq!(Compression::UNCOMPRESSED, column.compression());
+                        }
+                        None => assert_eq!(Compression::UNCOMPRESSED, column.compression()),
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_validate_hybrid_schema() {
+        assert!(validate_hybrid_schema(&build_schema()).is_ok());
+
+        // Tsid is missing.
+        let schema = Builder::new()
+            .auto_increment_column_id(true)
+            .add_key_column(
+                column_schema::Builder::new("timestamp".to_string(), DatumKind::Timestamp)
+                    .build()
+                    .unwrap(),
+            )
+            .unwrap()
+            .add_normal_column(
+                column_schema::Builder::new("value".to_string(), DatumKind::Int32)
+                    .build()
+                    .unwrap(),
+            )
+            .unwrap()
+            .build()
+            .unwrap();
+        assert!(matches!(
+            validate_hybrid_schema(&schema),
+            Err(Error::TsidRequired { .. })
+        ));
+
+        // Tag column is not string.
+        let schema = Builder::new()
+            .auto_increment_column_id(true)
+            .add_key_column(
+                column_schema::Builder::new(TSID_COLUMN.to_string(), DatumKind::UInt64)
+                    .build()
+                    .unwrap(),
+            )
+            .unwrap()
+            .add_key_column(
+                column_schema::Builder::new("timestamp".to_string(), DatumKind::Timestamp)
+                    .build()
+                    .unwrap(),
+            )
+            .unwrap()
+            .add_normal_column(
+                column_schema::Builder::new("host_id".to_string(), DatumKind::Int64)
+                    .is_tag(true)
+                    .build()
+                    .unwrap(),
+            )
+            .unwrap()
+            .build()
+            .unwrap();
+        assert!(matches!(
+            validate_hybrid_schema(&schema),
+            Err(Error::StringKeyColumnRequired { .. })
+        ));
+    }
+
+    #[test]
+    fn test_reject_duplicate_column_names() {
+        // The schema builder rejects the duplicate names, but the schema converted
+        // from the arrow schema is not checked.
+        let arrow_schema = build_schema().to_arrow_schema_ref();
+        let mut fields = arrow_schema.fields().clone();
+        let host_field = fields
+            .iter()
+            .find(|field| field.name() == "host")
+            .unwrap()
+            .clone();
+        fields.push(host_field);
+        let schema = Schema::try_from(Arc::new(ArrowSchema::new_with_metadata(
+            fields,
+            arrow_schema.metadata().clone(),
+        )))
+        .unwrap();
+
+        for format in [
+            StorageFormat::Columnar,
+            StorageFormat::ColumnarDeltaTs,
+            StorageFormat::Hybrid,
+        ] {
+            let meta_data = SstMetaData {
+                min_key: Bytes::from_static(b"100"),
+                max_key: Bytes::from_static(b"200"),
+                time_range: TimeRange::new_unchecked(Timestamp::new(100), Timestamp::new(102)),
+                max_sequence: 200,
+                schema: schema.clone(),
+                schema_fingerprint: schema.fingerprint(),
+                size: 10,
+                row_num: 0,
+                storage_format_opts: StorageFormatOptions::new(format),
+                bloom_filter: Default::default(),
+                row_group_time_ranges: Vec::new(),
+            };
+            let res = ParquetEncoder::try_new(
+                ParquetEncoderOptions {
+                    num_rows_per_row_group: 100,
+                    ..Default::default()
+                },
+                meta_data,
+            );
+            match res {
+                Err(Error::DuplicateColumnName { name, .. }) => assert_eq!("host", name),
+                _ => panic!(
+                    "duplicate column name should be rejected, format:{:?}",
+                    format
+                ),
+            }
+        }
+    }
+
+    #[test]
+    fn test_columnar_delta_ts_encode_and_decode() {
+        let schema = build_schema();
+        let num_rows: usize = 100;
+        let columns = vec![
+            Arc::new(UInt64Array::from_iter_values(
+                (0..num_rows).map(|i| i as u64 % 3),
+            )) as ArrayRef,
+            timestamp_array((0..num_rows).map(|i| 1000 + i as i64 * 10).collect()),
+            string_array((0..num_rows).map(|_| Some("host")).collect()),
+            string_array((0..num_rows).map(|_| Some("region")).collect()),
+            int32_array((0..num_rows as i32).map(Some).collect()),
+            string_array((0..num_rows).map(|_| Some("string_value")).collect()),
+        ];
+        let input_record_batch =
+            ArrowRecordBatch::try_new(schema.to_arrow_schema_ref(), columns).unwrap();
+        let storage_format_opts = StorageFormatOptions::new(StorageFormat::ColumnarDeltaTs);
+        let meta_data = SstMetaData {
+            min_key: Bytes::from_static(b"100"),
+            max_key: Bytes::from_static(b"200"),
+            time_range: TimeRange::new_unchecked(Timestamp::new(1000), Timestamp::new(2000)),
+            max_sequence: 200,
+            schema: schema.clone(),
+            schema_fingerprint: schema.fingerprint(),
+            size: 10,
+            row_num: num_rows as u64,
+            storage_format_opts: storage_format_opts.clone(),
+            bloom_filter: Default::default(),
+            row_group_time_ranges: Vec::new(),
+        };
+        let mut encoder = ParquetEncoder::try_new(
+            ParquetEncoderOptions {
+                num_rows_per_row_group: 100,
+                ..Default::default()
+            },
+            meta_data,
+        )
+        .unwrap();
+        encoder
+            .encode_record_batch(vec![input_record_batch.clone()])
+            .unwrap();
+        let bytes = Bytes::from(encoder.close().unwrap());
+
+        // Only the timestamp column is delta encoded.
+        let parquet_metadata = footer::parse_metadata(&bytes).unwrap();
+        for column in parquet_metadata.row_group(0).columns() {
+            let is_delta_encoded = column.encodings().contains(&Encoding::DELTA_BINARY_PACKED);
+            let is_timestamp = column.column_path().parts()[0] == "timestamp";
+            assert_eq!(is_timestamp, is_delta_encoded);
+        }
+
+        let mut reader = ParquetRecordBatchReaderBuilder::try_new(bytes)
+            .unwrap()
+            .with_batch_size(num_rows)
+            .build()
+            .unwrap();
+        let decoder = ParquetDecoder::try_new(storage_format_opts).unwrap();
+        let decoded_record_batch = decoder
+            .decode_record_batch(reader.next().unwrap().unwrap())
+            .unwrap();
+        assert_eq!(input_record_batch.columns(), decoded_record_batch.columns());
+    }
+
+    #[test]
+    fn test_decode_with_target_schema() {
+        let schema = build_schema();
+        let columns = vec![
+            Arc::new(UInt64Array::from(vec![1, 1, 2])) as ArrayRef,
+            timestamp_array(vec![100, 101, 100]),
+            string_array(vec![Some("host1"), Some("host1"), Some("host2")]),
+            string_array(vec![Some("region1"), Some("region1"), Some("region2")]),
+            int32_array(vec![Some(i32::MAX), None, Some(i32::MIN)]),
+            string_array(vec![Some("v1"), Some("v2"), Some("v3")]),
+        ];
+        let input_record_batch =
+            ArrowRecordBatch::try_new(schema.to_arrow_schema_ref(), columns).unwrap();
+        let storage_format_opts = StorageFormatOptions::new(StorageFormat::Columnar);
+        let meta_data = SstMetaData {
+            min_key: Bytes::from_static(b"100"),
+            max_key: Bytes::from_static(b"200"),
+            time_range: TimeRange::new_unchecked(Timestamp::new(100), Timestamp::new(102)),
+            max_sequence: 200,
+            schema: schema.clone(),
+            schema_fingerprint: schema.fingerprint(),
+            size: 10,
+            row_num: 3,
+            storage_format_opts: storage_format_opts.clone(),
+            bloom_filter: Default::default(),
+            row_group_time_ranges: Vec::new(),
+        };
+        let mut encoder = ParquetEncoder::try_new(
+            ParquetEncoderOptions {
+                num_rows_per_row_group: 100,
+                ..Default::default()
+            },
+            meta_data,
+        )
+        .unwrap();
+        encoder
+            .encode_record_batch(vec![input_record_batch.clone()])
+            .unwrap();
+        let bytes = Bytes::from(encoder.close().unwrap());
+
+        let value_idx = schema.index_of("value").unwrap();
+        let build_target_schema = |data_type: DataType| {
+            let arrow_schema = schema.to_arrow_schema_ref();
+            let mut fields = arrow_schema.fields().clone();
+            let field = &fields[value_idx];
+            fields[value_idx] = Field::new(field.name(), data_type, field.is_nullable())
+                .with_metadata(field.metadata().cloned());
+            Arc::new(ArrowSchema::new_with_metadata(
+                fields,
+                arrow_schema.metadata().clone(),
+            ))
+        };
+        let read_record_batch = || {
+            let mut reader = ParquetRecordBatchReaderBuilder::try_new(bytes.clone())
+                .unwrap()
+                .build()
+                .unwrap();
+            reader.next().unwrap().unwrap()
+        };
+
+        // Widen the int32 column to int64.
+        let decoder = ParquetDecoder::try_new(storage_format_opts.clone())
+            .unwrap()
+            .with_target_schema(build_target_schema(DataType::Int64));
+        let decoded_record_batch = decoder.decode_record_batch(read_record_batch()).unwrap();
+        assert_eq!(
+            &DataType::Int64,
+            decoded_record_batch.schema().field(value_idx).data_type()
+        );
+        let expected: ArrayRef = Arc::new(Int64Array::from(vec![
+            Some(i32::MAX as i64),
+            None,
+            Some(i32::MIN as i64),
+        ]));
+        assert_eq!(&expected, decoded_record_batch.column(value_idx));
+        for (idx, column) in input_record_batch.columns().iter().enumerate() {
+            if idx != value_idx {
+                assert_eq!(column, decoded_record_batch.column(idx));
+            }
+        }
+        // The decoded record batch conforms to the table schema after altering.
+        let altered_schema = Schema::try_from(decoded_record_batch.schema()).unwrap();
+        assert_eq!(DatumKind::Int64, altered_schema.column(value_idx).data_type);
+
+        // Narrowing the int32 column to int16 is rejected.
+        let decoder = ParquetDecoder::try_new(storage_format_opts)
+            .unwrap()
+            .with_target_schema(build_target_schema(DataType::Int16));
+        let res = decoder.decode_record_batch(read_record_batch());
+        assert!(matches!(res, Err(Error::UnsafeTypeCoercion { .. })));
+    }
+
+    #[test]
+    fn test_decoder_output_schema() {
+        let schema = build_schema();
+        let columns = vec![
+            Arc::new(UInt64Array::from(vec![1, 1, 2])) as ArrayRef,
+            timestamp_array(vec![100, 101, 100]),
+            string_array(vec![Some("host1"), Some("host1"), Some("host2")]),
+            string_array(vec![Some("region1"), Some("region1"), Some("region2")]),
+            int32_array(vec![Some(1), Some(2), Some(11)]),
+            string_array(vec![Some("v1"), Some("v2"), Some("v3")]),
+        ];
+        let input_record_batch =
+            ArrowRecordBatch::try_new(schema.to_arrow_schema_ref(), columns).unwrap();
+
+        for format in [StorageFormat::Columnar, StorageFormat::Hybrid] {
+            let storage_format_opts = StorageFormatOptions::for_schema(&schema, format);
+            let meta_data = SstMetaData {
+                min_key: Bytes::from_static(b"100"),
+                max_key: Bytes::from_static(b"200"),
+                time_range: TimeRange::new_unchecked(Timestamp::new(100), Timestamp::new(102)),
+                max_sequence: 200,
+                schema: schema.clone(),
+                schema_fingerprint: schema.fingerprint(),
+                size: 10,
+                row_num: 3,
+                storage_format_opts: storage_format_opts.clone(),
+                bloom_filter: Default::default(),
+                row_group_time_ranges: Vec::new(),
+            };
+            let mut encoder = ParquetEncoder::try_new(
+                ParquetEncoderOptions {
+                    num_rows_per_row_group: 100,
+                    ..Default::default()
+                },
+                meta_data,
+            )
+            .unwrap();
+            encoder
+                .encode_record_batch(vec![input_record_batch.clone()])
+                .unwrap();
+            let bytes = Bytes::from(encoder.close().unwrap());
+            let mut reader = ParquetRecordBatchReaderBuilder::try_new(bytes)
+                .unwrap()
+                .build()
+                .unwrap();
+            let record_batch = reader.next().unwrap().unwrap();
+            let stored_schema = record_batch.schema();
+
+            let decoder = ParquetDecoder::try_new(storage_format_opts).unwrap();
+            let output_schema = decoder.output_schema(&stored_schema);
+            match format {
+                StorageFormat::Columnar => assert_eq!(stored_schema, output_schema),
+                _ => assert_ne!(stored_schema, output_schema),
+            }
+            let decoded_record_batch = decoder.decode_record_batch(record_batch).unwrap();
+            assert_eq!(decoded_record_batch.schema(), output_schema);
+        }
+    }
+
+    #[test]
+    fn test_encode_timestamp_unit() {
+        let schema = build_schema();
+        let new_columns = |timestamps: ArrayRef| {
+            vec![
+                Arc::new(UInt64Array::from(vec![1, 1, 2])) as ArrayRef,
+                timestamps,
+                string_array(vec![Some("host1"), Some("host1"), Some("host2")]),
+                string_array(vec![Some("region1"), Some("region1"), Some("region2")]),
+                int32_array(vec![Some(1), Some(2), Some(3)]),
+                string_array(vec![Some("v1"), Some("v2"), Some("v3")]),
+            ]
+        };
+        let millis_record_batch = ArrowRecordBatch::try_new(
+            schema.to_arrow_schema_ref(),
+            new_columns(timestamp_array(vec![100, 101, 100])),
+        )
+        .unwrap();
+
+        let mut fields = schema.to_arrow_schema_ref().fields().clone();
+        let timestamp_field = fields[schema.timestamp_index()].clone();
+        fields[schema.timestamp_index()] = Field::new(
+            timestamp_field.name(),
+            DataType::Timestamp(TimeUnit::Microsecond, None),
+            timestamp_field.is_nullable(),
+        );
+        let micros_record_batch = ArrowRecordBatch::try_new(
+            Arc::new(ArrowSchema::new(fields)),
+            new_columns(Arc::new(TimestampMicrosecondArray::from(vec![
+                100_000, 101_000, 100_000,
+            ]))),
+        )
+        .unwrap();
+
+        for format in [
+            StorageFormat::Columnar,
+            StorageFormat::ColumnarDeltaTs,
+            StorageFormat::Hybrid,
+        ] {
+            let meta_data = SstMetaData {
+                min_key: Bytes::from_static(b"100"),
+                max_key: Bytes::from_static(b"200"),
+                time_range: TimeRange::new_unchecked(Timestamp::new(100), Timestamp::new(102)),
+                max_sequence: 200,
+                schema: schema.clone(),
+                schema_fingerprint: schema.fingerprint(),
+                size: 10,
+                row_num: 3,
+                storage_format_opts: StorageFormatOptions::new(format),
+                bloom_filter: Default::default(),
+                row_group_time_ranges: Vec::new(),
+            };
+            let new_encoder = |timestamp_unit| {
+                ParquetEncoder::try_new(
+                    ParquetEncoderOptions {
+                        num_rows_per_row_group: 100,
+                        timestamp_unit,
+                        ..Default::default()
+                    },
+                    meta_data.clone(),
+                )
+            };
+
+            // The timestamp unit is kept after the round trip.
+            for (timestamp_unit, record_batch) in [
+                (TimeUnit::Millisecond, &millis_record_batch),
+                (TimeUnit::Microsecond, &micros_record_batch),
+            ] {
+                let row_group_time_ranges =
+                    compute_row_group_time_ranges(&schema, &[vec![record_batch.clone()]]).unwrap();
+                assert_eq!(
+                    vec![TimeRange::new_unchecked(
+                        Timestamp::new(100),
+                        Timestamp::new(102)
+                    )],
+                    row_group_time_ranges
+                );
+
+                let mut encoder = new_encoder(timestamp_unit).unwrap();
+                encoder
+                    .encode_record_batch(vec![record_batch.clone()])
+                    .unwrap();
+                let bytes = Bytes::from(encoder.close().unwrap());
+                let parquet_meta_data = footer::parse_metadata(&bytes).unwrap();
+                let kv_meta = &parquet_meta_data
+                    .file_metadata()
+                    .key_value_metadata()
+                    .unwrap()[0];
+                let storage_format_opts =
+                    decode_sst_meta_data(kv_meta).unwrap().storage_format_opts;
+                let mut reader = ParquetRecordBatchReaderBuilder::try_new(bytes)
+                    .unwrap()
+                    .build()
+                    .unwrap();
+                let decoder = ParquetDecoder::try_new(storage_format_opts).unwrap();
+                let decoded_record_batch = decoder
+                    .decode_record_batch(reader.next().unwrap().unwrap())
+                    .unwrap();
+                assert_eq!(
+                    record_batch.column(schema.timestamp_index()),
+                    decoded_record_batch.column(schema.timestamp_index())
+                );
+            }
+
+            // Records in other units than the encoder are rejected rather than
+            // written without conversion.
+            let mut encoder = new_encoder(TimeUnit::Millisecond).unwrap();
+            let res = encoder.encode_record_batch(vec![micros_record_batch.clone()]);
+            assert!(matches!(res, Err(Error::TimestampTypeMismatch { .. })));
+
+            let res = new_encoder(TimeUnit::Second);
+            assert!(matches!(res, Err(Error::UnsupportedTimestampType { .. })));
+        }
+    }
+
+    #[test]
+    fn test_hybrid_flush() {
+        let schema = build_schema();
+        let storage_format_opts = StorageFormatOptions::new(StorageFormat::Hybrid);
+
+        let meta_data = SstMetaData {
+            min_key: Bytes::from_static(b"100"),
+            max_key: Bytes::from_static(b"200"),
+            time_range: TimeRange::new_unchecked(Timestamp::new(100), Timestamp::new(101)),
+            max_sequence: 200,
+            schema: schema.clone(),
+            schema_fingerprint: schema.fingerprint(),
+            size: 10,
+            row_num: 4,
+            storage_format_opts,
+            bloom_filter: Default::default(),
+            row_group_time_ranges: Vec::new(),
+        };
+        let mut encoder = HybridRecordEncoder::try_new(
+            10,
+            Compression::ZSTD,
+            EnabledStatistics::Page,
+            false,
+            &HashMap::new(),
+            true,
+            meta_data,
+        )
+        .unwrap();
+
+        let columns = vec![
+            Arc::new(UInt64Array::from(vec![1, 1, 2])) as ArrayRef,
+            timestamp_array(vec![100, 101, 100]),
+            string_array(vec![Some("host1"), Some("host1"), Some("host2")]),
+            string_array(vec![Some("region1"), Some("region1"), Some("region2")]),
+            int32_array(vec![Some(1), Some(2), Some(11)]),
+            string_array(vec![
+                Some("string_value1"),
+                Some("string_value2"),
+                Some("string_value3"),
+            ]),
+        ];
+
+        let columns2 = vec![
+            Arc::new(UInt64Array::from(vec![1, 2, 1, 2])) as ArrayRef,
+            timestamp_array(vec![100, 101, 100, 101]),
+            string_array(vec![
+                Some("host1"),
+                Some("host2"),
+                Some("host1"),
+                Some("host2"),
+            ]),
+            string_array(vec![
+                Some("region1"),
+                Some("region2"),
+                Some("region1"),
+                Some("region2"),
+            ]),
+            int32_array(vec![Some(1), Some(2), Some(11), Some(12)]),
+            string_array(vec![
+                Some("string_value1"),
+                Some("string_value2"),
+                Some("string_value3"),
+                Some("string_value4"),
+            ]),
+        ];
+
+        let columns3 = vec![
+            Arc::new(UInt64Array::from(vec![1, 2, 3, 4, 5, 6, 7, 8])) as ArrayRef,
+            timestamp_array(vec![100, 101, 100, 100, 101, 100, 102, 103]),
+            string_array(vec![
+                Some("host1"),
+                Some("host1"),
+                Some("host2"),
+                Some("host3"),
+                Some("host4"),
+                Some("host2"),
+                Some("host3"),
+                Some("host4"),
+            ]),
+            string_array(vec![
+                Some("region1"),
+                Some("region1"),
+                Some("region2"),
+                Some("region3"),
+                Some("region1"),
+                Some("region1"),
+                Some("region2"),
+                Some("region3"),
+            ]),
+            int32_array(vec![
+                Some(1),
+                Some(2),
+                Some(11),
+                Some(12),
+                Some(1),
+                Some(2),
+                Some(11),
+                Some(12),
+            ]),
+            string_array(vec![
+                Some("string_value1"),
+                Some("string_value2"),
+                Some("string_value3"),
+                Some("string_value4"),
+                Some("string_value1"),
+                Some("string_value2"),
+                Some("string_value3"),
+                Some("string_value4"),
+            ]),
+        ];
+
+        let input_record_batch =
+            ArrowRecordBatch::try_new(schema.to_arrow_schema_ref(), columns).unwrap();
+        let input_record_batch2 =
+            ArrowRecordBatch::try_new(schema.to_arrow_schema_ref(), columns2).unwrap();
+        let row_nums = encoder
+            .encode(vec![input_record_batch, input_record_batch2])
+            .unwrap();
+        assert_eq!(2, row_nums);
+
+        let input_record_batch3 =
+            ArrowRecordBatch::try_new(schema.to_arrow_schema_ref(), columns3).unwrap();
+        let row_nums2 = encoder.encode(vec![input_record_batch3]).unwrap();
+        assert_eq!(8, row_nums2);
+
+        let sst = encoder.close().unwrap();
+        let bytes = Bytes::from(sst);
+        let parquet_metadata = footer::parse_metadata(&bytes).unwrap();
+        assert_eq!(2, parquet_metadata.num_row_groups());
+    }
+
+    #[test]
+    fn test_hybrid_decode_row_group_layouts() {
+        // Collect the (tsid, timestamp, value) of the rows in the `batch`.
+        fn collect_rows(batch: &ArrowRecordBatch, rows: &mut Vec<(u64, i64, Option<i32>)>) {
+            let tsids = batch.column(0).as_any().downcast_ref::<UInt64Array>();
+            let timestamps = batch
+                .column(1)
+                .as_any()
+                .downcast_ref::<TimestampMillisecondArray>();
+            let values = batch.column(4).as_any().downcast_ref::<Int32Array>();
+            let (tsids, timestamps, values) =
+                (tsids.unwrap(), timestamps.unwrap(), values.unwrap());
+            for i in 0..batch.num_rows() {
+                rows.push((
+                    tsids.value(i),
+                    timestamps.value(i),
+                    values.is_valid(i).then(|| values.value(i)),
+                ));
+            }
+        }
+
+        let schema = build_schema();
+        let meta_data = SstMetaData {
+            min_key: Bytes::from_static(b"100"),
+            max_key: Bytes::from_static(b"200"),
+            time_range: TimeRange::new_unchecked(Timestamp::new(100), Timestamp::new(103)),
+            max_sequence: 200,
+            schema: schema.clone(),
+            schema_fingerprint: schema.fingerprint(),
+            size: 10,
+            row_num: 9,
+            storage_format_opts: StorageFormatOptions::new(StorageFormat::Hybrid),
+            bloom_filter: Default::default(),
+            row_group_time_ranges: Vec::new(),
+        };
+        let new_batch = |tsids: Vec<u64>, timestamps: Vec<i64>, values: Vec<Option<i32>>| {
+            let hosts: Vec<_> = tsids.iter().map(|tsid| format!("host{}", tsid)).collect();
+            let columns = vec![
+                Arc::new(UInt64Array::from(tsids)) as ArrayRef,
+                timestamp_array(timestamps),
+                string_array(hosts.iter().map(|v| Some(v.as_str())).collect()),
+                string_array(vec![Some("region1"); hosts.len()]),
+                int32_array(values),
+                string_array(vec![Some("string_value"); hosts.len()]),
+            ];
+            ArrowRecordBatch::try_new(schema.to_arrow_schema_ref(), columns).unwrap()
+        };
+        let batches = vec![
+            new_batch(
+                vec![1, 1, 2],
+                vec![100, 101, 100],
+                vec![Some(1), Some(2), Some(3)],
+            ),
+            new_batch(vec![1, 2], vec![102, 101], vec![Some(4), Some(9)]),
+            new_batch(
+                vec![2, 3, 3, 4],
+                vec![102, 100, 103, 101],
+                vec![Some(5), Some(6), Some(7), Some(8)],
+            ),
+        ];
+
+        // Returns the number of row groups and the sorted decoded rows.
+        let encode_and_decode = |encode_inputs: Vec<Vec<ArrowRecordBatch>>| {
+            let mut encoder = HybridRecordEncoder::try_new(
+                10,
+                Compression::ZSTD,
+                EnabledStatistics::Page,
+                false,
+                &HashMap::new(),
+                true,
+                meta_data.clone(),
+            )
+            .unwrap();
+            for input in encode_inputs {
+                encoder.encode(input).unwrap();
+            }
+            let bytes = Bytes::from(encoder.close().unwrap());
+            let num_row_groups = footer::parse_metadata(&bytes).unwrap().num_row_groups();
+
+            let storage_format_opts =
+                StorageFormatOptions::for_schema(&schema, StorageFormat::Hybrid);
+            let decoder = ParquetDecoder::try_new(storage_format_opts).unwrap();
+            let reader = ParquetRecordBatchReaderBuilder::try_new(bytes)
+                .unwrap()
+                .with_batch_size(3)
+                .build()
+                .unwrap();
+            let mut rows = Vec::new();
+            for hybrid_record_batch in reader {
+                let record_batch = decoder
+                    .decode_record_batch(hybrid_record_batch.unwrap())
+                    .unwrap();
+                collect_rows(&record_batch, &mut rows);
+            }
+            rows.sort_unstable();
+
+            (num_row_groups, rows)
+        };
+
+        // The layout of flushing a row group per batch.
+        let (num_row_groups, many_small_rows) =
+            encode_and_decode(batches.iter().map(|v| vec![v.clone()]).collect());
+        assert_eq!(3, num_row_groups);
+        // All the batches are coalesced into one row group.
+        let (num_row_groups, few_large_rows) = encode_and_decode(vec![batches.clone()]);
+        assert_eq!(1, num_row_groups);
+
+        let mut expect_rows = Vec::new();
+        for batch in &batches {
+            collect_rows(batch, &mut expect_rows);
+        }
+        expect_rows.sort_unstable();
+        assert_eq!(expect_rows, many_small_rows);
+        assert_eq!(expect_rows, few_large_rows);
+    }
+
+    #[test]
+    fn test_hybrid_decode_nested_list() {
+        let list =
+            ListArray::from_iter_primitive::<Int32Type, _, _>(vec![Some(vec![Some(1), Some(2)])]);
+        let inner_list = ListArray::from_iter_primitive::<Int32Type, _, _>(vec![
+            Some(vec![Some(1)]),
+            Some(vec![Some(2)]),
+        ]);
+        let nested_type = DataType::List(Box::new(Field::new(
+            "item",
+            inner_list.data_type().clone(),
+            true,
+        )));
+        let nested_list_data = ArrayData::builder(nested_type)
+            .len(1)
+            .add_buffer(Buffer::from_slice_ref(&[0i32, 2]))
+            .add_child_data(inner_list.data().clone())
+            .build()
+            .unwrap();
+        let nested_list = ListArray::from(nested_list_data);
+
+        let arrow_schema = ArrowSchema::new(vec![
+            Field::new("value", list.data_type().clone(), true),
+            Field::new("nested_value", nested_list.data_type().clone(), true),
+        ]);
+        let hybrid_record_batch = ArrowRecordBatch::try_new(
+            Arc::new(arrow_schema),
+            vec![
+                Arc::new(list) as ArrayRef,
+                Arc::new(nested_list) as ArrayRef,
+            ],
+        )
+        .unwrap();
+
+        let decoder = ParquetDecoder::try_new(StorageFormatOptions {
+            format: StorageFormat::Hybrid,
+            collapsible_cols_idx: vec![0],
+        })
+        .unwrap();
+        let res = decoder.decode_record_batch(hybrid_record_batch);
+        assert!(matches!(res, Err(Error::DecodeRecordBatch { .. })));
+        assert!(res
+            .unwrap_err()
+            .to_string()
+            .contains("nested list is not supported"));
+    }
+
+    #[test]
+    fn test_check_row_num() {
+        let schema = build_schema();
+        let num_rows: usize = 4;
+        let columns = vec![
+            // Only 2 rows are left after collapsed in hybrid format.
+            Arc::new(UInt64Array::from(vec![1, 1, 2, 2])) as ArrayRef,
+            timestamp_array(vec![100, 101, 100, 101]),
+            string_array(vec![
+                Some("host1"),
+                Some("host1"),
+                Some("host2"),
+                Some("host2"),
+            ]),
+            string_array(vec![
+                Some("region1"),
+                Some("region1"),
+                Some("region2"),
+                Some("region2"),
+            ]),
+            int32_array(vec![Some(1), Some(2), Some(11), Some(12)]),
+            string_array(vec![
+                Some("string_value1"),
+                Some("string_value2"),
+                Some("string_value3"),
+                Some("string_value4"),
+            ]),
+        ];
+        let input_record_batch =
+            ArrowRecordBatch::try_new(schema.to_arrow_schema_ref(), columns).unwrap();
+
+        for format in [StorageFormat::Columnar, StorageFormat::Hybrid] {
+            for row_num in [num_rows as u64, num_rows as u64 + 10] {
+                let meta_data = SstMetaData {
+                    min_key: Bytes::from_static(b"100"),
+                    max_key: Bytes::from_static(b"200"),
+                    time_range: TimeRange::new_unchecked(Timestamp::new(100), Timestamp::new(102)),
+                    max_sequence: 200,
+                    schema: schema.clone(),
+                    schema_fingerprint: schema.fingerprint(),
+                    size: 10,
+                    row_num,
+                    storage_format_opts: StorageFormatOptions::new(format),
+                    bloom_filter: Default::default(),
+                    row_group_time_ranges: Vec::new(),
+                };
+                let mut encoder = ParquetEncoder::try_new(
+                    ParquetEncoderOptions {
+                        num_rows_per_row_group: 100,
+                        ..Default::default()
+                    },
+                    meta_data,
+                )
+                .unwrap();
+                encoder
+                    .encode_record_batch(vec![input_record_batch.clone()])
+                    .unwrap();
+
+                let res = encoder.check_row_num();
+                if row_num == num_rows as u64 {
+                    assert!(res.is_ok(), "format:{:?}", format);
+                } else {
+                    match res {
+                        Err(Error::RowNumMismatch {
+                            row_num: actual_row_num,
+                            encoded_rows,
+                            ..
+                        }) => {
+                            assert_eq!(row_num, actual_row_num);
+                            assert_eq!(num_rows as u64, encoded_rows);
+                        }
+                        _ => panic!("row num mismatch should be reported, format:{:?}", format),
+                    }
+                }
+                // The mismatch doesn't fail the encoding.
+                assert!(encoder.close().is_ok());
+            }
+        }
+    }
+
+    #[test]
+    fn test_encode_batches_with_compatible_schemas() {
+        let schema = build_schema();
+        let arrow_schema = schema.to_arrow_schema_ref();
+        let build_columns = |value_column: ArrayRef| {
+            vec![
+                Arc::new(UInt64Array::from(vec![1, 2])) as ArrayRef,
+                timestamp_array(vec![100, 101]),
+                string_array(vec![Some("host1"), Some("host2")]),
+                string_array(vec![Some("region1"), Some("region2")]),
+                value_column,
+                string_array(vec![Some("v1"), Some("v2")]),
+            ]
+        };
+        let batch = ArrowRecordBatch::try_new(
+            arrow_schema.clone(),
+            build_columns(int32_array(vec![Some(1), Some(2)])),
+        )
+        .unwrap();
+
+        // All the columns are nullable and the value column is int64 in this batch.
+        let fields = arrow_schema
+            .fields()
+            .iter()
+            .map(|field| {
+                let data_type = if field.name() == "value" {
+                    DataType::Int64
+                } else {
+                    field.data_type().clone()
+                };
+                Field::new(field.name(), data_type, true)
+            })
+            .collect();
+        let compatible_batch = ArrowRecordBatch::try_new(
+            Arc::new(ArrowSchema::new(fields)),
+            build_columns(Arc::new(Int64Array::from(vec![Some(3), Some(4)]))),
+        )
+        .unwrap();
+        assert_ne!(arrow_schema, compatible_batch.schema());
+
+        let meta_data = SstMetaData {
+            min_key: Bytes::from_static(b"100"),
+            max_key: Bytes::from_static(b"200"),
+            time_range: TimeRange::new_unchecked(Timestamp::new(100), Timestamp::new(102)),
+            max_sequence: 200,
+            schema: schema.clone(),
+            schema_fingerprint: schema.fingerprint(),
+            size: 10,
+            row_num: 4,
+            storage_format_opts: StorageFormatOptions::new(StorageFormat::Columnar),
+            bloom_filter: Default::default(),
+            row_group_time_ranges: Vec::new(),
+        };
+        let mut encoder = ParquetEncoder::try_new(
+            ParquetEncoderOptions {
+                num_rows_per_row_group: 100,
+                ..Default::default()
+            },
+            meta_data,
+        )
+        .unwrap();
+        let num_rows = encoder
+            .encode_record_batch(vec![batch, compatible_batch])
+            .unwrap();
+        assert_eq!(4, num_rows);
+        let bytes = Bytes::from(encoder.close().unwrap());
+
+        let mut reader = ParquetRecordBatchReaderBuilder::try_new(bytes)
+            .unwrap()
+            .build()
+            .unwrap();
+        let record_batch = reader.next().unwrap().unwrap();
+        assert_eq!(
+            &int32_array(vec![Some(1), Some(2), Some(3), Some(4)]),
+            record_batch.column(4)
+        );
+
+        // The batch with mismatched columns is rejected.
+        let batch =
+            ArrowRecordBatch::try_from_iter(vec![("a", int32_array(vec![Some(1)]))]).unwrap();
+        assert!(matches!(
+            align_record_batch(&arrow_schema, batch),
+            Err(Error::IncompatibleRecordBatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_encode_metrics_by_storage_format() {
+        let schema = build_schema();
+        let columns = vec![
+            Arc::new(UInt64Array::from(vec![1, 1, 2])) as ArrayRef,
+            timestamp_array(vec![100, 101, 100]),
+            string_array(vec![Some("host1"), Some("host1"), Some("host2")]),
+            string_array(vec![Some("region1"), Some("region1"), Some("region2")]),
+            int32_array(vec![Some(1), Some(2), Some(11)]),
+            string_array(vec![Some("v1"), Some("v2"), Some("v3")]),
+        ];
+        let input_record_batch =
+            ArrowRecordBatch::try_new(schema.to_arrow_schema_ref(), columns).unwrap();
+
+        // Use standalone counters to avoid being affected by other encoders.
+        let rows_counter = IntCounterVec::new(
+            prometheus::Opts::new("test_sst_encode_rows", "test"),
+            &["storage_format"],
+        )
+        .unwrap();
+        let bytes_counter = IntCounterVec::new(
+            prometheus::Opts::new("test_sst_encode_bytes", "test"),
+            &["storage_format"],
+        )
+        .unwrap();
+        let counter_values = |format: StorageFormat| {
+            let label = format.to_string();
+            (
+                rows_counter.with_label_values(&[&label]).get(),
+                bytes_counter.with_label_values(&[&label]).get(),
+            )
+        };
+
+        let mut encoded_bytes = Vec::new();
+        for format in [StorageFormat::Columnar, StorageFormat::Hybrid] {
+            let meta_data = SstMetaData {
+                min_key: Bytes::from_static(b"100"),
+                max_key: Bytes::from_static(b"200"),
+                time_range: TimeRange::new_unchecked(Timestamp::new(100), Timestamp::new(102)),
+                max_sequence: 200,
+                schema: schema.clone(),
+                schema_fingerprint: schema.fingerprint(),
+                size: 10,
+                row_num: 3,
+                storage_format_opts: StorageFormatOptions::new(format),
+                bloom_filter: Default::default(),
+                row_group_time_ranges: Vec::new(),
+            };
+            let mut encoder = ParquetEncoder::try_new(
+                ParquetEncoderOptions {
+                    num_rows_per_row_group: 100,
+                    ..Default::default()
+                },
+                meta_data,
+            )
+            .unwrap();
+            encoder.encode_rows_counter = rows_counter.clone();
+            encoder.encode_bytes_counter = bytes_counter.clone();
+            encoder
+                .encode_record_batch(vec![input_record_batch.clone()])
+                .unwrap();
+            encoded_bytes.push(encoder.close().unwrap().len() as u64);
+        }
+
+        // The counters of each format only count the sst encoded in it.
+        assert_eq!(
+            (3, encoded_bytes[0]),
+            counter_values(StorageFormat::Columnar)
+        );
+        assert_eq!((3, encoded_bytes[1]), counter_values(StorageFormat::Hybrid));
+        assert_eq!((0, 0), counter_values(StorageFormat::ColumnarDeltaTs));
+    }
+
+    #[test]
+    fn test_get_array_offsets() {
+        let offsets = [0i32, 2, 5];
+        let bytes = offsets
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            offsets.to_vec(),
+            HybridRecordDecoder::get_array_offsets(&bytes).unwrap()
+        );
+
+        // The length of the truncated buffer is not a multiple of the offset size.
+        let res = HybridRecordDecoder::get_array_offsets(&bytes[..bytes.len() - 1]);
+        assert!(matches!(res, Err(Error::DecodeRecordBatch { .. })));
+        assert!(res
+            .unwrap_err()
+            .to_string()
+            .contains("misaligned offsets buffer"));
+    }
+
+    #[test]
+    fn test_compress_large_meta_data() {
+        let mut builder = Builder::new()
+            .auto_increment_column_id(true)
+            .add_key_column(
+                column_schema::Builder::new("timestamp".to_string(), DatumKind::Timestamp)
+                    .build()
+                    .unwrap(),
+            )
+            .unwrap();
+        for i in 0..1000 {
+            builder = builder
+                .add_normal_column(
+                    column_schema::Builder::new(
+                        format!("wide_table_field_column_{}", i),
+                        DatumKind::Double,
+                    )
+                    .build()
+                    .unwrap(),
+                )
+                .unwrap();
+        }
+        let schema = builder.build().unwrap();
+        let meta_data = SstMetaData {
+            min_key: Bytes::from_static(b"a"),
+            max_key: Bytes::from_static(b"z"),
+            time_range: TimeRange::new_unchecked(Timestamp::new(100), Timestamp::new(101)),
+            max_sequence: 200,
+            schema_fingerprint: schema.fingerprint(),
+            schema,
+            size: 10,
+            row_num: 2,
+            storage_format_opts: Default::default(),
+            bloom_filter: Default::default(),
+            row_group_time_ranges: Vec::new(),
+        };
+
+        let compressed = encode_sst_meta_data(meta_data.clone()).unwrap();
+        let uncompressed =
+            encode_sst_meta_data_with_threshold(meta_data.clone(), usize::MAX).unwrap();
+        let header_of = |kv: &KeyValue| base64::decode(kv.value.as_ref().unwrap()).unwrap()[0];
+        assert_eq!(COMPRESSED_META_VALUE_HEADER, header_of(&compressed));
+        assert_eq!(META_VALUE_HEADER, header_of(&uncompressed));
+        assert!(
+            compressed.value.as_ref().unwrap().len() < uncompressed.value.as_ref().unwrap().len()
+        );
+
+        assert_eq!(meta_data, decode_sst_meta_data(&compressed).unwrap());
+        assert_eq!(meta_data, decode_sst_meta_data(&uncompressed).unwrap());
+
+        let raw_bytes = encode_sst_meta_data_raw(meta_data.clone()).unwrap();
+        assert_eq!(COMPRESSED_META_VALUE_HEADER, raw_bytes[0]);
+        assert_eq!(meta_data, decode_sst_meta_data_raw(&raw_bytes).unwrap());
+    }
+
+    #[test]
+    fn test_encode_and_decode_long_keys() {
+        let schema = build_schema();
+        // Keys longer than a parquet page and the compression threshold.
+        for key_len in [8 * 1024, 2 * META_COMPRESSION_THRESHOLD] {
+            let min_key: Vec<u8> = (0..key_len).map(|i| (i % 251) as u8).collect();
+            let mut max_key = min_key.clone();
+            *max_key.last_mut().unwrap() = u8::MAX;
+            let meta_data = SstMetaData {
+                min_key: Bytes::from(min_key),
+                max_key: Bytes::from(max_key),
+                time_range: TimeRange::new_unchecked(Timestamp::new(100), Timestamp::new(101)),
+                max_sequence: 200,
+                schema_fingerprint: schema.fingerprint(),
+                schema: schema.clone(),
+                size: 10,
+                row_num: 0,
+                storage_format_opts: Default::default(),
+                bloom_filter: Default::default(),
+                row_group_time_ranges: Vec::new(),
+            };
+
+            let kv = encode_sst_meta_data(meta_data.clone()).unwrap();
+            let decoded = decode_sst_meta_data(&kv).unwrap();
+            assert_eq!(meta_data.min_key, decoded.min_key);
+            assert_eq!(meta_data.max_key, decoded.max_key);
+            assert_eq!(meta_data, decoded);
+
+            // The keys are also kept as is in the key value meta data of the sst.
+            let encoder = ParquetEncoder::try_new(
+                ParquetEncoderOptions {
+                    num_rows_per_row_group: 100,
+                    ..Default::default()
+                },
+                meta_data.clone(),
+            )
+            .unwrap();
+            let bytes = encoder.close().unwrap();
+            let parquet_metadata = decode_parquet_metadata(&bytes).unwrap();
+            let kv = &parquet_metadata
+                .file_metadata()
+                .key_value_metadata()
+                .unwrap()[0];
+            assert_eq!(meta_data, decode_sst_meta_data(kv).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_encode_and_decode_raw_meta_data() {
+        let schema = build_schema();
+        let meta_data = SstMetaData {
+            min_key: Bytes::from_static(b"a"),
+            max_key: Bytes::from_static(b"z"),
+            time_range: TimeRange::new_unchecked(Timestamp::new(100), Timestamp::new(101)),
+            max_sequence: 200,
+            schema_fingerprint: schema.fingerprint(),
+            schema,
+            size: 10,
+            row_num: 2,
+            storage_format_opts: StorageFormatOptions::new(StorageFormat::Hybrid),
+            bloom_filter: Default::default(),
+            row_group_time_ranges: Vec::new(),
+        };
+
+        let raw_bytes = encode_sst_meta_data_raw(meta_data.clone()).unwrap();
+        assert_eq!(META_VALUE_HEADER, raw_bytes[0]);
+        assert_eq!(meta_data, decode_sst_meta_data_raw(&raw_bytes).unwrap());
+
+        // The raw bytes are the same as the base64 decoded value of the key value.
+        let kv = encode_sst_meta_data(meta_data).unwrap();
+        assert_eq!(raw_bytes, base64::decode(kv.value.unwrap()).unwrap());
+
+        assert!(decode_sst_meta_data_raw(&[]).is_err());
+        let mut invalid_header = raw_bytes;
+        invalid_header[0] = 255;
+        assert!(matches!(
+            decode_sst_meta_data_raw(&invalid_header),
+            Err(Error::InvalidMetaValueHeader { .. })
+        ));
+    }
+
+    #[test]
+    fn test_decode_unknown_storage_format() {
+        let schema = build_schema();
+        let meta_data = SstMetaData {
+            min_key: Bytes::from_static(b"a"),
+            max_key: Bytes::from_static(b"z"),
+            time_range: TimeRange::new_unchecked(Timestamp::new(100), Timestamp::new(101)),
+            max_sequence: 200,
+            schema_fingerprint: schema.fingerprint(),
+            schema,
+            size: 10,
+            row_num: 2,
+            storage_format_opts: StorageFormatOptions::new(StorageFormat::Columnar),
+            bloom_filter: Default::default(),
+            row_group_time_ranges: Vec::new(),
+        };
+
+        // Simulate the sst written with a storage format unknown to this build.
+        let mut meta_data_pb = SstMetaDataPb::from(meta_data);
+        meta_data_pb.storage_format_opts.as_mut().unwrap().format = 100;
+        let mut raw_bytes = vec![META_VALUE_HEADER];
+        raw_bytes.extend(meta_data_pb.encode_to_vec());
+
+        let meta_data = decode_sst_meta_data_raw(&raw_bytes).unwrap();
+        assert_eq!(StorageFormat::Unknown(100), meta_data.storage_format());
+        assert!(matches!(
+            ParquetDecoder::try_new(meta_data.storage_format_opts),
+            Err(Error::UnsupportedStorageFormat {
+                format: StorageFormat::Unknown(100),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_encode_summary() {
+        let schema = build_schema();
+        let meta_data = SstMetaData {
+            min_key: Bytes::from_static(b"100"),
+            max_key: Bytes::from_static(b"200"),
+            time_range: TimeRange::new_unchecked(Timestamp::new(100), Timestamp::new(101)),
+            max_sequence: 200,
+            schema: schema.clone(),
+            schema_fingerprint: schema.fingerprint(),
+            size: 10,
+            row_num: 4,
+            storage_format_opts: StorageFormatOptions::new(StorageFormat::Columnar),
+            bloom_filter: Default::default(),
+            row_group_time_ranges: Vec::new(),
+        };
+        let mut encoder = ParquetEncoder::try_new(
+            ParquetEncoderOptions {
+                num_rows_per_row_group: 2,
+                ..Default::default()
+            },
+            meta_data,
+        )
+        .unwrap();
+
+        let columns = vec![
+            Arc::new(UInt64Array::from(vec![1, 1, 2, 2])) as ArrayRef,
+            timestamp_array(vec![100, 101, 100, 101]),
+            string_array(vec![
+                Some("host1"),
+                Some("host1"),
+                Some("host2"),
+                Some("host2"),
+            ]),
+            string_array(vec![Some("region1"), Some("region1"), None, None]),
+            int32_array(vec![Some(1), None, Some(2), Some(3)]),
+            string_array(vec![Some("a"), None, Some("b"), Some("c")]),
+        ];
+        let batch = ArrowRecordBatch::try_new(schema.to_arrow_schema_ref(), columns).unwrap();
+        encoder.encode_record_batch(vec![batch]).unwrap();
+
+        let (bytes, summary) = encoder.close_with_summary().unwrap();
+        assert_eq!(4, summary.num_rows);
+        assert_eq!(2, summary.num_row_groups);
+        assert!(summary.compressed_size > 0);
+        assert!(summary.compressed_size < bytes.len() as i64);
+
+        let column_names: Vec<_> = summary.columns.iter().map(|c| c.name.as_str()).collect();
+        let expect_names: Vec<_> = schema.columns().iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(expect_names, column_names);
+
+        let compressed_size: i64 = summary.columns.iter().map(|c| c.compressed_size).sum();
+        let uncompressed_size: i64 = summary.columns.iter().map(|c| c.uncompressed_size).sum();
+        assert_eq!(summary.compressed_size, compressed_size);
+        assert_eq!(summary.uncompressed_size, uncompressed_size);
+    }
+
+    #[test]
+    fn test_encode_without_meta_data() {
+        let schema = build_schema();
+        let meta_data = SstMetaData {
+            min_key: Bytes::from_static(b"100"),
+            max_key: Bytes::from_static(b"200"),
+            time_range: TimeRange::new_unchecked(Timestamp::new(100), Timestamp::new(101)),
+            max_sequence: 200,
+            schema: schema.clone(),
+            schema_fingerprint: schema.fingerprint(),
+            size: 10,
+            row_num: 2,
+            storage_format_opts: StorageFormatOptions::new(StorageFormat::Columnar),
+            bloom_filter: Default::default(),
+            row_group_time_ranges: Vec::new(),
+        };
+        let mut encoder = ParquetEncoder::try_new(
+            ParquetEncoderOptions {
+                num_rows_per_row_group: 10,
+                write_meta_data: false,
+                ..Default::default()
+            },
+            meta_data.clone(),
+        )
+        .unwrap();
+
+        let columns = vec![
+            Arc::new(UInt64Array::from(vec![1, 2])) as ArrayRef,
+            timestamp_array(vec![100, 101]),
+            string_array(vec![Some("host1"), Some("host2")]),
+            string_array(vec![Some("region1"), None]),
+            int32_array(vec![Some(1), None]),
+            string_array(vec![Some("a"), Some("b")]),
+        ];
+        let batch = ArrowRecordBatch::try_new(schema.to_arrow_schema_ref(), columns).unwrap();
+        encoder.encode_record_batch(vec![batch.clone()]).unwrap();
+        let bytes = Bytes::from(encoder.close().unwrap());
+
+        // Only the arrow schema may be stored in the key value meta data.
+        let parquet_metadata = footer::parse_metadata(&bytes).unwrap();
+        let kv_metas = parquet_metadata.file_metadata().key_value_metadata();
+        assert!(kv_metas.into_iter().flatten().all(|kv| kv.key != META_KEY));
+
+        // The plain parquet file can be read by the standard parquet reader, and
+        // decoded with the storage format options supplied out of band.
+        let mut reader = ParquetRecordBatchReaderBuilder::try_new(bytes)
+            .unwrap()
+            .build()
+            .unwrap();
+        let record_batch = reader.next().unwrap().unwrap();
+        let decoder = ParquetDecoder::try_new(meta_data.storage_format_opts).unwrap();
+        let decoded = decoder.decode_record_batch(record_batch).unwrap();
+        assert_eq!(batch.columns(), decoded.columns());
+        assert!(reader.next().is_none());
     }
 }
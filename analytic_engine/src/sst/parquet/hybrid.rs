@@ -5,17 +5,18 @@ use std::{collections::BTreeMap, sync::Arc};
 use arrow::{
     array::{
         Array, ArrayData, ArrayDataBuilder, ArrayRef, BinaryArray, ListArray, StringArray,
-        UInt64Array,
+        UInt32Array, UInt64Array,
     },
     bitmap::Bitmap,
     buffer::{Buffer, MutableBuffer},
+    compute,
     datatypes::Schema as ArrowSchema,
     record_batch::RecordBatch as ArrowRecordBatch,
     util::bit_util,
 };
 use common_types::{
     datum::DatumKind,
-    schema::{ArrowSchemaRef, DataType, Field, Schema},
+    schema::{ArrowSchemaRef, DataType, Field},
 };
 use snafu::{Backtrace, ResultExt, Snafu};
 
@@ -87,6 +88,8 @@ impl ArrayHandle {
 #[derive(Debug)]
 struct TsidBatch {
     non_collapsible_col_values: Vec<String>,
+    /// Number of the rows of the tsid.
+    num_rows: usize,
     // record_batch_idx -> ArrayHandle
     // Store collapsible data in multi record batch.
     // Vec<ArrayHandle> contains multi columns data.
@@ -97,6 +100,7 @@ impl TsidBatch {
     fn new(non_collapsible_col_values: Vec<String>) -> Self {
         Self {
             non_collapsible_col_values,
+            num_rows: 0,
             collapsible_col_arrays: BTreeMap::new(),
         }
     }
@@ -113,15 +117,17 @@ struct IndexedArray {
     array: ArrayRef,
 }
 
-/// Convert collapsible columns to list type
-pub fn build_hybrid_arrow_schema(schema: &Schema) -> ArrowSchemaRef {
-    let arrow_schema = schema.to_arrow_schema_ref();
+/// Convert the collapsible columns in `collapsible_cols_idx` to list type
+pub fn build_hybrid_arrow_schema(
+    arrow_schema: &ArrowSchemaRef,
+    collapsible_cols_idx: &[u32],
+) -> ArrowSchemaRef {
     let new_fields = arrow_schema
         .fields()
         .iter()
         .enumerate()
         .map(|(idx, field)| {
-            if schema.is_collapsible_column(idx) {
+            if collapsible_cols_idx.contains(&(idx as u32)) {
                 let field_type = DataType::List(Box::new(Field::new(
                     LIST_ITEM_NAME,
                     field.data_type().clone(),
@@ -181,6 +187,9 @@ impl_offsets!(BinaryArrayWrapper<'a>);
 /// ArrayHandle into one ListArray
 struct ListArrayBuilder {
     datum_kind: DatumKind,
+    // Arrow type of the list item, which may differ from the default arrow type
+    // of the `datum_kind`, e.g. the unit of the timestamp.
+    item_type: DataType,
     // Vec<ArrayHandle> of row
     multi_row_arrays: Vec<Vec<ArrayHandle>>,
 }
@@ -189,10 +198,16 @@ impl ListArrayBuilder {
     fn new(datum_kind: DatumKind, multi_row_arrays: Vec<Vec<ArrayHandle>>) -> Self {
         Self {
             datum_kind,
+            item_type: datum_kind.to_arrow_data_type(),
             multi_row_arrays,
         }
     }
 
+    fn with_item_type(mut self, item_type: DataType) -> Self {
+        self.item_type = item_type;
+        self
+    }
+
     fn build_child_data(&self, offsets: &mut MutableBuffer) -> Result<ArrayData> {
         // Num of raw data in child data.
         let values_num = self
@@ -231,7 +246,7 @@ impl ListArrayBuilder {
             }
         }
 
-        let mut builder = ArrayData::builder(self.datum_kind.to_arrow_data_type())
+        let mut builder = ArrayData::builder(self.item_type.clone())
             .len(values_num)
             .null_bit_buffer(Some(null_buffer.into()));
 
@@ -329,7 +344,7 @@ impl ListArrayBuilder {
         &self,
         array_handle: &'a ArrayHandle,
     ) -> Result<Box<dyn VariableSizeArray + 'a>> {
-        match self.datum_kind.to_arrow_data_type() {
+        match &self.item_type {
             DataType::Utf8 => Ok(Box::new(StringArrayWrapper(
                 array_handle
                     .array
@@ -344,10 +359,12 @@ impl ListArrayBuilder {
                     .downcast_ref::<BinaryArray>()
                     .expect("downcast BinaryArray failed"),
             ))),
-            typ => VariableLengthType { type_name: typ }
-                .fail()
-                .map_err(|e| Box::new(e) as _)
-                .context(EncodeRecordBatch),
+            typ => VariableLengthType {
+                type_name: typ.clone(),
+            }
+            .fail()
+            .map_err(|e| Box::new(e) as _)
+            .context(EncodeRecordBatch),
         }
     }
 
@@ -424,11 +441,7 @@ impl ListArrayBuilder {
         let array_len = self.multi_row_arrays.len();
         let mut offsets = MutableBuffer::new(array_len * std::mem::size_of::<i32>());
         let child_data = self.build_child_data(&mut offsets)?;
-        let field = Box::new(Field::new(
-            LIST_ITEM_NAME,
-            self.datum_kind.to_arrow_data_type(),
-            true,
-        ));
+        let field = Box::new(Field::new(LIST_ITEM_NAME, self.item_type.clone(), true));
         let array_data = ArrayData::builder(DataType::List(field))
             .len(array_len)
             .add_buffer(offsets.into())
@@ -446,16 +459,67 @@ impl ListArrayBuilder {
     }
 }
 
+/// Sort values of all the collapsible `ListArray`s within each tsid group by
+/// the values of `list_arrays[sort_pos]`.
+///
+/// Note: all the `list_arrays` are built from the same tsid groups, so they
+/// share the same offsets.
+fn sort_list_arrays_within_group(
+    list_arrays: Vec<ListArray>,
+    sort_pos: usize,
+) -> Result<Vec<ListArray>> {
+    let sort_array = &list_arrays[sort_pos];
+    let value_offsets = sort_array.value_offsets();
+    let mut indices = Vec::with_capacity(sort_array.values().len());
+    for (group_idx, group_start) in value_offsets.iter().take(sort_array.len()).enumerate() {
+        let sorted_indices = compute::sort_to_indices(&sort_array.value(group_idx), None, None)
+            .map_err(|e| Box::new(e) as _)
+            .context(EncodeRecordBatch)?;
+        indices.extend(
+            sorted_indices
+                .values()
+                .iter()
+                .map(|idx| *group_start as u32 + *idx),
+        );
+    }
+    let indices = UInt32Array::from(indices);
+
+    list_arrays
+        .into_iter()
+        .map(|list_array| {
+            let sorted_values = compute::take(list_array.values().as_ref(), &indices, None)
+                .map_err(|e| Box::new(e) as _)
+                .context(EncodeRecordBatch)?;
+            let array_data = ArrayData::builder(list_array.data_type().clone())
+                .len(list_array.len())
+                .add_buffer(list_array.data().buffers()[0].clone())
+                .add_child_data(sorted_values.data().clone())
+                .build()
+                .map_err(|e| Box::new(e) as _)
+                .context(EncodeRecordBatch)?;
+
+            Ok(ListArray::from(array_data))
+        })
+        .collect()
+}
+
 /// Builds hybrid record by concat timestamp and non key columns into
 /// `ListArray`.
+///
+/// If `timestamp_idx` is given, values of collapsible columns will be sorted by
+/// timestamp within each tsid group.
 fn build_hybrid_record(
     arrow_schema: ArrowSchemaRef,
     tsid_type: &IndexedType,
     non_collapsible_col_types: &[IndexedType],
     collapsible_col_types: &[IndexedType],
+    timestamp_idx: Option<usize>,
     // tsid -> TsidBatch
-    batch_by_tsid: BTreeMap<u64, TsidBatch>,
+    mut batch_by_tsid: BTreeMap<u64, TsidBatch>,
 ) -> Result<ArrowRecordBatch> {
+    // Skip the tsids without any rows, e.g. all of their rows are filtered out,
+    // so no empty lists are built and the tsids are aligned with the lists.
+    batch_by_tsid.retain(|_, batch| batch.num_rows > 0);
     let tsid_array = UInt64Array::from_iter_values(batch_by_tsid.keys().cloned());
 
     // col_idx -> tsid -> data array
@@ -493,16 +557,34 @@ fn build_hybrid_record(
             array: Arc::new(StringArray::from(c)) as ArrayRef,
         })
         .collect::<Vec<_>>();
-    let collapsible_col_arrays = collapsible_col_arrays
+    let mut collapsible_list_arrays = collapsible_col_arrays
         .into_iter()
-        .zip(collapsible_col_types.iter().map(|n| (n.idx, n.data_type)))
-        .map(|(handle, (idx, datum_type))| {
-            Ok(IndexedArray {
-                idx,
-                array: Arc::new(ListArrayBuilder::new(datum_type, handle).build()?),
-            })
+        .zip(collapsible_col_types)
+        .map(|(handle, col_type)| {
+            let mut builder = ListArrayBuilder::new(col_type.data_type, handle);
+            if let DataType::List(item_field) = arrow_schema.field(col_type.idx).data_type() {
+                builder = builder.with_item_type(item_field.data_type().clone());
+            }
+            builder.build()
         })
         .collect::<Result<Vec<_>>>()?;
+    if let Some(timestamp_idx) = timestamp_idx {
+        if let Some(sort_pos) = collapsible_col_types
+            .iter()
+            .position(|col| col.idx == timestamp_idx)
+        {
+            collapsible_list_arrays =
+                sort_list_arrays_within_group(collapsible_list_arrays, sort_pos)?;
+        }
+    }
+    let collapsible_col_arrays = collapsible_list_arrays
+        .into_iter()
+        .zip(collapsible_col_types.iter().map(|n| n.idx))
+        .map(|(list_array, idx)| IndexedArray {
+            idx,
+            array: Arc::new(list_array) as ArrayRef,
+        })
+        .collect::<Vec<_>>();
 
     let all_columns = [
         vec![tsid_array],
@@ -523,10 +605,14 @@ fn build_hybrid_record(
 
 /// Converts arrow record batch into hybrid record format describe in
 /// `StorageFormat::Hybrid`
+///
+/// Rows of the same tsid are kept in input order unless `timestamp_idx` is
+/// given, in which case they are sorted by the timestamp column.
 pub fn convert_to_hybrid_record(
     tsid_type: &IndexedType,
     non_collapsible_col_types: &[IndexedType],
     collapsible_col_types: &[IndexedType],
+    timestamp_idx: Option<usize>,
     hybrid_arrow_schema: ArrowSchemaRef,
     arrow_record_batches: Vec<ArrowRecordBatch>,
 ) -> Result<ArrowRecordBatch> {
@@ -594,6 +680,7 @@ pub fn convert_to_hybrid_record(
             for handle in collapsible_col_arrays {
                 handle.append_slice_arg(SliceArg { offset, length });
             }
+            batch.num_rows += length;
         }
     }
     build_hybrid_record(
@@ -601,6 +688,7 @@ pub fn convert_to_hybrid_record(
         tsid_type,
         non_collapsible_col_types,
         collapsible_col_types,
+        timestamp_idx,
         batch_by_tsid,
     )
 }
@@ -617,7 +705,7 @@ mod tests {
     use arrow::{
         array::{TimestampMillisecondArray, UInt16Array},
         buffer::Buffer,
-        datatypes::{TimestampMillisecondType, UInt16Type},
+        datatypes::{TimeUnit, TimestampMillisecondType, UInt16Type},
     };
 
     use super::*;
@@ -647,6 +735,80 @@ mod tests {
         Arc::new(arr)
     }
 
+    #[test]
+    fn build_hybrid_record_without_empty_group() {
+        let arrow_schema = Arc::new(ArrowSchema::new(vec![
+            Field::new("tsid", DataType::UInt64, false),
+            Field::new("host", DataType::Utf8, true),
+            Field::new(
+                "timestamp",
+                DataType::List(Box::new(Field::new(
+                    LIST_ITEM_NAME,
+                    DataType::Timestamp(TimeUnit::Millisecond, None),
+                    true,
+                ))),
+                true,
+            ),
+        ]));
+        let timestamps = timestamp_array(100, 104);
+        let new_batch = |host: &str, slice_args: Vec<SliceArg>| {
+            let mut batch = TsidBatch::new(vec![host.to_string()]);
+            batch.num_rows = slice_args.iter().map(|arg| arg.length).sum();
+            if !slice_args.is_empty() {
+                batch.collapsible_col_arrays.insert(
+                    0,
+                    vec![ArrayHandle::with_slice_args(timestamps.clone(), slice_args)],
+                );
+            }
+            batch
+        };
+        // The rows of tsid 2 are all filtered out.
+        let batch_by_tsid = BTreeMap::from([
+            (1, new_batch("host1", vec![(0, 2).into()])),
+            (2, new_batch("host2", vec![])),
+            (3, new_batch("host3", vec![(2, 2).into()])),
+        ]);
+
+        let record_batch = build_hybrid_record(
+            arrow_schema,
+            &IndexedType {
+                idx: 0,
+                data_type: DatumKind::UInt64,
+            },
+            &[IndexedType {
+                idx: 1,
+                data_type: DatumKind::String,
+            }],
+            &[IndexedType {
+                idx: 2,
+                data_type: DatumKind::Timestamp,
+            }],
+            None,
+            batch_by_tsid,
+        )
+        .unwrap();
+
+        assert_eq!(2, record_batch.num_rows());
+        let expected_tsids: ArrayRef = Arc::new(UInt64Array::from(vec![1, 3]));
+        assert_eq!(&expected_tsids, record_batch.column(0));
+        assert_eq!(
+            &string_array(vec![Some("host1"), Some("host3")]),
+            record_batch.column(1)
+        );
+        let expected_timestamps =
+            ListArray::from_iter_primitive::<TimestampMillisecondType, _, _>(vec![
+                Some(vec![Some(100), Some(101)]),
+                Some(vec![Some(102), Some(103)]),
+            ]);
+        let timestamps = record_batch
+            .column(2)
+            .as_any()
+            .downcast_ref::<ListArray>()
+            .unwrap();
+        assert_eq!(&expected_timestamps, timestamps);
+        assert_eq!(&[0, 2, 4], timestamps.value_offsets());
+    }
+
     #[test]
     fn merge_timestamp_array_to_list() {
         let row0 = vec![ArrayHandle::with_slice_args(
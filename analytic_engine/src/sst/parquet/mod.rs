@@ -6,6 +6,6 @@ pub mod async_reader;
 pub mod builder;
 pub mod encoding;
 mod hybrid;
-pub(crate) mod row_group_filter;
+pub mod row_group_filter;
 
 pub use async_reader::{Reader as AsyncParquetReader, ThreadedReader};
@@ -8,14 +8,14 @@ use arrow::datatypes::SchemaRef;
 use common_types::datum::Datum;
 use datafusion::{prelude::Expr, scalar::ScalarValue};
 use ethbloom::{Bloom, Input};
+use log::warn;
 use parquet::file::metadata::RowGroupMetaData;
 use parquet_ext::prune::{
     equal::{self, ColumnPosition},
     min_max,
 };
-use snafu::ensure;
 
-use crate::sst::reader::error::{OtherNoCause, Result};
+use crate::sst::reader::error::Result;
 
 /// A filter to prune row groups according to the provided predicates.
 ///
@@ -35,11 +35,20 @@ impl<'a> RowGroupFilter<'a> {
         blooms: Option<&'a [Vec<Bloom>]>,
         predicates: &'a [Expr],
     ) -> Result<Self> {
-        if let Some(blooms) = blooms {
-            ensure!(blooms.len() == row_groups.len(), OtherNoCause {
-                msg: format!("expect the same number of bloom filter as the number of row groups, num_bloom_filters:{}, num_row_groups:{}", blooms.len(), row_groups.len()),
-            });
-        }
+        // The bloom filters are built for the row groups in the encoder's input, which
+        // may not match the row groups in the file, e.g. the small row groups are
+        // coalesced by the encoder, so only the min max filter is applied then.
+        let blooms = blooms.filter(|blooms| {
+            let matched = blooms.len() == row_groups.len();
+            if !matched {
+                warn!(
+                    "Bloom filter is ignored for the number of row groups mismatch, num_bloom_filters:{}, num_row_groups:{}",
+                    blooms.len(),
+                    row_groups.len()
+                );
+            }
+            matched
+        });
 
         Ok(Self {
             schema,
@@ -117,6 +126,8 @@ impl<'a> RowGroupFilter<'a> {
 
 #[cfg(test)]
 mod tests {
+    use std::sync::Arc;
+
     use super::*;
 
     #[test]
@@ -135,4 +146,13 @@ mod tests {
             assert_eq!(real_row_groups, expect_row_groups)
         }
     }
+
+    #[test]
+    fn test_ignore_mismatched_blooms() {
+        let schema: SchemaRef = Arc::new(arrow::datatypes::Schema::empty());
+        let blooms = vec![vec![Bloom::default()]];
+        let filter = RowGroupFilter::try_new(&schema, &[], Some(blooms.as_slice()), &[]).unwrap();
+        assert!(filter.blooms.is_none());
+        assert!(filter.filter().is_empty());
+    }
 }
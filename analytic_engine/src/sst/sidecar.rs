@@ -0,0 +1,93 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+//! Sidecar of the sst holding the summary of its meta data in json, which
+//! helps the external catalogs to index the ssts without parsing them.
+
+use object_store::Path;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::sst::file::SstMetaData;
+
+/// Suffix appended to the sst path to build the path of its sidecar.
+pub const STATS_SIDECAR_SUFFIX: &str = ".stats.json";
+
+/// Summary of the [SstMetaData] written into the sidecar.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SstStats {
+    /// Inclusive start of the time range in milliseconds.
+    pub time_range_start: i64,
+    /// Exclusive end of the time range in milliseconds.
+    pub time_range_end: i64,
+    /// Hex encoded min key.
+    pub min_key: String,
+    /// Hex encoded max key.
+    pub max_key: String,
+    pub row_num: u64,
+    /// File size of the sst in bytes.
+    pub size: u64,
+}
+
+impl SstStats {
+    /// Build the stats from the `meta_data` of the sst, whose `row_num` and
+    /// `size` are only known after the sst is written, so they are provided
+    /// separately.
+    pub fn new(meta_data: &SstMetaData, row_num: u64, size: u64) -> Self {
+        Self {
+            time_range_start: meta_data.time_range.inclusive_start().as_i64(),
+            time_range_end: meta_data.time_range.exclusive_end().as_i64(),
+            min_key: encode_hex(&meta_data.min_key),
+            max_key: encode_hex(&meta_data.max_key),
+            row_num,
+            size,
+        }
+    }
+}
+
+/// Path of the stats sidecar of the sst at `sst_path`.
+pub fn stats_sidecar_path(sst_path: &Path) -> Path {
+    Path::from(format!("{}{}", sst_path, STATS_SIDECAR_SUFFIX))
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use common_types::{
+        bytes::Bytes,
+        tests::build_schema,
+        time::{TimeRange, Timestamp},
+    };
+
+    use super::*;
+    use crate::sst::file::tests::SstMetaDataMocker;
+
+    #[test]
+    fn test_sst_stats() {
+        let mut meta_data = SstMetaDataMocker::new(build_schema())
+            .time_range(TimeRange::new_unchecked(
+                Timestamp::new(100),
+                Timestamp::new(200),
+            ))
+            .build();
+        meta_data.min_key = Bytes::from_static(b"\x00\x0fa");
+        meta_data.max_key = Bytes::from_static(b"\xffz");
+
+        let stats = SstStats::new(&meta_data, 10, 1024);
+        let expect = SstStats {
+            time_range_start: 100,
+            time_range_end: 200,
+            min_key: "000f61".to_string(),
+            max_key: "ff7a".to_string(),
+            row_num: 10,
+            size: 1024,
+        };
+        assert_eq!(expect, stats);
+
+        assert_eq!(
+            Path::from("1/2/3.sst.stats.json"),
+            stats_sidecar_path(&Path::from("1/2/3.sst"))
+        );
+    }
+}
@@ -7,6 +7,7 @@ use std::{
     convert::TryInto,
     fmt,
     fmt::Formatter,
+    ops::Bound,
     sync::{
         atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
         Arc, Mutex,
@@ -17,6 +18,8 @@ use std::{
 use arc_swap::ArcSwap;
 use arena::CollectorRef;
 use common_types::{
+    projected_schema::ProjectedSchema,
+    record_batch::RecordBatchWithKey,
     schema::{Schema, Version},
     table::{ClusterVersion, ShardId},
     time::{TimeRange, Timestamp},
@@ -34,6 +37,7 @@ use crate::{
     memtable::{
         factory::{FactoryRef as MemTableFactoryRef, Options as MemTableOptions},
         skiplist::factory::SkiplistMemTableFactory,
+        ScanContext, ScanRequest,
     },
     meta::meta_update::AddTableMeta,
     space::SpaceId,
@@ -70,6 +74,9 @@ pub enum Error {
     FindMemTable {
         source: crate::table::version::Error,
     },
+
+    #[snafu(display("Failed to scan memtable, err:{}", source))]
+    ScanMemTable { source: crate::memtable::Error },
 }
 
 define_result!(Error);
@@ -416,6 +423,46 @@ impl TableData {
         }
     }
 
+    /// Snapshot the rows in all the memtables (including the sampling one) of
+    /// this table without flushing them.
+    ///
+    /// The memtables are scanned one by one, so rows from different memtables
+    /// are not merged.
+    pub fn snapshot_memtables(&self) -> Result<Vec<RecordBatchWithKey>> {
+        let read_view = self.current_version.pick_read_view(TimeRange::min_to_max());
+        let memtables = read_view
+            .sampling_mem
+            .map(|sampling_mem| sampling_mem.mem)
+            .into_iter()
+            .chain(
+                read_view
+                    .memtables
+                    .into_iter()
+                    .map(|mem_state| mem_state.mem),
+            );
+        let projected_schema = ProjectedSchema::no_projection(self.schema());
+
+        let mut record_batches = Vec::new();
+        for memtable in memtables {
+            let scan_req = ScanRequest {
+                start_user_key: Bound::Unbounded,
+                end_user_key: Bound::Unbounded,
+                sequence: common_types::MAX_SEQUENCE_NUMBER,
+                projected_schema: projected_schema.clone(),
+                need_dedup: self.dedup(),
+                reverse: false,
+            };
+            let iter = memtable
+                .scan(ScanContext::default(), scan_req)
+                .context(ScanMemTable)?;
+            for record_batch in iter {
+                record_batches.push(record_batch.context(ScanMemTable)?);
+            }
+        }
+
+        Ok(record_batches)
+    }
+
     /// Returns true if the memory usage of this table reaches flush threshold
     ///
     /// REQUIRE: Do in write worker
@@ -613,7 +660,9 @@ pub mod tests {
 
     use arena::NoopCollector;
     use common_types::{
-        datum::DatumKind,
+        datum::{Datum, DatumKind},
+        row::Row,
+        schema::IndexInWriterSchema,
         table::{DEFAULT_CLUSTER_VERSION, DEFAULT_SHARD_ID},
     };
     use common_util::config::ReadableDuration;
@@ -622,7 +671,7 @@ pub mod tests {
     use super::*;
     use crate::{
         instance::write_worker::tests::WriteHandleMocker,
-        memtable::{factory::Factory, MemTableRef},
+        memtable::{factory::Factory, key::KeySequence, MemTableRef, PutContext},
         sst::file::tests::FilePurgerMocker,
         table_options,
         tests::table,
@@ -818,4 +867,54 @@ pub mod tests {
             TimeRange::bucket_of(now_ts, table_options::DEFAULT_SEGMENT_DURATION).unwrap();
         assert_eq!(time_range, mem_state.time_range);
     }
+
+    #[test]
+    fn test_snapshot_memtables() {
+        let mocked_write_handle = WriteHandleMocker::default()
+            .space_id(DEFAULT_SPACE_ID)
+            .build();
+        let table_data = TableDataMocker::default()
+            .write_handle(mocked_write_handle.write_handle)
+            .build();
+        let worker_local = mocked_write_handle.worker_local;
+        let schema = table_data.schema();
+        assert!(table_data.snapshot_memtables().unwrap().is_empty());
+
+        let rows = [(1000, 1.0), (1001, 2.0), (1002, 3.0)];
+        let mut ctx = PutContext::new(IndexInWriterSchema::for_same_schema(schema.num_columns()));
+        for (idx, (ts, value)) in rows.iter().enumerate() {
+            let timestamp = Timestamp::new(*ts);
+            let row = Row::from_datums(vec![Datum::Timestamp(timestamp), Datum::Double(*value)]);
+            let mutable = table_data
+                .find_or_create_mutable(&worker_local, timestamp, &schema)
+                .unwrap();
+            mutable
+                .put(
+                    &mut ctx,
+                    KeySequence::new(1, idx as u32),
+                    &row,
+                    &schema,
+                    timestamp,
+                )
+                .unwrap();
+        }
+
+        let record_batches = table_data.snapshot_memtables().unwrap();
+        let snapshot_rows = record_batches
+            .iter()
+            .flat_map(|record_batch| {
+                (0..record_batch.num_rows()).map(|row_idx| {
+                    (
+                        record_batch.column(0).datum(row_idx),
+                        record_batch.column(1).datum(row_idx),
+                    )
+                })
+            })
+            .collect::<Vec<_>>();
+        let expected_rows = rows
+            .iter()
+            .map(|(ts, value)| (Datum::Timestamp(Timestamp::new(*ts)), Datum::Double(*value)))
+            .collect::<Vec<_>>();
+        assert_eq!(expected_rows, snapshot_rows);
+    }
 }
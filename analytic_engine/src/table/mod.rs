@@ -5,7 +5,7 @@
 use std::{collections::HashMap, fmt};
 
 use async_trait::async_trait;
-use common_types::{row::Row, schema::Schema, time::TimeRange};
+use common_types::{bytes::Bytes, row::Row, schema::Schema, time::TimeRange};
 use datafusion::logical_plan::{Column, Expr};
 use futures::TryStreamExt;
 use snafu::{ensure, OptionExt, ResultExt};
@@ -14,9 +14,10 @@ use table_engine::{
     predicate::PredicateBuilder,
     stream::{PartitionedStreams, SendableRecordBatchStream},
     table::{
-        AlterOptions, AlterSchema, AlterSchemaRequest, Compact, Flush, FlushRequest, Get,
-        GetInvalidPrimaryKey, GetNullPrimaryKey, GetRequest, ReadOptions, ReadOrder, ReadRequest,
-        Result, Scan, Table, TableId, TableStats, Write, WriteRequest,
+        AlterOptions, AlterSchema, AlterSchemaRequest, AlterSchemaVersionConflict, Compact,
+        CompactionSummary, Flush, FlushRequest, Get, GetInvalidPrimaryKey, GetNullPrimaryKey,
+        GetRequest, ImportSst, ReadOptions, ReadOrder, ReadRequest, Result, Scan, Table, TableId,
+        TableStats, Write, WriteRequest,
     },
 };
 use tokio::sync::oneshot;
@@ -24,6 +25,7 @@ use tokio::sync::oneshot;
 use self::data::TableDataRef;
 use crate::{
     instance::{
+        engine::Error as InstanceError,
         flush_compaction::{TableFlushOptions, TableFlushPolicy},
         Instance, InstanceRef,
     },
@@ -231,12 +233,26 @@ impl Table for TableImpl {
     }
 
     async fn alter_schema(&self, request: AlterSchemaRequest) -> Result<usize> {
-        self.instance
+        let res = self
+            .instance
             .alter_schema_of_table(&self.space_table, request)
-            .await
-            .map_err(|e| Box::new(e) as _)
-            .context(AlterSchema { table: self.name() })?;
-        Ok(0)
+            .await;
+        match res {
+            Ok(()) => Ok(0),
+            Err(InstanceError::InvalidPreVersion {
+                current_version,
+                pre_version,
+                ..
+            }) => AlterSchemaVersionConflict {
+                table: self.name(),
+                current_version,
+                pre_version,
+            }
+            .fail(),
+            Err(e) => Err(e)
+                .map_err(|e| Box::new(e) as _)
+                .context(AlterSchema { table: self.name() }),
+        }
     }
 
     async fn alter_options(&self, options: HashMap<String, String>) -> Result<usize> {
@@ -276,12 +292,20 @@ impl Table for TableImpl {
         Ok(())
     }
 
-    async fn compact(&self) -> Result<()> {
+    async fn compact(&self) -> Result<CompactionSummary> {
         self.instance
             .manual_compact_table(&self.space_table)
             .await
             .map_err(|e| Box::new(e) as _)
-            .context(Compact { table: self.name() })?;
+            .context(Compact { table: self.name() })
+    }
+
+    async fn import_sst(&self, sst: Bytes) -> Result<()> {
+        self.instance
+            .import_sst(&self.space_table, sst)
+            .await
+            .map_err(|e| Box::new(e) as _)
+            .context(ImportSst { table: self.name() })?;
         Ok(())
     }
 }
@@ -23,8 +23,8 @@ use table_engine::{
     },
     stream::{PartitionedStreams, SendableRecordBatchStream},
     table::{
-        AlterSchemaRequest, CreatePartitionRule, FlushRequest, GetRequest, LocatePartitions,
-        ReadRequest, Result, Scan, Table, TableId, TableStats, UnexpectedWithMsg,
+        AlterSchemaRequest, CompactionSummary, CreatePartitionRule, FlushRequest, GetRequest,
+        LocatePartitions, ReadRequest, Result, Scan, Table, TableId, TableStats, UnexpectedWithMsg,
         UnsupportedMethod, Write, WriteRequest,
     },
 };
@@ -262,7 +262,7 @@ impl Table for PartitionTableImpl {
         Ok(())
     }
 
-    async fn compact(&self) -> Result<()> {
-        Ok(())
+    async fn compact(&self) -> Result<CompactionSummary> {
+        Ok(CompactionSummary::default())
     }
 }
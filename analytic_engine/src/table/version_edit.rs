@@ -6,7 +6,7 @@ use std::convert::{TryFrom, TryInto};
 
 use common_types::{bytes::Bytes, schema::Schema, time::TimeRange, SequenceNumber};
 use common_util::define_result;
-use proto::{analytic_common as analytic_common_pb, common as common_pb, meta_update as meta_pb};
+use proto::{common as common_pb, meta_update as meta_pb};
 use snafu::{Backtrace, OptionExt, ResultExt, Snafu};
 
 use crate::{
@@ -15,7 +15,7 @@ use crate::{
         manager::FileId,
     },
     table::data::MemTableId,
-    table_options::StorageFormatOptions,
+    table_options::{StorageFormat, StorageFormatOptions},
 };
 
 #[derive(Debug, Snafu)]
@@ -64,8 +64,7 @@ impl From<AddFile> for meta_pb::AddFileMeta {
             schema: Some(common_pb::TableSchema::from(&v.file.meta.schema)),
             size: v.file.meta.size,
             row_num: v.file.meta.row_num,
-            storage_format: analytic_common_pb::StorageFormat::from(v.file.meta.storage_format())
-                as i32,
+            storage_format: i32::from(v.file.meta.storage_format()),
         }
     }
 }
@@ -74,7 +73,7 @@ impl TryFrom<meta_pb::AddFileMeta> for AddFile {
     type Error = Error;
 
     fn try_from(src: meta_pb::AddFileMeta) -> Result<Self> {
-        let storage_format = src.storage_format();
+        let storage_format = StorageFormat::from(src.storage_format);
         let time_range = {
             let time_range = src.time_range.context(TimeRangeNotFound)?;
             TimeRange::try_from(time_range).context(ConvertTimeRange)?
@@ -96,11 +95,13 @@ impl TryFrom<meta_pb::AddFileMeta> for AddFile {
                     max_key: Bytes::from(src.max_key),
                     time_range,
                     max_sequence: src.max_seq,
+                    schema_fingerprint: schema.fingerprint(),
                     schema,
                     size: src.size,
                     row_num: src.row_num,
-                    storage_format_opts: StorageFormatOptions::new(storage_format.into()),
+                    storage_format_opts: StorageFormatOptions::new(storage_format),
                     bloom_filter: Default::default(),
+                    row_group_time_ranges: Vec::new(),
                 },
             },
         };
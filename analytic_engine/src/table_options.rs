@@ -4,7 +4,7 @@
 
 use std::{collections::HashMap, string::ToString, time::Duration};
 
-use common_types::time::Timestamp;
+use common_types::{schema::Schema, time::Timestamp};
 use common_util::{
     config::{ReadableDuration, ReadableSize, TimeUnit},
     define_result,
@@ -30,6 +30,7 @@ pub const NUM_ROWS_PER_ROW_GROUP: &str = "num_rows_per_row_group";
 pub const UPDATE_MODE: &str = "update_mode";
 pub const COMPRESSION: &str = "compression";
 pub const STORAGE_FORMAT: &str = "storage_format";
+pub const HYBRID_UNCOLLAPSED_COLUMNS: &str = "hybrid_uncollapsed_columns";
 
 const UPDATE_MODE_OVERWRITE: &str = "OVERWRITE";
 const UPDATE_MODE_APPEND: &str = "APPEND";
@@ -39,6 +40,7 @@ const COMPRESSION_SNAPPY: &str = "SNAPPY";
 const COMPRESSION_ZSTD: &str = "ZSTD";
 const STORAGE_FORMAT_COLUMNAR: &str = "COLUMNAR";
 const STORAGE_FORMAT_HYBRID: &str = "HYBRID";
+const STORAGE_FORMAT_COLUMNAR_DELTA_TS: &str = "COLUMNAR_DELTA_TS";
 
 /// Default bucket duration (1d)
 const BUCKET_DURATION_1D: Duration = Duration::from_secs(24 * 60 * 60);
@@ -51,7 +53,7 @@ const DEFAULT_WRITE_BUFFER_SIZE: u32 = 32 * 1024 * 1024;
 /// Default ttl of table (7d).
 const DEFAULT_TTL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
 /// Default row number of a row group.
-const DEFAULT_NUM_ROW_PER_ROW_GROUP: usize = 8192;
+pub(crate) const DEFAULT_NUM_ROW_PER_ROW_GROUP: usize = 8192;
 
 /// Max arena block size (2G)
 const MAX_ARENA_BLOCK_SIZE: u32 = 2 * 1024 * 1024 * 1024;
@@ -239,22 +241,36 @@ pub enum StorageFormat {
     /// | ...       |                     |             |       |       |         |         |
     /// ```
     Hybrid,
+
+    /// Same as the [StorageFormat::Columnar] except that the timestamp column
+    /// is delta encoded, which is much more compact for the monotonically
+    /// increasing timestamps.
+    ColumnarDeltaTs,
+
+    /// Format written by a newer version and not understood by this build, the
+    /// raw value in the protobuf is kept.
+    #[serde(skip)]
+    Unknown(i32),
 }
 
-impl From<StorageFormat> for common_pb::StorageFormat {
+impl From<StorageFormat> for i32 {
     fn from(format: StorageFormat) -> Self {
         match format {
-            StorageFormat::Columnar => Self::Columnar,
-            StorageFormat::Hybrid => Self::Hybrid,
+            StorageFormat::Columnar => common_pb::StorageFormat::Columnar as i32,
+            StorageFormat::Hybrid => common_pb::StorageFormat::Hybrid as i32,
+            StorageFormat::ColumnarDeltaTs => common_pb::StorageFormat::ColumnarDeltaTs as i32,
+            StorageFormat::Unknown(v) => v,
         }
     }
 }
 
-impl From<common_pb::StorageFormat> for StorageFormat {
-    fn from(format: common_pb::StorageFormat) -> Self {
-        match format {
-            common_pb::StorageFormat::Columnar => Self::Columnar,
-            common_pb::StorageFormat::Hybrid => Self::Hybrid,
+impl From<i32> for StorageFormat {
+    fn from(v: i32) -> Self {
+        match common_pb::StorageFormat::from_i32(v) {
+            Some(common_pb::StorageFormat::Columnar) => Self::Columnar,
+            Some(common_pb::StorageFormat::Hybrid) => Self::Hybrid,
+            Some(common_pb::StorageFormat::ColumnarDeltaTs) => Self::ColumnarDeltaTs,
+            None => Self::Unknown(v),
         }
     }
 }
@@ -266,6 +282,7 @@ impl TryFrom<&str> for StorageFormat {
         let format = match value.to_uppercase().as_str() {
             STORAGE_FORMAT_COLUMNAR => Self::Columnar,
             STORAGE_FORMAT_HYBRID => Self::Hybrid,
+            STORAGE_FORMAT_COLUMNAR_DELTA_TS => Self::ColumnarDeltaTs,
             _ => return UnknownStorageFormat { value }.fail(),
         };
         Ok(format)
@@ -275,10 +292,11 @@ impl TryFrom<&str> for StorageFormat {
 impl ToString for StorageFormat {
     fn to_string(&self) -> String {
         match self {
-            Self::Columnar => STORAGE_FORMAT_COLUMNAR,
-            Self::Hybrid => STORAGE_FORMAT_HYBRID,
+            Self::Columnar => STORAGE_FORMAT_COLUMNAR.to_string(),
+            Self::Hybrid => STORAGE_FORMAT_HYBRID.to_string(),
+            Self::ColumnarDeltaTs => STORAGE_FORMAT_COLUMNAR_DELTA_TS.to_string(),
+            Self::Unknown(v) => format!("UNKNOWN({})", v),
         }
-        .to_string()
     }
 }
 
@@ -301,12 +319,30 @@ impl StorageFormatOptions {
             collapsible_cols_idx: Vec::new(),
         }
     }
+
+    /// Create the options of the `format` for the sst of the `schema`, and the
+    /// indexes of the collapsible columns are filled if the format is
+    /// [StorageFormat::Hybrid].
+    pub fn for_schema(schema: &Schema, format: StorageFormat) -> Self {
+        let collapsible_cols_idx = match format {
+            StorageFormat::Hybrid => (0..schema.num_columns())
+                .filter(|idx| schema.is_collapsible_column(*idx))
+                .map(|idx| idx as u32)
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        Self {
+            format,
+            collapsible_cols_idx,
+        }
+    }
 }
 
 impl From<StorageFormatOptions> for common_pb::StorageFormatOptions {
     fn from(v: StorageFormatOptions) -> Self {
         common_pb::StorageFormatOptions {
-            format: common_pb::StorageFormat::from(v.format) as i32,
+            format: i32::from(v.format),
             collapsible_cols_idx: v.collapsible_cols_idx,
         }
     }
@@ -314,9 +350,8 @@ impl From<StorageFormatOptions> for common_pb::StorageFormatOptions {
 
 impl From<common_pb::StorageFormatOptions> for StorageFormatOptions {
     fn from(v: common_pb::StorageFormatOptions) -> Self {
-        let format = v.format();
         Self {
-            format: StorageFormat::from(format),
+            format: StorageFormat::from(v.format),
             collapsible_cols_idx: v.collapsible_cols_idx,
         }
     }
@@ -352,6 +387,10 @@ pub struct TableOptions {
     pub num_rows_per_row_group: usize,
     /// Table Compression
     pub compression: Compression,
+    /// Columns not collapsed into lists in the hybrid format even if they are
+    /// collapsible, which must be string columns with the same value in every
+    /// time series.
+    pub hybrid_uncollapsed_columns: Vec<String>,
 }
 
 impl TableOptions {
@@ -398,6 +437,12 @@ impl TableOptions {
         ]
         .into_iter()
         .collect();
+        if !self.hybrid_uncollapsed_columns.is_empty() {
+            m.insert(
+                HYBRID_UNCOLLAPSED_COLUMNS.to_string(),
+                self.hybrid_uncollapsed_columns.join(","),
+            );
+        }
         self.compaction_strategy.fill_raw_map(&mut m);
 
         m
@@ -532,7 +577,8 @@ impl From<TableOptions> for common_pb::TableOptions {
             write_buffer_size: opts.write_buffer_size,
             compression: common_pb::Compression::from(opts.compression) as i32,
             sampling_segment_duration,
-            storage_format: common_pb::StorageFormat::from(opts.storage_format) as i32,
+            storage_format: i32::from(opts.storage_format),
+            hybrid_uncollapsed_columns: opts.hybrid_uncollapsed_columns,
         }
     }
 }
@@ -558,7 +604,6 @@ impl From<common_pb::UpdateMode> for UpdateMode {
 impl From<common_pb::TableOptions> for TableOptions {
     fn from(opts: common_pb::TableOptions) -> Self {
         let compression = opts.compression();
-        let storage_format = opts.storage_format();
         let update_mode = opts.update_mode();
 
         let compaction_strategy = match opts.compaction_strategy() {
@@ -601,7 +646,8 @@ impl From<common_pb::TableOptions> for TableOptions {
             update_mode: UpdateMode::from(update_mode),
             write_buffer_size: opts.write_buffer_size,
             compression: Compression::from(compression),
-            storage_format: StorageFormat::from(storage_format),
+            storage_format: StorageFormat::from(opts.storage_format),
+            hybrid_uncollapsed_columns: opts.hybrid_uncollapsed_columns,
         }
     }
 }
@@ -619,6 +665,7 @@ impl Default for TableOptions {
             write_buffer_size: DEFAULT_WRITE_BUFFER_SIZE,
             compression: Compression::Zstd,
             storage_format: StorageFormat::default(),
+            hybrid_uncollapsed_columns: Vec::new(),
         }
     }
 }
@@ -680,6 +727,14 @@ fn merge_table_options(
     if let Some(v) = options.get(STORAGE_FORMAT) {
         table_opts.storage_format = v.as_str().try_into()?;
     }
+    if let Some(v) = options.get(HYBRID_UNCOLLAPSED_COLUMNS) {
+        table_opts.hybrid_uncollapsed_columns = v
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(str::to_string)
+            .collect();
+    }
     Ok(table_opts)
 }
 
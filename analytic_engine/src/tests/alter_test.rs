@@ -147,13 +147,29 @@ async fn alter_schema_old_pre_version_case<T: EngineContext>(
         .build()
         .unwrap();
 
-    let request = AlterSchemaRequest {
-        schema: new_schema,
-        pre_schema_version: old_schema.version() - 1,
-    };
-
-    let res = test_ctx.try_alter_schema(table_name, request).await;
-    assert!(res.is_err());
+    for pre_schema_version in [old_schema.version() - 1, old_schema.version() + 1] {
+        let request = AlterSchemaRequest {
+            schema: new_schema.clone(),
+            pre_schema_version,
+        };
+
+        let res = test_ctx.try_alter_schema(table_name, request).await;
+        match res {
+            Err(table_engine::table::Error::AlterSchemaVersionConflict {
+                current_version,
+                pre_version,
+                ..
+            }) => {
+                assert_eq!(old_schema.version(), current_version);
+                assert_eq!(pre_schema_version, pre_version);
+            }
+            res => panic!("Unexpected result of alter schema, res:{:?}", res),
+        }
+    }
+    assert_eq!(
+        old_schema.version(),
+        test_ctx.table(table_name).schema().version()
+    );
 }
 
 async fn alter_schema_add_column_case<T: EngineContext>(
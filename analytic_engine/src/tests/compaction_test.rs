@@ -3,11 +3,12 @@
 //! Compaction integration tests.
 
 use common_types::time::Timestamp;
-use table_engine::table::FlushRequest;
+use table_engine::table::{self, FlushRequest};
 
 use super::util::{EngineContext, MemoryEngineContext, RocksDBEngineContext};
 use crate::{
     compaction::SizeTieredCompactionOptions,
+    instance::flush_compaction,
     tests::util::{self, TestEnv},
 };
 
@@ -102,3 +103,137 @@ fn test_table_compact_current_segment<T: EngineContext>(engine_context: T) {
         .await;
     });
 }
+
+#[test]
+fn test_table_compact_summary_rocks() {
+    let rocksdb_ctx = RocksDBEngineContext::default();
+    test_table_compact_summary(rocksdb_ctx);
+}
+
+#[test]
+fn test_table_compact_summary_mem_wal() {
+    let memory_ctx = MemoryEngineContext::default();
+    test_table_compact_summary(memory_ctx);
+}
+
+fn test_table_compact_summary<T: EngineContext>(engine_context: T) {
+    let env = TestEnv::builder().build();
+    let mut test_ctx = env.new_context(engine_context);
+
+    env.block_on(async {
+        test_ctx.open().await;
+
+        let test_table1 = "test_table1";
+        let fixed_schema_table = test_ctx.create_fixed_schema_table(test_table1).await;
+        let default_opts = SizeTieredCompactionOptions::default();
+
+        let mut expect_rows = Vec::new();
+        let start_ms = test_ctx.start_ms();
+        // Generate just enough ssts to be picked by the compaction.
+        let num_ssts = default_opts.min_threshold;
+        for offset in 0..num_ssts as i64 {
+            let rows = [(
+                "key1",
+                Timestamp::new(start_ms + offset),
+                "tag1-1",
+                11.0,
+                110.0,
+                "tag2-1",
+            )];
+            expect_rows.extend_from_slice(&rows);
+            let row_group = fixed_schema_table.rows_to_row_group(&rows);
+            test_ctx.write_to_table(test_table1, row_group).await;
+
+            test_ctx
+                .flush_table_with_request(
+                    test_table1,
+                    FlushRequest {
+                        compact_after_flush: false,
+                        sync: true,
+                    },
+                )
+                .await;
+        }
+
+        let summary = test_ctx.compact_table(test_table1).await;
+        assert_eq!(num_ssts, summary.num_input_files);
+        assert_eq!(1, summary.num_output_files);
+        assert!(summary.input_size > 0);
+        assert!(summary.output_size > 0);
+
+        util::check_read(
+            &test_ctx,
+            &fixed_schema_table,
+            "Test read after compaction",
+            test_table1,
+            &expect_rows,
+        )
+        .await;
+    });
+}
+
+#[test]
+fn test_table_compact_failure_rocks() {
+    let rocksdb_ctx = RocksDBEngineContext::default();
+    test_table_compact_failure(rocksdb_ctx, table::new_table_id(2, 1001));
+}
+
+#[test]
+fn test_table_compact_failure_mem_wal() {
+    let memory_ctx = MemoryEngineContext::default();
+    test_table_compact_failure(memory_ctx, table::new_table_id(2, 1002));
+}
+
+/// The injected failure is global, so every case uses a table id unique
+/// among all the tests.
+fn test_table_compact_failure<T: EngineContext>(engine_context: T, table_id: table::TableId) {
+    let env = TestEnv::builder().build();
+    let mut test_ctx = env.new_context(engine_context);
+
+    env.block_on(async {
+        test_ctx.open().await;
+
+        let test_table1 = "test_table1";
+        let fixed_schema_table = test_ctx
+            .create_fixed_schema_table_with_id(test_table1, table_id)
+            .await;
+        let default_opts = SizeTieredCompactionOptions::default();
+
+        let start_ms = test_ctx.start_ms();
+        let num_ssts = default_opts.min_threshold;
+        for offset in 0..num_ssts as i64 {
+            let rows = [(
+                "key1",
+                Timestamp::new(start_ms + offset),
+                "tag1-1",
+                11.0,
+                110.0,
+                "tag2-1",
+            )];
+            let row_group = fixed_schema_table.rows_to_row_group(&rows);
+            test_ctx.write_to_table(test_table1, row_group).await;
+
+            test_ctx
+                .flush_table_with_request(
+                    test_table1,
+                    FlushRequest {
+                        compact_after_flush: false,
+                        sync: true,
+                    },
+                )
+                .await;
+        }
+
+        // The waiter of the compaction gets the error.
+        flush_compaction::inject_compaction_failure(table_id);
+        let res = test_ctx.table(test_table1).compact().await;
+        flush_compaction::clear_compaction_failure(table_id);
+        assert!(res.is_err());
+
+        // The input files are unmarked after the failure, so they can be picked
+        // by the next compaction.
+        let summary = test_ctx.compact_table(test_table1).await;
+        assert_eq!(num_ssts, summary.num_input_files);
+        assert_eq!(1, summary.num_output_files);
+    });
+}
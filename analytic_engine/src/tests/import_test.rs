@@ -0,0 +1,159 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+//! Import sst tests.
+
+use std::sync::Arc;
+
+use common_types::{
+    bytes::Bytes,
+    request_id::RequestId,
+    row::Row,
+    schema::Schema,
+    tests::{build_row, build_schema},
+    time::{TimeRange, Timestamp},
+};
+use futures::stream;
+use object_store::{LocalFileSystem, ObjectStoreRef, Path};
+use tempfile::tempdir;
+
+use super::util::{EngineContext, MemoryEngineContext, RocksDBEngineContext};
+use crate::{
+    row_iter::tests::build_record_batch_with_key,
+    sst::{
+        factory::{Factory, FactoryImpl, ObjectStorePickerRef, SstBuilderOptions, SstType},
+        file::SstMetaData,
+    },
+    table_options::{Compression, StorageFormatOptions},
+    tests::util::{self, TestEnv},
+};
+
+#[test]
+fn test_import_sst_rocks() {
+    let rocksdb_ctx = RocksDBEngineContext::default();
+    test_import_sst(rocksdb_ctx);
+}
+
+#[test]
+fn test_import_sst_mem_wal() {
+    let memory_ctx = MemoryEngineContext::default();
+    test_import_sst(memory_ctx);
+}
+
+fn test_import_sst<T: EngineContext>(engine_context: T) {
+    let env = TestEnv::builder().build();
+    let mut test_ctx = env.new_context(engine_context);
+
+    env.block_on(async {
+        test_ctx.open().await;
+
+        let test_table1 = "test_import_sst_table1";
+        let fixed_schema_table = test_ctx.create_fixed_schema_table(test_table1).await;
+        let table = test_ctx.table(test_table1);
+
+        let start_ms = test_ctx.start_ms();
+        let rows = [
+            (
+                "key1",
+                Timestamp::new(start_ms),
+                "tag1-1",
+                11.0,
+                110.0,
+                "tag2-1",
+            ),
+            (
+                "key2",
+                Timestamp::new(start_ms + 1),
+                "tag1-2",
+                12.0,
+                120.0,
+                "tag2-2",
+            ),
+        ];
+        let row_group = fixed_schema_table.rows_to_row_group(&rows);
+        let time_range =
+            TimeRange::new_unchecked(Timestamp::new(start_ms), Timestamp::new(start_ms + 2));
+        let sst = build_sst(
+            table.schema(),
+            row_group.iter().cloned().collect(),
+            time_range,
+        )
+        .await;
+        table.import_sst(sst).await.unwrap();
+
+        util::check_read(
+            &test_ctx,
+            &fixed_schema_table,
+            "Test read after import",
+            test_table1,
+            &rows,
+        )
+        .await;
+
+        // The sst encoded with another schema should be rejected.
+        let mismatched_rows = vec![build_row(b"a", start_ms, 10.0, "v4")];
+        let sst = build_sst(build_schema(), mismatched_rows, time_range).await;
+        assert!(table.import_sst(sst).await.is_err());
+
+        util::check_read(
+            &test_ctx,
+            &fixed_schema_table,
+            "Test read after rejected import",
+            test_table1,
+            &rows,
+        )
+        .await;
+    });
+}
+
+async fn build_sst(schema: Schema, rows: Vec<Row>, time_range: TimeRange) -> Bytes {
+    let dir = tempdir().unwrap();
+    let store: ObjectStoreRef = Arc::new(LocalFileSystem::new_with_prefix(dir.path()).unwrap());
+    let store_picker: ObjectStorePickerRef = Arc::new(store.clone());
+    let sst_file_path = Path::from("data.par");
+
+    let sst_builder_options = SstBuilderOptions {
+        sst_type: SstType::Parquet,
+        num_rows_per_row_group: 8192,
+        compression: Compression::Uncompressed,
+        hybrid_encode_fallback: false,
+        max_row_group_bytes: None,
+        composite_bloom_filter_columns: Vec::new(),
+        hybrid_uncollapsed_columns: Vec::new(),
+        sort_dedup_by_key: false,
+        stats_sidecar: false,
+    };
+    let sst_meta = SstMetaData {
+        min_key: Bytes::new(),
+        max_key: Bytes::new(),
+        time_range,
+        max_sequence: 1,
+        schema: schema.clone(),
+        schema_fingerprint: schema.fingerprint(),
+        size: 0,
+        row_num: 0,
+        storage_format_opts: StorageFormatOptions::default(),
+        bloom_filter: Default::default(),
+        row_group_time_ranges: Vec::new(),
+    };
+    let record_batch = build_record_batch_with_key(schema, rows);
+
+    let mut builder = FactoryImpl
+        .new_sst_builder(&sst_builder_options, &sst_file_path, &store_picker)
+        .unwrap();
+    builder
+        .build(
+            RequestId::next_id(),
+            &sst_meta,
+            Box::new(stream::iter(vec![Ok(record_batch)])),
+        )
+        .await
+        .unwrap();
+
+    store
+        .get(&sst_file_path)
+        .await
+        .unwrap()
+        .bytes()
+        .await
+        .unwrap()
+}
@@ -9,6 +9,8 @@ mod compaction_test;
 #[cfg(test)]
 mod drop_test;
 #[cfg(test)]
+mod import_test;
+#[cfg(test)]
 mod open_test;
 #[cfg(test)]
 mod read_write_test;
@@ -23,13 +23,15 @@ use table_engine::{
         Result as EngineResult, TableEngineRef,
     },
     table::{
-        AlterSchemaRequest, FlushRequest, GetRequest, ReadOrder, ReadRequest, Result, SchemaId,
-        TableId, TableRef, WriteRequest,
+        AlterSchemaRequest, CompactionSummary, FlushRequest, GetRequest, ReadOrder, ReadRequest,
+        Result, SchemaId, TableId, TableRef, WriteRequest,
     },
 };
 use tempfile::TempDir;
 
 use crate::{
+    engine::TableEngineImpl,
+    instance::InstanceRef,
     setup::{
         EngineBuildContext, EngineBuildContextBuilder, EngineBuilder, MemWalEngineBuilder,
         RocksDBWalEngineBuilder,
@@ -113,6 +115,7 @@ pub struct TestContext<T: EngineContext> {
     runtimes: Arc<EngineRuntimes>,
     builder: T::EngineBuilder,
     pub engine: Option<TableEngineRef>,
+    instance: Option<InstanceRef>,
     pub schema_id: SchemaId,
     last_table_seq: u32,
 
@@ -121,12 +124,13 @@ pub struct TestContext<T: EngineContext> {
 
 impl<T: EngineContext> TestContext<T> {
     pub async fn open(&mut self) {
-        let engine = self
+        let instance = self
             .builder
-            .build(self.context.clone(), self.runtimes.clone())
+            .build_instance(self.context.clone(), self.runtimes.clone())
             .await
             .unwrap();
-        self.engine = Some(engine);
+        self.engine = Some(Arc::new(TableEngineImpl::new(instance.clone())));
+        self.instance = Some(instance);
     }
 
     pub async fn reopen(&mut self) {
@@ -134,7 +138,8 @@ impl<T: EngineContext> TestContext<T> {
             // Close all tables.
             self.name_to_tables.clear();
 
-            // Close engine.
+            // Close engine, and release the instance with it.
+            self.instance = None;
             let engine = self.engine.take().unwrap();
             engine.close().await.unwrap();
         }
@@ -154,7 +159,8 @@ impl<T: EngineContext> TestContext<T> {
             // Close all tables.
             self.name_to_tables.clear();
 
-            // Close engine.
+            // Close engine, and release the instance with it.
+            self.instance = None;
             let engine = self.engine.take().unwrap();
             engine.close().await.unwrap();
         }
@@ -238,10 +244,20 @@ impl<T: EngineContext> TestContext<T> {
     }
 
     pub async fn create_fixed_schema_table(&mut self, table_name: &str) -> FixedSchemaTable {
+        let table_id = self.next_table_id();
+        self.create_fixed_schema_table_with_id(table_name, table_id)
+            .await
+    }
+
+    pub async fn create_fixed_schema_table_with_id(
+        &mut self,
+        table_name: &str,
+        table_id: TableId,
+    ) -> FixedSchemaTable {
         let fixed_schema_table = FixedSchemaTable::builder()
             .schema_id(self.schema_id)
             .table_name(table_name.to_string())
-            .table_id(self.next_table_id())
+            .table_id(table_id)
             .ttl("7d".parse::<ReadableDuration>().unwrap())
             .build_fixed();
 
@@ -321,10 +337,10 @@ impl<T: EngineContext> TestContext<T> {
         table.flush(request).await.unwrap();
     }
 
-    pub async fn compact_table(&self, table_name: &str) {
+    pub async fn compact_table(&self, table_name: &str) -> CompactionSummary {
         let table = self.table(table_name);
 
-        table.compact().await.unwrap();
+        table.compact().await.unwrap()
     }
 
     pub async fn try_alter_schema(
@@ -356,6 +372,11 @@ impl<T: EngineContext> TestContext<T> {
         self.engine.as_ref().unwrap()
     }
 
+    #[inline]
+    pub fn instance(&self) -> &InstanceRef {
+        self.instance.as_ref().unwrap()
+    }
+
     fn next_table_id(&mut self) -> TableId {
         self.last_table_seq += 1;
         table::new_table_id(2, self.last_table_seq)
@@ -387,6 +408,7 @@ impl TestEnv {
             runtimes: self.runtimes.clone(),
             builder: engine_context.engine_builder(),
             engine: None,
+            instance: None,
             schema_id: SchemaId::from_u32(100),
             last_table_seq: 1,
             name_to_tables: HashMap::new(),
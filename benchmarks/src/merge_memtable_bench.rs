@@ -203,5 +203,6 @@ fn mock_sst_reader_options(
         runtime,
         background_read_parallelism: 1,
         num_rows_per_row_group: 500,
+        decode_memory_limit: None,
     }
 }
@@ -71,6 +71,7 @@ impl MergeSstBench {
             runtime: runtime.clone(),
             background_read_parallelism: 1,
             num_rows_per_row_group: config.read_batch_row_num,
+            decode_memory_limit: None,
         };
         let max_projections = cmp::min(config.max_projections, schema.num_columns());
 
@@ -47,6 +47,7 @@ impl SstBench {
             runtime: runtime.clone(),
             background_read_parallelism: 1,
             num_rows_per_row_group: config.read_batch_row_num,
+            decode_memory_limit: None,
         };
         let max_projections = cmp::min(config.max_projections, schema.num_columns());
 
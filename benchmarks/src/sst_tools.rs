@@ -50,6 +50,12 @@ async fn create_sst_from_stream(config: SstConfig, record_batch_stream: RecordBa
         sst_type: SstType::Parquet,
         num_rows_per_row_group: config.num_rows_per_row_group,
         compression: config.compression,
+        hybrid_encode_fallback: false,
+        max_row_group_bytes: None,
+        composite_bloom_filter_columns: Vec::new(),
+        hybrid_uncollapsed_columns: Vec::new(),
+        sort_dedup_by_key: false,
+        stats_sidecar: false,
     };
 
     info!(
@@ -103,6 +109,7 @@ pub async fn rebuild_sst(config: RebuildSstConfig, runtime: Arc<Runtime>) {
         runtime,
         background_read_parallelism: 1,
         num_rows_per_row_group: config.read_batch_row_num,
+        decode_memory_limit: None,
     };
 
     let record_batch_stream =
@@ -206,6 +213,7 @@ pub async fn merge_sst(config: MergeSstConfig, runtime: Arc<Runtime>) {
             runtime: runtime.clone(),
             background_read_parallelism: iter_options.sst_background_read_parallelism,
             num_rows_per_row_group: config.read_batch_row_num,
+            decode_memory_limit: None,
         };
 
         let sst_factory: SstFactoryRef = Arc::new(FactoryImpl::default());
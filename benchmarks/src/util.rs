@@ -106,6 +106,7 @@ pub async fn load_sst_to_memtable(
         runtime,
         background_read_parallelism: 1,
         num_rows_per_row_group: 500,
+        decode_memory_limit: None,
     };
     let sst_factory = FactoryImpl;
     let store_picker: ObjectStorePickerRef = Arc::new(store.clone());
@@ -23,6 +23,7 @@ use snafu::{ensure, Backtrace, OptionExt, ResultExt, Snafu};
 use crate::{
     column_schema::{self, ColumnId, ColumnSchema},
     datum::DatumKind,
+    hash,
     row::{contiguous, RowView},
 };
 
@@ -726,6 +727,23 @@ impl Schema {
         self.version
     }
 
+    /// Compute a stable fingerprint over the name, data type and key flag of
+    /// the columns in order.
+    ///
+    /// Schemas with the same fingerprint have the same data layout, so the
+    /// sst written with one of them can be read with the other.
+    pub fn fingerprint(&self) -> u64 {
+        let mut buf = Vec::new();
+        for (idx, column) in self.columns().iter().enumerate() {
+            buf.extend_from_slice(&(column.name.len() as u32).to_le_bytes());
+            buf.extend_from_slice(column.name.as_bytes());
+            buf.push(column.data_type.into_u8());
+            buf.push(self.is_primary_key_index(&idx) as u8);
+        }
+
+        hash::hash64(&buf)
+    }
+
     /// Compare the two rows.
     ///
     /// REQUIRES: the two rows must have the key columns defined by the schema.
@@ -1673,4 +1691,61 @@ mod tests {
 
         assert_eq!(schema, new_schema);
     }
+
+    #[test]
+    fn test_fingerprint() {
+        let schema = build_test_schema();
+        assert_eq!(schema.fingerprint(), build_test_schema().fingerprint());
+
+        // The schema with the same layout but a different version.
+        let schema_with_version = Builder::with_capacity(schema.num_columns())
+            .auto_increment_column_id(true)
+            .version(schema.version() + 1);
+        let schema_with_version = schema
+            .key_columns()
+            .into_iter()
+            .fold(schema_with_version, |builder, column| {
+                builder.add_key_column(column).unwrap()
+            });
+        let schema_with_version = schema
+            .normal_columns()
+            .into_iter()
+            .fold(schema_with_version, |builder, column| {
+                builder.add_normal_column(column).unwrap()
+            })
+            .build()
+            .unwrap();
+        assert_eq!(schema.fingerprint(), schema_with_version.fingerprint());
+
+        // The schema with the normal columns reordered.
+        let reordered_schema = Builder::new()
+            .auto_increment_column_id(true)
+            .add_key_column(
+                column_schema::Builder::new("key1".to_string(), DatumKind::Varbinary)
+                    .build()
+                    .expect("should succeed build column schema"),
+            )
+            .unwrap()
+            .add_key_column(
+                column_schema::Builder::new("timestamp".to_string(), DatumKind::Timestamp)
+                    .build()
+                    .expect("should succeed build column schema"),
+            )
+            .unwrap()
+            .add_normal_column(
+                column_schema::Builder::new("field2".to_string(), DatumKind::Double)
+                    .build()
+                    .expect("should succeed build column schema"),
+            )
+            .unwrap()
+            .add_normal_column(
+                column_schema::Builder::new("field1".to_string(), DatumKind::Double)
+                    .build()
+                    .expect("should succeed build column schema"),
+            )
+            .unwrap()
+            .build()
+            .unwrap();
+        assert_ne!(schema.fingerprint(), reordered_schema.fingerprint());
+    }
 }
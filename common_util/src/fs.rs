@@ -0,0 +1,39 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+//! File system utilities
+
+use std::{ffi::CString, io, mem::MaybeUninit, os::unix::ffi::OsStrExt, path::Path};
+
+/// Get the available space in bytes of the file system containing `path`,
+/// which is the space available to the unprivileged users.
+pub fn available_space(path: &Path) -> io::Result<u64> {
+    let c_path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let mut stat = MaybeUninit::<libc::statvfs>::uninit();
+
+    // Safety: `c_path` is a valid nul-terminated string and `stat` is only read
+    // after `statvfs` succeeds.
+    let stat = unsafe {
+        if libc::statvfs(c_path.as_ptr(), stat.as_mut_ptr()) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        stat.assume_init()
+    };
+
+    #[allow(clippy::useless_conversion)]
+    let available = u64::from(stat.f_bavail) * u64::from(stat.f_frsize);
+    Ok(available)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_available_space() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(available_space(dir.path()).unwrap() > 0);
+
+        assert!(available_space(&dir.path().join("not_exist")).is_err());
+    }
+}
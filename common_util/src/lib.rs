@@ -13,6 +13,7 @@ pub mod avro;
 pub mod codec;
 pub mod config;
 pub mod error;
+pub mod fs;
 pub mod metric;
 pub mod panic;
 pub mod record_batch;
@@ -8,7 +8,7 @@ use std::sync::Arc;
 
 pub use upstream::{
     local::LocalFileSystem, path::Path, Error as ObjectStoreError, GetResult, ListResult,
-    ObjectMeta, ObjectStore,
+    MultipartId, ObjectMeta, ObjectStore,
 };
 
 pub mod aliyun;
@@ -7,7 +7,7 @@ use std::{
     fs::{File, OpenOptions},
     io,
     io::Read,
-    sync::{Mutex, MutexGuard},
+    sync::{Mutex, MutexGuard, TryLockError},
     thread, time,
 };
 
@@ -16,7 +16,11 @@ use log::{error, info};
 
 #[derive(Debug)]
 pub enum Error {
-    Internal { msg: String },
+    Internal {
+        msg: String,
+    },
+    /// Another profiling is in progress.
+    Busy,
     IO(io::Error),
     Jemalloc(jemalloc_ctl::Error),
 }
@@ -91,8 +95,11 @@ impl Profiler {
     // TODO(xikai): limit the profiling duration
     pub fn dump_mem_prof(&self, seconds: u64) -> Result<Vec<u8>> {
         // concurrent profiling is disabled.
-        let lock_guard = self.mem_prof_lock.try_lock().map_err(|e| Error::Internal {
-            msg: format!("failed to acquire mem_prof_lock, err:{}", e),
+        let lock_guard = self.mem_prof_lock.try_lock().map_err(|e| match e {
+            TryLockError::WouldBlock => Error::Busy,
+            TryLockError::Poisoned(e) => Error::Internal {
+                msg: format!("failed to acquire mem_prof_lock, err:{}", e),
+            },
         })?;
         info!(
             "Profiler::dump_mem_prof start memory profiling {} seconds",
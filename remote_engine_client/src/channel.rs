@@ -5,6 +5,8 @@
 use std::num::NonZeroUsize;
 
 use clru::CLruCache;
+use futures::{stream, StreamExt};
+use log::warn;
 use router::endpoint::Endpoint;
 use snafu::ResultExt;
 use tokio::sync::Mutex;
@@ -22,6 +24,9 @@ pub struct ChannelPool {
 
     /// Channel builder
     builder: ChannelBuilder,
+
+    /// Max number of the channels built concurrently in warming up
+    warmup_concurrency: usize,
 }
 
 impl ChannelPool {
@@ -29,9 +34,57 @@ impl ChannelPool {
         let channels = Mutex::new(CLruCache::new(
             NonZeroUsize::new(config.channel_pool_max_size).unwrap(),
         ));
+        let warmup_concurrency = config.channel_pool_warmup_concurrency.max(1);
         let builder = ChannelBuilder::new(config);
 
-        Self { channels, builder }
+        Self {
+            channels,
+            builder,
+            warmup_concurrency,
+        }
+    }
+
+    /// Build the channels to the `endpoints` not in the pool yet, at most
+    /// `warmup_concurrency` channels are built concurrently.
+    ///
+    /// The endpoints failed to connect are skipped, and the number of the
+    /// channels put into the pool is returned.
+    pub async fn warmup(&self, endpoints: &[Endpoint]) -> usize {
+        let endpoints = {
+            let inner = self.channels.lock().await;
+            endpoints
+                .iter()
+                .filter(|endpoint| inner.peek(*endpoint).is_none())
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+
+        let built_channels = stream::iter(endpoints)
+            .map(|endpoint| async move {
+                let res = self.builder.build(endpoint.to_string().as_str()).await;
+                (endpoint, res)
+            })
+            .buffer_unordered(self.warmup_concurrency)
+            .collect::<Vec<_>>()
+            .await;
+
+        let mut inner = self.channels.lock().await;
+        let mut num_warmed = 0;
+        for (endpoint, res) in built_channels {
+            match res {
+                Ok(channel) => {
+                    inner.put(endpoint, channel);
+                    num_warmed += 1;
+                }
+                Err(e) => warn!(
+                    "Failed to warm up channel, endpoint:{}, err:{}",
+                    endpoint.to_string(),
+                    e
+                ),
+            }
+        }
+
+        num_warmed
     }
 
     pub async fn get(&self, endpoint: &Endpoint) -> Result<Channel> {
@@ -94,3 +147,49 @@ impl ChannelBuilder {
 fn make_formatted_endpoint(endpoint: &str) -> String {
     format!("http://{}", endpoint)
 }
+
+#[cfg(test)]
+mod tests {
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_warmup() {
+        let mut endpoints = Vec::new();
+        let mut listeners = Vec::new();
+        for _ in 0..4 {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let port = listener.local_addr().unwrap().port();
+            endpoints.push(Endpoint::new("127.0.0.1".to_string(), port));
+            listeners.push(listener);
+        }
+        // Keep the accepted connections open until the test ends.
+        let accept_handles = listeners
+            .into_iter()
+            .map(|listener| {
+                tokio::spawn(async move {
+                    let mut conns = Vec::new();
+                    while let Ok((conn, _)) = listener.accept().await {
+                        conns.push(conn);
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let config = Config {
+            channel_pool_max_size: 8,
+            channel_pool_warmup_concurrency: 2,
+            ..Default::default()
+        };
+        let pool = ChannelPool::new(config);
+        assert_eq!(4, pool.warmup(&endpoints).await);
+        assert_eq!(4, pool.channels.lock().await.len());
+        // The channels in the pool are not built again.
+        assert_eq!(0, pool.warmup(&endpoints).await);
+
+        for handle in accept_handles {
+            handle.abort();
+        }
+    }
+}
@@ -36,6 +36,11 @@ impl Client {
         }
     }
 
+    /// Build the channels to the `endpoints` in advance.
+    pub async fn warmup(&self, endpoints: &[Endpoint]) -> usize {
+        self.channel_pool.warmup(endpoints).await
+    }
+
     pub async fn read(&self, request: ReadRequest) -> Result<ClientReadRecordBatchStream> {
         // Find the endpoint from router firstly.
         let endpoint = self.route(&request.table).await?;
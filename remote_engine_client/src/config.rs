@@ -12,6 +12,9 @@ use serde_derive::Deserialize;
 pub struct Config {
     pub connect_timeout: ReadableDuration,
     pub channel_pool_max_size: usize,
+    /// Max number of the channels built concurrently when warming up the
+    /// channel pool.
+    pub channel_pool_warmup_concurrency: usize,
     pub channel_keep_alive_while_idle: bool,
     pub channel_keep_alive_timeout: ReadableDuration,
     pub channel_keep_alive_interval: ReadableDuration,
@@ -22,9 +25,25 @@ impl Default for Config {
         Self {
             connect_timeout: ReadableDuration::from_str("3s").unwrap(),
             channel_pool_max_size: 128,
+            channel_pool_warmup_concurrency: 16,
             channel_keep_alive_interval: ReadableDuration::from_str("600s").unwrap(),
             channel_keep_alive_timeout: ReadableDuration::from_str("3s").unwrap(),
             channel_keep_alive_while_idle: true,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deserialize_warmup_concurrency() {
+        let config: Config = toml::from_str("channel_pool_warmup_concurrency = 4").unwrap();
+        assert_eq!(4, config.channel_pool_warmup_concurrency);
+        assert_eq!(128, config.channel_pool_max_size);
+
+        let config: Config = toml::from_str("").unwrap();
+        assert_eq!(16, config.channel_pool_warmup_concurrency);
+    }
+}
@@ -16,7 +16,7 @@ use async_trait::async_trait;
 use common_types::{record_batch::RecordBatch, schema::RecordSchema};
 use config::Config;
 use futures::{Stream, StreamExt};
-use router::RouterRef;
+use router::{endpoint::Endpoint, RouterRef};
 use snafu::ResultExt;
 use table_engine::{
     remote::{
@@ -122,6 +122,12 @@ impl RemoteEngineImpl {
 
         Self(client)
     }
+
+    /// Warm up the channels to the `endpoints`, and return the number of the
+    /// channels built.
+    pub async fn warmup(&self, endpoints: &[Endpoint]) -> usize {
+        self.0.warmup(endpoints).await
+    }
 }
 
 #[async_trait]
@@ -0,0 +1,113 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+//! Registry of the in-flight http requests, which helps to find out the stuck
+//! requests.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Instant,
+};
+
+use serde_derive::Serialize;
+
+struct ActiveRequest {
+    path: String,
+    tenant: Option<String>,
+    start: Instant,
+}
+
+/// Snapshot of an in-flight request.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ActiveRequestInfo {
+    pub path: String,
+    pub tenant: Option<String>,
+    pub elapsed_ms: u64,
+}
+
+/// In-flight requests indexed by an id allocated on registering.
+#[derive(Default)]
+pub struct ActiveRequests {
+    next_id: AtomicU64,
+    requests: Mutex<HashMap<u64, ActiveRequest>>,
+}
+
+pub type ActiveRequestsRef = Arc<ActiveRequests>;
+
+impl ActiveRequests {
+    /// Register a request, which is removed from the registry once the
+    /// returned guard is dropped.
+    pub fn register(self: &Arc<Self>, path: String, tenant: Option<String>) -> ActiveRequestGuard {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let request = ActiveRequest {
+            path,
+            tenant,
+            start: Instant::now(),
+        };
+        self.requests.lock().unwrap().insert(id, request);
+
+        ActiveRequestGuard {
+            requests: self.clone(),
+            id,
+        }
+    }
+
+    /// List the in-flight requests, the longest running one comes first.
+    pub fn list(&self) -> Vec<ActiveRequestInfo> {
+        let mut requests: Vec<_> = self
+            .requests
+            .lock()
+            .unwrap()
+            .values()
+            .map(|request| ActiveRequestInfo {
+                path: request.path.clone(),
+                tenant: request.tenant.clone(),
+                elapsed_ms: request.start.elapsed().as_millis() as u64,
+            })
+            .collect();
+        requests.sort_by(|a, b| b.elapsed_ms.cmp(&a.elapsed_ms));
+
+        requests
+    }
+}
+
+/// Remove the request from the registry on drop, so the request is removed
+/// even if it is canceled.
+pub struct ActiveRequestGuard {
+    requests: ActiveRequestsRef,
+    id: u64,
+}
+
+impl Drop for ActiveRequestGuard {
+    fn drop(&mut self) {
+        self.requests.requests.lock().unwrap().remove(&self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_register_active_requests() {
+        let requests = Arc::new(ActiveRequests::default());
+        let guard1 = requests.register("/sql".to_string(), Some("public".to_string()));
+        let guard2 = requests.register("/metrics".to_string(), None);
+
+        let mut paths: Vec<_> = requests.list().into_iter().map(|v| v.path).collect();
+        paths.sort();
+        assert_eq!(vec!["/metrics", "/sql"], paths);
+
+        drop(guard1);
+        let listed = requests.list();
+        assert_eq!(1, listed.len());
+        assert_eq!("/metrics", listed[0].path);
+        assert_eq!(None, listed[0].tenant);
+
+        drop(guard2);
+        assert!(requests.list().is_empty());
+    }
+}
@@ -0,0 +1,211 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+//! A bounded thread pool to run the blocking work of the handlers, so the cpu
+//! heavy work like profiling can't starve the runtimes executing the queries.
+
+use std::{
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        mpsc::{self, Receiver, SyncSender, TrySendError},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+use log::{error, info};
+use snafu::{Backtrace, GenerateBacktrace, OptionExt, ResultExt, Snafu};
+use tokio::sync::oneshot;
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display(
+        "Failed to spawn thread of blocking pool, name:{}, err:{}.\nBacktrace:\n{}",
+        name,
+        source,
+        backtrace
+    ))]
+    SpawnThread {
+        name: String,
+        source: std::io::Error,
+        backtrace: Backtrace,
+    },
+
+    #[snafu(display(
+        "Blocking pool is busy, name:{}, queue_len:{}.\nBacktrace:\n{}",
+        name,
+        queue_len,
+        backtrace
+    ))]
+    PoolBusy {
+        name: String,
+        queue_len: usize,
+        backtrace: Backtrace,
+    },
+
+    #[snafu(display(
+        "Blocking task is dropped or panicked, name:{}.\nBacktrace:\n{}",
+        name,
+        backtrace
+    ))]
+    TaskCanceled { name: String, backtrace: Backtrace },
+}
+
+define_result!(Error);
+
+type Task = Box<dyn FnOnce() + Send>;
+
+/// A pool with fixed number of threads, and at most `queue_len` tasks can wait
+/// for the threads.
+///
+/// The threads exit once the pool is dropped and the queued tasks are done.
+pub struct BlockingPool {
+    name: String,
+    queue_len: usize,
+    sender: SyncSender<Task>,
+}
+
+pub type BlockingPoolRef = Arc<BlockingPool>;
+
+impl BlockingPool {
+    /// Create a pool with `thread_num` threads named by `name`, at least one
+    /// thread is created.
+    pub fn try_new(name: &str, thread_num: usize, queue_len: usize) -> Result<Self> {
+        let (sender, receiver) = mpsc::sync_channel(queue_len);
+        let receiver = Arc::new(Mutex::new(receiver));
+        let thread_num = thread_num.max(1);
+        for _ in 0..thread_num {
+            let receiver = receiver.clone();
+            thread::Builder::new()
+                .name(name.to_string())
+                .spawn(move || run_worker(receiver))
+                .context(SpawnThread { name })?;
+        }
+        info!(
+            "Blocking pool is created, name:{}, thread_num:{}, queue_len:{}",
+            name, thread_num, queue_len
+        );
+
+        Ok(Self {
+            name: name.to_string(),
+            queue_len,
+            sender,
+        })
+    }
+
+    /// Run the `func` in the pool and wait for its result, error is returned
+    /// immediately if the queue of the pool is full.
+    pub async fn run<F, R>(&self, func: F) -> Result<R>
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let (tx, rx) = oneshot::channel();
+        let task: Task = Box::new(move || {
+            // The caller may have gone.
+            let _ = tx.send(func());
+        });
+
+        match self.sender.try_send(task) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => {
+                return PoolBusy {
+                    name: &self.name,
+                    queue_len: self.queue_len,
+                }
+                .fail();
+            }
+            // The threads never exit while the sender is alive unless they fail to be
+            // spawned, which is checked when creating the pool.
+            Err(TrySendError::Disconnected(_)) => {
+                return Err(Error::TaskCanceled {
+                    name: self.name.clone(),
+                    backtrace: Backtrace::generate(),
+                });
+            }
+        }
+
+        rx.await.ok().context(TaskCanceled { name: &self.name })
+    }
+}
+
+fn run_worker(receiver: Arc<Mutex<Receiver<Task>>>) {
+    loop {
+        let task = receiver.lock().unwrap().recv();
+        match task {
+            Ok(task) => {
+                // Keep the thread alive if the task panics, the caller will be notified
+                // as the result sender is dropped.
+                if panic::catch_unwind(AssertUnwindSafe(task)).is_err() {
+                    error!(
+                        "Blocking task panicked, thread:{:?}",
+                        thread::current().name()
+                    );
+                }
+            }
+            // The pool is dropped.
+            Err(_) => return,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Barrier, time::Duration};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_run_in_pool() {
+        let pool = BlockingPool::try_new("test-blocking-pool", 2, 4).unwrap();
+
+        let thread_name = pool
+            .run(|| thread::current().name().map(|v| v.to_string()))
+            .await
+            .unwrap();
+        assert_eq!(Some("test-blocking-pool".to_string()), thread_name);
+
+        // The pool still works after a task panics.
+        let res = pool
+            .run(|| -> i32 { panic!("panic in blocking task") })
+            .await;
+        assert!(matches!(res, Err(Error::TaskCanceled { .. })));
+        assert_eq!(3, pool.run(|| 1 + 2).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_pool_busy() {
+        let pool = Arc::new(BlockingPool::try_new("test-busy-pool", 1, 1).unwrap());
+
+        // Occupy the only thread.
+        let barrier = Arc::new(Barrier::new(2));
+        let barrier_clone = barrier.clone();
+        let pool_clone = pool.clone();
+        let running = tokio::spawn(async move {
+            pool_clone
+                .run(move || {
+                    barrier_clone.wait();
+                    barrier_clone.wait();
+                })
+                .await
+        });
+        let wait_barrier = barrier.clone();
+        tokio::task::spawn_blocking(move || wait_barrier.wait())
+            .await
+            .unwrap();
+
+        // Fill the queue.
+        let pool_clone = pool.clone();
+        let queued = tokio::spawn(async move { pool_clone.run(|| 1).await });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let res = pool.run(|| 2).await;
+        assert!(matches!(res, Err(Error::PoolBusy { .. })));
+
+        let wait_barrier = barrier.clone();
+        tokio::task::spawn_blocking(move || wait_barrier.wait())
+            .await
+            .unwrap();
+        running.await.unwrap().unwrap();
+        assert_eq!(1, queued.await.unwrap().unwrap());
+    }
+}
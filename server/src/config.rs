@@ -7,6 +7,7 @@ use std::collections::HashMap;
 use analytic_engine;
 use cluster::config::{ClusterConfig, SchemaConfig};
 use common_types::schema::TIMESTAMP_COLUMN;
+use common_util::config::ReadableDuration;
 use meta_client::types::ShardId;
 use router::{
     endpoint::Endpoint,
@@ -15,7 +16,15 @@ use router::{
 use serde_derive::Deserialize;
 use table_engine::ANALYTIC_ENGINE_TYPE;
 
-use crate::{grpc::forward, http::DEFAULT_MAX_BODY_SIZE, limiter::LimiterConfig};
+use crate::{
+    grpc::forward,
+    http::{
+        DEFAULT_BLOCKING_QUEUE_LEN, DEFAULT_BLOCKING_THREAD_NUM, DEFAULT_HTTP2_KEEPALIVE_TIMEOUT,
+        DEFAULT_MAX_BODY_SIZE, DEFAULT_MAX_IMPORT_SST_SIZE, DEFAULT_SHUTDOWN_TIMEOUT,
+        DEFAULT_SLOW_QUERY_THRESHOLD, DEFAULT_TCP_KEEPALIVE,
+    },
+    limiter::LimiterConfig,
+};
 
 /// The deployment mode decides how to start the CeresDB.
 ///
@@ -127,8 +136,34 @@ pub struct Config {
     pub mysql_port: u16,
     pub http_port: u16,
     pub http_max_body_size: u64,
+    /// Max size of the sst imported through the http service.
+    pub http_max_import_sst_size: u64,
+    /// Max time to wait for the in-flight http requests when shutting down
+    pub http_shutdown_timeout: ReadableDuration,
+    /// Interval of the TCP keepalive probes of the http connections, disabled
+    /// if not set.
+    pub http_tcp_keepalive: Option<ReadableDuration>,
+    /// Interval of the HTTP/2 keepalive pings, disabled if not set.
+    pub http2_keepalive_interval: Option<ReadableDuration>,
+    /// Timeout to receive the acknowledgement of the HTTP/2 keepalive ping.
+    pub http2_keepalive_timeout: ReadableDuration,
+    /// Close the http connections without any reading or writing for the
+    /// duration, disabled if not set.
+    pub http_idle_timeout: Option<ReadableDuration>,
+    /// The http sql queries taking longer than it are logged as slow queries,
+    /// disabled if not set.
+    pub http_slow_query_threshold: Option<ReadableDuration>,
+    /// Number of the threads to run the cpu heavy work of the http handlers,
+    /// e.g. profiling.
+    pub http_blocking_thread_num: usize,
+    /// Max number of the cpu heavy http requests waiting for the threads.
+    pub http_blocking_queue_len: usize,
     pub grpc_port: u16,
     pub grpc_server_cq_count: usize,
+    /// Return the full error cause (including the backtrace if any) in the
+    /// grpc responses instead of its first line, only enable it for the
+    /// trusted callers.
+    pub grpc_verbose_error: bool,
 
     /// Engine related configs:
     pub runtime: RuntimeConfig,
@@ -181,9 +216,19 @@ impl Default for Config {
             bind_addr: String::from("127.0.0.1"),
             http_port: 5000,
             http_max_body_size: DEFAULT_MAX_BODY_SIZE,
+            http_max_import_sst_size: DEFAULT_MAX_IMPORT_SST_SIZE,
+            http_shutdown_timeout: ReadableDuration(DEFAULT_SHUTDOWN_TIMEOUT),
+            http_tcp_keepalive: Some(ReadableDuration(DEFAULT_TCP_KEEPALIVE)),
+            http2_keepalive_interval: None,
+            http2_keepalive_timeout: ReadableDuration(DEFAULT_HTTP2_KEEPALIVE_TIMEOUT),
+            http_idle_timeout: None,
+            http_slow_query_threshold: Some(ReadableDuration(DEFAULT_SLOW_QUERY_THRESHOLD)),
+            http_blocking_thread_num: DEFAULT_BLOCKING_THREAD_NUM,
+            http_blocking_queue_len: DEFAULT_BLOCKING_QUEUE_LEN,
             mysql_port: 3307,
             grpc_port,
             grpc_server_cq_count: 20,
+            grpc_verbose_error: false,
             runtime: RuntimeConfig::default(),
             log_level: "debug".to_string(),
             enable_async_log: true,
@@ -6,3 +6,6 @@
 pub const CATALOG_HEADER: &str = "x-ceresdb-catalog";
 /// Header of tenant name
 pub const TENANT_HEADER: &str = "x-ceresdb-access-tenant";
+/// Header of request id, the query can be canceled by this id within the same
+/// tenant. The id is returned by this header in the response of the sql request.
+pub const REQUEST_ID_HEADER: &str = "x-ceresdb-request-id";
@@ -4,6 +4,7 @@
 
 use std::sync::Arc;
 
+use common_types::request_id::RequestId;
 use common_util::runtime::Runtime;
 use snafu::{ensure, Backtrace, OptionExt, Snafu};
 
@@ -34,6 +35,9 @@ pub struct RequestContext {
     pub tenant: String,
     /// Runtime of this request
     pub runtime: Arc<Runtime>,
+    /// Id of this request, a new id is generated if not specified by the
+    /// client
+    pub request_id: RequestId,
 }
 
 impl RequestContext {
@@ -47,6 +51,7 @@ pub struct Builder {
     catalog: String,
     tenant: String,
     runtime: Option<Arc<Runtime>>,
+    request_id: Option<RequestId>,
 }
 
 impl Builder {
@@ -65,6 +70,11 @@ impl Builder {
         self
     }
 
+    pub fn request_id(mut self, request_id: RequestId) -> Self {
+        self.request_id = Some(request_id);
+        self
+    }
+
     pub fn build(self) -> Result<RequestContext> {
         ensure!(!self.catalog.is_empty(), MissingCatalog);
         // We use tenant as schema, so we use default schema if tenant is not specific
@@ -76,6 +86,7 @@ impl Builder {
             catalog: self.catalog,
             tenant: self.tenant,
             runtime,
+            request_id: self.request_id.unwrap_or_else(RequestId::next_id),
         })
     }
 }
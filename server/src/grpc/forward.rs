@@ -3,6 +3,7 @@
 //! Forward for grpc services
 use std::{
     collections::HashMap,
+    future::Future,
     net::Ipv4Addr,
     sync::{Arc, RwLock},
     time::Duration,
@@ -10,16 +11,30 @@ use std::{
 
 use async_trait::async_trait;
 use ceresdbproto::storage::{storage_service_client::StorageServiceClient, RouteRequest};
+use futures::{
+    future,
+    stream::{BoxStream, StreamExt},
+};
 use log::{debug, error, warn};
+use prometheus::IntGauge;
 use router::{endpoint::Endpoint, RouterRef};
 use serde_derive::Deserialize;
 use snafu::{ensure, Backtrace, ResultExt, Snafu};
 use tonic::{
-    metadata::errors::InvalidMetadataValue,
+    codec::CompressionEncoding,
+    metadata::{
+        errors::{InvalidMetadataKey, InvalidMetadataValue},
+        AsciiMetadataKey,
+    },
     transport::{self, Channel},
 };
 
-use crate::consts::TENANT_HEADER;
+use crate::{
+    consts::TENANT_HEADER,
+    grpc::metrics::{
+        FORWARD_CLIENT_CACHE_GAUGE, FORWARD_ROUTE_DURATION_HISTOGRAM, FORWARD_ROUTE_FAILURE_COUNTER,
+    },
+};
 
 #[derive(Debug, Snafu)]
 pub enum Error {
@@ -45,6 +60,18 @@ pub enum Error {
         backtrace: Backtrace,
     },
 
+    #[snafu(display(
+        "Invalid tenant header, header:{}, err:{}.\nBacktrace:\n{}",
+        header,
+        source,
+        backtrace
+    ))]
+    InvalidTenantHeader {
+        header: String,
+        source: InvalidMetadataKey,
+        backtrace: Backtrace,
+    },
+
     #[snafu(display(
         "Invalid schema, schema:{}, err:{}.\nBacktrace:\n{}",
         schema,
@@ -68,12 +95,48 @@ pub enum Error {
         source: tonic::transport::Error,
         backtrace: Backtrace,
     },
+
+    #[snafu(display(
+        "Forwarded response is too large, endpoint:{:?}, len:{}, limit:{}.\nBacktrace:\n{}",
+        endpoint,
+        len,
+        limit,
+        backtrace
+    ))]
+    ResponseTooLarge {
+        endpoint: Endpoint,
+        len: usize,
+        limit: usize,
+        backtrace: Backtrace,
+    },
 }
 
 define_result!(Error);
 
 pub type ForwarderRef = Arc<Forwarder<DefaultClientBuilder>>;
 
+/// Stream of the responses of a forwarded streaming request.
+pub type ForwardStream<Resp, Err> = BoxStream<'static, std::result::Result<Resp, Err>>;
+
+type ClientCache = Arc<RwLock<HashMap<Endpoint, StorageServiceClient<Channel>>>>;
+
+/// Compression of the messages of the forwarded requests.
+///
+/// Only gzip is provided now because zstd is not supported by the current
+/// version of tonic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum Compression {
+    Gzip,
+}
+
+impl From<Compression> for CompressionEncoding {
+    fn from(compression: Compression) -> Self {
+        match compression {
+            Compression::Gzip => CompressionEncoding::Gzip,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Deserialize)]
 #[serde(default)]
 pub struct Config {
@@ -84,6 +147,10 @@ pub struct Config {
     pub max_send_msg_len: i32,
     /// -1 means unlimited
     pub max_recv_msg_len: i32,
+    /// Max encoded length of a forwarded response, which is checked on every
+    /// decoded response before relaying it as the grpc client (tonic 0.8) has
+    /// no limit on decoding, -1 means unlimited
+    pub max_response_len: i64,
     /// Sets an interval for HTTP2 Ping frames should be sent to keep a
     /// connection alive.
     pub keep_alive_interval: Duration,
@@ -94,7 +161,20 @@ pub struct Config {
     /// default keep http2 connections alive while idle
     pub keep_alive_while_idle: bool,
     pub connect_timeout: Duration,
+    /// Max times to retry connecting to the endpoint, 0 means no retry
+    pub connect_max_retries: usize,
+    /// Backoff before the first connect retry, which is doubled for every
+    /// following retry
+    pub connect_retry_backoff: Duration,
     pub forward_timeout: Duration,
+    /// Kinds of the requests which are never forwarded
+    pub disabled_request_kinds: Vec<RequestKind>,
+    /// Name of the header to carry the tenant in the forwarded requests
+    pub tenant_header: String,
+    /// Compress the forwarded requests and accept the compressed responses if
+    /// set, which helps to save the bandwidth across datacenters. The server
+    /// also compresses its responses for the clients accepting it if set.
+    pub compression: Option<Compression>,
 }
 
 impl Default for Config {
@@ -106,11 +186,17 @@ impl Default for Config {
             max_send_msg_len: 20 * (1 << 20),
             // 1GB
             max_recv_msg_len: 1 << 30,
+            max_response_len: -1,
             keep_alive_interval: Duration::from_secs(60 * 10),
             keep_alive_timeout: Duration::from_secs(3),
             keep_alive_while_idle: true,
             connect_timeout: Duration::from_secs(3),
+            connect_max_retries: 2,
+            connect_retry_backoff: Duration::from_millis(100),
             forward_timeout: Duration::from_secs(60),
+            disabled_request_kinds: Vec::new(),
+            tenant_header: TENANT_HEADER.to_string(),
+            compression: None,
         }
     }
 }
@@ -129,6 +215,20 @@ impl DefaultClientBuilder {
     fn make_endpoint_with_scheme(endpoint: &Endpoint) -> String {
         format!("http://{}:{}", endpoint.addr, endpoint.port)
     }
+
+    /// The encoding to compress the requests and the responses of the clients.
+    #[inline]
+    fn compression_encoding(&self) -> Option<CompressionEncoding> {
+        self.config.compression.map(CompressionEncoding::from)
+    }
+
+    fn build_client(&self, channel: Channel) -> StorageServiceClient<Channel> {
+        let client = StorageServiceClient::new(channel);
+        match self.compression_encoding() {
+            Some(encoding) => client.send_compressed(encoding).accept_compressed(encoding),
+            None => client,
+        }
+    }
 }
 
 #[async_trait]
@@ -150,11 +250,50 @@ impl ClientBuilder for DefaultClientBuilder {
                 .connect_timeout(self.config.connect_timeout)
                 .keep_alive_while_idle(false),
         };
-        let channel = configured_endpoint.connect().await.context(Connect {
-            endpoint: &endpoint_with_scheme,
-        })?;
+        let (configured_endpoint, endpoint_with_scheme) =
+            (&configured_endpoint, &endpoint_with_scheme);
+        let channel = connect_with_retry(
+            self.config.connect_max_retries,
+            self.config.connect_retry_backoff,
+            move || async move {
+                configured_endpoint.connect().await.context(Connect {
+                    endpoint: endpoint_with_scheme,
+                })
+            },
+        )
+        .await?;
 
-        Ok(StorageServiceClient::new(channel))
+        Ok(self.build_client(channel))
+    }
+}
+
+/// Call `connect` until it succeeds or `max_retries` retries are used up, and
+/// the backoff between retries grows exponentially from `backoff`.
+async fn connect_with_retry<T, F, Fut>(
+    max_retries: usize,
+    backoff: Duration,
+    mut connect: F,
+) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let mut backoff = backoff;
+    let mut retries = 0;
+    loop {
+        match connect().await {
+            Ok(v) => return Ok(v),
+            Err(e) if retries < max_retries => {
+                retries += 1;
+                warn!(
+                    "Fail to connect, retry after {:?}, retries:{}, max_retries:{}, err:{}",
+                    backoff, retries, max_retries, e
+                );
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+            }
+            Err(e) => return Err(e),
+        }
     }
 }
 
@@ -172,8 +311,13 @@ pub struct Forwarder<B> {
     config: Config,
     router: RouterRef,
     local_endpoint: Endpoint,
+    /// Parsed from the `tenant_header` in the config.
+    tenant_header: AsciiMetadataKey,
     client_builder: B,
-    clients: RwLock<HashMap<Endpoint, StorageServiceClient<Channel>>>,
+    /// Shared with the forwarded streams to release the client on error.
+    clients: ClientCache,
+    /// Gauge of the number of the cached clients.
+    client_cache_gauge: IntGauge,
 }
 
 /// The result of forwarding.
@@ -181,14 +325,51 @@ pub struct Forwarder<B> {
 /// If no forwarding happens, [`Original`] can be used.
 pub enum ForwardResult<Resp, Err> {
     Original,
-    Forwarded(std::result::Result<Resp, Err>),
+    Forwarded {
+        /// The endpoint which the request is forwarded to.
+        endpoint: Endpoint,
+        result: std::result::Result<Resp, Err>,
+    },
+}
+
+/// Kind of the request to forward.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum RequestKind {
+    Read,
+    Write,
+}
+
+impl RequestKind {
+    /// Classify the `sql` by its first keyword, and the statements modifying
+    /// the data or the tables are [RequestKind::Write].
+    pub fn from_sql(sql: &str) -> Self {
+        const WRITE_KEYWORDS: [&str; 6] = ["INSERT", "CREATE", "ALTER", "DROP", "DELETE", "UPDATE"];
+
+        let keyword = sql
+            .trim_start()
+            .split(|c: char| !c.is_ascii_alphabetic())
+            .next()
+            .unwrap_or_default();
+        if WRITE_KEYWORDS
+            .iter()
+            .any(|write_keyword| write_keyword.eq_ignore_ascii_case(keyword))
+        {
+            RequestKind::Write
+        } else {
+            RequestKind::Read
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct ForwardRequest<Req> {
     pub schema: String,
     pub metric: String,
+    pub kind: RequestKind,
     pub req: tonic::Request<Req>,
+    /// Timeout of the forwarded request, overriding the `forward_timeout` in
+    /// the config if set.
+    pub timeout: Option<Duration>,
 }
 
 impl Forwarder<DefaultClientBuilder> {
@@ -201,33 +382,82 @@ impl Forwarder<DefaultClientBuilder> {
     }
 }
 
-impl<B> Forwarder<B> {
-    #[inline]
-    fn is_loopback_ip(ip_addr: &str) -> bool {
-        ip_addr
-            .parse::<Ipv4Addr>()
-            .map(|ip| ip.is_loopback())
-            .unwrap_or(false)
+#[inline]
+fn is_loopback_ip(ip_addr: &str) -> bool {
+    ip_addr
+        .parse::<Ipv4Addr>()
+        .map(|ip| ip.is_loopback())
+        .unwrap_or(false)
+}
+
+/// Check whether the target endpoint is the same as the local endpoint.
+pub(crate) fn is_local_endpoint(local_endpoint: &Endpoint, target: &Endpoint) -> bool {
+    if local_endpoint == target {
+        return true;
+    }
+
+    if local_endpoint.port != target.port {
+        return false;
     }
 
+    // Only need to check the remote is loopback addr.
+    is_loopback_ip(&target.addr)
+}
+
+impl<B> Forwarder<B> {
     /// Check whether the target endpoint is the same as the local endpoint.
     fn is_local_endpoint(&self, target: &Endpoint) -> bool {
-        if &self.local_endpoint == target {
-            return true;
-        }
+        is_local_endpoint(&self.local_endpoint, target)
+    }
+
+    /// Release the client for the given endpoint.
+    fn release_client(&self, endpoint: &Endpoint) -> Option<StorageServiceClient<Channel>> {
+        release_client(&self.clients, &self.client_cache_gauge, endpoint)
+    }
+}
 
-        if self.local_endpoint.port != target.port {
-            return false;
+/// Check the encoded length of the response from the `endpoint` against the
+/// `max_response_len`, -1 means unlimited.
+fn check_response_len<Resp: prost::Message>(
+    max_response_len: i64,
+    endpoint: &Endpoint,
+    resp: &Resp,
+) -> Result<()> {
+    if max_response_len < 0 {
+        return Ok(());
+    }
+
+    let len = resp.encoded_len();
+    let limit = max_response_len as usize;
+    ensure!(
+        len <= limit,
+        ResponseTooLarge {
+            endpoint: endpoint.clone(),
+            len,
+            limit,
         }
+    );
+
+    Ok(())
+}
 
-        // Only need to check the remote is loopback addr.
-        Self::is_loopback_ip(&target.addr)
+fn release_client(
+    clients: &ClientCache,
+    client_cache_gauge: &IntGauge,
+    endpoint: &Endpoint,
+) -> Option<StorageServiceClient<Channel>> {
+    let mut clients = clients.write().unwrap();
+    let client = clients.remove(endpoint);
+    if client.is_some() {
+        client_cache_gauge.dec();
     }
+    client
+}
 
-    /// Release the client for the given endpoint.
-    fn release_client(&self, endpoint: &Endpoint) -> Option<StorageServiceClient<Channel>> {
-        let mut clients = self.clients.write().unwrap();
-        clients.remove(endpoint)
+impl<B> Drop for Forwarder<B> {
+    fn drop(&mut self) {
+        let num_clients = self.clients.read().unwrap().len();
+        self.client_cache_gauge.sub(num_clients as i64);
     }
 }
 
@@ -238,28 +468,57 @@ impl<B: ClientBuilder> Forwarder<B> {
         local_endpoint: Endpoint,
         client_builder: B,
     ) -> Result<Self> {
-        let loopback_local_endpoint = Self::is_loopback_ip(&local_endpoint.addr);
+        let loopback_local_endpoint = is_loopback_ip(&local_endpoint.addr);
         ensure!(
             !loopback_local_endpoint,
             LoopbackLocalIpAddr {
                 ip_addr: &local_endpoint.addr,
             }
         );
+        let tenant_header = AsciiMetadataKey::from_bytes(config.tenant_header.as_bytes()).context(
+            InvalidTenantHeader {
+                header: &config.tenant_header,
+            },
+        )?;
 
         Ok(Self {
             config,
             local_endpoint,
+            tenant_header,
             router,
-            clients: RwLock::new(HashMap::new()),
+            clients: Arc::new(RwLock::new(HashMap::new())),
+            client_cache_gauge: FORWARD_CLIENT_CACHE_GAUGE.clone(),
             client_builder,
         })
     }
 
+    /// Build and cache the clients of the `endpoints` eagerly to avoid the
+    /// cold-start latency of the first forwarding, e.g. at startup or after
+    /// the routing table is refreshed.
+    ///
+    /// The local endpoint and the endpoints whose clients are cached already
+    /// are skipped, and the failure of connecting is only logged because the
+    /// client will be created again by the forwarding.
+    pub async fn preconnect(&self, endpoints: &[Endpoint]) {
+        let connects = endpoints
+            .iter()
+            .filter(|endpoint| !self.is_local_endpoint(endpoint))
+            .map(|endpoint| async move {
+                if let Err(e) = self.get_or_create_client(endpoint).await {
+                    warn!("Failed to preconnect, endpoint:{:?}, err:{}", endpoint, e);
+                }
+            });
+
+        future::join_all(connects).await;
+    }
+
     /// Forward the request according to the configured router.
     ///
     /// Error will be thrown if it happens in the forwarding procedure, that is
-    /// to say, some errors like the output from the `do_rpc` will be
-    /// wrapped in the [`ForwardResult::Forwarded`].
+    /// to say, some errors like the output from the `do_rpc` or the response
+    /// exceeding the `max_response_len` will be wrapped in the
+    /// [`ForwardResult::Forwarded`] with the endpoint the request is forwarded
+    /// to.
     pub async fn forward<Req, Resp, Err, F>(
         &self,
         forward_req: ForwardRequest<Req>,
@@ -274,50 +533,239 @@ impl<B: ClientBuilder> Forwarder<B> {
             dyn std::future::Future<Output = std::result::Result<Resp, Err>> + Send + Unpin,
         >,
         Req: std::fmt::Debug + Clone,
+        Resp: prost::Message,
+        Err: From<Error>,
+    {
+        let (endpoint, req) = match self.prepare_forward(forward_req).await? {
+            Some(v) => v,
+            None => return Ok(ForwardResult::Original),
+        };
+
+        self.do_forward(endpoint, req, do_rpc).await
+    }
+
+    /// Forward the request to the `endpoint` known by the caller, e.g. the
+    /// one resolved by a prior routing, so the router is bypassed.
+    ///
+    /// Like [Forwarder::forward], [ForwardResult::Original] is returned if the
+    /// request should not be forwarded or the `endpoint` is the local one.
+    pub async fn forward_to<Req, Resp, Err, F>(
+        &self,
+        endpoint: Endpoint,
+        forward_req: ForwardRequest<Req>,
+        do_rpc: F,
+    ) -> Result<ForwardResult<Resp, Err>>
+    where
+        F: FnOnce(
+            StorageServiceClient<Channel>,
+            tonic::Request<Req>,
+            &Endpoint,
+        ) -> Box<
+            dyn std::future::Future<Output = std::result::Result<Resp, Err>> + Send + Unpin,
+        >,
+        Req: std::fmt::Debug + Clone,
+        Resp: prost::Message,
+        Err: From<Error>,
     {
-        if !self.config.enable {
+        if !self.should_forward(forward_req.kind) {
             return Ok(ForwardResult::Original);
         }
 
+        let ForwardRequest {
+            schema,
+            req,
+            timeout,
+            ..
+        } = forward_req;
+        let (endpoint, req) = match self.prepare_request(schema, endpoint, req, timeout)? {
+            Some(v) => v,
+            None => return Ok(ForwardResult::Original),
+        };
+
+        self.do_forward(endpoint, req, do_rpc).await
+    }
+
+    async fn do_forward<Req, Resp, Err, F>(
+        &self,
+        endpoint: Endpoint,
+        req: tonic::Request<Req>,
+        do_rpc: F,
+    ) -> Result<ForwardResult<Resp, Err>>
+    where
+        F: FnOnce(
+            StorageServiceClient<Channel>,
+            tonic::Request<Req>,
+            &Endpoint,
+        ) -> Box<
+            dyn std::future::Future<Output = std::result::Result<Resp, Err>> + Send + Unpin,
+        >,
+        Resp: prost::Message,
+        Err: From<Error>,
+    {
+        let client = self.get_or_create_client(&endpoint).await?;
+        let result = match do_rpc(client, req, &endpoint).await {
+            Ok(resp) => check_response_len(self.config.max_response_len, &endpoint, &resp)
+                .map(|_| resp)
+                .map_err(Err::from),
+            Err(e) => {
+                // Release the grpc client for the error doesn't belong to the normal error.
+                self.release_client(&endpoint);
+                Err(e)
+            }
+        };
+
+        Ok(ForwardResult::Forwarded { endpoint, result })
+    }
+
+    /// Like [Forwarder::forward], but forward the streaming request whose
+    /// `do_rpc` returns a stream of the responses.
+    ///
+    /// The responses are piped through the returned stream, and the client is
+    /// released once the rpc or any response in the stream fails. Every
+    /// response is checked against the `max_response_len` in the config too.
+    pub async fn forward_stream<Req, Resp, Err, F>(
+        &self,
+        forward_req: ForwardRequest<Req>,
+        do_rpc: F,
+    ) -> Result<ForwardResult<ForwardStream<Resp, Err>, Err>>
+    where
+        F: FnOnce(
+            StorageServiceClient<Channel>,
+            tonic::Request<Req>,
+            &Endpoint,
+        ) -> Box<
+            dyn std::future::Future<Output = std::result::Result<ForwardStream<Resp, Err>, Err>>
+                + Send
+                + Unpin,
+        >,
+        Req: std::fmt::Debug + Clone,
+        Resp: prost::Message + 'static,
+        Err: From<Error> + Send + 'static,
+    {
+        let (endpoint, req) = match self.prepare_forward(forward_req).await? {
+            Some(v) => v,
+            None => return Ok(ForwardResult::Original),
+        };
+
+        let client = self.get_or_create_client(&endpoint).await?;
+        let result = match do_rpc(client, req, &endpoint).await {
+            Ok(stream) => {
+                let clients = self.clients.clone();
+                let client_cache_gauge = self.client_cache_gauge.clone();
+                let stream_endpoint = endpoint.clone();
+                let endpoint_for_check = endpoint.clone();
+                let max_response_len = self.config.max_response_len;
+                let mut released = false;
+                let stream = stream
+                    .inspect(move |resp| {
+                        if resp.is_err() && !released {
+                            released = true;
+                            release_client(&clients, &client_cache_gauge, &stream_endpoint);
+                        }
+                    })
+                    .map(move |resp| -> std::result::Result<Resp, Err> {
+                        let resp = resp?;
+                        check_response_len(max_response_len, &endpoint_for_check, &resp)?;
+                        Ok(resp)
+                    });
+                Ok(stream.boxed())
+            }
+            Err(e) => {
+                self.release_client(&endpoint);
+                Err(e)
+            }
+        };
+
+        Ok(ForwardResult::Forwarded { endpoint, result })
+    }
+
+    /// Decide the endpoint to forward the request to, and inject the headers
+    /// into the request.
+    ///
+    /// None is returned if the request should not be forwarded.
+    async fn prepare_forward<Req>(
+        &self,
+        forward_req: ForwardRequest<Req>,
+    ) -> Result<Option<(Endpoint, tonic::Request<Req>)>>
+    where
+        Req: std::fmt::Debug + Clone,
+    {
+        if !self.should_forward(forward_req.kind) {
+            return Ok(None);
+        }
+
         let ForwardRequest {
             schema,
             metric,
-            mut req,
+            req,
+            timeout,
+            ..
         } = forward_req;
 
         let route_req = RouteRequest {
             metrics: vec![metric],
         };
 
-        let endpoint = match self.router.route(&schema, route_req).await {
+        let timer = FORWARD_ROUTE_DURATION_HISTOGRAM
+            .with_label_values(&[&schema])
+            .start_timer();
+        let route_res = self.router.route(&schema, route_req).await;
+        timer.observe_duration();
+
+        let endpoint = match route_res {
             Ok(mut routes) => {
                 if routes.len() != 1 || routes[0].endpoint.is_none() {
                     warn!(
                         "Fail to forward request for multiple route results, routes result:{:?}, req:{:?}",
                         routes, req
                     );
-                    return Ok(ForwardResult::Original);
+                    return Ok(None);
                 }
 
                 Endpoint::from(routes.remove(0).endpoint.unwrap())
             }
             Err(e) => {
+                FORWARD_ROUTE_FAILURE_COUNTER
+                    .with_label_values(&[&schema])
+                    .inc();
                 error!("Fail to route request, req:{:?}, err:{}", req, e);
-                return Ok(ForwardResult::Original);
+                return Ok(None);
             }
         };
 
+        self.prepare_request(schema, endpoint, req, timeout)
+    }
+
+    /// Whether the requests of the `kind` can be forwarded.
+    fn should_forward(&self, kind: RequestKind) -> bool {
+        self.config.enable && !self.config.disabled_request_kinds.contains(&kind)
+    }
+
+    /// Inject the headers into the request to forward to the `endpoint`, and
+    /// the `timeout` overrides the `forward_timeout` in the config if set.
+    ///
+    /// None is returned if the `endpoint` is the local one.
+    fn prepare_request<Req>(
+        &self,
+        schema: String,
+        endpoint: Endpoint,
+        mut req: tonic::Request<Req>,
+        timeout: Option<Duration>,
+    ) -> Result<Option<(Endpoint, tonic::Request<Req>)>>
+    where
+        Req: std::fmt::Debug,
+    {
         if self.is_local_endpoint(&endpoint) {
-            return Ok(ForwardResult::Original);
+            return Ok(None);
         }
 
         // Update the request.
         {
             // TODO: we should use the timeout from the original request.
-            req.set_timeout(self.config.forward_timeout);
+            req.set_timeout(timeout.unwrap_or(self.config.forward_timeout));
             let metadata = req.metadata_mut();
             metadata.insert(
-                TENANT_HEADER,
+                self.tenant_header.clone(),
                 schema.parse().context(InvalidSchema { schema })?,
             );
         }
@@ -327,15 +775,8 @@ impl<B: ClientBuilder> Forwarder<B> {
             "Try to forward request to {:?}, request:{:?}",
             endpoint, req,
         );
-        let client = self.get_or_create_client(&endpoint).await?;
-        match do_rpc(client, req, &endpoint).await {
-            Err(e) => {
-                // Release the grpc client for the error doesn't belong to the normal error.
-                self.release_client(&endpoint);
-                Ok(ForwardResult::Forwarded(Err(e)))
-            }
-            Ok(resp) => Ok(ForwardResult::Forwarded(Ok(resp))),
-        }
+
+        Ok(Some((endpoint, req)))
     }
 
     async fn get_or_create_client(
@@ -356,6 +797,7 @@ impl<B: ClientBuilder> Forwarder<B> {
                 return Ok(v.clone());
             }
             clients.insert(endpoint.clone(), new_client.clone());
+            self.client_cache_gauge.inc();
         }
 
         Ok(new_client)
@@ -363,10 +805,13 @@ impl<B: ClientBuilder> Forwarder<B> {
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
     use ceresdbproto::storage::{QueryRequest, QueryResponse, Route};
-    use futures::FutureExt;
+    use futures::{stream, FutureExt};
     use router::Router;
+    use snafu::GenerateBacktrace;
     use tonic::IntoRequest;
 
     use super::*;
@@ -375,19 +820,17 @@ mod tests {
     fn test_check_loopback_endpoint() {
         let loopback_ips = vec!["127.0.0.1", "127.0.0.2"];
         for loopback_ip in loopback_ips {
-            assert!(Forwarder::<DefaultClientBuilder>::is_loopback_ip(
-                loopback_ip
-            ));
+            assert!(is_loopback_ip(loopback_ip));
         }
 
         let normal_ips = vec!["10.100.10.14", "192.168.1.2", "0.0.0.0"];
         for ip in normal_ips {
-            assert!(!Forwarder::<DefaultClientBuilder>::is_loopback_ip(ip));
+            assert!(!is_loopback_ip(ip));
         }
 
         let invalid_addrs = vec!["hello.world.com", "test", "localhost", ""];
         for ip in invalid_addrs {
-            assert!(!Forwarder::<DefaultClientBuilder>::is_loopback_ip(ip));
+            assert!(!is_loopback_ip(ip));
         }
     }
 
@@ -410,7 +853,7 @@ mod tests {
         }
     }
 
-    struct MockClientBuilder;
+    pub(crate) struct MockClientBuilder;
 
     #[async_trait]
     impl ClientBuilder for MockClientBuilder {
@@ -420,6 +863,64 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_client_compression() {
+        let builder = DefaultClientBuilder {
+            config: Config::default(),
+        };
+        assert!(builder.compression_encoding().is_none());
+
+        let config = Config {
+            compression: Some(Compression::Gzip),
+            ..Default::default()
+        };
+        let builder = DefaultClientBuilder { config };
+        assert_eq!(
+            Some(CompressionEncoding::Gzip),
+            builder.compression_encoding()
+        );
+        // The client can be built without connecting to the endpoint.
+        let (channel, _) = Channel::balance_channel::<usize>(10);
+        let _client = builder.build_client(channel);
+    }
+
+    #[tokio::test]
+    async fn test_client_cache_gauge() {
+        let config = Config {
+            enable: true,
+            ..Default::default()
+        };
+        let mock_router = Arc::new(MockRouter {
+            routing_tables: HashMap::new(),
+        });
+        let local_endpoint = Endpoint::new("192.168.1.1".to_string(), 8831);
+        let mut forwarder = Forwarder::try_new_with_client_builder(
+            config,
+            mock_router as _,
+            local_endpoint,
+            MockClientBuilder,
+        )
+        .unwrap();
+        // Use a standalone gauge to avoid being affected by other forwarders.
+        let gauge = IntGauge::new("test_client_cache_size", "test").unwrap();
+        forwarder.client_cache_gauge = gauge.clone();
+
+        let endpoint0 = Endpoint::new("192.168.1.2".to_string(), 8831);
+        let endpoint1 = Endpoint::new("192.168.1.3".to_string(), 8831);
+        for endpoint in [&endpoint0, &endpoint1, &endpoint0] {
+            forwarder.get_or_create_client(endpoint).await.unwrap();
+        }
+        assert_eq!(2, gauge.get());
+
+        assert!(forwarder.release_client(&endpoint0).is_some());
+        assert_eq!(1, gauge.get());
+        assert!(forwarder.release_client(&endpoint0).is_none());
+        assert_eq!(1, gauge.get());
+
+        drop(forwarder);
+        assert_eq!(0, gauge.get());
+    }
+
     #[tokio::test]
     async fn test_normal_forward() {
         let config = Config {
@@ -469,7 +970,9 @@ mod tests {
             ForwardRequest {
                 schema: "public".to_string(),
                 metric: metric.to_string(),
+                kind: RequestKind::Read,
                 req: query_request.into_request(),
+                timeout: None,
             }
         };
 
@@ -499,12 +1002,719 @@ mod tests {
                 );
             } else {
                 assert!(!forwarder.is_local_endpoint(endpoint));
-                assert!(
-                    matches!(forward_res, ForwardResult::Forwarded(_)),
-                    "endpoint is:{:?}",
-                    endpoint
-                );
+                match forward_res {
+                    ForwardResult::Forwarded {
+                        endpoint: forwarded_endpoint,
+                        result,
+                    } => {
+                        assert_eq!(endpoint, &forwarded_endpoint);
+                        assert!(result.is_ok());
+                    }
+                    ForwardResult::Original => panic!("endpoint is:{:?}", endpoint),
+                }
+            }
+        }
+    }
+
+    /// Router counting the calls to it.
+    #[derive(Default)]
+    pub(crate) struct CountingRouter {
+        pub(crate) num_calls: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl Router for CountingRouter {
+        async fn route(&self, _schema: &str, _req: RouteRequest) -> router::Result<Vec<Route>> {
+            self.num_calls.fetch_add(1, Ordering::SeqCst);
+            Ok(vec![])
+        }
+    }
+
+    /// Router taking `delay` to route, and it fails if `fail` is set.
+    struct SlowRouter {
+        delay: Duration,
+        fail: bool,
+    }
+
+    #[async_trait]
+    impl Router for SlowRouter {
+        async fn route(&self, schema: &str, _req: RouteRequest) -> router::Result<Vec<Route>> {
+            tokio::time::sleep(self.delay).await;
+            if self.fail {
+                return Err(router::Error::RouteNotFound {
+                    schema: schema.to_string(),
+                    backtrace: Backtrace::generate(),
+                });
             }
+            Ok(vec![])
         }
     }
+
+    #[tokio::test]
+    async fn test_route_metrics() {
+        let forward_with_router = |schema: &'static str, router: SlowRouter| async move {
+            let config = Config {
+                enable: true,
+                ..Default::default()
+            };
+            let forwarder = Forwarder::try_new_with_client_builder(
+                config,
+                Arc::new(router) as _,
+                Endpoint::new("192.168.1.1".to_string(), 8831),
+                MockClientBuilder,
+            )
+            .unwrap();
+            let forward_req = ForwardRequest {
+                schema: schema.to_string(),
+                metric: "test_metric".to_string(),
+                kind: RequestKind::Read,
+                req: QueryRequest::default().into_request(),
+                timeout: None,
+            };
+            let do_rpc = |_client, _req: tonic::Request<QueryRequest>, _endpoint: &Endpoint| {
+                Box::new(async move { Ok(QueryResponse::default()) }.boxed()) as _
+            };
+            let res: ForwardResult<QueryResponse, Error> =
+                forwarder.forward(forward_req, do_rpc).await.unwrap();
+            assert!(matches!(res, ForwardResult::Original));
+        };
+        let delay = Duration::from_millis(50);
+
+        // The slow routing is recorded without failure.
+        let schema = "test_route_metrics_slow";
+        forward_with_router(schema, SlowRouter { delay, fail: false }).await;
+        let histogram = FORWARD_ROUTE_DURATION_HISTOGRAM.with_label_values(&[schema]);
+        assert_eq!(1, histogram.get_sample_count());
+        assert!(histogram.get_sample_sum() >= delay.as_secs_f64());
+        assert_eq!(
+            0,
+            FORWARD_ROUTE_FAILURE_COUNTER
+                .with_label_values(&[schema])
+                .get()
+        );
+
+        // The failed routing is recorded as well.
+        let schema = "test_route_metrics_failed";
+        forward_with_router(schema, SlowRouter { delay, fail: true }).await;
+        let histogram = FORWARD_ROUTE_DURATION_HISTOGRAM.with_label_values(&[schema]);
+        assert_eq!(1, histogram.get_sample_count());
+        assert!(histogram.get_sample_sum() >= delay.as_secs_f64());
+        assert_eq!(
+            1,
+            FORWARD_ROUTE_FAILURE_COUNTER
+                .with_label_values(&[schema])
+                .get()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_forward_to_endpoint() {
+        let config = Config {
+            enable: true,
+            tenant_header: "x-test-tenant".to_string(),
+            ..Default::default()
+        };
+        let router = Arc::new(CountingRouter::default());
+        let local_endpoint = Endpoint::new("192.168.1.1".to_string(), 8831);
+        let forwarder = Forwarder::try_new_with_client_builder(
+            config,
+            router.clone() as _,
+            local_endpoint.clone(),
+            MockClientBuilder,
+        )
+        .unwrap();
+
+        let make_forward_req = || ForwardRequest {
+            schema: "test_schema".to_string(),
+            metric: "test_metric".to_string(),
+            kind: RequestKind::Read,
+            req: QueryRequest::default().into_request(),
+            timeout: None,
+        };
+        let remote_endpoint = Endpoint::new("192.168.1.2".to_string(), 8831);
+        let expect_endpoint = remote_endpoint.clone();
+        let do_rpc = move |_client, req: tonic::Request<QueryRequest>, endpoint: &Endpoint| {
+            let tenant = req
+                .metadata()
+                .get("x-test-tenant")
+                .unwrap()
+                .to_str()
+                .unwrap();
+            assert_eq!("test_schema", tenant);
+            assert_eq!(&expect_endpoint, endpoint);
+            Box::new(async move { Ok(QueryResponse::default()) }.boxed()) as _
+        };
+
+        let res: ForwardResult<QueryResponse, Error> = forwarder
+            .forward_to(remote_endpoint.clone(), make_forward_req(), do_rpc)
+            .await
+            .unwrap();
+        match res {
+            ForwardResult::Forwarded { endpoint, result } => {
+                assert_eq!(remote_endpoint, endpoint);
+                assert!(result.is_ok());
+            }
+            ForwardResult::Original => panic!("the request should be forwarded"),
+        }
+        // The client of the endpoint is cached.
+        assert!(forwarder
+            .clients
+            .read()
+            .unwrap()
+            .contains_key(&remote_endpoint));
+
+        // No forwarding to the local endpoint.
+        let do_rpc = |_client,
+                      _req,
+                      _endpoint: &Endpoint|
+         -> Box<
+            dyn std::future::Future<Output = std::result::Result<QueryResponse, Error>>
+                + Send
+                + Unpin,
+        > { panic!("the request should not be forwarded") };
+        let res = forwarder
+            .forward_to(local_endpoint, make_forward_req(), do_rpc)
+            .await
+            .unwrap();
+        assert!(matches!(res, ForwardResult::Original));
+
+        assert_eq!(0, router.num_calls.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_request_kind_from_sql() {
+        let cases = [
+            ("SELECT * FROM t", RequestKind::Read),
+            ("  select 1", RequestKind::Read),
+            ("SHOW CREATE TABLE t", RequestKind::Read),
+            ("DESCRIBE t", RequestKind::Read),
+            ("", RequestKind::Read),
+            ("INSERT INTO t(a) VALUES(1)", RequestKind::Write),
+            ("\n insert into t(a) values(1)", RequestKind::Write),
+            ("CREATE TABLE t(a int)", RequestKind::Write),
+            ("ALTER TABLE t ADD COLUMN b int", RequestKind::Write),
+            ("drop table t", RequestKind::Write),
+        ];
+        for (sql, kind) in cases {
+            assert_eq!(kind, RequestKind::from_sql(sql), "sql:{}", sql);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_skip_disabled_request_kind() {
+        let config = Config {
+            enable: true,
+            disabled_request_kinds: vec![RequestKind::Write],
+            ..Default::default()
+        };
+
+        let test_metric = "test_metric";
+        let remote_endpoint = Endpoint::new("192.168.1.2".to_string(), 8831);
+        let mut routing_tables = HashMap::new();
+        routing_tables.insert(test_metric.to_string(), remote_endpoint.clone());
+        let mock_router = Arc::new(MockRouter { routing_tables });
+
+        let local_endpoint = Endpoint::new("192.168.1.1".to_string(), 8831);
+        let forwarder = Forwarder::try_new_with_client_builder(
+            config,
+            mock_router as _,
+            local_endpoint,
+            MockClientBuilder,
+        )
+        .unwrap();
+
+        let make_forward_req = |kind: RequestKind| {
+            let query_request = QueryRequest {
+                metrics: vec![test_metric.to_string()],
+                ql: "".to_string(),
+            };
+            ForwardRequest {
+                schema: "public".to_string(),
+                metric: test_metric.to_string(),
+                kind,
+                req: query_request.into_request(),
+                timeout: None,
+            }
+        };
+
+        let do_rpc = |_client, _req: tonic::Request<QueryRequest>, _endpoint: &Endpoint| {
+            let resp = QueryResponse::default();
+            Box::new(async move { Ok(resp) }.boxed()) as _
+        };
+
+        let res: Result<ForwardResult<QueryResponse, Error>> = forwarder
+            .forward(make_forward_req(RequestKind::Write), do_rpc)
+            .await;
+        assert!(matches!(
+            res.expect("should succeed in forwarding"),
+            ForwardResult::Original
+        ));
+
+        let res: Result<ForwardResult<QueryResponse, Error>> = forwarder
+            .forward(make_forward_req(RequestKind::Read), do_rpc)
+            .await;
+        match res.expect("should succeed in forwarding") {
+            ForwardResult::Forwarded { endpoint, result } => {
+                assert_eq!(endpoint, remote_endpoint);
+                assert!(result.is_ok());
+            }
+            ForwardResult::Original => panic!("read request should be forwarded"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_forward_with_custom_tenant_header() {
+        let tenant_header = "x-federated-tenant";
+        let config = Config {
+            enable: true,
+            tenant_header: tenant_header.to_string(),
+            ..Default::default()
+        };
+
+        let test_metric = "test_metric";
+        let remote_endpoint = Endpoint::new("192.168.1.2".to_string(), 8831);
+        let mut routing_tables = HashMap::new();
+        routing_tables.insert(test_metric.to_string(), remote_endpoint);
+        let mock_router = Arc::new(MockRouter { routing_tables });
+
+        let local_endpoint = Endpoint::new("192.168.1.1".to_string(), 8831);
+        let forwarder = Forwarder::try_new_with_client_builder(
+            config,
+            mock_router as _,
+            local_endpoint,
+            MockClientBuilder,
+        )
+        .unwrap();
+
+        let query_request = QueryRequest {
+            metrics: vec![test_metric.to_string()],
+            ql: "".to_string(),
+        };
+        let forward_req = ForwardRequest {
+            schema: "public".to_string(),
+            metric: test_metric.to_string(),
+            kind: RequestKind::Read,
+            req: query_request.into_request(),
+            timeout: None,
+        };
+        let do_rpc = |_client, req: tonic::Request<QueryRequest>, _endpoint: &Endpoint| {
+            let tenant = req.metadata().get(tenant_header).unwrap().to_str().unwrap();
+            assert_eq!(tenant, "public");
+            assert!(req.metadata().get(TENANT_HEADER).is_none());
+
+            let resp = QueryResponse::default();
+            Box::new(async move { Ok(resp) }.boxed()) as _
+        };
+
+        let res: Result<ForwardResult<QueryResponse, Error>> =
+            forwarder.forward(forward_req, do_rpc).await;
+        match res.expect("should succeed in forwarding") {
+            ForwardResult::Forwarded { result, .. } => assert!(result.is_ok()),
+            ForwardResult::Original => panic!("request should be forwarded"),
+        }
+
+        // The invalid header name is rejected.
+        let config = Config {
+            enable: true,
+            tenant_header: "invalid header".to_string(),
+            ..Default::default()
+        };
+        let res = Forwarder::try_new_with_client_builder(
+            config,
+            Arc::new(MockRouter {
+                routing_tables: HashMap::new(),
+            }) as _,
+            Endpoint::new("192.168.1.1".to_string(), 8831),
+            MockClientBuilder,
+        );
+        assert!(matches!(res, Err(Error::InvalidTenantHeader { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_forward_with_request_timeout() {
+        let config = Config {
+            enable: true,
+            forward_timeout: Duration::from_secs(60),
+            ..Default::default()
+        };
+
+        let test_metric = "test_metric";
+        let mut routing_tables = HashMap::new();
+        routing_tables.insert(
+            test_metric.to_string(),
+            Endpoint::new("192.168.1.2".to_string(), 8831),
+        );
+        let mock_router = Arc::new(MockRouter { routing_tables });
+
+        let local_endpoint = Endpoint::new("192.168.1.1".to_string(), 8831);
+        let forwarder = Forwarder::try_new_with_client_builder(
+            config,
+            mock_router as _,
+            local_endpoint,
+            MockClientBuilder,
+        )
+        .unwrap();
+
+        let grpc_timeout = |timeout: Duration| {
+            let mut req = tonic::Request::new(());
+            req.set_timeout(timeout);
+            req.metadata().get("grpc-timeout").unwrap().clone()
+        };
+        let forward_with_timeout = |timeout: Option<Duration>, expect: Duration| {
+            let forward_req = ForwardRequest {
+                schema: "public".to_string(),
+                metric: test_metric.to_string(),
+                kind: RequestKind::Read,
+                req: QueryRequest::default().into_request(),
+                timeout,
+            };
+            let expect_timeout = grpc_timeout(expect);
+            let do_rpc = move |_client, req: tonic::Request<QueryRequest>, _: &Endpoint| {
+                assert_eq!(Some(&expect_timeout), req.metadata().get("grpc-timeout"));
+                Box::new(async move { Ok(QueryResponse::default()) }.boxed()) as _
+            };
+            forwarder.forward(forward_req, do_rpc)
+        };
+
+        // The timeout of the request overrides the one in the config.
+        let res: ForwardResult<QueryResponse, Error> =
+            forward_with_timeout(Some(Duration::from_secs(3)), Duration::from_secs(3))
+                .await
+                .unwrap();
+        assert!(matches!(
+            res,
+            ForwardResult::Forwarded { result: Ok(_), .. }
+        ));
+
+        // The timeout in the config is used if the request has none.
+        let res: ForwardResult<QueryResponse, Error> =
+            forward_with_timeout(None, Duration::from_secs(60))
+                .await
+                .unwrap();
+        assert!(matches!(
+            res,
+            ForwardResult::Forwarded { result: Ok(_), .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_reject_oversize_response() {
+        let config = Config {
+            enable: true,
+            max_response_len: 64,
+            ..Default::default()
+        };
+
+        let test_metric = "test_metric";
+        let remote_endpoint = Endpoint::new("192.168.1.2".to_string(), 8831);
+        let mut routing_tables = HashMap::new();
+        routing_tables.insert(test_metric.to_string(), remote_endpoint.clone());
+        let mock_router = Arc::new(MockRouter { routing_tables });
+
+        let local_endpoint = Endpoint::new("192.168.1.1".to_string(), 8831);
+        let forwarder = Forwarder::try_new_with_client_builder(
+            config,
+            mock_router as _,
+            local_endpoint,
+            MockClientBuilder,
+        )
+        .unwrap();
+
+        let make_forward_req = || {
+            let query_request = QueryRequest {
+                metrics: vec![test_metric.to_string()],
+                ql: "".to_string(),
+            };
+            ForwardRequest {
+                schema: "public".to_string(),
+                metric: test_metric.to_string(),
+                kind: RequestKind::Read,
+                req: query_request.into_request(),
+                timeout: None,
+            }
+        };
+        let forward_with_resp_len = |len: usize| {
+            let do_rpc = move |_client, _req: tonic::Request<QueryRequest>, _: &Endpoint| {
+                let resp = QueryResponse {
+                    rows: vec![vec![0; len]],
+                    ..Default::default()
+                };
+                Box::new(async move { Ok::<_, Error>(resp) }.boxed()) as _
+            };
+            forwarder.forward(make_forward_req(), do_rpc)
+        };
+
+        let res = forward_with_resp_len(16).await.unwrap();
+        assert!(matches!(
+            res,
+            ForwardResult::Forwarded { result: Ok(_), .. }
+        ));
+
+        // The oversize response is returned as the forwarded error, so it won't
+        // be executed locally again.
+        let res = forward_with_resp_len(1024).await.unwrap();
+        match res {
+            ForwardResult::Forwarded {
+                result:
+                    Err(Error::ResponseTooLarge {
+                        endpoint,
+                        len,
+                        limit,
+                        ..
+                    }),
+                ..
+            } => {
+                assert_eq!(remote_endpoint, endpoint);
+                assert!(len > 1024);
+                assert_eq!(64, limit);
+            }
+            _ => panic!("oversize response should be rejected"),
+        }
+
+        // Every response in the stream is checked.
+        let do_rpc = |_client, _req: tonic::Request<QueryRequest>, _: &Endpoint| {
+            let resps = [16, 1024]
+                .into_iter()
+                .map(|len| {
+                    Ok(QueryResponse {
+                        rows: vec![vec![0; len]],
+                        ..Default::default()
+                    })
+                })
+                .collect::<Vec<_>>();
+            let stream: ForwardStream<QueryResponse, Error> = stream::iter(resps).boxed();
+            Box::new(async move { Ok(stream) }.boxed()) as _
+        };
+        let res = forwarder
+            .forward_stream(make_forward_req(), do_rpc)
+            .await
+            .unwrap();
+        let mut stream = match res {
+            ForwardResult::Forwarded { result, .. } => result.unwrap(),
+            ForwardResult::Original => panic!("request should be forwarded"),
+        };
+        assert!(stream.next().await.unwrap().is_ok());
+        assert!(matches!(
+            stream.next().await.unwrap(),
+            Err(Error::ResponseTooLarge { .. })
+        ));
+        assert!(stream.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_forward_stream() {
+        let config = Config {
+            enable: true,
+            ..Default::default()
+        };
+
+        let test_metric = "test_metric";
+        let remote_endpoint = Endpoint::new("192.168.1.2".to_string(), 8831);
+        let mut routing_tables = HashMap::new();
+        routing_tables.insert(test_metric.to_string(), remote_endpoint.clone());
+        let mock_router = Arc::new(MockRouter { routing_tables });
+
+        let local_endpoint = Endpoint::new("192.168.1.1".to_string(), 8831);
+        let mut forwarder = Forwarder::try_new_with_client_builder(
+            config,
+            mock_router as _,
+            local_endpoint,
+            MockClientBuilder,
+        )
+        .unwrap();
+        let gauge = IntGauge::new("test_forward_stream_client_cache_size", "test").unwrap();
+        forwarder.client_cache_gauge = gauge.clone();
+
+        let make_forward_req = || {
+            let query_request = QueryRequest {
+                metrics: vec![test_metric.to_string()],
+                ql: "".to_string(),
+            };
+            ForwardRequest {
+                schema: "public".to_string(),
+                metric: test_metric.to_string(),
+                kind: RequestKind::Read,
+                req: query_request.into_request(),
+                timeout: None,
+            }
+        };
+        let make_resp = |i: u8| QueryResponse {
+            rows: vec![vec![i]],
+            ..Default::default()
+        };
+
+        // All the responses are forwarded in order.
+        let do_rpc = |_client, req: tonic::Request<QueryRequest>, _endpoint: &Endpoint| {
+            let tenant = req.metadata().get(TENANT_HEADER).unwrap().to_str().unwrap();
+            assert_eq!(tenant, "public");
+
+            let resps = (0..3).map(|i| Ok(make_resp(i))).collect::<Vec<_>>();
+            let stream: ForwardStream<QueryResponse, Error> = stream::iter(resps).boxed();
+            Box::new(async move { Ok(stream) }.boxed()) as _
+        };
+        let res = forwarder
+            .forward_stream(make_forward_req(), do_rpc)
+            .await
+            .unwrap();
+        let stream = match res {
+            ForwardResult::Forwarded { endpoint, result } => {
+                assert_eq!(remote_endpoint, endpoint);
+                result.unwrap()
+            }
+            ForwardResult::Original => panic!("request should be forwarded"),
+        };
+        let resps = stream.map(|resp| resp.unwrap()).collect::<Vec<_>>().await;
+        assert_eq!(vec![make_resp(0), make_resp(1), make_resp(2)], resps);
+        assert_eq!(1, gauge.get());
+
+        // The client is released once a response fails.
+        let do_rpc = |_client, _req: tonic::Request<QueryRequest>, endpoint: &Endpoint| {
+            let err = Error::LoopbackLocalIpAddr {
+                ip_addr: endpoint.addr.clone(),
+                backtrace: Backtrace::generate(),
+            };
+            let stream: ForwardStream<QueryResponse, Error> =
+                stream::iter(vec![Ok(make_resp(0)), Err(err)]).boxed();
+            Box::new(async move { Ok(stream) }.boxed()) as _
+        };
+        let res = forwarder
+            .forward_stream(make_forward_req(), do_rpc)
+            .await
+            .unwrap();
+        let mut stream = match res {
+            ForwardResult::Forwarded { result, .. } => result.unwrap(),
+            ForwardResult::Original => panic!("request should be forwarded"),
+        };
+        assert_eq!(make_resp(0), stream.next().await.unwrap().unwrap());
+        assert_eq!(1, gauge.get());
+        assert!(stream.next().await.unwrap().is_err());
+        assert!(stream.next().await.is_none());
+        assert_eq!(0, gauge.get());
+    }
+
+    #[derive(Default)]
+    struct CountingClientBuilder {
+        num_connects: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl ClientBuilder for CountingClientBuilder {
+        async fn connect(&self, endpoint: &Endpoint) -> Result<StorageServiceClient<Channel>> {
+            self.num_connects.fetch_add(1, Ordering::Relaxed);
+            MockClientBuilder.connect(endpoint).await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_preconnect() {
+        let config = Config {
+            enable: true,
+            ..Default::default()
+        };
+
+        let endpoint0 = Endpoint::new("192.168.1.2".to_string(), 8831);
+        let endpoint1 = Endpoint::new("192.168.1.3".to_string(), 8831);
+        let mut routing_tables = HashMap::new();
+        routing_tables.insert("metric0".to_string(), endpoint0.clone());
+        routing_tables.insert("metric1".to_string(), endpoint1.clone());
+        let mock_router = Arc::new(MockRouter { routing_tables });
+
+        let local_endpoint = Endpoint::new("192.168.1.1".to_string(), 8831);
+        let mut forwarder = Forwarder::try_new_with_client_builder(
+            config,
+            mock_router as _,
+            local_endpoint.clone(),
+            CountingClientBuilder::default(),
+        )
+        .unwrap();
+        let gauge = IntGauge::new("test_preconnect_client_cache_size", "test").unwrap();
+        forwarder.client_cache_gauge = gauge.clone();
+
+        // The local endpoint is skipped.
+        forwarder
+            .preconnect(&[endpoint0.clone(), endpoint1.clone(), local_endpoint])
+            .await;
+        assert_eq!(
+            2,
+            forwarder
+                .client_builder
+                .num_connects
+                .load(Ordering::Relaxed)
+        );
+        assert_eq!(2, gauge.get());
+
+        // The forwarding reuses the preconnected clients.
+        for (metric, expect_endpoint) in [("metric0", &endpoint0), ("metric1", &endpoint1)] {
+            let query_request = QueryRequest {
+                metrics: vec![metric.to_string()],
+                ql: "".to_string(),
+            };
+            let forward_req = ForwardRequest {
+                schema: "public".to_string(),
+                metric: metric.to_string(),
+                kind: RequestKind::Read,
+                req: query_request.into_request(),
+                timeout: None,
+            };
+            let do_rpc = |_client, _req: tonic::Request<QueryRequest>, _endpoint: &Endpoint| {
+                Box::new(async move { Ok::<_, Error>(QueryResponse::default()) }.boxed()) as _
+            };
+            match forwarder.forward(forward_req, do_rpc).await.unwrap() {
+                ForwardResult::Forwarded { endpoint, result } => {
+                    assert_eq!(expect_endpoint, &endpoint);
+                    assert!(result.is_ok());
+                }
+                ForwardResult::Original => panic!("request should be forwarded"),
+            }
+        }
+        assert_eq!(
+            2,
+            forwarder
+                .client_builder
+                .num_connects
+                .load(Ordering::Relaxed)
+        );
+
+        // Preconnecting the cached endpoints again builds no client.
+        forwarder.preconnect(&[endpoint0, endpoint1]).await;
+        assert_eq!(
+            2,
+            forwarder
+                .client_builder
+                .num_connects
+                .load(Ordering::Relaxed)
+        );
+        assert_eq!(2, gauge.get());
+    }
+
+    #[tokio::test]
+    async fn test_connect_with_retry() {
+        let connect_times = &AtomicUsize::new(0);
+        let mock_connect = move || async move {
+            if connect_times.fetch_add(1, Ordering::Relaxed) == 0 {
+                // Make a transport error by an invalid endpoint.
+                let endpoint = "invalid endpoint".to_string();
+                let err = transport::Endpoint::from_shared(endpoint.clone()).unwrap_err();
+                return Err(Error::Connect {
+                    endpoint,
+                    source: err,
+                    backtrace: Backtrace::generate(),
+                });
+            }
+
+            let (channel, _) = Channel::balance_channel::<usize>(10);
+            Ok(StorageServiceClient::<Channel>::new(channel))
+        };
+
+        // The first connect fails and the retry succeeds.
+        let client = connect_with_retry(1, Duration::from_millis(1), mock_connect).await;
+        assert!(client.is_ok());
+        assert_eq!(2, connect_times.load(Ordering::Relaxed));
+
+        // No retry is allowed.
+        connect_times.store(0, Ordering::Relaxed);
+        let client = connect_with_retry(0, Duration::from_millis(1), mock_connect).await;
+        assert!(client.is_err());
+        assert_eq!(1, connect_times.load(Ordering::Relaxed));
+    }
 }
@@ -3,7 +3,10 @@
 // Grpc server metrics
 
 use lazy_static::lazy_static;
-use prometheus::{exponential_buckets, register_histogram_vec, HistogramVec};
+use prometheus::{
+    exponential_buckets, register_histogram_vec, register_int_counter_vec, register_int_gauge,
+    HistogramVec, IntCounterVec, IntGauge,
+};
 use prometheus_static_metric::{auto_flush_from, make_auto_flush_static_metric};
 
 // Register auto flush static metrics.
@@ -31,6 +34,24 @@ lazy_static! {
             exponential_buckets(0.0005, 2.0, 20).unwrap()
         )
         .unwrap();
+    pub static ref FORWARD_CLIENT_CACHE_GAUGE: IntGauge = register_int_gauge!(
+        "grpc_forward_client_cache_size",
+        "Number of the cached clients in the forwarder"
+    )
+    .unwrap();
+    pub static ref FORWARD_ROUTE_DURATION_HISTOGRAM: HistogramVec = register_histogram_vec!(
+        "grpc_forward_route_duration",
+        "Bucketed histogram of the routing in the forwarder",
+        &["schema"],
+        exponential_buckets(0.0005, 2.0, 20).unwrap()
+    )
+    .unwrap();
+    pub static ref FORWARD_ROUTE_FAILURE_COUNTER: IntCounterVec = register_int_counter_vec!(
+        "grpc_forward_route_failures",
+        "Number of the failed routings in the forwarder",
+        &["schema"]
+    )
+    .unwrap();
 }
 
 // Register thread local metrics with default flush interval (1s).
@@ -31,7 +31,7 @@ use router::{endpoint::Endpoint, RouterRef};
 use snafu::{Backtrace, OptionExt, ResultExt, Snafu};
 use table_engine::engine::EngineRuntimes;
 use tokio::sync::oneshot::{self, Sender};
-use tonic::transport::Server;
+use tonic::{codec::CompressionEncoding, transport::Server};
 
 use crate::{
     grpc::{
@@ -206,6 +206,7 @@ pub struct Builder<Q> {
     cluster: Option<ClusterRef>,
     schema_config_provider: Option<SchemaConfigProviderRef>,
     forward_config: Option<forward::Config>,
+    verbose_error: bool,
 }
 
 impl<Q> Builder<Q> {
@@ -219,6 +220,7 @@ impl<Q> Builder<Q> {
             cluster: None,
             schema_config_provider: None,
             forward_config: None,
+            verbose_error: false,
         }
     }
 
@@ -263,6 +265,11 @@ impl<Q> Builder<Q> {
         self.forward_config = Some(config);
         self
     }
+
+    pub fn verbose_error(mut self, verbose_error: bool) -> Self {
+        self.verbose_error = verbose_error;
+        self
+    }
 }
 
 impl<Q: QueryExecutor + 'static> Builder<Q> {
@@ -292,6 +299,7 @@ impl<Q: QueryExecutor + 'static> Builder<Q> {
         };
 
         let forward_config = self.forward_config.unwrap_or_default();
+        let forward_compression = forward_config.compression;
         let forwarder = if forward_config.enable {
             let local_endpoint =
                 Endpoint::from_str(&self.local_endpoint.context(MissingLocalEndpoint)?)
@@ -311,8 +319,15 @@ impl<Q: QueryExecutor + 'static> Builder<Q> {
             runtimes,
             schema_config_provider,
             forwarder,
+            verbose_error: self.verbose_error,
         };
-        let rpc_server = StorageServiceServer::new(storage_service);
+        // Accept the compressed requests forwarded by other servers, and only
+        // compress the responses if the compression of forwarding is configured.
+        let mut rpc_server =
+            StorageServiceServer::new(storage_service).accept_compressed(CompressionEncoding::Gzip);
+        if let Some(compression) = forward_compression {
+            rpc_server = rpc_server.send_compressed(compression.into());
+        }
 
         let serve_addr = self.endpoint.parse().context(InvalidRpcServeAddr)?;
 
@@ -7,7 +7,7 @@ use common_util::define_result;
 use http::StatusCode;
 use snafu::Snafu;
 
-use crate::error_util;
+use crate::{error_util, grpc::forward};
 
 define_result!(Error);
 
@@ -45,12 +45,42 @@ impl Error {
             }
         }
     }
+
+    /// Get the error message with the full cause, including the backtrace
+    /// carried by the cause if any.
+    pub fn verbose_error_message(&self) -> String {
+        match self {
+            Error::ErrNoCause { msg, .. } => msg.clone(),
+
+            Error::ErrWithCause { msg, source, .. } => {
+                format!("{}. Caused by: {}", msg, source)
+            }
+        }
+    }
+}
+
+impl From<forward::Error> for Error {
+    fn from(e: forward::Error) -> Self {
+        Error::ErrWithCause {
+            code: StatusCode::INTERNAL_SERVER_ERROR,
+            msg: "Failed to forward request".to_string(),
+            source: Box::new(e),
+        }
+    }
 }
 
-pub fn build_err_header(err: Error) -> ResponseHeader {
+/// Build the response header from the `err`, the full error cause is returned
+/// if `verbose` is true, otherwise only the first line of the cause.
+pub fn build_err_header(err: Error, verbose: bool) -> ResponseHeader {
+    let error = if verbose {
+        err.verbose_error_message()
+    } else {
+        err.error_message()
+    };
+
     ResponseHeader {
         code: err.code().as_u16() as u32,
-        error: err.error_message(),
+        error,
     }
 }
 
@@ -79,3 +109,51 @@ impl From<router::Error> for Error {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use snafu::{Backtrace, GenerateBacktrace};
+
+    use super::*;
+
+    #[derive(Debug, Snafu)]
+    enum TableError {
+        #[snafu(display("Failed to read table.\nBacktrace:\n{}", backtrace))]
+        ReadTable { backtrace: Backtrace },
+    }
+
+    fn new_err_with_cause() -> Error {
+        let source = TableError::ReadTable {
+            backtrace: Backtrace::generate(),
+        };
+        Error::ErrWithCause {
+            code: StatusCode::INTERNAL_SERVER_ERROR,
+            msg: "fail to query".to_string(),
+            source: Box::new(source),
+        }
+    }
+
+    #[test]
+    fn test_build_err_header() {
+        let header = build_err_header(new_err_with_cause(), false);
+        assert_eq!(500, header.code);
+        assert_eq!(
+            "fail to query. Caused by: Failed to read table.",
+            header.error
+        );
+
+        let header = build_err_header(new_err_with_cause(), true);
+        assert_eq!(500, header.code);
+        assert!(header
+            .error
+            .starts_with("fail to query. Caused by: Failed to read table.\nBacktrace:\n"));
+
+        let err = Error::ErrNoCause {
+            code: StatusCode::BAD_REQUEST,
+            msg: "invalid request".to_string(),
+        };
+        let header = build_err_header(err, true);
+        assert_eq!(400, header.code);
+        assert_eq!("invalid request", header.error);
+    }
+}
@@ -171,6 +171,8 @@ pub struct StorageServiceImpl<Q: QueryExecutor + 'static> {
     pub runtimes: Arc<EngineRuntimes>,
     pub schema_config_provider: SchemaConfigProviderRef,
     pub forwarder: Option<ForwarderRef>,
+    /// Whether to return the full error cause to the callers.
+    pub verbose_error: bool,
 }
 
 impl<Q: QueryExecutor + 'static> Clone for StorageServiceImpl<Q> {
@@ -181,6 +183,7 @@ impl<Q: QueryExecutor + 'static> Clone for StorageServiceImpl<Q> {
             runtimes: self.runtimes.clone(),
             schema_config_provider: self.schema_config_provider.clone(),
             forwarder: self.forwarder.clone(),
+            verbose_error: self.verbose_error,
         }
     }
 }
@@ -246,7 +249,7 @@ macro_rules! handle_request {
                     Ok(Ok(v)) => v,
                     Ok(Err(e)) | Err(e) => {
                         let mut resp = $resp_ty::default();
-                        let header = error::build_err_header(e);
+                        let header = error::build_err_header(e, self.verbose_error);
                         resp.header = Some(header);
                         resp
                     },
@@ -317,7 +320,7 @@ impl<Q: QueryExecutor + 'static> StorageServiceImpl<Q> {
             match write_result {
                 Ok(write_resp) => total_success += write_resp.success,
                 Err(e) => {
-                    resp.header = Some(error::build_err_header(e));
+                    resp.header = Some(error::build_err_header(e, self.verbose_error));
                     has_err = true;
                     break;
                 }
@@ -434,7 +437,7 @@ impl<Q: QueryExecutor + 'static> StorageService for StorageServiceImpl<Q> {
         let resp = match self.stream_write_internal(request).await {
             Ok(resp) => resp,
             Err(e) => WriteResponse {
-                header: Some(error::build_err_header(e)),
+                header: Some(error::build_err_header(e, self.verbose_error)),
                 ..Default::default()
             },
         };
@@ -445,24 +448,26 @@ impl<Q: QueryExecutor + 'static> StorageService for StorageServiceImpl<Q> {
         &self,
         request: tonic::Request<QueryRequest>,
     ) -> std::result::Result<tonic::Response<Self::StreamQueryStream>, tonic::Status> {
+        let verbose_error = self.verbose_error;
         match self.stream_query_internal(request).await {
             Ok(stream) => {
-                let new_stream: Self::StreamQueryStream = Box::pin(stream.map(|res| match res {
-                    Ok(resp) => Ok(resp),
-                    Err(e) => {
-                        let resp = QueryResponse {
-                            header: Some(error::build_err_header(e)),
-                            ..Default::default()
-                        };
-                        Ok(resp)
-                    }
-                }));
+                let new_stream: Self::StreamQueryStream =
+                    Box::pin(stream.map(move |res| match res {
+                        Ok(resp) => Ok(resp),
+                        Err(e) => {
+                            let resp = QueryResponse {
+                                header: Some(error::build_err_header(e, verbose_error)),
+                                ..Default::default()
+                            };
+                            Ok(resp)
+                        }
+                    }));
 
                 Ok(tonic::Response::new(new_stream))
             }
             Err(e) => {
                 let resp = QueryResponse {
-                    header: Some(error::build_err_header(e)),
+                    header: Some(error::build_err_header(e, verbose_error)),
                     ..Default::default()
                 };
                 let stream = stream::once(async { Ok(resp) });
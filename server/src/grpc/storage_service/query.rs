@@ -15,7 +15,7 @@ use common_util::{avro, time::InstantExt};
 use futures::FutureExt;
 use http::StatusCode;
 use interpreters::{context::Context as InterpreterContext, factory::Factory, interpreter::Output};
-use log::{error, info, warn};
+use log::{debug, error, info, warn};
 use query_engine::executor::{Executor as QueryExecutor, RecordBatchVec};
 use router::endpoint::Endpoint;
 use snafu::{ensure, ResultExt};
@@ -26,7 +26,7 @@ use sql::{
 use tonic::{transport::Channel, IntoRequest};
 
 use crate::grpc::{
-    forward::{ForwardRequest, ForwardResult},
+    forward::{ClientBuilder, ForwardRequest, ForwardResult, Forwarder, RequestKind},
     storage_service::{
         error::{ErrNoCause, ErrWithCause, Result},
         HandlerContext,
@@ -48,11 +48,14 @@ fn empty_ok_resp() -> QueryResponse {
     }
 }
 
-async fn maybe_forward_query<Q: QueryExecutor + 'static>(
-    ctx: &HandlerContext<'_, Q>,
+/// Forward the query to the endpoint of its metric, and the kind of the
+/// request is classified by the sql so the writes can be kept local.
+async fn maybe_forward_query<B: ClientBuilder>(
+    forwarder: Option<&Forwarder<B>>,
+    schema: &str,
     req: &QueryRequest,
 ) -> Option<Result<QueryResponse>> {
-    let forwarder = ctx.forwarder.as_ref()?;
+    let forwarder = forwarder?;
 
     if req.metrics.len() != 1 {
         warn!(
@@ -64,9 +67,11 @@ async fn maybe_forward_query<Q: QueryExecutor + 'static>(
     }
 
     let forward_req = ForwardRequest {
-        schema: ctx.schema.clone(),
+        schema: schema.to_string(),
         metric: req.metrics[0].clone(),
+        kind: RequestKind::from_sql(&req.ql),
         req: req.clone().into_request(),
+        timeout: None,
     };
     let do_query = |mut client: StorageServiceClient<Channel>,
                     request: tonic::Request<QueryRequest>,
@@ -89,7 +94,10 @@ async fn maybe_forward_query<Q: QueryExecutor + 'static>(
 
     match forwarder.forward(forward_req, do_query).await {
         Ok(forward_res) => match forward_res {
-            ForwardResult::Forwarded(v) => Some(v),
+            ForwardResult::Forwarded { endpoint, result } => {
+                debug!("Query is forwarded, endpoint:{:?}, req:{:?}", endpoint, req);
+                Some(result)
+            }
             ForwardResult::Original => None,
         },
         Err(e) => {
@@ -103,7 +111,7 @@ pub async fn handle_query<Q: QueryExecutor + 'static>(
     ctx: &HandlerContext<'_, Q>,
     req: QueryRequest,
 ) -> Result<QueryResponse> {
-    let req = match maybe_forward_query(ctx, &req).await {
+    let req = match maybe_forward_query(ctx.forwarder.as_deref(), &ctx.schema, &req).await {
         Some(resp) => return resp,
         None => req,
     };
@@ -288,3 +296,60 @@ pub fn convert_records(records: &[RecordBatch]) -> Result<QueryResponse> {
 
     Ok(resp)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{atomic::Ordering, Arc},
+        time::Duration,
+    };
+
+    use super::*;
+    use crate::grpc::forward::{
+        tests::{CountingRouter, MockClientBuilder},
+        Config as ForwardConfig,
+    };
+
+    #[tokio::test]
+    async fn test_skip_forwarding_disabled_write_query() {
+        let config = ForwardConfig {
+            enable: true,
+            disabled_request_kinds: vec![RequestKind::Write],
+            ..Default::default()
+        };
+        let router = Arc::new(CountingRouter::default());
+        let forwarder = Forwarder::try_new_with_client_builder(
+            config,
+            router.clone() as _,
+            Endpoint::new("192.168.1.1".to_string(), 8831),
+            MockClientBuilder,
+        )
+        .unwrap();
+
+        let forward = |ql: &str| {
+            let req = QueryRequest {
+                metrics: vec!["test_metric".to_string()],
+                ql: ql.to_string(),
+            };
+            let forwarder = &forwarder;
+            async move {
+                tokio::time::timeout(
+                    Duration::from_secs(5),
+                    maybe_forward_query(Some(forwarder), "public", &req),
+                )
+                .await
+                .expect("should not forward to the remote")
+            }
+        };
+
+        // The insert is kept local without routing.
+        assert!(forward("INSERT INTO test_metric(t, v) VALUES(1, 1)")
+            .await
+            .is_none());
+        assert_eq!(0, router.num_calls.load(Ordering::SeqCst));
+
+        // The select is routed, but no route is found.
+        assert!(forward("SELECT * FROM test_metric").await.is_none());
+        assert_eq!(1, router.num_calls.load(Ordering::SeqCst));
+    }
+}
@@ -4,7 +4,7 @@
 
 use snafu::{Backtrace, Snafu};
 
-use crate::limiter;
+use crate::{limiter, running_query};
 // TODO(yingwen): Avoid printing huge sql string
 // TODO(yingwen): Maybe add an error type to sql sub mod
 
@@ -55,6 +55,85 @@ pub enum Error {
         query: String,
         source: limiter::Error,
     },
+
+    #[snafu(display("Failed to register query, err:{}", source))]
+    RegisterQuery { source: running_query::Error },
+
+    #[snafu(display(
+        "Query is canceled, request_id:{}.\nBacktrace:\n{}",
+        request_id,
+        backtrace
+    ))]
+    QueryCanceled {
+        request_id: u64,
+        backtrace: Backtrace,
+    },
+
+    #[snafu(display(
+        "Running query not found, tenant:{}, request_id:{}.\nBacktrace:\n{}",
+        tenant,
+        request_id,
+        backtrace
+    ))]
+    QueryNotFound {
+        tenant: String,
+        request_id: u64,
+        backtrace: Backtrace,
+    },
+
+    #[snafu(display(
+        "Failed to find table, catalog:{}, schema:{}, table:{}, err:{}",
+        catalog,
+        schema,
+        table,
+        source
+    ))]
+    FindTable {
+        catalog: String,
+        schema: String,
+        table: String,
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    #[snafu(display(
+        "Table not found, catalog:{}, schema:{}, table:{}.\nBacktrace:\n{}",
+        catalog,
+        schema,
+        table,
+        backtrace
+    ))]
+    TableNotFound {
+        catalog: String,
+        schema: String,
+        table: String,
+        backtrace: Backtrace,
+    },
+
+    #[snafu(display(
+        "Failed to import sst, catalog:{}, schema:{}, table:{}, err:{}",
+        catalog,
+        schema,
+        table,
+        source
+    ))]
+    ImportSst {
+        catalog: String,
+        schema: String,
+        table: String,
+        source: table_engine::table::Error,
+    },
+
+    #[snafu(display(
+        "Failed to route metric, schema:{}, metric:{}, err:{}",
+        schema,
+        metric,
+        source
+    ))]
+    RouteMetric {
+        schema: String,
+        metric: String,
+        source: router::Error,
+    },
 }
 
 define_result!(Error);
@@ -4,7 +4,9 @@
 
 pub mod admin;
 pub mod error;
+pub mod route;
 pub mod sql;
+pub mod table;
 
 mod prelude {
     pub use catalog::manager::Manager as CatalogManager;
@@ -0,0 +1,153 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+//! Route request handler
+
+use ceresdbproto::storage::RouteRequest as RouteRequestPb;
+use router::{endpoint::Endpoint, RouterRef};
+
+use crate::{
+    grpc::forward,
+    handlers::{error::RouteMetric, prelude::*},
+};
+
+#[derive(Debug, Deserialize)]
+pub struct RouteRequest {
+    pub schema: String,
+    pub metric: String,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct RouteItem {
+    pub metric: String,
+    /// Endpoint in the form of `addr:port`, `None` if the router returns no
+    /// endpoint for the metric.
+    pub endpoint: Option<String>,
+    /// Whether the endpoint is the local server, that is to say, the request
+    /// won't be forwarded.
+    pub is_local: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RouteResponse {
+    pub schema: String,
+    pub routes: Vec<RouteItem>,
+}
+
+pub async fn handle_route(
+    router: &RouterRef,
+    local_endpoint: &Endpoint,
+    request: RouteRequest,
+) -> Result<RouteResponse> {
+    let RouteRequest { schema, metric } = request;
+    let route_req = RouteRequestPb {
+        metrics: vec![metric.clone()],
+    };
+    let routes = router
+        .route(&schema, route_req)
+        .await
+        .context(RouteMetric {
+            schema: &schema,
+            metric,
+        })?;
+
+    let routes = routes
+        .into_iter()
+        .map(|route| {
+            let endpoint = route.endpoint.map(Endpoint::from);
+            let is_local = endpoint
+                .as_ref()
+                .map_or(false, |v| forward::is_local_endpoint(local_endpoint, v));
+            RouteItem {
+                metric: route.metric,
+                endpoint: endpoint.map(|v| v.to_string()),
+                is_local,
+            }
+        })
+        .collect();
+
+    Ok(RouteResponse { schema, routes })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::Arc};
+
+    use async_trait::async_trait;
+    use ceresdbproto::storage::Route;
+    use router::Router;
+    use snafu::OptionExt;
+
+    use super::*;
+
+    struct MockRouter {
+        routing_tables: HashMap<String, Endpoint>,
+    }
+
+    #[async_trait]
+    impl Router for MockRouter {
+        async fn route(&self, schema: &str, req: RouteRequestPb) -> router::Result<Vec<Route>> {
+            let endpoint = self.routing_tables.get(&req.metrics[0]).with_context(|| {
+                router::ShardNotFound {
+                    schema,
+                    table: &req.metrics[0],
+                }
+            })?;
+            Ok(vec![Route {
+                metric: req.metrics[0].clone(),
+                endpoint: Some(endpoint.clone().into()),
+                ext: vec![],
+            }])
+        }
+    }
+
+    #[tokio::test]
+    async fn test_handle_route() {
+        let local_endpoint = Endpoint::new("192.168.1.1".to_string(), 8831);
+        let remote_endpoint = Endpoint::new("192.168.1.2".to_string(), 8831);
+        let loopback_endpoint = Endpoint::new("127.0.0.1".to_string(), 8831);
+        let mut routing_tables = HashMap::new();
+        routing_tables.insert("local".to_string(), local_endpoint.clone());
+        routing_tables.insert("remote".to_string(), remote_endpoint.clone());
+        routing_tables.insert("loopback".to_string(), loopback_endpoint.clone());
+        let router: RouterRef = Arc::new(MockRouter { routing_tables });
+
+        let cases = [
+            ("local", local_endpoint.clone(), true),
+            ("remote", remote_endpoint, false),
+            ("loopback", loopback_endpoint, true),
+        ];
+        for (metric, endpoint, is_local) in cases {
+            let request = RouteRequest {
+                schema: "public".to_string(),
+                metric: metric.to_string(),
+            };
+            let resp = handle_route(&router, &local_endpoint, request)
+                .await
+                .unwrap();
+            assert_eq!("public", resp.schema);
+            assert_eq!(
+                vec![RouteItem {
+                    metric: metric.to_string(),
+                    endpoint: Some(endpoint.to_string()),
+                    is_local,
+                }],
+                resp.routes
+            );
+        }
+
+        let request = RouteRequest {
+            schema: "public".to_string(),
+            metric: "unknown".to_string(),
+        };
+        let err = handle_route(&router, &local_endpoint, request)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::RouteMetric {
+                source: router::Error::ShardNotFound { .. },
+                ..
+            }
+        ));
+    }
+}
@@ -2,7 +2,10 @@
 
 //! SQL request handler
 
-use std::time::Instant;
+use std::{
+    future::Future,
+    time::{Duration, Instant},
+};
 
 use arrow::error::Result as ArrowResult;
 use common_types::{
@@ -12,23 +15,32 @@ use common_types::{
 };
 use common_util::time::InstantExt;
 use interpreters::{context::Context as InterpreterContext, factory::Factory, interpreter::Output};
-use log::info;
+use log::{info, warn};
 use query_engine::executor::RecordBatchVec;
 use serde::{
     ser::{SerializeMap, SerializeSeq},
     Serialize,
 };
-use snafu::{ensure, ResultExt};
+use snafu::{ensure, OptionExt, ResultExt};
 use sql::{
     frontend::{Context as SqlContext, Frontend},
     provider::CatalogMetaProvider,
 };
 
-use crate::handlers::{
-    error::{ArrowToString, CreatePlan, InterpreterExec, ParseSql, QueryBlock, TooMuchStmt},
-    prelude::*,
+use crate::{
+    handlers::{
+        error::{
+            ArrowToString, CreatePlan, InterpreterExec, ParseSql, QueryBlock, QueryCanceled,
+            QueryNotFound, RegisterQuery, TooMuchStmt,
+        },
+        prelude::*,
+    },
+    metrics::SLOW_QUERY_COUNTER,
 };
 
+/// Max length of the sql printed in the slow query log.
+const MAX_SLOW_QUERY_LOG_LEN: usize = 1024;
+
 #[derive(Debug, Deserialize)]
 pub struct Request {
     query: String,
@@ -105,12 +117,121 @@ impl From<Bytes> for Request {
     }
 }
 
+#[derive(Debug, Serialize)]
+pub struct CancelResponse {
+    pub request_id: u64,
+}
+
+/// Handle the sql request, and the request taking longer than the
+/// `slow_query_threshold` is logged as a slow query.
+///
+/// The request can be canceled by [handle_cancel] with its request id before
+/// it finishes.
 pub async fn handle_sql<Q: QueryExecutor + 'static>(
     ctx: RequestContext,
     instance: InstanceRef<Q>,
     request: Request,
+    slow_query_threshold: Option<Duration>,
+) -> Result<Response> {
+    let request_id = ctx.request_id;
+    let tenant = ctx.tenant.clone();
+    let running_queries = instance.running_queries.clone();
+    let slow_query = slow_query_threshold.map(|threshold| SlowQuery {
+        threshold,
+        catalog: ctx.catalog.clone(),
+        tenant: ctx.tenant.clone(),
+    });
+
+    let query = async {
+        running_queries
+            .run(&tenant, request_id, execute_sql(ctx, instance, &request))
+            .await
+            .context(RegisterQuery)?
+            .context(QueryCanceled {
+                request_id: request_id.as_u64(),
+            })?
+    };
+
+    match slow_query {
+        Some(slow_query) => slow_query.observe(&request.query, query).await,
+        None => query.await,
+    }
+}
+
+/// Cancel the running sql request with given `request_id`, only the request of
+/// the tenant in `ctx` can be canceled.
+pub fn handle_cancel<Q>(
+    ctx: RequestContext,
+    instance: InstanceRef<Q>,
+    request_id: RequestId,
+) -> Result<CancelResponse> {
+    ensure!(
+        instance.running_queries.cancel(&ctx.tenant, request_id),
+        QueryNotFound {
+            tenant: ctx.tenant,
+            request_id: request_id.as_u64(),
+        }
+    );
+
+    info!(
+        "sql handler canceled request, tenant:{}, request_id:{}",
+        ctx.tenant, request_id
+    );
+
+    Ok(CancelResponse {
+        request_id: request_id.as_u64(),
+    })
+}
+
+/// Context to detect and log the slow query.
+struct SlowQuery {
+    threshold: Duration,
+    catalog: String,
+    tenant: String,
+}
+
+impl SlowQuery {
+    /// Run the `query_future` and log the `query` if it takes longer than the
+    /// threshold.
+    async fn observe<T>(&self, query: &str, query_future: impl Future<Output = T>) -> T {
+        let begin_instant = Instant::now();
+        let output = query_future.await;
+        let elapsed = begin_instant.saturating_elapsed();
+
+        if elapsed > self.threshold {
+            SLOW_QUERY_COUNTER.inc();
+            warn!(
+                "Slow query, cost:{}ms, catalog:{}, tenant:{}, sql:{}",
+                elapsed.as_millis(),
+                self.catalog,
+                self.tenant,
+                truncate_sql(query, MAX_SLOW_QUERY_LOG_LEN),
+            );
+        }
+
+        output
+    }
+}
+
+/// Truncate the `sql` to at most `max_len` bytes without breaking a char.
+fn truncate_sql(sql: &str, max_len: usize) -> &str {
+    if sql.len() <= max_len {
+        return sql;
+    }
+
+    let mut end = max_len;
+    while !sql.is_char_boundary(end) {
+        end -= 1;
+    }
+    &sql[..end]
+}
+
+async fn execute_sql<Q: QueryExecutor + 'static>(
+    ctx: RequestContext,
+    instance: InstanceRef<Q>,
+    request: &Request,
 ) -> Result<Response> {
-    let request_id = RequestId::next_id();
+    let request_id = ctx.request_id;
     let begin_instant = Instant::now();
     info!(
         "sql handler try to process request, request_id:{}, request:{:?}",
@@ -145,7 +266,7 @@ pub async fn handle_sql<Q: QueryExecutor + 'static>(
         stmts.len() == 1,
         TooMuchStmt {
             len: stmts.len(),
-            query: request.query,
+            query: &request.query,
         }
     );
 
@@ -242,3 +363,48 @@ fn convert_records(records: RecordBatchVec) -> ArrowResult<Response> {
         data: column_data,
     }))
 }
+
+#[cfg(test)]
+mod tests {
+    use tokio::time;
+
+    use super::*;
+
+    #[test]
+    fn test_truncate_sql() {
+        let cases = [
+            ("SELECT 1", 100, "SELECT 1"),
+            ("SELECT 1", 6, "SELECT"),
+            ("SELECT 1", 0, ""),
+            // Each char takes 3 bytes.
+            ("数据库", 4, "数"),
+            ("数据库", 6, "数据"),
+        ];
+        for (sql, max_len, expect) in cases {
+            assert_eq!(expect, truncate_sql(sql, max_len));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_observe_slow_query() {
+        let slow_query = SlowQuery {
+            threshold: Duration::from_millis(50),
+            catalog: "ceresdb".to_string(),
+            tenant: "public".to_string(),
+        };
+
+        let slow_before = SLOW_QUERY_COUNTER.get();
+        let output = slow_query.observe("SELECT * FROM fast", async { 1 }).await;
+        assert_eq!(1, output);
+        assert_eq!(slow_before, SLOW_QUERY_COUNTER.get());
+
+        let output = slow_query
+            .observe("SELECT * FROM slow", async {
+                time::sleep(Duration::from_millis(100)).await;
+                2
+            })
+            .await;
+        assert_eq!(2, output);
+        assert_eq!(slow_before + 1, SLOW_QUERY_COUNTER.get());
+    }
+}
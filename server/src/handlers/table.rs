@@ -0,0 +1,220 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+//! Table request handler
+
+use common_types::{bytes::Bytes, schema::Schema};
+use snafu::OptionExt;
+use table_engine::table::TableRef;
+
+use crate::handlers::{
+    error::{FindTable, ImportSst, TableNotFound},
+    prelude::*,
+};
+
+#[derive(Debug)]
+pub struct TableSchemaRequest {
+    pub catalog: String,
+    pub schema: String,
+    pub table: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ColumnSchemaResponse {
+    name: String,
+    data_type: String,
+    is_key: bool,
+    is_tag: bool,
+    is_nullable: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TableSchemaResponse {
+    columns: Vec<ColumnSchemaResponse>,
+    timestamp_column: String,
+}
+
+impl From<&Schema> for TableSchemaResponse {
+    fn from(schema: &Schema) -> Self {
+        let columns = schema
+            .columns()
+            .iter()
+            .enumerate()
+            .map(|(idx, column)| ColumnSchemaResponse {
+                name: column.name.clone(),
+                data_type: column.data_type.to_string(),
+                is_key: schema.is_primary_key_index(&idx),
+                is_tag: column.is_tag,
+                is_nullable: column.is_nullable,
+            })
+            .collect();
+
+        Self {
+            columns,
+            timestamp_column: schema.column(schema.timestamp_index()).name.clone(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ImportSstRequest {
+    pub catalog: String,
+    pub schema: String,
+    pub table: String,
+    /// The encoded sst.
+    pub sst: Bytes,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImportSstResponse {
+    size: usize,
+}
+
+pub async fn handle_table_schema<Q: QueryExecutor + 'static>(
+    _ctx: RequestContext,
+    instance: InstanceRef<Q>,
+    request: TableSchemaRequest,
+) -> Result<TableSchemaResponse> {
+    let TableSchemaRequest {
+        catalog,
+        schema,
+        table,
+    } = &request;
+    let table_ref = find_table(&instance, catalog, schema, table)?;
+
+    Ok(TableSchemaResponse::from(&table_ref.schema()))
+}
+
+pub async fn handle_import_sst<Q: QueryExecutor + 'static>(
+    _ctx: RequestContext,
+    instance: InstanceRef<Q>,
+    request: ImportSstRequest,
+) -> Result<ImportSstResponse> {
+    let ImportSstRequest {
+        catalog,
+        schema,
+        table,
+        sst,
+    } = request;
+    let table_ref = find_table(&instance, &catalog, &schema, &table)?;
+
+    let size = sst.len();
+    table_ref.import_sst(sst).await.context(ImportSst {
+        catalog: &catalog,
+        schema: &schema,
+        table: &table,
+    })?;
+
+    Ok(ImportSstResponse { size })
+}
+
+fn find_table<Q>(
+    instance: &InstanceRef<Q>,
+    catalog: &str,
+    schema: &str,
+    table: &str,
+) -> Result<TableRef> {
+    let catalog_ref = instance
+        .catalog_manager
+        .catalog_by_name(catalog)
+        .map_err(|e| Box::new(e) as _)
+        .context(FindTable {
+            catalog,
+            schema,
+            table,
+        })?
+        .context(TableNotFound {
+            catalog,
+            schema,
+            table,
+        })?;
+    let schema_ref = catalog_ref
+        .schema_by_name(schema)
+        .map_err(|e| Box::new(e) as _)
+        .context(FindTable {
+            catalog,
+            schema,
+            table,
+        })?
+        .context(TableNotFound {
+            catalog,
+            schema,
+            table,
+        })?;
+    schema_ref
+        .table_by_name(table)
+        .map_err(|e| Box::new(e) as _)
+        .context(FindTable {
+            catalog,
+            schema,
+            table,
+        })?
+        .context(TableNotFound {
+            catalog,
+            schema,
+            table,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use common_types::{column_schema, datum::DatumKind, schema};
+
+    use super::*;
+
+    #[test]
+    fn test_table_schema_response() {
+        let schema = schema::Builder::new()
+            .auto_increment_column_id(true)
+            .add_key_column(
+                column_schema::Builder::new("host".to_string(), DatumKind::String)
+                    .is_nullable(false)
+                    .build()
+                    .unwrap(),
+            )
+            .unwrap()
+            .add_key_column(
+                column_schema::Builder::new("ts".to_string(), DatumKind::Timestamp)
+                    .is_nullable(false)
+                    .build()
+                    .unwrap(),
+            )
+            .unwrap()
+            .add_normal_column(
+                column_schema::Builder::new("region".to_string(), DatumKind::String)
+                    .is_tag(true)
+                    .is_nullable(true)
+                    .build()
+                    .unwrap(),
+            )
+            .unwrap()
+            .add_normal_column(
+                column_schema::Builder::new("value".to_string(), DatumKind::Double)
+                    .is_nullable(true)
+                    .build()
+                    .unwrap(),
+            )
+            .unwrap()
+            .build()
+            .unwrap();
+
+        let response = TableSchemaResponse::from(&schema);
+        assert_eq!("ts", response.timestamp_column);
+
+        let expect_columns = vec![
+            ("host", "string", true, false, false),
+            ("ts", "timestamp", true, false, false),
+            ("region", "string", false, true, true),
+            ("value", "double", false, false, true),
+        ];
+        assert_eq!(expect_columns.len(), response.columns.len());
+        for (column, (name, data_type, is_key, is_tag, is_nullable)) in
+            response.columns.iter().zip(expect_columns)
+        {
+            assert_eq!(name, column.name);
+            assert_eq!(data_type, column.data_type);
+            assert_eq!(is_key, column.is_key);
+            assert_eq!(is_tag, column.is_tag);
+            assert_eq!(is_nullable, column.is_nullable);
+        }
+    }
+}
@@ -3,31 +3,63 @@
 //! Http service
 
 use std::{
-    collections::HashMap, convert::Infallible, error::Error as StdError, net::IpAddr, sync::Arc,
+    collections::HashMap,
+    convert::Infallible,
+    error::Error as StdError,
+    future::Future,
+    io::Write,
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::Duration,
 };
 
-use log::error;
+use bytes::Bytes;
+use common_types::request_id::RequestId;
+use common_util::runtime::JoinHandle;
+use flate2::write::GzEncoder;
+use futures::FutureExt;
+use hyper::{
+    server::{conn::AddrIncoming, Builder as ServerBuilder},
+    service::make_service_fn,
+};
+use log::{error, warn};
 use logger::RuntimeLevel;
 use profile::Profiler;
 use query_engine::executor::Executor as QueryExecutor;
-use router::endpoint::Endpoint;
-use serde_derive::Serialize;
+use regex::Regex;
+use router::{endpoint::Endpoint, RouterRef};
+use serde_derive::{Deserialize, Serialize};
 use snafu::{Backtrace, OptionExt, ResultExt, Snafu};
 use table_engine::{engine::EngineRuntimes, table::FlushRequest};
-use tokio::sync::oneshot::{self, Sender};
+use tokio::{
+    sync::oneshot::{self, Receiver, Sender},
+    time,
+};
 use warp::{
     header,
-    http::StatusCode,
+    http::{
+        header::{HeaderValue, ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_TYPE, RETRY_AFTER, VARY},
+        StatusCode,
+    },
+    path::FullPath,
     reject,
     reply::{self, Reply},
     Filter,
 };
 
 use crate::{
+    active_requests::{ActiveRequestGuard, ActiveRequests, ActiveRequestsRef},
+    blocking_pool::{self, BlockingPool, BlockingPoolRef},
     consts,
     context::RequestContext,
     error_util,
-    handlers::{self, sql::Request},
+    handlers::{
+        self,
+        route::RouteRequest,
+        sql::Request,
+        table::{ImportSstRequest, TableSchemaRequest},
+    },
+    idle_conn::IdleTimeoutIncoming,
     instance::InstanceRef,
     metrics,
 };
@@ -54,6 +86,12 @@ pub enum Error {
     #[snafu(display("Missing instance to build service.\nBacktrace:\n{}", backtrace))]
     MissingInstance { backtrace: Backtrace },
 
+    #[snafu(display("Missing router to build service.\nBacktrace:\n{}", backtrace))]
+    MissingRouter { backtrace: Backtrace },
+
+    #[snafu(display("Missing local endpoint to build service.\nBacktrace:\n{}", backtrace))]
+    MissingLocalEndpoint { backtrace: Backtrace },
+
     #[snafu(display(
         "Fail to do heap profiling, err:{}.\nBacktrace:\n{}",
         source,
@@ -64,8 +102,11 @@ pub enum Error {
         backtrace: Backtrace,
     },
 
-    #[snafu(display("Fail to join async task, err:{}.", source))]
-    JoinAsyncTask { source: common_util::runtime::Error },
+    #[snafu(display("Failed to build blocking pool, err:{}", source))]
+    BuildBlockingPool { source: blocking_pool::Error },
+
+    #[snafu(display("Failed to run blocking task, err:{}", source))]
+    RunBlockingTask { source: blocking_pool::Error },
 
     #[snafu(display(
         "Failed to parse ip addr, ip:{}, err:{}.\nBacktrace:\n{}",
@@ -79,10 +120,41 @@ pub enum Error {
         backtrace: Backtrace,
     },
 
+    #[snafu(display("Failed to bind addr, addr:{}, err:{}", addr, source))]
+    BindAddr {
+        addr: SocketAddr,
+        source: hyper::Error,
+    },
+
+    #[snafu(display(
+        "Invalid regex to filter metrics, regex:{}, err:{}.\nBacktrace:\n{}",
+        regex,
+        source,
+        backtrace
+    ))]
+    InvalidMetricsRegex {
+        regex: String,
+        source: regex::Error,
+        backtrace: Backtrace,
+    },
+
+    #[snafu(display(
+        "Invalid json body, expect a json like {{\"query\": \"<sql>\"}} or the sql as plain text, err:{}.\nBacktrace:\n{}",
+        source,
+        backtrace
+    ))]
+    InvalidJsonBody {
+        source: serde_json::Error,
+        backtrace: Backtrace,
+    },
+
     #[snafu(display("Internal err:{}.", source))]
     Internal {
         source: Box<dyn StdError + Send + Sync>,
     },
+
+    #[snafu(display("Server is overloaded, retry_after:{:?}, msg:{}", retry_after, msg))]
+    Overloaded { msg: String, retry_after: Duration },
 }
 
 define_result!(Error);
@@ -90,6 +162,16 @@ define_result!(Error);
 impl reject::Reject for Error {}
 
 pub const DEFAULT_MAX_BODY_SIZE: u64 = 64 * 1024;
+pub const DEFAULT_MAX_IMPORT_SST_SIZE: u64 = 256 * 1024 * 1024;
+pub const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+pub const DEFAULT_TCP_KEEPALIVE: Duration = Duration::from_secs(60);
+pub const DEFAULT_HTTP2_KEEPALIVE_TIMEOUT: Duration = Duration::from_secs(20);
+pub const DEFAULT_SLOW_QUERY_THRESHOLD: Duration = Duration::from_secs(5);
+pub const DEFAULT_BLOCKING_THREAD_NUM: usize = 2;
+pub const DEFAULT_BLOCKING_QUEUE_LEN: usize = 16;
+const BLOCKING_POOL_NAME: &str = "ceres-http-blocking";
+/// Delay to retry if the server is unavailable but no delay is specified.
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(1);
 
 /// Http service
 ///
@@ -97,28 +179,59 @@ pub const DEFAULT_MAX_BODY_SIZE: u64 = 64 * 1024;
 pub struct Service<Q> {
     engine_runtimes: Arc<EngineRuntimes>,
     log_runtime: Arc<RuntimeLevel>,
+    /// Pool to run the cpu heavy work of the handlers, e.g. profiling.
+    blocking_pool: BlockingPoolRef,
     instance: InstanceRef<Q>,
+    router: RouterRef,
+    /// The grpc endpoint of this server, used to tell whether a route is
+    /// local.
+    local_endpoint: Endpoint,
     profiler: Arc<Profiler>,
+    /// The in-flight requests of the service.
+    active_requests: ActiveRequestsRef,
     tx: Sender<()>,
+    server_handle: Option<JoinHandle<()>>,
     config: HttpConfig,
 }
 
 impl<Q> Service<Q> {
-    // TODO(yingwen): Maybe log error or return error
-    pub fn stop(self) {
+    pub fn config(&self) -> &HttpConfig {
+        &self.config
+    }
+
+    /// Stop the service and wait for the server to exit, which is forcibly
+    /// dropped if the in-flight requests are not finished within the
+    /// `shutdown_timeout` in [HttpConfig].
+    pub async fn stop(self) {
         let _ = self.tx.send(());
+
+        if let Some(server_handle) = self.server_handle {
+            if let Err(e) = server_handle.await {
+                error!("Failed to wait for http service to stop, err:{}", e);
+            }
+        }
     }
 }
 
 impl<Q: QueryExecutor + 'static> Service<Q> {
     fn routes(&self) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
-        self.home()
+        let routes = self
+            .home()
             .or(self.metrics())
             .or(self.sql())
+            .or(self.cancel_sql())
             .or(self.heap_profile())
+            .or(self.debug_route())
+            .or(self.debug_requests())
             .or(self.admin_block())
             .or(self.flush_memtable())
             .or(self.update_log_level())
+            .or(self.table_schema())
+            .or(self.import_sst());
+
+        track_active_request(self.active_requests.clone())
+            .and(routes)
+            .map(|_guard: ActiveRequestGuard, reply| reply)
     }
 
     fn home(&self) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
@@ -131,19 +244,17 @@ impl<Q: QueryExecutor + 'static> Service<Q> {
 
     // TODO(yingwen): Avoid boilterplate code if there are more handlers
     fn sql(&self) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
-        // accept json or plain text
-        let extract_request = warp::body::json()
-            .or(warp::body::bytes().map(Request::from))
-            .unify();
-
+        let slow_query_threshold = self.config.slow_query_threshold;
         warp::path!("sql")
             .and(warp::post())
             .and(warp::body::content_length_limit(self.config.max_body_size))
-            .and(extract_request)
+            .and(sql_request())
             .and(self.with_context())
             .and(self.with_instance())
-            .and_then(|req, ctx, instance| async move {
-                let result = handlers::sql::handle_sql(ctx, instance, req)
+            .and_then(move |req, ctx: RequestContext, instance| async move {
+                // Return the request id to the client, as it may be generated by the server.
+                let request_id = ctx.request_id;
+                let result = handlers::sql::handle_sql(ctx, instance, req, slow_query_threshold)
                     .await
                     .map_err(|e| {
                         // TODO(yingwen): Maybe truncate and print the sql
@@ -151,6 +262,33 @@ impl<Q: QueryExecutor + 'static> Service<Q> {
                         Box::new(e)
                     })
                     .context(HandleRequest);
+                match result {
+                    Ok(res) => Ok(reply::with_header(
+                        reply::json(&res),
+                        consts::REQUEST_ID_HEADER,
+                        request_id.to_string(),
+                    )),
+                    Err(e) => Err(reject::custom(e)),
+                }
+            })
+    }
+
+    // DELETE /sql/{request_id}
+    fn cancel_sql(
+        &self,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("sql" / u64)
+            .and(warp::delete())
+            .and(self.with_context())
+            .and(self.with_instance())
+            .and_then(|request_id, ctx, instance| async move {
+                let result =
+                    handlers::sql::handle_cancel(ctx, instance, RequestId::from(request_id))
+                        .map_err(|e| {
+                            error!("Http service failed to cancel sql, err:{}", e);
+                            Box::new(e)
+                        })
+                        .context(HandleRequest);
                 match result {
                     Ok(res) => Ok(reply::json(&res)),
                     Err(e) => Err(reject::custom(e)),
@@ -213,8 +351,9 @@ impl<Q: QueryExecutor + 'static> Service<Q> {
             })
     }
 
+    // GET /metrics?prefix=xxx&regex=xxx
     fn metrics(&self) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
-        warp::path!("metrics").and(warp::get()).map(metrics::dump)
+        metrics_route()
     }
 
     fn heap_profile(
@@ -223,14 +362,22 @@ impl<Q: QueryExecutor + 'static> Service<Q> {
         warp::path!("debug" / "heap_profile" / ..)
             .and(warp::path::param::<u64>())
             .and(warp::get())
-            .and(self.with_context())
+            .and(self.with_blocking_pool())
             .and(self.with_profiler())
             .and_then(
-                |duration_sec: u64, ctx: RequestContext, profiler: Arc<Profiler>| async move {
-                    let handle = ctx.runtime.spawn_blocking(move || {
-                        profiler.dump_mem_prof(duration_sec).context(ProfileHeap)
-                    });
-                    let result = handle.await.context(JoinAsyncTask);
+                |duration_sec: u64, pool: BlockingPoolRef, profiler: Arc<Profiler>| async move {
+                    let result = pool
+                        .run(move || match profiler.dump_mem_prof(duration_sec) {
+                            // Assume the ongoing profiling lasts as long as this one.
+                            Err(profile::Error::Busy) => Overloaded {
+                                msg: "another heap profiling is in progress",
+                                retry_after: Duration::from_secs(duration_sec),
+                            }
+                            .fail(),
+                            res => res.context(ProfileHeap),
+                        })
+                        .await
+                        .context(RunBlockingTask);
                     match result {
                         Ok(Ok(prof_data)) => Ok(prof_data.into_response()),
                         Ok(Err(e)) => Err(reject::custom(e)),
@@ -240,6 +387,41 @@ impl<Q: QueryExecutor + 'static> Service<Q> {
             )
     }
 
+    // GET /debug/route?schema=xxx&metric=xxx
+    fn debug_route(
+        &self,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        let local_endpoint = self.local_endpoint.clone();
+        warp::path!("debug" / "route")
+            .and(warp::get())
+            .and(warp::query::<RouteRequest>())
+            .and(self.with_router())
+            .and_then(move |req, router: RouterRef| {
+                let local_endpoint = local_endpoint.clone();
+                async move {
+                    let result = handlers::route::handle_route(&router, &local_endpoint, req)
+                        .await
+                        .map_err(|e| {
+                            error!("Http service failed to handle route, err:{}", e);
+                            Box::new(e)
+                        })
+                        .context(HandleRequest);
+
+                    match result {
+                        Ok(res) => Ok(reply::json(&res)),
+                        Err(e) => Err(reject::custom(e)),
+                    }
+                }
+            })
+    }
+
+    // GET /debug/requests
+    fn debug_requests(
+        &self,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        list_active_requests(self.active_requests.clone())
+    }
+
     fn update_log_level(
         &self,
     ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
@@ -259,6 +441,66 @@ impl<Q: QueryExecutor + 'static> Service<Q> {
             )
     }
 
+    // GET /tables/{catalog}/{schema}/{table}/schema
+    fn table_schema(
+        &self,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("tables" / String / String / String / "schema")
+            .and(warp::get())
+            .and(self.with_context())
+            .and(self.with_instance())
+            .and_then(|catalog, schema, table, ctx, instance| async move {
+                let request = TableSchemaRequest {
+                    catalog,
+                    schema,
+                    table,
+                };
+                let result = handlers::table::handle_table_schema(ctx, instance, request)
+                    .await
+                    .map_err(|e| {
+                        error!("Http service failed to handle table schema, err:{}", e);
+                        Box::new(e)
+                    })
+                    .context(HandleRequest);
+
+                match result {
+                    Ok(res) => Ok(reply::json(&res)),
+                    Err(e) => Err(reject::custom(e)),
+                }
+            })
+    }
+
+    // POST /tables/{catalog}/{schema}/{table}/sst
+    fn import_sst(
+        &self,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("tables" / String / String / String / "sst")
+            .and(warp::post())
+            .and(sst_body(self.config.max_import_sst_size))
+            .and(self.with_context())
+            .and(self.with_instance())
+            .and_then(|catalog, schema, table, sst, ctx, instance| async move {
+                let request = ImportSstRequest {
+                    catalog,
+                    schema,
+                    table,
+                    sst,
+                };
+                let result = handlers::table::handle_import_sst(ctx, instance, request)
+                    .await
+                    .map_err(|e| {
+                        error!("Http service failed to handle import sst, err:{}", e);
+                        Box::new(e)
+                    })
+                    .context(HandleRequest);
+
+                match result {
+                    Ok(res) => Ok(reply::json(&res)),
+                    Err(e) => Err(reject::custom(e)),
+                }
+            })
+    }
+
     fn with_context(
         &self,
     ) -> impl Filter<Extract = (RequestContext,), Error = warp::Rejection> + Clone {
@@ -277,21 +519,32 @@ impl<Q: QueryExecutor + 'static> Service<Q> {
 
         header::optional::<String>(consts::CATALOG_HEADER)
             .and(header::optional::<String>(consts::TENANT_HEADER))
-            .and_then(move |catalog: Option<_>, tenant: Option<_>| {
-                // Clone the captured variables
-                let default_catalog = default_catalog.clone();
-                let default_schema = default_schema.clone();
-                let runtime = runtime.clone();
-                async {
-                    RequestContext::builder()
-                        .catalog(catalog.unwrap_or(default_catalog))
-                        .tenant(tenant.unwrap_or(default_schema))
-                        .runtime(runtime)
-                        .build()
-                        .context(CreateContext)
-                        .map_err(reject::custom)
-                }
-            })
+            .and(header::optional::<u64>(consts::REQUEST_ID_HEADER))
+            .and_then(
+                move |catalog: Option<_>, tenant: Option<_>, request_id: Option<u64>| {
+                    // Clone the captured variables
+                    let default_catalog = default_catalog.clone();
+                    let default_schema = default_schema.clone();
+                    let runtime = runtime.clone();
+                    async move {
+                        let mut builder = RequestContext::builder()
+                            .catalog(catalog.unwrap_or(default_catalog))
+                            .tenant(tenant.unwrap_or(default_schema))
+                            .runtime(runtime);
+                        if let Some(request_id) = request_id {
+                            builder = builder.request_id(RequestId::from(request_id));
+                        }
+                        builder.context(CreateContext).map_err(reject::custom)
+                    }
+                },
+            )
+    }
+
+    fn with_blocking_pool(
+        &self,
+    ) -> impl Filter<Extract = (BlockingPoolRef,), Error = Infallible> + Clone {
+        let blocking_pool = self.blocking_pool.clone();
+        warp::any().map(move || blocking_pool.clone())
     }
 
     fn with_profiler(&self) -> impl Filter<Extract = (Arc<Profiler>,), Error = Infallible> + Clone {
@@ -306,6 +559,11 @@ impl<Q: QueryExecutor + 'static> Service<Q> {
         warp::any().map(move || instance.clone())
     }
 
+    fn with_router(&self) -> impl Filter<Extract = (RouterRef,), Error = Infallible> + Clone {
+        let router = self.router.clone();
+        warp::any().map(move || router.clone())
+    }
+
     fn with_log_runtime(
         &self,
     ) -> impl Filter<Extract = (Arc<RuntimeLevel>,), Error = Infallible> + Clone {
@@ -344,6 +602,8 @@ pub struct Builder<Q> {
     engine_runtimes: Option<Arc<EngineRuntimes>>,
     log_runtime: Option<Arc<RuntimeLevel>>,
     instance: Option<InstanceRef<Q>>,
+    router: Option<RouterRef>,
+    local_endpoint: Option<Endpoint>,
 }
 
 impl<Q> Builder<Q> {
@@ -353,6 +613,8 @@ impl<Q> Builder<Q> {
             engine_runtimes: None,
             log_runtime: None,
             instance: None,
+            router: None,
+            local_endpoint: None,
         }
     }
 
@@ -370,6 +632,16 @@ impl<Q> Builder<Q> {
         self.instance = Some(instance);
         self
     }
+
+    pub fn router(mut self, router: RouterRef) -> Self {
+        self.router = Some(router);
+        self
+    }
+
+    pub fn local_endpoint(mut self, local_endpoint: Endpoint) -> Self {
+        self.local_endpoint = Some(local_endpoint);
+        self
+    }
 }
 
 impl<Q: QueryExecutor + 'static> Builder<Q> {
@@ -378,14 +650,27 @@ impl<Q: QueryExecutor + 'static> Builder<Q> {
         let engine_runtime = self.engine_runtimes.context(MissingEngineRuntimes)?;
         let log_runtime = self.log_runtime.context(MissingLogRuntime)?;
         let instance = self.instance.context(MissingInstance)?;
+        let router = self.router.context(MissingRouter)?;
+        let local_endpoint = self.local_endpoint.context(MissingLocalEndpoint)?;
+        let blocking_pool = BlockingPool::try_new(
+            BLOCKING_POOL_NAME,
+            self.config.blocking_thread_num,
+            self.config.blocking_queue_len,
+        )
+        .context(BuildBlockingPool)?;
         let (tx, rx) = oneshot::channel();
 
-        let service = Service {
+        let mut service = Service {
             engine_runtimes: engine_runtime.clone(),
             log_runtime,
+            blocking_pool: Arc::new(blocking_pool),
             instance,
+            router,
+            local_endpoint,
             profiler: Arc::new(Profiler::default()),
+            active_requests: Arc::new(ActiveRequests::default()),
             tx,
+            server_handle: None,
             config: self.config.clone(),
         };
 
@@ -395,24 +680,127 @@ impl<Q: QueryExecutor + 'static> Builder<Q> {
 
         // Register filters to warp and rejection handler
         let routes = service.routes().recover(handle_rejection);
-        let (_addr, server) = warp::serve(routes).bind_with_graceful_shutdown(
-            (ip_addr, self.config.endpoint.port),
-            async {
-                rx.await.ok();
-            },
-        );
+        let warp_service = warp::service(routes);
+        let make_service = make_service_fn(move |_| {
+            let warp_service = warp_service.clone();
+            async move { Ok::<_, Infallible>(warp_service) }
+        });
+        let (graceful_tx, graceful_rx) = oneshot::channel();
+        let (_addr, server_builder) = bind_server(
+            SocketAddr::new(ip_addr, self.config.endpoint.port),
+            &self.config,
+        )?;
+        let server = server_builder
+            .serve(make_service)
+            .with_graceful_shutdown(async {
+                graceful_rx.await.ok();
+            })
+            .map(|res| {
+                if let Err(e) = res {
+                    error!("Http server exits with error, err:{}", e);
+                }
+            });
         // Run the service
-        engine_runtime.bg_runtime.spawn(server);
+        let server_handle = engine_runtime.bg_runtime.spawn(serve_with_shutdown_timeout(
+            server,
+            rx,
+            graceful_tx,
+            self.config.shutdown_timeout,
+        ));
+        service.server_handle = Some(server_handle);
 
         Ok(service)
     }
 }
 
+/// Bind the `addr` and apply the keepalive and idle timeout settings in
+/// `config` to the server, the actual bound address is also returned.
+fn bind_server(
+    addr: SocketAddr,
+    config: &HttpConfig,
+) -> Result<(SocketAddr, ServerBuilder<IdleTimeoutIncoming>)> {
+    let mut incoming = AddrIncoming::bind(&addr).context(BindAddr { addr })?;
+    incoming.set_keepalive(config.tcp_keepalive);
+    let local_addr = incoming.local_addr();
+    let incoming = IdleTimeoutIncoming::new(incoming, config.idle_timeout);
+
+    let builder = hyper::Server::builder(incoming)
+        .http2_keep_alive_interval(config.http2_keepalive_interval)
+        .http2_keep_alive_timeout(config.http2_keepalive_timeout);
+
+    Ok((local_addr, builder))
+}
+
+/// Run the `server` until the stop signal is received from `stop_rx`, and
+/// then notify the server to shutdown gracefully through `graceful_tx`. The
+/// server will be dropped if the graceful shutdown is not done in
+/// `shutdown_timeout`.
+async fn serve_with_shutdown_timeout<F>(
+    server: F,
+    stop_rx: Receiver<()>,
+    graceful_tx: Sender<()>,
+    shutdown_timeout: Duration,
+) where
+    F: Future<Output = ()>,
+{
+    tokio::pin!(server);
+
+    tokio::select! {
+        _ = &mut server => return,
+        _ = stop_rx => {},
+    }
+
+    let _ = graceful_tx.send(());
+    if time::timeout(shutdown_timeout, server).await.is_err() {
+        warn!(
+            "Http service is forcibly shutdown because the graceful shutdown is not done in {:?}",
+            shutdown_timeout
+        );
+    }
+}
+
+/// Read the whole body of the sst to import, which is limited by
+/// `max_import_sst_size` instead of the `max_body_size` of the other requests.
+fn sst_body(
+    max_import_sst_size: u64,
+) -> impl Filter<Extract = (Bytes,), Error = warp::Rejection> + Clone {
+    warp::body::content_length_limit(max_import_sst_size).and(warp::body::bytes())
+}
+
 /// Http service config
 #[derive(Debug, Clone)]
 pub struct HttpConfig {
     pub endpoint: Endpoint,
     pub max_body_size: u64,
+    /// Max size of the sst imported through the http service, which is
+    /// usually much larger than the other requests.
+    pub max_import_sst_size: u64,
+    /// Max time to wait for the in-flight requests when shutting down
+    pub shutdown_timeout: Duration,
+    /// Interval of the TCP keepalive probes, disabled if it is `None`.
+    pub tcp_keepalive: Option<Duration>,
+    /// Interval of the HTTP/2 keepalive pings, disabled if it is `None`.
+    pub http2_keepalive_interval: Option<Duration>,
+    /// Timeout to receive the acknowledgement of the HTTP/2 keepalive ping.
+    pub http2_keepalive_timeout: Duration,
+    /// The connections without any reading or writing for the duration are
+    /// closed, disabled if it is `None`.
+    pub idle_timeout: Option<Duration>,
+    /// The sql queries taking longer than it are logged as slow queries,
+    /// disabled if it is `None`.
+    pub slow_query_threshold: Option<Duration>,
+    /// Number of the threads to run the cpu heavy work of the handlers.
+    pub blocking_thread_num: usize,
+    /// Max number of the cpu heavy tasks waiting for the threads, the request
+    /// is rejected if it is exceeded.
+    pub blocking_queue_len: usize,
+}
+
+/// Query params to filter the metrics by their names.
+#[derive(Debug, Deserialize)]
+struct MetricsQuery {
+    prefix: Option<String>,
+    regex: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -421,33 +809,224 @@ struct ErrorResponse {
     message: String,
 }
 
+fn handler_error_to_status_code(err: &handlers::error::Error) -> StatusCode {
+    use handlers::error::Error as HandlerError;
+    use router::Error as RouterError;
+    use sql::{frontend::Error as FrontendError, planner::Error as PlannerError};
+
+    match err {
+        HandlerError::ParseSql { .. } | HandlerError::TooMuchStmt { .. } => StatusCode::BAD_REQUEST,
+        HandlerError::CreatePlan { source, .. } => match source {
+            FrontendError::CreatePlan {
+                source: PlannerError::TableNotFound { .. },
+            } => StatusCode::NOT_FOUND,
+            _ => StatusCode::BAD_REQUEST,
+        },
+        HandlerError::QueryBlock { .. } => StatusCode::FORBIDDEN,
+        HandlerError::RegisterQuery { .. } => StatusCode::CONFLICT,
+        HandlerError::QueryNotFound { .. } => StatusCode::NOT_FOUND,
+        // Same as the nginx's "Client Closed Request".
+        HandlerError::QueryCanceled { .. } => StatusCode::from_u16(499).unwrap(),
+        HandlerError::ImportSst { .. } => StatusCode::BAD_REQUEST,
+        HandlerError::TableNotFound { .. } => StatusCode::NOT_FOUND,
+        HandlerError::RouteMetric { source, .. } => match source {
+            RouterError::RouteNotFound { .. } | RouterError::ShardNotFound { .. } => {
+                StatusCode::NOT_FOUND
+            }
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        },
+        HandlerError::InterpreterExec { .. }
+        | HandlerError::ArrowToString { .. }
+        | HandlerError::FindTable { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+/// Dump the metrics, and the output is compressed if the client accepts
+/// gzip.
+fn metrics_route() -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("metrics")
+        .and(warp::get())
+        .and(warp::query::<MetricsQuery>())
+        .and(header::optional::<String>(ACCEPT_ENCODING.as_str()))
+        .and_then(
+            |query: MetricsQuery, accept_encoding: Option<String>| async move {
+                let regex = match &query.regex {
+                    Some(regex) => Some(
+                        Regex::new(regex)
+                            .context(InvalidMetricsRegex { regex })
+                            .map_err(reject::custom)?,
+                    ),
+                    None => None,
+                };
+                let filter = |name: &str| {
+                    query
+                        .prefix
+                        .as_ref()
+                        .map_or(true, |prefix| name.starts_with(prefix))
+                        && regex.as_ref().map_or(true, |regex| regex.is_match(name))
+                };
+
+                let body = metrics::dump_filtered(filter);
+                compressed_reply(body, accept_encoding.as_deref()).map_err(reject::custom)
+            },
+        )
+}
+
+/// Build the text reply of the `body`, which is compressed by gzip if it is
+/// accepted by the `accept_encoding` header of the request.
+fn compressed_reply(body: String, accept_encoding: Option<&str>) -> Result<reply::Response> {
+    if !accept_encoding.map_or(false, accepts_gzip) {
+        return Ok(body.into_response());
+    }
+
+    let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder
+        .write_all(body.as_bytes())
+        .map_err(|e| Box::new(e) as _)
+        .context(Internal)?;
+    let compressed = encoder
+        .finish()
+        .map_err(|e| Box::new(e) as _)
+        .context(Internal)?;
+
+    let mut resp = reply::Response::new(compressed.into());
+    let headers = resp.headers_mut();
+    headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("text/plain; charset=utf-8"),
+    );
+    headers.insert(CONTENT_ENCODING, HeaderValue::from_static("gzip"));
+    headers.insert(VARY, HeaderValue::from_static("accept-encoding"));
+
+    Ok(resp)
+}
+
+/// Whether gzip is accepted by the `accept_encoding` header, e.g.
+/// `gzip, deflate` or `gzip;q=0.8`, and a zero quality means not acceptable.
+fn accepts_gzip(accept_encoding: &str) -> bool {
+    accept_encoding.split(',').any(|coding| {
+        let mut parts = coding.split(';').map(str::trim);
+        let name = parts.next().unwrap_or_default();
+        if !name.eq_ignore_ascii_case("gzip") && name != "*" {
+            return false;
+        }
+
+        let quality = parts
+            .find_map(|param| param.strip_prefix("q="))
+            .map_or(Some(1.0), |v| v.parse::<f64>().ok());
+        quality.map_or(false, |v| v > 0.0)
+    })
+}
+
+/// Register the request to the `active_requests` until the request is
+/// finished, and the guard of the registration is extracted.
+fn track_active_request(
+    active_requests: ActiveRequestsRef,
+) -> impl Filter<Extract = (ActiveRequestGuard,), Error = warp::Rejection> + Clone {
+    warp::path::full()
+        .and(header::optional::<String>(consts::TENANT_HEADER))
+        .map(move |path: FullPath, tenant: Option<String>| {
+            active_requests.register(path.as_str().to_string(), tenant)
+        })
+}
+
+/// List the in-flight requests in `active_requests`, the longest running one
+/// comes first.
+fn list_active_requests(
+    active_requests: ActiveRequestsRef,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("debug" / "requests")
+        .and(warp::get())
+        .map(move || reply::json(&active_requests.list()))
+}
+
+/// Extract the sql request from the body, which is either a json like
+/// `{"query": "<sql>"}` or the sql as plain text.
+///
+/// The body must be a valid json if the content type says so, while the body
+/// without content type is taken as plain text if it isn't a valid json.
+fn sql_request() -> impl Filter<Extract = (Request,), Error = warp::Rejection> + Clone {
+    header::optional::<String>(CONTENT_TYPE.as_str())
+        .and(warp::body::bytes())
+        .and_then(|content_type: Option<String>, body: Bytes| async move {
+            match content_type {
+                Some(content_type) if is_json_content_type(&content_type) => {
+                    serde_json::from_slice::<Request>(&body)
+                        .context(InvalidJsonBody)
+                        .map_err(reject::custom)
+                }
+                Some(_) => Ok(Request::from(body)),
+                None => Ok(serde_json::from_slice::<Request>(&body)
+                    .unwrap_or_else(|_| Request::from(body))),
+            }
+        })
+}
+
+/// Whether the `content_type` is json, the parameters like charset are
+/// ignored.
+fn is_json_content_type(content_type: &str) -> bool {
+    let mime = content_type.split(';').next().unwrap_or_default();
+    mime.trim().eq_ignore_ascii_case("application/json")
+}
+
 fn error_to_status_code(err: &Error) -> StatusCode {
     match err {
-        Error::CreateContext { .. } => StatusCode::BAD_REQUEST,
-        // TODO(yingwen): Map handle request error to more accurate status code
-        Error::HandleRequest { .. }
-        | Error::MissingEngineRuntimes { .. }
+        Error::CreateContext { .. }
+        | Error::InvalidMetricsRegex { .. }
+        | Error::InvalidJsonBody { .. } => StatusCode::BAD_REQUEST,
+        Error::HandleRequest { source } => handler_error_to_status_code(source),
+        Error::MissingEngineRuntimes { .. }
         | Error::MissingLogRuntime { .. }
         | Error::MissingInstance { .. }
+        | Error::MissingRouter { .. }
+        | Error::MissingLocalEndpoint { .. }
         | Error::ParseIpAddr { .. }
+        | Error::BindAddr { .. }
         | Error::ProfileHeap { .. }
         | Error::Internal { .. }
-        | Error::JoinAsyncTask { .. }
+        | Error::BuildBlockingPool { .. }
         | Error::HandleUpdateLogLevel { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+        Error::RunBlockingTask { source } => match source {
+            blocking_pool::Error::PoolBusy { .. } => StatusCode::SERVICE_UNAVAILABLE,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        },
+        Error::Overloaded { .. } => StatusCode::SERVICE_UNAVAILABLE,
+    }
+}
+
+/// The delay the client should wait before retrying, only available if the
+/// server is overloaded.
+fn error_to_retry_after(err: &Error) -> Option<Duration> {
+    match err {
+        Error::Overloaded { retry_after, .. } => Some(*retry_after),
+        _ => None,
     }
 }
 
+/// Format the `Retry-After` header value in seconds, which is rounded up and
+/// at least one second.
+fn retry_after_header_value(retry_after: Duration) -> HeaderValue {
+    let mut secs = retry_after.as_secs();
+    if retry_after.subsec_nanos() > 0 {
+        secs += 1;
+    }
+
+    HeaderValue::from(secs.max(1))
+}
+
 async fn handle_rejection(
     rejection: warp::Rejection,
 ) -> std::result::Result<impl warp::Reply, Infallible> {
     let code;
     let message;
+    let mut retry_after = None;
 
     if rejection.is_not_found() {
         code = StatusCode::NOT_FOUND;
         message = String::from("NOT_FOUND");
     } else if let Some(err) = rejection.find() {
         code = error_to_status_code(err);
+        retry_after = error_to_retry_after(err);
         let err_string = err.to_string();
         message = error_util::first_line_in_error(&err_string).to_string();
     } else {
@@ -461,5 +1040,464 @@ async fn handle_rejection(
         message,
     });
 
-    Ok(reply::with_status(json, code))
+    let mut resp = reply::with_status(json, code).into_response();
+    if code == StatusCode::SERVICE_UNAVAILABLE {
+        let retry_after = retry_after.unwrap_or(DEFAULT_RETRY_AFTER);
+        resp.headers_mut()
+            .insert(RETRY_AFTER, retry_after_header_value(retry_after));
+    }
+
+    Ok(resp)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Read, time::Instant};
+
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpStream,
+        sync::Notify,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_handler_error_to_status_code() {
+        use handlers::error::Error as HandlerError;
+        use snafu::GenerateBacktrace;
+        use sql::{
+            frontend::Error as FrontendError, parser::Parser, planner::Error as PlannerError,
+        };
+
+        let new_parse_error = || FrontendError::InvalidSql {
+            sql: "SELEC".to_string(),
+            source: Parser::parse_sql("SELEC").unwrap_err(),
+        };
+        let cases = vec![
+            (
+                HandlerError::ParseSql {
+                    source: new_parse_error(),
+                },
+                StatusCode::BAD_REQUEST,
+            ),
+            (
+                HandlerError::CreatePlan {
+                    query: "DROP TABLE t".to_string(),
+                    source: FrontendError::CreatePlan {
+                        source: PlannerError::TableNotFound {
+                            name: "t".to_string(),
+                        },
+                    },
+                },
+                StatusCode::NOT_FOUND,
+            ),
+            (
+                HandlerError::CreatePlan {
+                    query: "SELEC".to_string(),
+                    source: new_parse_error(),
+                },
+                StatusCode::BAD_REQUEST,
+            ),
+            (
+                HandlerError::QueryCanceled {
+                    request_id: 1,
+                    backtrace: Backtrace::generate(),
+                },
+                StatusCode::from_u16(499).unwrap(),
+            ),
+            (
+                HandlerError::QueryNotFound {
+                    request_id: 1,
+                    backtrace: Backtrace::generate(),
+                },
+                StatusCode::NOT_FOUND,
+            ),
+        ];
+
+        for (handler_err, expect_code) in cases {
+            let err = Error::HandleRequest {
+                source: Box::new(handler_err),
+            };
+            assert_eq!(expect_code, error_to_status_code(&err));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retry_after_on_overload() {
+        let rejection = reject::custom(Error::Overloaded {
+            msg: "memory pressure".to_string(),
+            retry_after: Duration::from_millis(2500),
+        });
+        let resp = handle_rejection(rejection).await.unwrap().into_response();
+        assert_eq!(StatusCode::SERVICE_UNAVAILABLE, resp.status());
+        assert_eq!("3", resp.headers()[RETRY_AFTER]);
+
+        // Other errors don't carry the header.
+        let rejection = reject::custom(Error::HandleUpdateLogLevel {
+            msg: "invalid level".to_string(),
+        });
+        let resp = handle_rejection(rejection).await.unwrap().into_response();
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, resp.status());
+        assert!(resp.headers().get(RETRY_AFTER).is_none());
+    }
+
+    #[test]
+    fn test_retry_after_header_value() {
+        let cases = [
+            (Duration::from_millis(0), "1"),
+            (Duration::from_millis(100), "1"),
+            (Duration::from_secs(2), "2"),
+            (Duration::from_millis(2001), "3"),
+        ];
+        for (retry_after, expect) in cases {
+            assert_eq!(expect, retry_after_header_value(retry_after));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sql_request_content_type() {
+        let route = warp::path!("sql")
+            .and(warp::post())
+            .and(sql_request())
+            .map(|_| "ok")
+            .recover(handle_rejection);
+        let post_sql = |content_type: Option<&str>, body: &'static str| {
+            let req = warp::test::request().method("POST").path("/sql").body(body);
+            match content_type {
+                Some(v) => req.header("content-type", v),
+                None => req,
+            }
+        };
+
+        let cases = [
+            (Some("application/json"), r#"{"query": "SELECT 1"}"#),
+            (
+                Some("application/json; charset=utf-8"),
+                r#"{"query": "SELECT 1"}"#,
+            ),
+            (Some("text/plain"), "SELECT 1"),
+            (None, r#"{"query": "SELECT 1"}"#),
+            (None, "SELECT 1"),
+        ];
+        for (content_type, body) in cases {
+            let resp = post_sql(content_type, body).reply(&route).await;
+            assert_eq!(StatusCode::OK, resp.status());
+        }
+
+        // Malformed json claimed to be json.
+        let resp = post_sql(Some("application/json"), r#"{"query": "#)
+            .reply(&route)
+            .await;
+        assert_eq!(StatusCode::BAD_REQUEST, resp.status());
+        let body: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(400, body["code"]);
+        let message = body["message"].as_str().unwrap();
+        assert!(message.starts_with("Invalid json body"));
+        assert!(message.contains("or the sql as plain text"));
+    }
+
+    #[tokio::test]
+    async fn test_list_active_requests() {
+        let active_requests = Arc::new(ActiveRequests::default());
+        let finish_slow = Arc::new(Notify::new());
+        let finish_slow_clone = finish_slow.clone();
+        let slow_route = warp::path!("slow").and_then(move || {
+            let finish_slow = finish_slow_clone.clone();
+            async move {
+                finish_slow.notified().await;
+                Ok::<_, Infallible>("done")
+            }
+        });
+        let route = track_active_request(active_requests.clone())
+            .and(slow_route.or(list_active_requests(active_requests)))
+            .map(|_guard: ActiveRequestGuard, reply| reply);
+
+        let list_slow_requests = |route| async move {
+            let resp = warp::test::request()
+                .path("/debug/requests")
+                .reply(route)
+                .await;
+            assert_eq!(StatusCode::OK, resp.status());
+            let requests: Vec<serde_json::Value> = serde_json::from_slice(resp.body()).unwrap();
+            requests
+                .into_iter()
+                .filter(|request| request["path"] == "/slow")
+                .collect::<Vec<_>>()
+        };
+
+        let route_clone = route.clone();
+        let slow_handle = tokio::spawn(async move {
+            warp::test::request()
+                .path("/slow")
+                .header(consts::TENANT_HEADER, "test_tenant")
+                .reply(&route_clone)
+                .await
+        });
+        let mut slow_requests = list_slow_requests(&route).await;
+        while slow_requests.is_empty() {
+            time::sleep(Duration::from_millis(10)).await;
+            slow_requests = list_slow_requests(&route).await;
+        }
+        assert_eq!(1, slow_requests.len());
+        assert_eq!("test_tenant", slow_requests[0]["tenant"]);
+        assert!(slow_requests[0]["elapsed_ms"].is_u64());
+
+        // The request is removed once it is finished.
+        finish_slow.notify_one();
+        assert_eq!(StatusCode::OK, slow_handle.await.unwrap().status());
+        assert!(list_slow_requests(&route).await.is_empty());
+    }
+
+    #[test]
+    fn test_accepts_gzip() {
+        let cases = [
+            ("gzip", true),
+            ("deflate, gzip;q=0.8", true),
+            ("GZIP", true),
+            ("*", true),
+            ("gzip;q=0", false),
+            ("deflate, br", false),
+            ("", false),
+        ];
+        for (accept_encoding, expect) in cases {
+            assert_eq!(expect, accepts_gzip(accept_encoding), "{}", accept_encoding);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_compress_metrics() {
+        use flate2::read::GzDecoder;
+        use prometheus::{register_int_gauge, IntGauge};
+
+        let gauge: IntGauge = register_int_gauge!(
+            "http_test_compress_metrics_gauge",
+            "Gauge for testing compressing metrics"
+        )
+        .unwrap();
+        gauge.set(1);
+
+        let route = metrics_route();
+        let plain_resp = warp::test::request()
+            .path("/metrics?prefix=http_test_compress_metrics")
+            .reply(&route)
+            .await;
+        assert_eq!(StatusCode::OK, plain_resp.status());
+        assert!(plain_resp.headers().get(CONTENT_ENCODING).is_none());
+        let plain_body = String::from_utf8(plain_resp.body().to_vec()).unwrap();
+        assert!(plain_body.contains("http_test_compress_metrics_gauge 1"));
+
+        let gzip_resp = warp::test::request()
+            .path("/metrics?prefix=http_test_compress_metrics")
+            .header(ACCEPT_ENCODING, "deflate, gzip")
+            .reply(&route)
+            .await;
+        assert_eq!(StatusCode::OK, gzip_resp.status());
+        assert_eq!("gzip", gzip_resp.headers()[CONTENT_ENCODING]);
+        let mut decoded_body = String::new();
+        GzDecoder::new(gzip_resp.body().as_ref())
+            .read_to_string(&mut decoded_body)
+            .unwrap();
+        assert_eq!(plain_body, decoded_body);
+    }
+
+    #[tokio::test]
+    async fn test_import_sst_body_limit() {
+        let make_route = |max_import_sst_size| {
+            warp::path!("sst")
+                .and(warp::post())
+                .and(sst_body(max_import_sst_size))
+                .map(|sst: Bytes| sst.len().to_string())
+        };
+        // The sst is larger than the max body size of the other requests.
+        let sst = vec![1u8; 2 * DEFAULT_MAX_BODY_SIZE as usize];
+
+        let resp = warp::test::request()
+            .method("POST")
+            .path("/sst")
+            .body(sst.clone())
+            .reply(&make_route(DEFAULT_MAX_IMPORT_SST_SIZE))
+            .await;
+        assert_eq!(StatusCode::OK, resp.status());
+        assert_eq!(sst.len().to_string().as_bytes(), resp.body().as_ref());
+
+        let resp = warp::test::request()
+            .method("POST")
+            .path("/sst")
+            .body(sst)
+            .reply(&make_route(DEFAULT_MAX_BODY_SIZE))
+            .await;
+        assert_eq!(StatusCode::PAYLOAD_TOO_LARGE, resp.status());
+    }
+
+    #[tokio::test]
+    async fn test_run_handler_in_blocking_pool() {
+        let blocking_pool: BlockingPoolRef =
+            Arc::new(BlockingPool::try_new(BLOCKING_POOL_NAME, 1, 1).unwrap());
+        let route = warp::path!("thread")
+            .and(warp::any().map(move || blocking_pool.clone()))
+            .and_then(|blocking_pool: BlockingPoolRef| async move {
+                blocking_pool
+                    .run(|| {
+                        std::thread::current()
+                            .name()
+                            .unwrap_or_default()
+                            .to_string()
+                    })
+                    .await
+                    .context(RunBlockingTask)
+                    .map_err(reject::custom)
+            });
+
+        let resp = warp::test::request().path("/thread").reply(&route).await;
+        assert_eq!(StatusCode::OK, resp.status());
+        assert_eq!(BLOCKING_POOL_NAME.as_bytes(), resp.body().as_ref());
+    }
+
+    #[tokio::test]
+    async fn test_serve_with_keepalive() {
+        let config = HttpConfig {
+            endpoint: Endpoint::new("127.0.0.1".to_string(), 0),
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+            max_import_sst_size: DEFAULT_MAX_IMPORT_SST_SIZE,
+            shutdown_timeout: DEFAULT_SHUTDOWN_TIMEOUT,
+            tcp_keepalive: Some(Duration::from_secs(5)),
+            http2_keepalive_interval: Some(Duration::from_secs(3)),
+            http2_keepalive_timeout: Duration::from_secs(1),
+            idle_timeout: None,
+            slow_query_threshold: Some(DEFAULT_SLOW_QUERY_THRESHOLD),
+            blocking_thread_num: DEFAULT_BLOCKING_THREAD_NUM,
+            blocking_queue_len: DEFAULT_BLOCKING_QUEUE_LEN,
+        };
+        let ip_addr: IpAddr = config.endpoint.addr.parse().unwrap();
+        let (addr, server_builder) =
+            bind_server(SocketAddr::new(ip_addr, config.endpoint.port), &config).unwrap();
+        assert_ne!(0, addr.port());
+
+        let warp_service = warp::service(warp::path!("ping").map(|| "pong"));
+        let make_service = make_service_fn(move |_| {
+            let warp_service = warp_service.clone();
+            async move { Ok::<_, Infallible>(warp_service) }
+        });
+        let (stop_tx, stop_rx) = oneshot::channel();
+        let server_handle = tokio::spawn(
+            server_builder
+                .serve(make_service)
+                .with_graceful_shutdown(async {
+                    stop_rx.await.ok();
+                }),
+        );
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"GET /ping HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        let mut resp = String::new();
+        stream.read_to_string(&mut resp).await.unwrap();
+        assert!(resp.starts_with("HTTP/1.1 200 OK"));
+        assert!(resp.ends_with("pong"));
+
+        stop_tx.send(()).unwrap();
+        server_handle.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_close_idle_connection() {
+        let idle_timeout = Duration::from_millis(300);
+        let config = HttpConfig {
+            endpoint: Endpoint::new("127.0.0.1".to_string(), 0),
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+            max_import_sst_size: DEFAULT_MAX_IMPORT_SST_SIZE,
+            shutdown_timeout: DEFAULT_SHUTDOWN_TIMEOUT,
+            tcp_keepalive: None,
+            http2_keepalive_interval: None,
+            http2_keepalive_timeout: DEFAULT_HTTP2_KEEPALIVE_TIMEOUT,
+            idle_timeout: Some(idle_timeout),
+            slow_query_threshold: None,
+            blocking_thread_num: DEFAULT_BLOCKING_THREAD_NUM,
+            blocking_queue_len: DEFAULT_BLOCKING_QUEUE_LEN,
+        };
+        let ip_addr: IpAddr = config.endpoint.addr.parse().unwrap();
+        let (addr, server_builder) =
+            bind_server(SocketAddr::new(ip_addr, config.endpoint.port), &config).unwrap();
+
+        let warp_service = warp::service(warp::path!("ping").map(|| "pong"));
+        let make_service = make_service_fn(move |_| {
+            let warp_service = warp_service.clone();
+            async move { Ok::<_, Infallible>(warp_service) }
+        });
+        let (stop_tx, stop_rx) = oneshot::channel();
+        let server_handle = tokio::spawn(
+            server_builder
+                .serve(make_service)
+                .with_graceful_shutdown(async {
+                    stop_rx.await.ok();
+                }),
+        );
+
+        // Keep the connection alive after the request, and then stay idle.
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"GET /ping HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await
+            .unwrap();
+        let start = Instant::now();
+        let mut resp = Vec::new();
+        time::timeout(Duration::from_secs(10), stream.read_to_end(&mut resp))
+            .await
+            .expect("the idle connection should be closed by the server")
+            .unwrap();
+        assert!(start.elapsed() >= idle_timeout);
+        let resp = String::from_utf8(resp).unwrap();
+        assert!(resp.starts_with("HTTP/1.1 200 OK"));
+        assert!(resp.ends_with("pong"));
+
+        stop_tx.send(()).unwrap();
+        server_handle.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_with_slow_request() {
+        let slow_route = warp::path!("slow").and_then(|| async {
+            time::sleep(Duration::from_secs(60)).await;
+            Ok::<_, Infallible>("done")
+        });
+        let (graceful_tx, graceful_rx) = oneshot::channel();
+        let (addr, server) =
+            warp::serve(slow_route).bind_with_graceful_shutdown(([127, 0, 0, 1], 0), async {
+                graceful_rx.await.ok();
+            });
+        let (stop_tx, stop_rx) = oneshot::channel();
+        let shutdown_timeout = Duration::from_millis(200);
+        let server_handle = tokio::spawn(serve_with_shutdown_timeout(
+            server,
+            stop_rx,
+            graceful_tx,
+            shutdown_timeout,
+        ));
+
+        // Start a slow request and make sure it is being handled by the server.
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"GET /slow HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await
+            .unwrap();
+        let mut buf = [0; 16];
+        assert!(
+            time::timeout(Duration::from_millis(100), stream.read(&mut buf))
+                .await
+                .is_err()
+        );
+
+        let begin = Instant::now();
+        stop_tx.send(()).unwrap();
+        time::timeout(Duration::from_secs(5), server_handle)
+            .await
+            .unwrap()
+            .unwrap();
+        let elapsed = begin.elapsed();
+        assert!(elapsed >= shutdown_timeout);
+        assert!(elapsed < Duration::from_secs(5));
+    }
 }
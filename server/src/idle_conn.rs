@@ -0,0 +1,200 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+//! Close the http connections staying idle for too long.
+
+use std::{
+    future::Future,
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use hyper::server::{
+    accept::Accept,
+    conn::{AddrIncoming, AddrStream},
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    time::{self, Instant, Sleep},
+};
+
+/// Wrap the connections accepted by [AddrIncoming] into [IdleTimeoutStream].
+pub struct IdleTimeoutIncoming {
+    inner: AddrIncoming,
+    idle_timeout: Option<Duration>,
+}
+
+impl IdleTimeoutIncoming {
+    /// The connections never time out if `idle_timeout` is `None`.
+    pub fn new(inner: AddrIncoming, idle_timeout: Option<Duration>) -> Self {
+        Self {
+            inner,
+            idle_timeout,
+        }
+    }
+}
+
+impl Accept for IdleTimeoutIncoming {
+    type Conn = IdleTimeoutStream<AddrStream>;
+    type Error = io::Error;
+
+    fn poll_accept(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Conn, Self::Error>>> {
+        let this = self.get_mut();
+        let idle_timeout = this.idle_timeout;
+        Pin::new(&mut this.inner)
+            .poll_accept(cx)
+            .map_ok(|stream| IdleTimeoutStream::new(stream, idle_timeout))
+    }
+}
+
+/// A stream regarded as closed by the peer once nothing is read from or
+/// written to it in the `idle_timeout`.
+///
+/// Note that a request being handled without any io also makes the
+/// connection idle, so the timeout should be longer than the slowest request.
+pub struct IdleTimeoutStream<S> {
+    inner: S,
+    idle_timeout: Option<Duration>,
+    deadline: Option<Pin<Box<Sleep>>>,
+}
+
+impl<S> IdleTimeoutStream<S> {
+    pub fn new(inner: S, idle_timeout: Option<Duration>) -> Self {
+        Self {
+            inner,
+            idle_timeout,
+            deadline: idle_timeout.map(|timeout| Box::pin(time::sleep(timeout))),
+        }
+    }
+
+    fn reset_deadline(&mut self) {
+        if let (Some(timeout), Some(deadline)) = (self.idle_timeout, &mut self.deadline) {
+            deadline.as_mut().reset(Instant::now() + timeout);
+        }
+    }
+
+    fn poll_idle_timeout(&mut self, cx: &mut Context<'_>) -> bool {
+        match &mut self.deadline {
+            Some(deadline) => deadline.as_mut().poll(cx).is_ready(),
+            None => false,
+        }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for IdleTimeoutStream<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let filled = buf.filled().len();
+        match Pin::new(&mut this.inner).poll_read(cx, buf) {
+            Poll::Ready(res) => {
+                if buf.filled().len() > filled {
+                    this.reset_deadline();
+                }
+                Poll::Ready(res)
+            }
+            // Reading nothing, which means the end of the stream, makes the
+            // connection closed gracefully.
+            Poll::Pending if this.poll_idle_timeout(cx) => Poll::Ready(Ok(())),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for IdleTimeoutStream<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        match Pin::new(&mut this.inner).poll_write(cx, buf) {
+            Poll::Ready(res) => {
+                if matches!(res, Ok(n) if n > 0) {
+                    this.reset_deadline();
+                }
+                Poll::Ready(res)
+            }
+            Poll::Pending if this.poll_idle_timeout(cx) => Poll::Ready(Err(timeout_error())),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        match Pin::new(&mut this.inner).poll_write_vectored(cx, bufs) {
+            Poll::Ready(res) => {
+                if matches!(res, Ok(n) if n > 0) {
+                    this.reset_deadline();
+                }
+                Poll::Ready(res)
+            }
+            Poll::Pending if this.poll_idle_timeout(cx) => Poll::Ready(Err(timeout_error())),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+fn timeout_error() -> io::Error {
+    io::Error::new(io::ErrorKind::TimedOut, "connection idle timeout")
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{duplex, AsyncReadExt, AsyncWriteExt};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_idle_timeout_stream() {
+        let (client, server) = duplex(64);
+        let mut client = client;
+        let mut stream = IdleTimeoutStream::new(server, Some(Duration::from_millis(200)));
+
+        client.write_all(b"ping").await.unwrap();
+        let mut buf = [0; 4];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(b"ping", &buf);
+
+        // Nothing to read, the stream ends after the idle timeout.
+        let start = Instant::now();
+        assert_eq!(0, stream.read(&mut buf).await.unwrap());
+        assert!(start.elapsed() >= Duration::from_millis(200));
+    }
+
+    #[tokio::test]
+    async fn test_stream_without_idle_timeout() {
+        let (_client, server) = duplex(64);
+        let mut stream = IdleTimeoutStream::new(server, None);
+
+        let mut buf = [0; 4];
+        assert!(
+            time::timeout(Duration::from_millis(200), stream.read(&mut buf))
+                .await
+                .is_err()
+        );
+    }
+}
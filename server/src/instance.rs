@@ -9,7 +9,7 @@ use df_operator::registry::FunctionRegistryRef;
 use interpreters::table_manipulator::TableManipulatorRef;
 use table_engine::engine::TableEngineRef;
 
-use crate::limiter::Limiter;
+use crate::{limiter::Limiter, running_query::RunningQueriesRef};
 
 /// A cluster instance. Usually there is only one instance per cluster
 ///
@@ -22,6 +22,8 @@ pub struct Instance<Q> {
     pub function_registry: FunctionRegistryRef,
     pub limiter: Limiter,
     pub table_manipulator: TableManipulatorRef,
+    // Queries running in this instance, indexed by request id.
+    pub running_queries: RunningQueriesRef,
 }
 
 /// A reference counted instance pointer
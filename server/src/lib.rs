@@ -8,6 +8,8 @@
 #[macro_use]
 extern crate common_util;
 
+mod active_requests;
+mod blocking_pool;
 pub mod config;
 mod consts;
 mod context;
@@ -15,12 +17,14 @@ pub(crate) mod error_util;
 mod grpc;
 mod handlers;
 mod http;
+mod idle_conn;
 mod instance;
 pub mod limiter;
 pub mod local_tables;
 pub mod logger;
 mod metrics;
 mod mysql;
+mod running_query;
 pub mod schema_config_provider;
 pub mod server;
 pub mod table_engine;
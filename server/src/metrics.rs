@@ -2,18 +2,68 @@
 
 //! Metrics util for server.
 
+use lazy_static::lazy_static;
 use log::warn;
-use prometheus::{Encoder, TextEncoder};
+use prometheus::{register_int_counter, Encoder, IntCounter, TextEncoder};
+
+lazy_static! {
+    pub static ref SLOW_QUERY_COUNTER: IntCounter = register_int_counter!(
+        "sql_slow_query_counter",
+        "Number of the sql queries taking longer than the slow query threshold"
+    )
+    .unwrap();
+}
 
 /// Gather and dump prometheus to string.
 pub fn dump() -> String {
+    dump_filtered(|_| true)
+}
+
+/// Gather and dump the prometheus metrics whose names are accepted by the
+/// `filter` to string.
+pub fn dump_filtered(filter: impl Fn(&str) -> bool) -> String {
     let mut buffer = vec![];
     let encoder = TextEncoder::new();
     let metric_families = prometheus::gather();
     for mf in metric_families {
+        if !filter(mf.get_name()) {
+            continue;
+        }
+
         if let Err(e) = encoder.encode(&[mf], &mut buffer) {
             warn!("prometheus encoding error, err:{}", e);
         }
     }
     String::from_utf8(buffer).unwrap()
 }
+
+#[cfg(test)]
+mod tests {
+    use prometheus::{register_int_gauge, IntGauge};
+
+    use super::*;
+
+    #[test]
+    fn test_dump_filtered() {
+        let compaction_gauge: IntGauge = register_int_gauge!(
+            "compaction_test_dump_filtered_gauge",
+            "Gauge for testing dump filtered"
+        )
+        .unwrap();
+        let unrelated_gauge: IntGauge = register_int_gauge!(
+            "unrelated_test_dump_filtered_gauge",
+            "Gauge for testing dump filtered"
+        )
+        .unwrap();
+        compaction_gauge.set(1);
+        unrelated_gauge.set(2);
+
+        let all = dump();
+        assert!(all.contains("compaction_test_dump_filtered_gauge"));
+        assert!(all.contains("unrelated_test_dump_filtered_gauge"));
+
+        let filtered = dump_filtered(|name| name.starts_with("compaction_"));
+        assert!(filtered.contains("compaction_test_dump_filtered_gauge 1"));
+        assert!(!filtered.contains("unrelated_test_dump_filtered_gauge"));
+    }
+}
@@ -107,7 +107,7 @@ where
         let ctx = self.create_ctx()?;
 
         let req = Request::from(sql.to_string());
-        handlers::sql::handle_sql(ctx, self.instance.clone(), req)
+        handlers::sql::handle_sql(ctx, self.instance.clone(), req, None)
             .await
             .map_err(|e| {
                 error!("Mysql service Failed to handle sql, err: {}", e);
@@ -0,0 +1,193 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+//! Registry of the running queries, a running query can be canceled by its
+//! tenant and request id.
+
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Arc, Mutex},
+};
+
+use common_types::request_id::RequestId;
+use futures::future::{AbortHandle, Abortable};
+use snafu::{ensure, Backtrace, Snafu};
+
+#[derive(Debug, Snafu)]
+#[snafu(visibility(pub(crate)))]
+pub enum Error {
+    #[snafu(display(
+        "Request id is already used by another running query, tenant:{}, request_id:{}.\nBacktrace:\n{}",
+        tenant,
+        request_id,
+        backtrace
+    ))]
+    DuplicateRequestId {
+        tenant: String,
+        request_id: u64,
+        backtrace: Backtrace,
+    },
+}
+
+define_result!(Error);
+
+/// Key of the running query.
+///
+/// The request id can be specified by the client, so it is only unique within
+/// the tenant, and the query of other tenants can't be canceled by guessing
+/// the id.
+type QueryKey = (String, u64);
+
+/// Running queries indexed by tenant and request id.
+#[derive(Default)]
+pub struct RunningQueries {
+    queries: Mutex<HashMap<QueryKey, AbortHandle>>,
+}
+
+pub type RunningQueriesRef = Arc<RunningQueries>;
+
+impl RunningQueries {
+    /// Run the `query` until it finishes or it is canceled by
+    /// [RunningQueries::cancel]. The canceled query is dropped immediately so
+    /// the execution inside the query engine is stopped, and `None` is
+    /// returned.
+    pub async fn run<F: Future>(
+        &self,
+        tenant: &str,
+        request_id: RequestId,
+        query: F,
+    ) -> Result<Option<F::Output>> {
+        let key = (tenant.to_string(), request_id.as_u64());
+        let (abort_handle, abort_registration) = AbortHandle::new_pair();
+        {
+            let mut queries = self.queries.lock().unwrap();
+            ensure!(
+                !queries.contains_key(&key),
+                DuplicateRequestId {
+                    tenant,
+                    request_id: request_id.as_u64(),
+                }
+            );
+            queries.insert(key.clone(), abort_handle);
+        }
+        // Deregister the query even if the caller drops this future.
+        let _guard = RunningQueryGuard { queries: self, key };
+
+        Ok(Abortable::new(query, abort_registration).await.ok())
+    }
+
+    /// Cancel the running query of the `tenant` with given `request_id`,
+    /// returns false if no such query.
+    pub fn cancel(&self, tenant: &str, request_id: RequestId) -> bool {
+        let key = (tenant.to_string(), request_id.as_u64());
+        match self.queries.lock().unwrap().remove(&key) {
+            Some(abort_handle) => {
+                abort_handle.abort();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns true if the query of the `tenant` with given `request_id` is
+    /// running.
+    pub fn contains(&self, tenant: &str, request_id: RequestId) -> bool {
+        let key = (tenant.to_string(), request_id.as_u64());
+        self.queries.lock().unwrap().contains_key(&key)
+    }
+}
+
+/// Remove the query from the registry on drop.
+struct RunningQueryGuard<'a> {
+    queries: &'a RunningQueries,
+    key: QueryKey,
+}
+
+impl<'a> Drop for RunningQueryGuard<'a> {
+    fn drop(&mut self) {
+        self.queries.queries.lock().unwrap().remove(&self.key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::time;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_cancel_running_query() {
+        let queries = Arc::new(RunningQueries::default());
+        let request_id = RequestId::from(1000);
+
+        let queries_clone = queries.clone();
+        let handle = tokio::spawn(async move {
+            queries_clone
+                .run("test", request_id, async {
+                    time::sleep(Duration::from_secs(60)).await;
+                    1
+                })
+                .await
+        });
+
+        while !queries.contains("test", request_id) {
+            time::sleep(Duration::from_millis(1)).await;
+        }
+        assert!(queries.cancel("test", request_id));
+
+        let output = time::timeout(Duration::from_secs(5), handle)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert!(output.is_none());
+        assert!(!queries.contains("test", request_id));
+        // Cancel again.
+        assert!(!queries.cancel("test", request_id));
+    }
+
+    #[tokio::test]
+    async fn test_run_query() {
+        let queries = RunningQueries::default();
+        let request_id = RequestId::from(1001);
+
+        let output = queries.run("test", request_id, async { 1 }).await.unwrap();
+        assert_eq!(Some(1), output);
+        assert!(!queries.contains("test", request_id));
+        assert!(!queries.cancel("test", request_id));
+
+        // The request id can't be shared by running queries.
+        let query = queries.run("test", request_id, async {
+            assert!(matches!(
+                queries.run("test", request_id, async { 2 }).await,
+                Err(Error::DuplicateRequestId { .. })
+            ));
+            3
+        });
+        assert_eq!(Some(3), query.await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_request_id_of_tenants() {
+        let queries = RunningQueries::default();
+        let request_id = RequestId::from(1002);
+
+        let query = queries.run("tenant1", request_id, async {
+            // Same request id of other tenant is allowed.
+            assert_eq!(
+                Some(2),
+                queries
+                    .run("tenant2", request_id, async { 2 })
+                    .await
+                    .unwrap()
+            );
+            // The query can't be canceled by other tenant.
+            assert!(!queries.cancel("tenant2", request_id));
+            assert!(queries.contains("tenant1", request_id));
+            3
+        });
+        assert_eq!(Some(3), query.await.unwrap());
+    }
+}
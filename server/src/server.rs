@@ -24,6 +24,7 @@ use crate::{
     local_tables::{self, LocalTablesRecoverer},
     mysql,
     mysql::error::Error as MysqlError,
+    running_query::RunningQueries,
     schema_config_provider::SchemaConfigProviderRef,
 };
 
@@ -100,7 +101,7 @@ pub struct Server<Q: QueryExecutor + 'static> {
 impl<Q: QueryExecutor + 'static> Server<Q> {
     pub async fn stop(mut self) {
         self.rpc_services.shutdown().await;
-        self.http_service.stop();
+        self.http_service.stop().await;
         self.mysql_service.shutdown();
 
         if let Some(cluster) = &self.cluster {
@@ -277,6 +278,7 @@ impl<Q: QueryExecutor + 'static> Builder<Q> {
                 function_registry,
                 limiter: self.limiter,
                 table_manipulator,
+                running_queries: Arc::new(RunningQueries::default()),
             };
             InstanceRef::new(instance)
         };
@@ -290,15 +292,30 @@ impl<Q: QueryExecutor + 'static> Builder<Q> {
         let http_config = HttpConfig {
             endpoint,
             max_body_size: self.config.http_max_body_size,
+            max_import_sst_size: self.config.http_max_import_sst_size,
+            shutdown_timeout: self.config.http_shutdown_timeout.0,
+            tcp_keepalive: self.config.http_tcp_keepalive.map(|v| v.0),
+            http2_keepalive_interval: self.config.http2_keepalive_interval.map(|v| v.0),
+            http2_keepalive_timeout: self.config.http2_keepalive_timeout.0,
+            idle_timeout: self.config.http_idle_timeout.map(|v| v.0),
+            slow_query_threshold: self.config.http_slow_query_threshold.map(|v| v.0),
+            blocking_thread_num: self.config.http_blocking_thread_num,
+            blocking_queue_len: self.config.http_blocking_queue_len,
         };
 
         // Start http service
         let engine_runtimes = self.engine_runtimes.context(MissingEngineRuntimes)?;
         let log_runtime = self.log_runtime.context(MissingLogRuntime)?;
+        let router = self.router.context(MissingRouter)?;
         let http_service = http::Builder::new(http_config)
             .engine_runtimes(engine_runtimes.clone())
             .log_runtime(log_runtime)
             .instance(instance.clone())
+            .router(router.clone())
+            .local_endpoint(Endpoint::new(
+                self.config.cluster.node.addr.clone(),
+                self.config.grpc_port,
+            ))
             .build()
             .context(StartHttpService)?;
 
@@ -313,7 +330,6 @@ impl<Q: QueryExecutor + 'static> Builder<Q> {
             .build()
             .context(BuildMysqlService)?;
 
-        let router = self.router.context(MissingRouter)?;
         let provider = self
             .schema_config_provider
             .context(MissingSchemaConfigProvider)?;
@@ -328,6 +344,7 @@ impl<Q: QueryExecutor + 'static> Builder<Q> {
             .cluster(self.cluster.clone())
             .schema_config_provider(provider)
             .forward_config(self.config.forward)
+            .verbose_error(self.config.grpc_verbose_error)
             .build()
             .context(BuildGrpcService)?;
 
@@ -23,8 +23,8 @@ use table_engine::{
     stream,
     stream::{PartitionedStreams, RecordBatchStream, SendableRecordBatchStream},
     table::{
-        AlterSchemaRequest, FlushRequest, GetRequest, ReadRequest, SchemaId, Table, TableId,
-        TableSeq, TableStats, WriteRequest,
+        AlterSchemaRequest, CompactionSummary, FlushRequest, GetRequest, ReadRequest, SchemaId,
+        Table, TableId, TableSeq, TableStats, WriteRequest,
     },
 };
 
@@ -163,8 +163,8 @@ impl Table for SystemTableAdapter {
         Ok(())
     }
 
-    async fn compact(&self) -> table_engine::table::Result<()> {
-        Ok(())
+    async fn compact(&self) -> table_engine::table::Result<CompactionSummary> {
+        Ok(CompactionSummary::default())
     }
 }
 
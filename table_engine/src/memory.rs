@@ -27,8 +27,8 @@ use crate::{
         SendableRecordBatchStream,
     },
     table::{
-        AlterSchemaRequest, FlushRequest, GetRequest, ReadRequest, Result, Table, TableId,
-        TableStats, UnsupportedMethod, WriteRequest,
+        AlterSchemaRequest, CompactionSummary, FlushRequest, GetRequest, ReadRequest, Result,
+        Table, TableId, TableStats, UnsupportedMethod, WriteRequest,
     },
 };
 
@@ -153,7 +153,7 @@ impl Table for MemoryTable {
         .fail()
     }
 
-    async fn compact(&self) -> Result<()> {
+    async fn compact(&self) -> Result<CompactionSummary> {
         // Compact is not supported now.
         UnsupportedMethod {
             table: self.name(),
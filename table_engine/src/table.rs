@@ -13,6 +13,7 @@ use std::{
 
 use async_trait::async_trait;
 use common_types::{
+    bytes::Bytes,
     column_schema::ColumnSchema,
     datum::Datum,
     projected_schema::ProjectedSchema,
@@ -110,6 +111,20 @@ pub enum Error {
         source: Box<dyn std::error::Error + Send + Sync>,
     },
 
+    #[snafu(display(
+        "Schema version conflict in altering schema, table:{}, current_version:{}, pre_version:{}.\nBacktrace:\n{}",
+        table,
+        current_version,
+        pre_version,
+        backtrace
+    ))]
+    AlterSchemaVersionConflict {
+        table: String,
+        current_version: Version,
+        pre_version: Version,
+        backtrace: Backtrace,
+    },
+
     #[snafu(display("Failed to alter options, table:{}, err:{}", table, source))]
     AlterOptions {
         table: String,
@@ -128,6 +143,12 @@ pub enum Error {
         source: Box<dyn std::error::Error + Send + Sync>,
     },
 
+    #[snafu(display("Failed to import sst, table:{}, err:{}", table, source))]
+    ImportSst {
+        table: String,
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
     #[snafu(display("Failed to convert read request to pb, msg:{}, err:{}", msg, source))]
     ReadRequestToPb {
         msg: String,
@@ -542,7 +563,9 @@ pub trait Table: std::fmt::Debug {
     async fn partitioned_read(&self, request: ReadRequest) -> Result<PartitionedStreams>;
 
     /// Alter table schema to the schema specific in [AlterSchemaRequest] if
-    /// the `pre_schema_version` is equal to current schema version.
+    /// the `pre_schema_version` is equal to current schema version, otherwise
+    /// [Error::AlterSchemaVersionConflict] is returned as the schema has been
+    /// modified concurrently.
     ///
     /// Returns the affected rows (always 0).
     async fn alter_schema(&self, request: AlterSchemaRequest) -> Result<usize>;
@@ -556,7 +579,19 @@ pub trait Table: std::fmt::Debug {
     async fn flush(&self, request: FlushRequest) -> Result<()>;
 
     /// Compact this table and wait until compaction completes.
-    async fn compact(&self) -> Result<()>;
+    ///
+    /// Returns the summary of the finished compaction.
+    async fn compact(&self) -> Result<CompactionSummary>;
+
+    /// Import an externally generated sst into this table without re-encoding
+    /// it.
+    async fn import_sst(&self, _sst: Bytes) -> Result<()> {
+        UnsupportedMethod {
+            table: self.name(),
+            method: "import_sst",
+        }
+        .fail()
+    }
 }
 
 /// Basic statistics of table.
@@ -570,6 +605,19 @@ pub struct TableStats {
     pub num_flush: u64,
 }
 
+/// Summary of a finished compaction of the table.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CompactionSummary {
+    /// Number of the input sst files, including the expired ones.
+    pub num_input_files: usize,
+    /// Total size in bytes of the input sst files.
+    pub input_size: u64,
+    /// Number of the sst files generated by the compaction.
+    pub num_output_files: usize,
+    /// Total size in bytes of the generated sst files.
+    pub output_size: u64,
+}
+
 /// A reference-counted pointer to Table
 pub type TableRef = Arc<dyn Table + Send + Sync>;
 
@@ -0,0 +1,61 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+//! A cli to check the consistency between the meta data and the data of a sst
+
+use std::{process, sync::Arc};
+
+use clap::Parser;
+use common_util::runtime::{self, Runtime};
+use object_store::{LocalFileSystem, Path};
+use tools::sst_util;
+
+#[derive(Parser, Debug)]
+#[clap(author, version, about, long_about = None)]
+struct Args {
+    /// Root dir of storage
+    #[clap(short, long, required(true))]
+    store_path: String,
+
+    /// Sst file to check(relative to store_path)
+    #[clap(short, long, required(true))]
+    input: String,
+}
+
+fn new_runtime(thread_num: usize) -> Runtime {
+    runtime::Builder::default()
+        .thread_name("tools")
+        .worker_threads(thread_num)
+        .enable_all()
+        .build()
+        .unwrap()
+}
+
+fn main() {
+    let args = Args::parse();
+    let rt = new_runtime(1);
+    let passed = rt.block_on(async move {
+        let storage = LocalFileSystem::new_with_prefix(args.store_path).expect("invalid path");
+        let store = Arc::new(storage) as _;
+        let input_path = Path::from(args.input);
+        match sst_util::check_sst_consistency(&store, &input_path).await {
+            Ok(inconsistencies) if inconsistencies.is_empty() => {
+                println!("Check passed, path:{}", input_path);
+                true
+            }
+            Ok(inconsistencies) => {
+                for inconsistency in inconsistencies {
+                    println!("{}", inconsistency);
+                }
+                false
+            }
+            Err(e) => {
+                eprintln!("Check failed, err:{}", e);
+                false
+            }
+        }
+    });
+
+    if !passed {
+        process::exit(1);
+    }
+}
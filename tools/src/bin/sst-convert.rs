@@ -2,7 +2,7 @@
 
 //! A cli to convert ssts between different options
 
-use std::{error::Error, sync::Arc};
+use std::{error::Error, sync::Arc, time::Duration};
 
 use analytic_engine::{
     sst::factory::{
@@ -43,9 +43,17 @@ struct Args {
     #[clap(short, long, default_value_t = 8192)]
     batch_size: usize,
 
-    /// Storage format(values: columnar/hybrid)
+    /// Storage format(values: columnar/hybrid/columnar_delta_ts)
     #[clap(short, long, default_value = "columnar")]
     format: String,
+
+    /// Timeout in seconds of the requests to the storage
+    #[clap(long, default_value_t = 60)]
+    store_timeout_secs: u64,
+
+    /// Write a json sidecar summarizing the meta data next to the new sst
+    #[clap(long)]
+    stats_sidecar: bool,
 }
 
 fn new_runtime(thread_num: usize) -> Runtime {
@@ -72,7 +80,12 @@ async fn run(args: Args, runtime: Arc<Runtime>) -> Result<()> {
     let storage = LocalFileSystem::new_with_prefix(args.store_path).expect("invalid path");
     let store = Arc::new(storage) as _;
     let input_path = Path::from(args.input);
-    let mut sst_meta = sst_util::meta_from_sst(&store, &input_path).await;
+    let mut sst_meta = sst_util::try_meta_from_sst_with_timeout(
+        &store,
+        &input_path,
+        Duration::from_secs(args.store_timeout_secs),
+    )
+    .await?;
     let factory = FactoryImpl;
     let reader_opts = SstReaderOptions {
         read_batch_row_num: 8192,
@@ -84,6 +97,7 @@ async fn run(args: Args, runtime: Arc<Runtime>) -> Result<()> {
         runtime,
         background_read_parallelism: 1,
         num_rows_per_row_group: 8192,
+        decode_memory_limit: None,
     };
     let store_picker: ObjectStorePickerRef = Arc::new(store);
     let mut reader = factory
@@ -95,6 +109,12 @@ async fn run(args: Args, runtime: Arc<Runtime>) -> Result<()> {
         num_rows_per_row_group: args.batch_size,
         compression: Compression::parse_from(&args.compression)
             .with_context(|| format!("invalid compression:{}", args.compression))?,
+        hybrid_encode_fallback: false,
+        max_row_group_bytes: None,
+        composite_bloom_filter_columns: Vec::new(),
+        hybrid_uncollapsed_columns: Vec::new(),
+        sort_dedup_by_key: false,
+        stats_sidecar: args.stats_sidecar,
     };
     let output = Path::from(args.output);
     let mut builder = factory
@@ -1,3 +1,5 @@
 // Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
 
+pub mod memtable_util;
+pub mod metrics;
 pub mod sst_util;
@@ -0,0 +1,94 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+use analytic_engine::Instance;
+use anyhow::{Context, Result};
+use arrow::record_batch::RecordBatch as ArrowRecordBatch;
+use table_engine::table::SchemaId;
+
+/// Snapshot the rows in the memtables of the table `table_name` under the
+/// `schema_id` in the running engine `instance` for inspection, the memtables
+/// won't be flushed.
+pub async fn memtable_snapshot(
+    instance: &Instance,
+    schema_id: SchemaId,
+    table_name: &str,
+) -> Result<Vec<ArrowRecordBatch>> {
+    let table_data = instance
+        .find_table_in_schema(schema_id, table_name)
+        .await?
+        .with_context(|| {
+            format!(
+                "Table not found, schema_id:{:?}, table:{}",
+                schema_id, table_name
+            )
+        })?;
+    let record_batches = table_data.snapshot_memtables()?;
+
+    Ok(record_batches
+        .into_iter()
+        .map(|record_batch| record_batch.into_record_batch().into_arrow_record_batch())
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use analytic_engine::tests::util::{MemoryEngineContext, TestEnv};
+    use common_types::{column::ColumnBlock, datum::Datum, time::Timestamp};
+
+    use super::*;
+
+    #[test]
+    fn test_memtable_snapshot() {
+        let env = TestEnv::builder().build();
+        let mut test_ctx = env.new_context(MemoryEngineContext::default());
+
+        env.block_on(async {
+            test_ctx.open().await;
+
+            let test_table = "test_table";
+            let fixed_schema_table = test_ctx.create_fixed_schema_table(test_table).await;
+            let start_ms = test_ctx.start_ms();
+            let rows = [
+                (
+                    "key1",
+                    Timestamp::new(start_ms),
+                    "tag1-1",
+                    11.0,
+                    110.0,
+                    "tag2-1",
+                ),
+                (
+                    "key2",
+                    Timestamp::new(start_ms + 1),
+                    "tag1-2",
+                    12.0,
+                    120.0,
+                    "tag2-2",
+                ),
+            ];
+            let row_group = fixed_schema_table.rows_to_row_group(&rows);
+            test_ctx.write_to_table(test_table, row_group).await;
+
+            let record_batches =
+                memtable_snapshot(test_ctx.instance(), test_ctx.schema_id, test_table)
+                    .await
+                    .unwrap();
+            let mut keys = Vec::new();
+            for record_batch in &record_batches {
+                let key_column =
+                    ColumnBlock::try_cast_arrow_array_ref(record_batch.column(0)).unwrap();
+                for row_idx in 0..record_batch.num_rows() {
+                    keys.push(key_column.datum(row_idx));
+                }
+            }
+            let expected: Vec<_> = rows.iter().map(|row| Datum::from(row.0)).collect();
+            assert_eq!(expected, keys);
+
+            assert!(
+                memtable_snapshot(test_ctx.instance(), test_ctx.schema_id, "not_exist")
+                    .await
+                    .is_err()
+            );
+        });
+    }
+}
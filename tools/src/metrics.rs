@@ -0,0 +1,16 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+//! Metrics of tools.
+
+use lazy_static::lazy_static;
+use prometheus::{register_int_counter_vec, IntCounterVec};
+
+lazy_static! {
+    // Counters:
+    pub static ref SST_META_DECODE_FAILURE_COUNTER: IntCounterVec = register_int_counter_vec!(
+        "sst_meta_decode_failure_counter",
+        "Sst meta decode failure counter by reason",
+        &["reason"]
+    )
+    .unwrap();
+}
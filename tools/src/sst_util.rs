@@ -1,15 +1,2878 @@
 // Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
 
-use analytic_engine::sst::{file::SstMetaData, parquet::encoding};
-use object_store::{ObjectStoreRef, Path};
-use parquet::file::footer;
+use std::{cmp::Ordering, fmt, future::Future, ops::Range, sync::Arc, time::Duration};
+
+use analytic_engine::{
+    sst::{
+        file::SstMetaData,
+        parquet::{
+            encoding::{
+                self, Error as EncodingError, ParquetDecoder, ParquetEncoder, ParquetEncoderOptions,
+            },
+            row_group_filter::RowGroupFilter,
+        },
+    },
+    table_options::{StorageFormat, StorageFormatOptions},
+};
+use anyhow::{anyhow, ensure, Context, Result};
+use arrow::{
+    array::BooleanArray,
+    compute,
+    datatypes::{DataType, TimeUnit},
+    record_batch::RecordBatch as ArrowRecordBatch,
+};
+use common_types::{
+    bytes::Bytes,
+    column::ColumnBlock,
+    datum::Datum,
+    time::{TimeRange, Timestamp},
+    SequenceNumber,
+};
+use common_util::codec::{memcomparable::MemComparable, Encoder};
+use datafusion::{
+    datasource::file_format,
+    error::DataFusionError,
+    logical_expr::{col, lit, Expr},
+};
+use futures::{
+    future::{self, BoxFuture},
+    FutureExt, TryFutureExt, TryStreamExt,
+};
+use log::warn;
+use object_store::{ObjectStoreError, ObjectStoreRef, Path};
+use parquet::{
+    arrow::{
+        self as parquet_arrow, arrow_reader::ParquetRecordBatchReaderBuilder,
+        async_reader::AsyncFileReader, ParquetRecordBatchStreamBuilder, ProjectionMask,
+    },
+    basic::{Compression, SortOrder},
+    column::reader::{ColumnReader, ColumnReaderImpl},
+    data_type::DataType as ParquetDataType,
+    errors::ParquetError,
+    file::{
+        footer,
+        metadata::ParquetMetaData,
+        page_index::{index::Index, index_reader},
+        reader::{ChunkReader, FileReader, RowGroupReader},
+        serialized_reader::SerializedFileReader,
+        statistics::{Statistics, ValueStatistics},
+    },
+    format::PageLocation,
+};
+
+use crate::metrics::SST_META_DECODE_FAILURE_COUNTER;
+
+/// Reason why the meta data fails to be decoded from the sst file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetaDecodeFailure {
+    /// The parquet footer is corrupted.
+    CorruptFooter,
+    /// The key value meta data with the meta key is missing.
+    MissingKv,
+    /// The key or the header of the meta value is invalid.
+    BadHeader,
+    /// The payload of the meta value can't be decompressed or decoded as
+    /// protobuf.
+    ProtoError,
+}
+
+impl MetaDecodeFailure {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::CorruptFooter => "corrupt_footer",
+            Self::MissingKv => "missing_kv",
+            Self::BadHeader => "bad_header",
+            Self::ProtoError => "proto_error",
+        }
+    }
+
+    fn from_encoding_error(e: &EncodingError) -> Self {
+        match e {
+            EncodingError::Base64MetaValueNotFound { .. } => Self::MissingKv,
+            EncodingError::DecompressMetaValue { .. }
+            | EncodingError::DecodeFromPb { .. }
+            | EncodingError::ConvertSstMetaData { .. } => Self::ProtoError,
+            _ => Self::BadHeader,
+        }
+    }
+
+    fn observe(&self) {
+        SST_META_DECODE_FAILURE_COUNTER
+            .with_label_values(&[self.as_str()])
+            .inc();
+    }
+}
+
+/// The default timeout of the requests to the object store.
+pub const DEFAULT_OBJECT_STORE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The request to the object store is not finished within the timeout, which
+/// can be found by downcasting the returned error.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObjectStoreTimeout {
+    pub path: String,
+    pub timeout: Duration,
+}
+
+impl fmt::Display for ObjectStoreTimeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Object store request timeout, path:{}, timeout:{:?}",
+            self.path, self.timeout
+        )
+    }
+}
+
+impl std::error::Error for ObjectStoreTimeout {}
+
+/// Wait for the `request` to the object store at most `timeout`, and an
+/// [ObjectStoreTimeout] error is returned if it is not finished in time.
+async fn request_with_timeout<T, E, F>(path: &Path, timeout: Duration, request: F) -> Result<T>
+where
+    E: std::error::Error + Send + Sync + 'static,
+    F: Future<Output = std::result::Result<T, E>>,
+{
+    match tokio::time::timeout(timeout, request).await {
+        Ok(res) => Ok(res?),
+        Err(_) => Err(ObjectStoreTimeout {
+            path: path.to_string(),
+            timeout,
+        }
+        .into()),
+    }
+}
+
+/// Read the whole sst file from the object store within the `timeout`.
+async fn read_sst(store: &ObjectStoreRef, sst_path: &Path, timeout: Duration) -> Result<Bytes> {
+    request_with_timeout(sst_path, timeout, async {
+        store.get(sst_path).await?.bytes().await
+    })
+    .await
+}
 
 /// Extract the meta data from the sst file.
 pub async fn meta_from_sst(store: &ObjectStoreRef, sst_path: &Path) -> SstMetaData {
-    let get_result = store.get(sst_path).await.unwrap();
-    let chunk_reader = get_result.bytes().await.unwrap();
-    let metadata = footer::parse_metadata(&chunk_reader).unwrap();
-    let kv_metas = metadata.file_metadata().key_value_metadata().unwrap();
+    try_meta_from_sst(store, sst_path).await.unwrap()
+}
+
+/// Extract the meta data from the sst file, and the decode failure is counted
+/// by its reason in [SST_META_DECODE_FAILURE_COUNTER].
+pub async fn try_meta_from_sst(store: &ObjectStoreRef, sst_path: &Path) -> Result<SstMetaData> {
+    try_meta_from_sst_with_timeout(store, sst_path, DEFAULT_OBJECT_STORE_TIMEOUT).await
+}
+
+/// Same as [try_meta_from_sst], but the reading of the sst file fails with
+/// [ObjectStoreTimeout] if it takes longer than `timeout`.
+pub async fn try_meta_from_sst_with_timeout(
+    store: &ObjectStoreRef,
+    sst_path: &Path,
+    timeout: Duration,
+) -> Result<SstMetaData> {
+    let chunk_reader = read_sst(store, sst_path, timeout).await?;
+    let metadata = parse_parquet_meta_data(&chunk_reader)?;
+
+    sst_meta_from_parquet(&metadata, sst_path)
+}
+
+/// Parse the parquet meta data from the footer of the sst file, and the
+/// failure is counted as [MetaDecodeFailure::CorruptFooter].
+fn parse_parquet_meta_data<R: ChunkReader>(chunk_reader: &R) -> Result<ParquetMetaData> {
+    footer::parse_metadata(chunk_reader).map_err(|e| {
+        MetaDecodeFailure::CorruptFooter.observe();
+        e.into()
+    })
+}
+
+/// Get the time ranges of the row groups in the sst file, which are computed
+/// when the sst is built.
+///
+/// An empty list is returned if the sst is written without them.
+pub async fn row_group_time_ranges(
+    store: &ObjectStoreRef,
+    sst_path: &Path,
+) -> Result<Vec<TimeRange>> {
+    let meta_data = try_meta_from_sst(store, sst_path).await?;
+
+    Ok(meta_data.row_group_time_ranges)
+}
+
+/// List the keys of all the key value meta data in the footer of the sst file,
+/// in the order they are stored.
+///
+/// Besides the [encoding::META_KEY], the sst may carry the arrow schema and
+/// other custom keys, and an empty list is returned if there is no key value
+/// meta data.
+pub async fn list_kv_meta_keys(store: &ObjectStoreRef, sst_path: &Path) -> Result<Vec<String>> {
+    let chunk_reader = read_sst(store, sst_path, DEFAULT_OBJECT_STORE_TIMEOUT).await?;
+    let parquet_meta_data = parse_parquet_meta_data(&chunk_reader)?;
+
+    let keys = parquet_meta_data
+        .file_metadata()
+        .key_value_metadata()
+        .map(|kv_metas| kv_metas.iter().map(|kv| kv.key.clone()).collect())
+        .unwrap_or_default();
+    Ok(keys)
+}
+
+/// Decode the rows in the `row_group_idx`-th row group of the sst file
+/// according to the storage format stored in its meta data.
+pub async fn decode_row_group(
+    store: &ObjectStoreRef,
+    sst_path: &Path,
+    row_group_idx: usize,
+) -> Result<ArrowRecordBatch> {
+    let chunk_reader = read_sst(store, sst_path, DEFAULT_OBJECT_STORE_TIMEOUT).await?;
+    let parquet_meta_data = parse_parquet_meta_data(&chunk_reader)?;
+
+    let num_row_groups = parquet_meta_data.num_row_groups();
+    ensure!(
+        row_group_idx < num_row_groups,
+        "Row group index out of range, index:{}, num_row_groups:{}",
+        row_group_idx,
+        num_row_groups
+    );
+    let sst_meta_data = sst_meta_from_parquet(&parquet_meta_data, sst_path)?;
+
+    let record_batch = read_row_group(chunk_reader, &parquet_meta_data, row_group_idx)?;
+    let decoder = ParquetDecoder::try_new(sst_meta_data.storage_format_opts)?;
+    Ok(decoder.decode_record_batch(record_batch)?)
+}
+
+/// Re-encode the sst file at `src_path` with `compression` and write it to
+/// `dst_path`.
+///
+/// The logical data, the storage format, the row groups and the meta data are
+/// all preserved, and the meta data of the new sst is returned.
+pub async fn recompress_sst(
+    store: &ObjectStoreRef,
+    src_path: &Path,
+    dst_path: &Path,
+    compression: Compression,
+) -> Result<SstMetaData> {
+    let chunk_reader = read_sst(store, src_path, DEFAULT_OBJECT_STORE_TIMEOUT).await?;
+    let parquet_meta_data = parse_parquet_meta_data(&chunk_reader)?;
+    let sst_meta_data = sst_meta_from_parquet(&parquet_meta_data, src_path)?;
+
+    // The collapsible columns will be collected again by the encoder.
+    let mut encode_meta_data = sst_meta_data.clone();
+    encode_meta_data
+        .storage_format_opts
+        .collapsible_cols_idx
+        .clear();
+    let mut encoder = new_rewrite_encoder(&parquet_meta_data, Some(compression), encode_meta_data)?;
+
+    let decoder = ParquetDecoder::try_new(sst_meta_data.storage_format_opts.clone())?;
+    for row_group_idx in 0..parquet_meta_data.num_row_groups() {
+        let record_batch = read_row_group(chunk_reader.clone(), &parquet_meta_data, row_group_idx)?;
+        let record_batch = decoder.decode_record_batch(record_batch)?;
+        // Encode every row group separately to keep the row groups unchanged.
+        encoder.encode_record_batch(vec![record_batch])?;
+    }
+    let sst_bytes = encoder.close()?;
+    request_with_timeout(
+        dst_path,
+        DEFAULT_OBJECT_STORE_TIMEOUT,
+        store.put(dst_path, sst_bytes.into()),
+    )
+    .await?;
+
+    Ok(sst_meta_data)
+}
+
+/// A range of the primary keys `[start, end)`.
+///
+/// The bounds are compared with the datums of the key columns of a row
+/// lexicographically, so a prefix of the key columns can be used as the bound,
+/// e.g. `[["a"], ["b"])` covers all the rows whose first key column is `"a"`.
+#[derive(Debug, Clone)]
+pub struct KeyRange {
+    pub start: Vec<Datum>,
+    pub end: Vec<Datum>,
+}
+
+impl KeyRange {
+    fn contains(&self, key: &[Datum]) -> bool {
+        key >= self.start.as_slice() && key < self.end.as_slice()
+    }
+}
+
+/// Rewrite the sst file at `src_path` to `dst_path` with the rows whose
+/// primary keys fall in the `key_range` removed.
+///
+/// The storage format and the compression are preserved, and the row groups
+/// without any remaining rows are dropped. The row number, the time ranges and
+/// the min/max keys (the memcomparable encoded primary keys of the first and
+/// the last remaining rows) in the meta data are recomputed, while the bloom
+/// filter is dropped as the row groups may change. The new meta data is
+/// returned.
+pub async fn delete_sst_key_range(
+    store: &ObjectStoreRef,
+    src_path: &Path,
+    dst_path: &Path,
+    key_range: &KeyRange,
+) -> Result<SstMetaData> {
+    let chunk_reader = read_sst(store, src_path, DEFAULT_OBJECT_STORE_TIMEOUT).await?;
+    let parquet_meta_data = parse_parquet_meta_data(&chunk_reader)?;
+    let sst_meta_data = sst_meta_from_parquet(&parquet_meta_data, src_path)?;
+    let schema = &sst_meta_data.schema;
+
+    let decoder = ParquetDecoder::try_new(sst_meta_data.storage_format_opts.clone())?;
+    let mut row_groups = Vec::with_capacity(parquet_meta_data.num_row_groups());
+    // The rows in the sst are sorted by the primary keys.
+    let (mut min_key, mut max_key) = (None, None);
+    for row_group_idx in 0..parquet_meta_data.num_row_groups() {
+        let record_batch = read_row_group(chunk_reader.clone(), &parquet_meta_data, row_group_idx)?;
+        let record_batch = decoder.decode_record_batch(record_batch)?;
+        let key_columns = schema
+            .primary_key_indexes()
+            .iter()
+            .map(|idx| ColumnBlock::try_cast_arrow_array_ref(record_batch.column(*idx)))
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        let mut num_retained_rows = 0;
+        let selection = (0..record_batch.num_rows())
+            .map(|row_idx| {
+                let key = key_columns
+                    .iter()
+                    .map(|column| column.datum(row_idx))
+                    .collect::<Vec<_>>();
+                let retained = !key_range.contains(&key);
+                if retained {
+                    if min_key.is_none() {
+                        min_key = Some(key.clone());
+                    }
+                    max_key = Some(key);
+                    num_retained_rows += 1;
+                }
+                Some(retained)
+            })
+            .collect::<BooleanArray>();
+        if num_retained_rows == 0 {
+            continue;
+        }
+
+        row_groups.push(vec![compute::filter_record_batch(
+            &record_batch,
+            &selection,
+        )?]);
+    }
+    let (min_key, max_key) = min_key
+        .zip(max_key)
+        .with_context(|| format!("All rows are deleted, path:{}", src_path))?;
+
+    let row_group_time_ranges = encoding::compute_row_group_time_ranges(schema, &row_groups)?;
+    let time_range = row_group_time_ranges
+        .iter()
+        .filter(|time_range| time_range.inclusive_start() < time_range.exclusive_end())
+        .fold(None, |acc: Option<TimeRange>, time_range| match acc {
+            Some(acc) => Some(TimeRange::new_unchecked(
+                acc.inclusive_start().min(time_range.inclusive_start()),
+                acc.exclusive_end().max(time_range.exclusive_end()),
+            )),
+            None => Some(*time_range),
+        })
+        .unwrap_or_else(TimeRange::empty);
+    let mut new_meta_data = sst_meta_data.clone();
+    new_meta_data.min_key = encode_primary_key(&min_key)?;
+    new_meta_data.max_key = encode_primary_key(&max_key)?;
+    new_meta_data.time_range = time_range;
+    new_meta_data.row_num = row_groups
+        .iter()
+        .flatten()
+        .map(|record_batch| record_batch.num_rows() as u64)
+        .sum();
+    new_meta_data.bloom_filter = None;
+    new_meta_data.row_group_time_ranges = row_group_time_ranges;
+    // The collapsible columns will be collected again by the encoder.
+    new_meta_data
+        .storage_format_opts
+        .collapsible_cols_idx
+        .clear();
+
+    let mut encoder = new_rewrite_encoder(&parquet_meta_data, None, new_meta_data.clone())?;
+    for record_batches in row_groups {
+        // Encode every row group separately to keep the remaining row groups.
+        encoder.encode_record_batch(record_batches)?;
+    }
+    let sst_bytes = encoder.close()?;
+    request_with_timeout(
+        dst_path,
+        DEFAULT_OBJECT_STORE_TIMEOUT,
+        store.put(dst_path, sst_bytes.into()),
+    )
+    .await?;
+
+    Ok(new_meta_data)
+}
+
+/// Re-encode the sst file at `sst_path` in the storage `format` and replace
+/// the original file with it.
+///
+/// The new sst is written to a temporary path first, and it only replaces the
+/// original file after it is verified to hold the same rows, so the original
+/// file is left untouched if anything goes wrong. The row groups, the
+/// compression and the meta data except the storage format are preserved, and
+/// the meta data of the new sst is returned. The temporary file may be left
+/// behind if it fails to be removed after the replacement.
+pub async fn convert_sst_storage_format(
+    store: &ObjectStoreRef,
+    sst_path: &Path,
+    format: StorageFormat,
+) -> Result<SstMetaData> {
+    let chunk_reader = read_sst(store, sst_path, DEFAULT_OBJECT_STORE_TIMEOUT).await?;
+    let parquet_meta_data = parse_parquet_meta_data(&chunk_reader)?;
+    let sst_meta_data = sst_meta_from_parquet(&parquet_meta_data, sst_path)?;
+
+    let mut new_meta_data = sst_meta_data.clone();
+    new_meta_data.storage_format_opts = StorageFormatOptions::new(format);
+    let mut encoder = new_rewrite_encoder(&parquet_meta_data, None, new_meta_data.clone())?;
+
+    let decoder = ParquetDecoder::try_new(sst_meta_data.storage_format_opts)?;
+    for row_group_idx in 0..parquet_meta_data.num_row_groups() {
+        let record_batch = read_row_group(chunk_reader.clone(), &parquet_meta_data, row_group_idx)?;
+        let record_batch = decoder.decode_record_batch(record_batch)?;
+        // Encode every row group separately to keep the row groups unchanged.
+        encoder.encode_record_batch(vec![record_batch])?;
+    }
+    let sst_bytes = encoder.close()?;
+
+    let tmp_path = Path::from(format!("{}.tmp", sst_path));
+    request_with_timeout(
+        &tmp_path,
+        DEFAULT_OBJECT_STORE_TIMEOUT,
+        store.put(&tmp_path, sst_bytes.into()),
+    )
+    .await?;
+    let verified = match sst_equal(store, sst_path, &tmp_path).await {
+        Ok(None) => Ok(()),
+        Ok(Some(diff)) => Err(anyhow!(
+            "Converted sst mismatches the original one, path:{}, diff:{}",
+            sst_path,
+            diff
+        )),
+        Err(e) => Err(e),
+    };
+    if let Err(e) = verified {
+        request_with_timeout(
+            &tmp_path,
+            DEFAULT_OBJECT_STORE_TIMEOUT,
+            store.delete(&tmp_path),
+        )
+        .await?;
+        return Err(e);
+    }
+
+    request_with_timeout(
+        sst_path,
+        DEFAULT_OBJECT_STORE_TIMEOUT,
+        store.copy(&tmp_path, sst_path),
+    )
+    .await?;
+    // The original sst is already replaced, so the failure to clean up the
+    // temporary file is not treated as the failure of the conversion.
+    if let Err(e) = request_with_timeout(
+        &tmp_path,
+        DEFAULT_OBJECT_STORE_TIMEOUT,
+        store.delete(&tmp_path),
+    )
+    .await
+    {
+        warn!(
+            "Failed to delete the temporary sst after conversion, path:{}, err:{}",
+            tmp_path, e
+        );
+    }
+
+    // The collapsible columns are collected by the encoder.
+    try_meta_from_sst(store, sst_path).await
+}
+
+/// Encode the datums of the key columns in the memcomparable format.
+fn encode_primary_key(key: &[Datum]) -> Result<Bytes> {
+    let encoder = MemComparable;
+    let mut buf = Vec::new();
+    for datum in key {
+        encoder.encode(&mut buf, datum)?;
+    }
+
+    Ok(buf.into())
+}
+
+/// Decode all the rows in the sst file and dump them as json lines.
+///
+/// Every row is dumped as one json object whose fields are ordered by the
+/// columns in the schema, so the output is stable and suitable to be diffed.
+pub async fn dump_sst_rows(store: &ObjectStoreRef, sst_path: &Path) -> Result<String> {
+    let (_, record_batches) = decode_sst(store, sst_path).await?;
+
+    let mut output = String::new();
+    for record_batch in &record_batches {
+        dump_record_batch(record_batch, &mut output)?;
+    }
+
+    Ok(output)
+}
+
+/// Decode all the row groups of the sst file according to its storage format.
+async fn decode_sst(
+    store: &ObjectStoreRef,
+    sst_path: &Path,
+) -> Result<(SstMetaData, Vec<ArrowRecordBatch>)> {
+    let chunk_reader = read_sst(store, sst_path, DEFAULT_OBJECT_STORE_TIMEOUT).await?;
+    let parquet_meta_data = parse_parquet_meta_data(&chunk_reader)?;
+    let sst_meta_data = sst_meta_from_parquet(&parquet_meta_data, sst_path)?;
+    let decoder = ParquetDecoder::try_new(sst_meta_data.storage_format_opts.clone())?;
+
+    let mut record_batches = Vec::with_capacity(parquet_meta_data.num_row_groups());
+    for row_group_idx in 0..parquet_meta_data.num_row_groups() {
+        let record_batch = read_row_group(chunk_reader.clone(), &parquet_meta_data, row_group_idx)?;
+        record_batches.push(decoder.decode_record_batch(record_batch)?);
+    }
+
+    Ok((sst_meta_data, record_batches))
+}
+
+/// Decode the `columns` of all the rows in the sst file, and only the column
+/// chunks of these columns are fetched from the object store besides the meta
+/// data, which saves the io of reading a few columns of a wide table.
+///
+/// The columns of the returned record batches are ordered as the `columns`.
+pub async fn decode_sst_columns(
+    store: &ObjectStoreRef,
+    sst_path: &Path,
+    columns: &[&str],
+) -> Result<Vec<ArrowRecordBatch>> {
+    decode_sst_columns_with_timeout(store, sst_path, columns, DEFAULT_OBJECT_STORE_TIMEOUT).await
+}
+
+/// Same as [decode_sst_columns], but every request to the object store fails
+/// with [ObjectStoreTimeout] if it takes longer than `timeout`.
+pub async fn decode_sst_columns_with_timeout(
+    store: &ObjectStoreRef,
+    sst_path: &Path,
+    columns: &[&str],
+    timeout: Duration,
+) -> Result<Vec<ArrowRecordBatch>> {
+    let object_meta = request_with_timeout(sst_path, timeout, store.head(sst_path)).await?;
+    let parquet_meta_data = request_with_timeout(
+        sst_path,
+        timeout,
+        file_format::parquet::fetch_parquet_metadata(store.as_ref(), &object_meta, None).map_err(
+            |e| {
+                // Only the failure of reading the object store is not caused by the
+                // footer.
+                if !matches!(e, DataFusionError::ObjectStore(_)) {
+                    MetaDecodeFailure::CorruptFooter.observe();
+                }
+                e
+            },
+        ),
+    )
+    .await?;
+    let sst_meta_data = sst_meta_from_parquet(&parquet_meta_data, sst_path)?;
+    let schema = &sst_meta_data.schema;
+    let storage_format_opts = &sst_meta_data.storage_format_opts;
+
+    let mut column_indexes = columns
+        .iter()
+        .map(|name| {
+            schema
+                .index_of(name)
+                .with_context(|| format!("Column not found, path:{}, column:{}", sst_path, name))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    // The hybrid record batches are stretched by the offsets of the collapsible
+    // columns, so at least one of them must be read.
+    let collapsible_cols_idx = &storage_format_opts.collapsible_cols_idx;
+    if storage_format_opts.format == StorageFormat::Hybrid
+        && !column_indexes
+            .iter()
+            .any(|idx| collapsible_cols_idx.contains(&(*idx as u32)))
+    {
+        if let Some(idx) = collapsible_cols_idx.first() {
+            column_indexes.push(*idx as usize);
+        }
+    }
+
+    // The columns are read in the order of the schema.
+    let mut read_indexes = column_indexes.clone();
+    read_indexes.sort_unstable();
+    read_indexes.dedup();
+    let decoder = ParquetDecoder::try_new(StorageFormatOptions {
+        format: storage_format_opts.format,
+        collapsible_cols_idx: collapsible_cols_idx
+            .iter()
+            .filter_map(|idx| read_indexes.binary_search(&(*idx as usize)).ok())
+            .map(|pos| pos as u32)
+            .collect(),
+    })?;
+    let output_positions = column_indexes[..columns.len()]
+        .iter()
+        .map(|idx| read_indexes.binary_search(idx).unwrap())
+        .collect::<Vec<_>>();
+
+    let projection = ProjectionMask::roots(
+        parquet_meta_data.file_metadata().schema_descr(),
+        read_indexes.iter().copied(),
+    );
+    let reader = ObjectStoreChunkReader {
+        store: store.clone(),
+        path: sst_path.clone(),
+        parquet_meta_data: Arc::new(parquet_meta_data),
+        timeout,
+    };
+    let record_batches: Vec<_> = ParquetRecordBatchStreamBuilder::new(reader)
+        .await?
+        .with_projection(projection)
+        .build()?
+        .try_collect()
+        .await?;
+
+    record_batches
+        .into_iter()
+        .map(|record_batch| {
+            let record_batch = decoder.decode_record_batch(record_batch)?;
+            Ok(record_batch.project(&output_positions)?)
+        })
+        .collect()
+}
+
+/// [AsyncFileReader] fetching the byte ranges of the sst file from the object
+/// store on demand, with the meta data fetched in advance.
+///
+/// Every range is fetched separately rather than coalesced with the nearby
+/// ones, so the chunks of the columns not read are never fetched.
+struct ObjectStoreChunkReader {
+    store: ObjectStoreRef,
+    path: Path,
+    parquet_meta_data: Arc<ParquetMetaData>,
+    /// Timeout of fetching each range.
+    timeout: Duration,
+}
+
+impl AsyncFileReader for ObjectStoreChunkReader {
+    fn get_bytes(&mut self, range: Range<usize>) -> BoxFuture<'_, parquet::errors::Result<Bytes>> {
+        request_with_timeout(
+            &self.path,
+            self.timeout,
+            self.store.get_range(&self.path, range),
+        )
+        .map_err(|e| {
+            ParquetError::General(format!(
+                "Failed to fetch range from object store, err:{}",
+                e
+            ))
+        })
+        .boxed()
+    }
+
+    fn get_metadata(&mut self) -> BoxFuture<'_, parquet::errors::Result<Arc<ParquetMetaData>>> {
+        future::ready(Ok(self.parquet_meta_data.clone())).boxed()
+    }
+}
+
+/// Max number of the differing rows of each sst kept in the [SstDiff].
+const MAX_DIFF_ROWS: usize = 10;
+
+/// Summary of the difference between the logical data of two ssts.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SstDiff {
+    /// The columns of the two ssts, only set if their schemas are different,
+    /// and the rows are not compared in that case.
+    pub schema_diff: Option<(String, String)>,
+    pub num_rows_a: usize,
+    pub num_rows_b: usize,
+    /// Number of the rows only in the first sst.
+    pub num_rows_only_in_a: usize,
+    /// Number of the rows only in the second sst.
+    pub num_rows_only_in_b: usize,
+    /// The sorted rows only in the first sst dumped as json, at most
+    /// [MAX_DIFF_ROWS] rows are kept.
+    pub rows_only_in_a: Vec<String>,
+    /// The sorted rows only in the second sst dumped as json, at most
+    /// [MAX_DIFF_ROWS] rows are kept.
+    pub rows_only_in_b: Vec<String>,
+}
+
+impl SstDiff {
+    fn is_empty(&self) -> bool {
+        self.schema_diff.is_none() && self.num_rows_only_in_a == 0 && self.num_rows_only_in_b == 0
+    }
+
+    fn add_row_only_in_a(&mut self, row: &str) {
+        self.num_rows_only_in_a += 1;
+        if self.rows_only_in_a.len() < MAX_DIFF_ROWS {
+            self.rows_only_in_a.push(row.to_string());
+        }
+    }
+
+    fn add_row_only_in_b(&mut self, row: &str) {
+        self.num_rows_only_in_b += 1;
+        if self.rows_only_in_b.len() < MAX_DIFF_ROWS {
+            self.rows_only_in_b.push(row.to_string());
+        }
+    }
+}
+
+impl fmt::Display for SstDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some((columns_a, columns_b)) = &self.schema_diff {
+            return write!(
+                f,
+                "Schema mismatch, columns_a:[{}], columns_b:[{}]",
+                columns_a, columns_b
+            );
+        }
+
+        writeln!(
+            f,
+            "Rows mismatch, num_rows_a:{}, num_rows_b:{}, num_rows_only_in_a:{}, num_rows_only_in_b:{}",
+            self.num_rows_a, self.num_rows_b, self.num_rows_only_in_a, self.num_rows_only_in_b
+        )?;
+        for row in &self.rows_only_in_a {
+            writeln!(f, "- {}", row)?;
+        }
+        for row in &self.rows_only_in_b {
+            writeln!(f, "+ {}", row)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Compare the logical data of the ssts at `path_a` and `path_b`, which may be
+/// encoded in different storage formats or row group layouts.
+///
+/// The rows are sorted before comparing, so their order is ignored. Returns
+/// `None` if the two ssts hold the same rows, otherwise the summary of the
+/// difference is returned.
+pub async fn sst_equal(
+    store: &ObjectStoreRef,
+    path_a: &Path,
+    path_b: &Path,
+) -> Result<Option<SstDiff>> {
+    let (meta_data_a, rows_a) = decode_sorted_rows(store, path_a).await?;
+    let (meta_data_b, rows_b) = decode_sorted_rows(store, path_b).await?;
+    let mut diff = SstDiff {
+        num_rows_a: rows_a.len(),
+        num_rows_b: rows_b.len(),
+        ..Default::default()
+    };
+
+    let columns_a = format_columns(&meta_data_a);
+    let columns_b = format_columns(&meta_data_b);
+    if columns_a != columns_b {
+        diff.schema_diff = Some((columns_a, columns_b));
+        return Ok(Some(diff));
+    }
+
+    let (mut idx_a, mut idx_b) = (0, 0);
+    while idx_a < rows_a.len() || idx_b < rows_b.len() {
+        let ordering = match (rows_a.get(idx_a), rows_b.get(idx_b)) {
+            (Some(row_a), Some(row_b)) => row_a.cmp(row_b),
+            (Some(_), None) => Ordering::Less,
+            (None, _) => Ordering::Greater,
+        };
+        match ordering {
+            Ordering::Equal => {
+                idx_a += 1;
+                idx_b += 1;
+            }
+            Ordering::Less => {
+                diff.add_row_only_in_a(&rows_a[idx_a]);
+                idx_a += 1;
+            }
+            Ordering::Greater => {
+                diff.add_row_only_in_b(&rows_b[idx_b]);
+                idx_b += 1;
+            }
+        }
+    }
+
+    Ok((!diff.is_empty()).then_some(diff))
+}
+
+/// Decode the rows of the sst and dump them as sorted json lines.
+async fn decode_sorted_rows(
+    store: &ObjectStoreRef,
+    sst_path: &Path,
+) -> Result<(SstMetaData, Vec<String>)> {
+    let (sst_meta_data, record_batches) = decode_sst(store, sst_path).await?;
+
+    let mut output = String::new();
+    for record_batch in &record_batches {
+        dump_record_batch(record_batch, &mut output)?;
+    }
+    let mut rows: Vec<_> = output.lines().map(|row| row.to_string()).collect();
+    rows.sort_unstable();
+
+    Ok((sst_meta_data, rows))
+}
+
+fn format_columns(sst_meta_data: &SstMetaData) -> String {
+    sst_meta_data
+        .schema
+        .columns()
+        .iter()
+        .map(|column| format!("{}:{}", column.name, column.data_type))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn dump_record_batch(record_batch: &ArrowRecordBatch, output: &mut String) -> Result<()> {
+    let schema = record_batch.schema();
+    let mut column_names = Vec::with_capacity(schema.fields().len());
+    for field in schema.fields() {
+        column_names.push(serde_json::to_string(field.name())?);
+    }
+    let column_blocks = record_batch
+        .columns()
+        .iter()
+        .map(ColumnBlock::try_cast_arrow_array_ref)
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+
+    for row_idx in 0..record_batch.num_rows() {
+        output.push('{');
+        for (col_idx, (column_name, column_block)) in
+            column_names.iter().zip(&column_blocks).enumerate()
+        {
+            if col_idx > 0 {
+                output.push(',');
+            }
+            output.push_str(column_name);
+            output.push(':');
+            output.push_str(&serde_json::to_string(&column_block.datum(row_idx))?);
+        }
+        output.push_str("}\n");
+    }
+
+    Ok(())
+}
+
+/// The page index of one row group in the sst file.
+#[derive(Debug, Clone)]
+pub struct RowGroupPageIndex {
+    /// The column index of every column, which holds the min/max bounds of
+    /// the pages.
+    pub column_index: Vec<Index>,
+    /// The offset index of every column, which holds the locations of the
+    /// pages.
+    pub offset_index: Vec<Vec<PageLocation>>,
+}
+
+/// Read the page index (ColumnIndex and OffsetIndex) of every row group in the
+/// sst file, which is helpful to check whether the pages can be skipped.
+pub async fn read_page_index(
+    store: &ObjectStoreRef,
+    sst_path: &Path,
+) -> Result<Vec<RowGroupPageIndex>> {
+    let chunk_reader = read_sst(store, sst_path, DEFAULT_OBJECT_STORE_TIMEOUT).await?;
+    let parquet_meta_data = parse_parquet_meta_data(&chunk_reader)?;
+
+    parquet_meta_data
+        .row_groups()
+        .iter()
+        .map(|row_group| {
+            let column_index =
+                index_reader::read_columns_indexes(&chunk_reader, row_group.columns())
+                    .with_context(|| format!("Failed to read column index, path:{}", sst_path))?;
+            let offset_index =
+                index_reader::read_pages_locations(&chunk_reader, row_group.columns())
+                    .with_context(|| format!("Failed to read offset index, path:{}", sst_path))?;
+
+            Ok(RowGroupPageIndex {
+                column_index,
+                offset_index,
+            })
+        })
+        .collect()
+}
+
+/// A column chunk whose pages fail to be verified, found by
+/// [verify_sst_pages].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CorruptPage {
+    pub row_group_idx: usize,
+    pub column_idx: usize,
+    pub column: String,
+    /// The error of the first failure found in the column chunk.
+    pub error: String,
+}
+
+/// Read every page of every column chunk in the sst file, and report the
+/// column chunks whose pages can't be read, e.g. the page header is invalid or
+/// the page fails to be decompressed to the size in its header, or whose
+/// decoded values mismatch the statistics of the column chunk.
+///
+/// The parquet writer of this version doesn't support writing the page
+/// checksums, so the corruption of the page data is found by checking the
+/// decoded values against the min/max and the null count in the statistics
+/// instead, and a corrupted value still in the range can't be found.
+pub async fn verify_sst_pages(store: &ObjectStoreRef, sst_path: &Path) -> Result<Vec<CorruptPage>> {
+    let chunk_reader = read_sst(store, sst_path, DEFAULT_OBJECT_STORE_TIMEOUT).await?;
+    let file_reader = SerializedFileReader::new(chunk_reader)
+        .map_err(|e| {
+            MetaDecodeFailure::CorruptFooter.observe();
+            e
+        })
+        .with_context(|| format!("Failed to read parquet meta data, path:{}", sst_path))?;
+
+    let mut corrupt_pages = Vec::new();
+    for row_group_idx in 0..file_reader.num_row_groups() {
+        let row_group_reader = file_reader.get_row_group(row_group_idx)?;
+        let row_group_meta_data = row_group_reader.metadata();
+        for column_idx in 0..row_group_meta_data.num_columns() {
+            let read_pages = || -> std::result::Result<(), ParquetError> {
+                for page in row_group_reader.get_column_page_reader(column_idx)? {
+                    page?;
+                }
+                Ok(())
+            };
+
+            let res = read_pages()
+                .map_err(anyhow::Error::from)
+                .and_then(|_| verify_column_values(row_group_reader.as_ref(), column_idx));
+            if let Err(e) = res {
+                corrupt_pages.push(CorruptPage {
+                    row_group_idx,
+                    column_idx,
+                    column: row_group_meta_data
+                        .column(column_idx)
+                        .column_path()
+                        .string(),
+                    error: e.to_string(),
+                });
+            }
+        }
+    }
+
+    Ok(corrupt_pages)
+}
+
+/// Decode all the values of the `column_idx`-th column chunk, and check them
+/// against the statistics of the column chunk.
+///
+/// The null count is only checked for the non-repeated column, as the null
+/// values can't be told from the empty lists by the levels simply.
+fn verify_column_values(row_group_reader: &dyn RowGroupReader, column_idx: usize) -> Result<()> {
+    let column_meta_data = row_group_reader.metadata().column(column_idx);
+    let column_descr = column_meta_data.column_descr();
+    let statistics = column_meta_data.statistics();
+    // The values are compared in the sort order of the statistics, and the
+    // range check is skipped if the order is undefined.
+    let sort_order = column_descr.sort_order();
+    let ordered = sort_order != SortOrder::UNDEFINED;
+    let signed = sort_order == SortOrder::SIGNED;
+
+    let num_nulls = match row_group_reader.get_column_reader(column_idx)? {
+        ColumnReader::BoolColumnReader(reader) => {
+            let statistics = match statistics {
+                Some(Statistics::Boolean(v)) => Some(v),
+                _ => None,
+            };
+            read_column_values(reader, statistics, |a, b| ordered.then(|| a.cmp(b)))?
+        }
+        ColumnReader::Int32ColumnReader(reader) => {
+            let statistics = match statistics {
+                Some(Statistics::Int32(v)) => Some(v),
+                _ => None,
+            };
+            read_column_values(reader, statistics, |a, b| {
+                ordered.then(|| {
+                    if signed {
+                        a.cmp(b)
+                    } else {
+                        (*a as u32).cmp(&(*b as u32))
+                    }
+                })
+            })?
+        }
+        ColumnReader::Int64ColumnReader(reader) => {
+            let statistics = match statistics {
+                Some(Statistics::Int64(v)) => Some(v),
+                _ => None,
+            };
+            read_column_values(reader, statistics, |a, b| {
+                ordered.then(|| {
+                    if signed {
+                        a.cmp(b)
+                    } else {
+                        (*a as u64).cmp(&(*b as u64))
+                    }
+                })
+            })?
+        }
+        ColumnReader::Int96ColumnReader(reader) => read_column_values(reader, None, |_, _| None)?,
+        ColumnReader::FloatColumnReader(reader) => {
+            let statistics = match statistics {
+                Some(Statistics::Float(v)) => Some(v),
+                _ => None,
+            };
+            read_column_values(reader, statistics, |a, b| {
+                ordered.then(|| a.partial_cmp(b)).flatten()
+            })?
+        }
+        ColumnReader::DoubleColumnReader(reader) => {
+            let statistics = match statistics {
+                Some(Statistics::Double(v)) => Some(v),
+                _ => None,
+            };
+            read_column_values(reader, statistics, |a, b| {
+                ordered.then(|| a.partial_cmp(b)).flatten()
+            })?
+        }
+        ColumnReader::ByteArrayColumnReader(reader) => {
+            let statistics = match statistics {
+                Some(Statistics::ByteArray(v)) => Some(v),
+                _ => None,
+            };
+            read_column_values(reader, statistics, |a, b| {
+                ordered.then(|| a.data().cmp(b.data()))
+            })?
+        }
+        ColumnReader::FixedLenByteArrayColumnReader(reader) => {
+            let statistics = match statistics {
+                Some(Statistics::FixedLenByteArray(v)) => Some(v),
+                _ => None,
+            };
+            read_column_values(reader, statistics, |a, b| {
+                ordered.then(|| a.data().cmp(b.data()))
+            })?
+        }
+    };
+
+    if let Some(statistics) = statistics {
+        if column_descr.max_rep_level() == 0 {
+            ensure!(
+                statistics.null_count() == num_nulls,
+                "Null count mismatches the statistics, statistics:{}, actual:{}",
+                statistics.null_count(),
+                num_nulls
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Read all the values by the column `reader`, and return the number of the
+/// levels without value, that is, the null values of the non-repeated column.
+///
+/// The values are checked to be in the min/max of the `statistics` if `cmp`
+/// can compare them.
+fn read_column_values<T: ParquetDataType>(
+    mut reader: ColumnReaderImpl<T>,
+    statistics: Option<&ValueStatistics<T::T>>,
+    cmp: impl Fn(&T::T, &T::T) -> Option<Ordering>,
+) -> Result<u64> {
+    const BATCH_SIZE: usize = 1024;
+
+    let min_max = statistics
+        .filter(|v| v.has_min_max_set())
+        .map(|v| (v.min(), v.max()));
+    let mut def_levels = vec![0; BATCH_SIZE];
+    let mut rep_levels = vec![0; BATCH_SIZE];
+    let mut values = vec![T::T::default(); BATCH_SIZE];
+    let mut num_nulls = 0;
+    loop {
+        let (num_values, num_levels) = reader.read_batch(
+            BATCH_SIZE,
+            Some(&mut def_levels),
+            Some(&mut rep_levels),
+            &mut values,
+        )?;
+        if num_values == 0 && num_levels == 0 {
+            break;
+        }
+        // The levels of the required column are not read.
+        num_nulls += num_levels.saturating_sub(num_values) as u64;
+
+        if let Some((min, max)) = min_max {
+            for value in &values[..num_values] {
+                let out_of_range = cmp(value, min) == Some(Ordering::Less)
+                    || cmp(value, max) == Some(Ordering::Greater);
+                ensure!(
+                    !out_of_range,
+                    "Value out of the range in the statistics, value:{:?}, min:{:?}, max:{:?}",
+                    value,
+                    min,
+                    max
+                );
+            }
+        }
+    }
+
+    Ok(num_nulls)
+}
+
+/// A discrepancy between the meta data and the data of the sst found by
+/// [check_sst_consistency].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SstInconsistency {
+    /// The `row_num` in the meta data differs from the number of the decoded
+    /// rows.
+    RowNum { meta: u64, actual: u64 },
+    /// Some decoded key is less than the `min_key` in the meta data, and
+    /// `actual` is the min decoded key.
+    MinKey { meta: Bytes, actual: Bytes },
+    /// Some decoded key is greater than the `max_key` in the meta data, and
+    /// `actual` is the max decoded key.
+    MaxKey { meta: Bytes, actual: Bytes },
+    /// Some decoded timestamp is out of the `time_range` in the meta data, and
+    /// `actual` is the time range covering all the decoded timestamps.
+    TimeRange { meta: TimeRange, actual: TimeRange },
+}
+
+impl fmt::Display for SstInconsistency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::RowNum { meta, actual } => {
+                write!(f, "Row num mismatch, meta:{}, actual:{}", meta, actual)
+            }
+            Self::MinKey { meta, actual } => write!(
+                f,
+                "Key less than min key, meta:{:?}, actual:{:?}",
+                meta, actual
+            ),
+            Self::MaxKey { meta, actual } => write!(
+                f,
+                "Key greater than max key, meta:{:?}, actual:{:?}",
+                meta, actual
+            ),
+            Self::TimeRange { meta, actual } => write!(
+                f,
+                "Timestamp out of time range, meta:{:?}, actual:{:?}",
+                meta, actual
+            ),
+        }
+    }
+}
+
+/// Decode all the row groups of the sst file and check them against its meta
+/// data, that is, the `row_num` equals to the number of the decoded rows, and
+/// all the decoded keys and timestamps fall in the min/max keys and the time
+/// range.
+///
+/// The keys are compared in the memcomparable format, and the meta data of
+/// the sst built by compaction may cover a wider range than the data, so only
+/// the bounds are checked. The min/max keys written by the engine are internal
+/// keys with a sequence suffix, so only their user key prefix is compared. An
+/// empty list is returned if no discrepancy is found.
+pub async fn check_sst_consistency(
+    store: &ObjectStoreRef,
+    sst_path: &Path,
+) -> Result<Vec<SstInconsistency>> {
+    let (meta_data, record_batches) = decode_sst(store, sst_path).await?;
+    let schema = &meta_data.schema;
+    let timestamp_index = schema.timestamp_index();
+
+    let mut num_rows = 0;
+    let (mut min_key, mut max_key): (Option<Bytes>, Option<Bytes>) = (None, None);
+    let (mut min_timestamp, mut max_timestamp): (Option<Timestamp>, Option<Timestamp>) =
+        (None, None);
+    for record_batch in &record_batches {
+        let key_columns = schema
+            .primary_key_indexes()
+            .iter()
+            .map(|idx| ColumnBlock::try_cast_arrow_array_ref(record_batch.column(*idx)))
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        let timestamp_column =
+            ColumnBlock::try_cast_arrow_array_ref(record_batch.column(timestamp_index))?;
+
+        for row_idx in 0..record_batch.num_rows() {
+            let key = key_columns
+                .iter()
+                .map(|column| column.datum(row_idx))
+                .collect::<Vec<_>>();
+            let key = encode_primary_key(&key)?;
+            if min_key.as_ref().map_or(true, |min_key| key < *min_key) {
+                min_key = Some(key.clone());
+            }
+            if max_key.as_ref().map_or(true, |max_key| key > *max_key) {
+                max_key = Some(key);
+            }
+
+            if let Some(timestamp) = timestamp_column.datum(row_idx).as_timestamp() {
+                min_timestamp = Some(min_timestamp.map_or(timestamp, |v| v.min(timestamp)));
+                max_timestamp = Some(max_timestamp.map_or(timestamp, |v| v.max(timestamp)));
+            }
+        }
+        num_rows += record_batch.num_rows() as u64;
+    }
+
+    let mut inconsistencies = Vec::new();
+    if num_rows != meta_data.row_num {
+        inconsistencies.push(SstInconsistency::RowNum {
+            meta: meta_data.row_num,
+            actual: num_rows,
+        });
+    }
+    if let Some(min_key) = min_key {
+        if min_key.as_ref() < user_key_prefix(&meta_data.min_key, &min_key) {
+            inconsistencies.push(SstInconsistency::MinKey {
+                meta: meta_data.min_key.clone(),
+                actual: min_key,
+            });
+        }
+    }
+    if let Some(max_key) = max_key {
+        if max_key.as_ref() > user_key_prefix(&meta_data.max_key, &max_key) {
+            inconsistencies.push(SstInconsistency::MaxKey {
+                meta: meta_data.max_key.clone(),
+                actual: max_key,
+            });
+        }
+    }
+    if let Some((min_timestamp, max_timestamp)) = min_timestamp.zip(max_timestamp) {
+        if !meta_data.time_range.contains(min_timestamp)
+            || !meta_data.time_range.contains(max_timestamp)
+        {
+            let exclusive_end = max_timestamp.checked_add_i64(1).unwrap_or(max_timestamp);
+            let actual = TimeRange::new_unchecked(min_timestamp, exclusive_end);
+            inconsistencies.push(SstInconsistency::TimeRange {
+                meta: meta_data.time_range,
+                actual,
+            });
+        }
+    }
+
+    Ok(inconsistencies)
+}
+
+/// Returns the prefix of the `meta_key` to compare with the decoded `key`.
+///
+/// The meta key may be an internal key, i.e. the user key followed by the
+/// sequence. As the memcomparable encoding of the user keys is prefix free,
+/// truncating the meta key to the length of the decoded key keeps the order
+/// between them and drops the sequence if the user keys are equal.
+fn user_key_prefix<'a>(meta_key: &'a [u8], key: &[u8]) -> &'a [u8] {
+    &meta_key[..meta_key.len().min(key.len())]
+}
+
+/// The condition on the column of the [ScanPredicate].
+#[derive(Debug, Clone)]
+pub enum ScanCondition {
+    /// The value equals to the datum.
+    Eq(Datum),
+    /// The value is in the range `[start, end)`.
+    Range { start: Datum, end: Datum },
+}
+
+/// A simple predicate on one key column used by [scan_sst].
+#[derive(Debug, Clone)]
+pub struct ScanPredicate {
+    pub column: String,
+    pub condition: ScanCondition,
+}
+
+impl ScanPredicate {
+    fn to_exprs(&self) -> Result<Vec<Expr>> {
+        let to_scalar = |datum: &Datum| {
+            datum
+                .as_scalar_value()
+                .with_context(|| format!("Unsupported datum in predicate, datum:{:?}", datum))
+        };
+
+        let exprs = match &self.condition {
+            ScanCondition::Eq(value) => vec![col(&self.column).eq(lit(to_scalar(value)?))],
+            ScanCondition::Range { start, end } => vec![
+                col(&self.column).gt_eq(lit(to_scalar(start)?)),
+                col(&self.column).lt(lit(to_scalar(end)?)),
+            ],
+        };
+        Ok(exprs)
+    }
+
+    fn matches(&self, datum: &Datum) -> bool {
+        match &self.condition {
+            ScanCondition::Eq(value) => datum == value,
+            ScanCondition::Range { start, end } => datum >= start && datum < end,
+        }
+    }
+}
+
+/// The output of [scan_sst].
+#[derive(Debug)]
+pub struct ScanResult {
+    /// The indexes of the row groups which are read and decoded.
+    pub read_row_groups: Vec<usize>,
+    /// The rows matching the predicate, with only the projected columns.
+    pub record_batches: Vec<ArrowRecordBatch>,
+    /// Whether some row groups which may contain matching rows are not read
+    /// for the limit of the row groups.
+    pub truncated: bool,
+}
+
+/// Scan the rows in the sst file matching the `predicate` and only keep the
+/// columns in the `projection` (all columns if it is `None`).
+///
+/// The row groups are pruned by the statistics and the bloom filters before
+/// decoding, so only the row groups which may contain matching rows are read.
+pub async fn scan_sst(
+    store: &ObjectStoreRef,
+    sst_path: &Path,
+    projection: Option<&[String]>,
+    predicate: Option<&ScanPredicate>,
+) -> Result<ScanResult> {
+    scan_sst_with_limit(store, sst_path, projection, predicate, None).await
+}
+
+/// Same as [scan_sst], but at most `max_row_groups` row groups are read if it
+/// is set, and [ScanResult::truncated] tells whether the scan stops early.
+pub async fn scan_sst_with_limit(
+    store: &ObjectStoreRef,
+    sst_path: &Path,
+    projection: Option<&[String]>,
+    predicate: Option<&ScanPredicate>,
+    max_row_groups: Option<usize>,
+) -> Result<ScanResult> {
+    let chunk_reader = read_sst(store, sst_path, DEFAULT_OBJECT_STORE_TIMEOUT).await?;
+    let parquet_meta_data = parse_parquet_meta_data(&chunk_reader)?;
+    let sst_meta_data = sst_meta_from_parquet(&parquet_meta_data, sst_path)?;
+    let schema = &sst_meta_data.schema;
+
+    let projected_idxs = match projection {
+        Some(columns) => columns
+            .iter()
+            .map(|column| {
+                schema
+                    .index_of(column)
+                    .with_context(|| format!("Projected column not found, column:{}", column))
+            })
+            .collect::<Result<Vec<_>>>()?,
+        None => (0..schema.num_columns()).collect(),
+    };
+
+    let (mut read_row_groups, predicate_col_idx) = match predicate {
+        Some(predicate) => {
+            let col_idx = schema.index_of(&predicate.column).with_context(|| {
+                format!("Predicate column not found, column:{}", predicate.column)
+            })?;
+            ensure!(
+                schema.is_primary_key_index(&col_idx),
+                "Predicate column must be a key column, column:{}",
+                predicate.column
+            );
+
+            let exprs = predicate.to_exprs()?;
+            let arrow_schema = schema.to_arrow_schema_ref();
+            let row_group_filter = RowGroupFilter::try_new(
+                &arrow_schema,
+                parquet_meta_data.row_groups(),
+                sst_meta_data.bloom_filter.as_ref().map(|v| v.filters()),
+                &exprs,
+            )?;
+            (row_group_filter.filter(), Some(col_idx))
+        }
+        None => ((0..parquet_meta_data.num_row_groups()).collect(), None),
+    };
+    let truncated = match max_row_groups {
+        Some(max_row_groups) if read_row_groups.len() > max_row_groups => {
+            read_row_groups.truncate(max_row_groups);
+            true
+        }
+        _ => false,
+    };
+
+    let decoder = ParquetDecoder::try_new(sst_meta_data.storage_format_opts.clone())?;
+    let mut record_batches = Vec::with_capacity(read_row_groups.len());
+    for row_group_idx in &read_row_groups {
+        let record_batch =
+            read_row_group(chunk_reader.clone(), &parquet_meta_data, *row_group_idx)?;
+        let mut record_batch = decoder.decode_record_batch(record_batch)?;
+        if let (Some(predicate), Some(col_idx)) = (predicate, predicate_col_idx) {
+            let column_block = ColumnBlock::try_cast_arrow_array_ref(record_batch.column(col_idx))?;
+            let selection = (0..record_batch.num_rows())
+                .map(|row_idx| Some(predicate.matches(&column_block.datum(row_idx))))
+                .collect::<BooleanArray>();
+            record_batch = compute::filter_record_batch(&record_batch, &selection)?;
+        }
+        if record_batch.num_rows() > 0 {
+            record_batches.push(record_batch.project(&projected_idxs)?);
+        }
+    }
+
+    Ok(ScanResult {
+        read_row_groups,
+        record_batches,
+        truncated,
+    })
+}
+
+/// Scan the ssts at `sst_paths` like [scan_sst], but the ssts older than
+/// `low_watermark` are skipped, see [SstMetaData::is_older_than].
+///
+/// Returns the paths of the scanned ssts together with their [ScanResult]s.
+pub async fn scan_ssts_since(
+    store: &ObjectStoreRef,
+    sst_paths: &[Path],
+    low_watermark: SequenceNumber,
+    projection: Option<&[String]>,
+    predicate: Option<&ScanPredicate>,
+) -> Result<Vec<(Path, ScanResult)>> {
+    let mut results = Vec::with_capacity(sst_paths.len());
+    for sst_path in sst_paths {
+        let sst_meta_data = try_meta_from_sst(store, sst_path).await?;
+        if sst_meta_data.is_older_than(low_watermark) {
+            continue;
+        }
+
+        let result = scan_sst(store, sst_path, projection, predicate).await?;
+        results.push((sst_path.clone(), result));
+    }
+
+    Ok(results)
+}
+
+/// Decode the [SstMetaData] from the key value meta data of the
+/// [encoding::META_KEY] in the parquet meta data.
+fn sst_meta_from_parquet(
+    parquet_meta_data: &ParquetMetaData,
+    sst_path: &Path,
+) -> Result<SstMetaData> {
+    // The arrow schema is also stored in the key value meta data by the parquet
+    // writer, so the meta data must be found by its key.
+    let kv_meta = parquet_meta_data
+        .file_metadata()
+        .key_value_metadata()
+        .and_then(|kv_metas| kv_metas.iter().find(|kv| kv.key == encoding::META_KEY));
+    let kv_meta = match kv_meta {
+        Some(v) => v,
+        None => {
+            MetaDecodeFailure::MissingKv.observe();
+            return Err(anyhow!("Key value meta data not found, path:{}", sst_path));
+        }
+    };
+
+    encoding::decode_sst_meta_data(kv_meta).map_err(|e| {
+        MetaDecodeFailure::from_encoding_error(&e).observe();
+        e.into()
+    })
+}
+
+/// Create the encoder to rewrite the sst of the `parquet_meta_data` with the
+/// `meta_data`, and the size of the row groups and the unit of the timestamp
+/// column are preserved.
+///
+/// The compression of the sst is preserved if `compression` is not set, and
+/// [Compression::ZSTD] is used if the sst has no row group to tell it.
+fn new_rewrite_encoder(
+    parquet_meta_data: &ParquetMetaData,
+    compression: Option<Compression>,
+    meta_data: SstMetaData,
+) -> Result<ParquetEncoder> {
+    let num_rows_per_row_group = parquet_meta_data
+        .row_groups()
+        .iter()
+        .map(|row_group| row_group.num_rows() as usize)
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    let compression = compression.unwrap_or_else(|| {
+        parquet_meta_data
+            .row_groups()
+            .first()
+            .map(|row_group| row_group.column(0).compression())
+            .unwrap_or(Compression::ZSTD)
+    });
+    let timestamp_unit = sst_timestamp_unit(parquet_meta_data, &meta_data)?;
+    let encoder = ParquetEncoder::try_new(
+        ParquetEncoderOptions {
+            num_rows_per_row_group,
+            compression,
+            timestamp_unit,
+            ..Default::default()
+        },
+        meta_data,
+    )?;
+
+    Ok(encoder)
+}
+
+/// Find the unit of the timestamp column stored in the sst, which is also the
+/// unit of the decoded records.
+fn sst_timestamp_unit(
+    parquet_meta_data: &ParquetMetaData,
+    meta_data: &SstMetaData,
+) -> Result<TimeUnit> {
+    let file_meta_data = parquet_meta_data.file_metadata();
+    let arrow_schema = parquet_arrow::parquet_to_arrow_schema(
+        file_meta_data.schema_descr(),
+        file_meta_data.key_value_metadata(),
+    )?;
+    let timestamp_field = arrow_schema.field(meta_data.schema.timestamp_index());
+    // The timestamp column of the sst in hybrid format is a list.
+    let timestamp_type = match timestamp_field.data_type() {
+        DataType::List(item_field) => item_field.data_type(),
+        data_type => data_type,
+    };
+    match timestamp_type {
+        DataType::Timestamp(unit, _) => Ok(*unit),
+        data_type => Err(anyhow!(
+            "Invalid type of timestamp column, name:{}, type:{:?}",
+            timestamp_field.name(),
+            data_type
+        )),
+    }
+}
+
+/// Read the whole `row_group_idx`-th row group in one batch without decoding.
+fn read_row_group<R: ChunkReader + 'static>(
+    chunk_reader: R,
+    parquet_meta_data: &ParquetMetaData,
+    row_group_idx: usize,
+) -> Result<ArrowRecordBatch> {
+    let num_rows = parquet_meta_data.row_group(row_group_idx).num_rows() as usize;
+    let mut reader = ParquetRecordBatchReaderBuilder::try_new(chunk_reader)?
+        .with_row_groups(vec![row_group_idx])
+        .with_batch_size(num_rows.max(1))
+        .build()?;
+
+    let record_batch = reader
+        .next()
+        .with_context(|| format!("Row group is empty, index:{}", row_group_idx))??;
+    Ok(record_batch)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use analytic_engine::{
+        sst::parquet::encoding::ColumnEncoding,
+        tests::util::{MemoryEngineContext, TestEnv},
+    };
+    use arrow::array::{ArrayRef, Int32Array, StringArray, TimestampMillisecondArray, UInt64Array};
+    use async_trait::async_trait;
+    use common_types::{
+        bytes::Bytes,
+        column_schema,
+        datum::DatumKind,
+        schema::{self, Schema, TSID_COLUMN},
+        time::{TimeRange, Timestamp},
+    };
+    use futures::stream::BoxStream;
+    use object_store::{
+        GetResult, ListResult, LocalFileSystem, MultipartId, ObjectMeta, ObjectStore,
+    };
+    use parquet::{
+        arrow::{ArrowWriter, ARROW_SCHEMA_META_KEY},
+        basic::Encoding,
+        file::{metadata::KeyValue, properties::WriterProperties},
+    };
+
+    use super::*;
+
+    type StoreResult<T> = std::result::Result<T, ObjectStoreError>;
+
+    /// Length of the magic at the head of the parquet file.
+    const PARQUET_MAGIC_LEN: usize = 4;
+
+    /// The object store whose requests never respond.
+    #[derive(Debug)]
+    struct PendingObjectStore;
+
+    impl fmt::Display for PendingObjectStore {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "PendingObjectStore")
+        }
+    }
+
+    #[async_trait]
+    impl ObjectStore for PendingObjectStore {
+        async fn put(&self, _location: &Path, _bytes: Bytes) -> StoreResult<()> {
+            futures::future::pending().await
+        }
+
+        async fn put_multipart(
+            &self,
+            _location: &Path,
+        ) -> StoreResult<(MultipartId, Box<dyn tokio::io::AsyncWrite + Unpin + Send>)> {
+            futures::future::pending().await
+        }
+
+        async fn abort_multipart(
+            &self,
+            _location: &Path,
+            _multipart_id: &MultipartId,
+        ) -> StoreResult<()> {
+            futures::future::pending().await
+        }
+
+        async fn get(&self, _location: &Path) -> StoreResult<GetResult> {
+            futures::future::pending().await
+        }
+
+        async fn get_range(
+            &self,
+            _location: &Path,
+            _range: std::ops::Range<usize>,
+        ) -> StoreResult<Bytes> {
+            futures::future::pending().await
+        }
+
+        async fn head(&self, _location: &Path) -> StoreResult<ObjectMeta> {
+            futures::future::pending().await
+        }
+
+        async fn delete(&self, _location: &Path) -> StoreResult<()> {
+            futures::future::pending().await
+        }
+
+        async fn list(
+            &self,
+            _prefix: Option<&Path>,
+        ) -> StoreResult<BoxStream<'_, StoreResult<ObjectMeta>>> {
+            futures::future::pending().await
+        }
+
+        async fn list_with_delimiter(&self, _prefix: Option<&Path>) -> StoreResult<ListResult> {
+            futures::future::pending().await
+        }
+
+        async fn copy(&self, _from: &Path, _to: &Path) -> StoreResult<()> {
+            futures::future::pending().await
+        }
+
+        async fn copy_if_not_exists(&self, _from: &Path, _to: &Path) -> StoreResult<()> {
+            futures::future::pending().await
+        }
+    }
+
+    /// The object store recording the ranges fetched by `get_range`, and the
+    /// deletes fail if `fail_delete` is set.
+    ///
+    /// If `pending_file_head` is set, the ranges starting right after the magic
+    /// at the head of the file never respond, that is the first column chunk
+    /// can't be fetched while the meta data at the tail can.
+    #[derive(Debug)]
+    struct RecordingStore {
+        inner: ObjectStoreRef,
+        fetched_ranges: std::sync::Mutex<Vec<std::ops::Range<usize>>>,
+        fail_delete: bool,
+        pending_file_head: bool,
+    }
+
+    impl fmt::Display for RecordingStore {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "RecordingStore({})", self.inner)
+        }
+    }
+
+    #[async_trait]
+    impl ObjectStore for RecordingStore {
+        async fn put(&self, location: &Path, bytes: Bytes) -> StoreResult<()> {
+            self.inner.put(location, bytes).await
+        }
+
+        async fn put_multipart(
+            &self,
+            location: &Path,
+        ) -> StoreResult<(MultipartId, Box<dyn tokio::io::AsyncWrite + Unpin + Send>)> {
+            self.inner.put_multipart(location).await
+        }
+
+        async fn abort_multipart(
+            &self,
+            location: &Path,
+            multipart_id: &MultipartId,
+        ) -> StoreResult<()> {
+            self.inner.abort_multipart(location, multipart_id).await
+        }
+
+        async fn get(&self, location: &Path) -> StoreResult<GetResult> {
+            self.inner.get(location).await
+        }
+
+        async fn get_range(
+            &self,
+            location: &Path,
+            range: std::ops::Range<usize>,
+        ) -> StoreResult<Bytes> {
+            self.fetched_ranges.lock().unwrap().push(range.clone());
+            if self.pending_file_head && range.start <= PARQUET_MAGIC_LEN {
+                return futures::future::pending().await;
+            }
+            self.inner.get_range(location, range).await
+        }
+
+        async fn head(&self, location: &Path) -> StoreResult<ObjectMeta> {
+            self.inner.head(location).await
+        }
+
+        async fn delete(&self, location: &Path) -> StoreResult<()> {
+            if self.fail_delete {
+                return Err(ObjectStoreError::NotImplemented);
+            }
+            self.inner.delete(location).await
+        }
+
+        async fn list(
+            &self,
+            prefix: Option<&Path>,
+        ) -> StoreResult<BoxStream<'_, StoreResult<ObjectMeta>>> {
+            self.inner.list(prefix).await
+        }
+
+        async fn list_with_delimiter(&self, prefix: Option<&Path>) -> StoreResult<ListResult> {
+            self.inner.list_with_delimiter(prefix).await
+        }
+
+        async fn copy(&self, from: &Path, to: &Path) -> StoreResult<()> {
+            self.inner.copy(from, to).await
+        }
+
+        async fn copy_if_not_exists(&self, from: &Path, to: &Path) -> StoreResult<()> {
+            self.inner.copy_if_not_exists(from, to).await
+        }
+    }
+
+    fn new_store() -> (tempfile::TempDir, ObjectStoreRef) {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Arc::new(LocalFileSystem::new_with_prefix(dir.path()).unwrap());
+        (dir, store)
+    }
+
+    fn build_schema() -> Schema {
+        schema::Builder::new()
+            .auto_increment_column_id(true)
+            .add_key_column(
+                column_schema::Builder::new("key".to_string(), DatumKind::String)
+                    .build()
+                    .unwrap(),
+            )
+            .unwrap()
+            .add_key_column(
+                column_schema::Builder::new("timestamp".to_string(), DatumKind::Timestamp)
+                    .build()
+                    .unwrap(),
+            )
+            .unwrap()
+            .add_normal_column(
+                column_schema::Builder::new("value".to_string(), DatumKind::Int32)
+                    .build()
+                    .unwrap(),
+            )
+            .unwrap()
+            .build()
+            .unwrap()
+    }
+
+    fn build_record_batch(schema: &Schema, num_rows: usize) -> ArrowRecordBatch {
+        let keys = (0..num_rows)
+            .map(|i| format!("key{}", i))
+            .collect::<Vec<_>>();
+        let columns = vec![
+            Arc::new(StringArray::from_iter_values(keys)) as ArrayRef,
+            Arc::new(TimestampMillisecondArray::from_iter_values(
+                (0..num_rows).map(|i| 100 + i as i64),
+            )) as ArrayRef,
+            Arc::new(Int32Array::from_iter_values(0..num_rows as i32)) as ArrayRef,
+        ];
+        ArrowRecordBatch::try_new(schema.to_arrow_schema_ref(), columns).unwrap()
+    }
+
+    fn build_meta_data(schema: &Schema, num_rows: usize, format: StorageFormat) -> SstMetaData {
+        SstMetaData {
+            min_key: Bytes::from_static(b"key0"),
+            max_key: Bytes::from(format!("key{}", num_rows - 1)),
+            time_range: TimeRange::new_unchecked(
+                Timestamp::new(100),
+                Timestamp::new(100 + num_rows as i64),
+            ),
+            max_sequence: 1,
+            schema: schema.clone(),
+            schema_fingerprint: schema.fingerprint(),
+            size: 0,
+            row_num: num_rows as u64,
+            storage_format_opts: StorageFormatOptions::new(format),
+            bloom_filter: Default::default(),
+            row_group_time_ranges: Vec::new(),
+        }
+    }
+
+    /// Write the `record_batch` into a sst with `num_rows_per_row_group` rows
+    /// in every row group.
+    async fn write_sst(
+        store: &ObjectStoreRef,
+        sst_path: &Path,
+        num_rows_per_row_group: usize,
+        meta_data: SstMetaData,
+        record_batch: ArrowRecordBatch,
+    ) {
+        let mut encoder = ParquetEncoder::try_new(
+            ParquetEncoderOptions {
+                num_rows_per_row_group,
+                ..Default::default()
+            },
+            meta_data,
+        )
+        .unwrap();
+        encoder.encode_record_batch(vec![record_batch]).unwrap();
+        let sst_bytes = encoder.close().unwrap();
+        store.put(sst_path, sst_bytes.into()).await.unwrap();
+    }
+
+    fn encode_parquet(kv_metas: Option<Vec<KeyValue>>) -> Vec<u8> {
+        let array = Arc::new(Int32Array::from(vec![1, 2, 3])) as ArrayRef;
+        let record_batch = ArrowRecordBatch::try_from_iter(vec![("a", array)]).unwrap();
+        let write_props = WriterProperties::builder()
+            .set_key_value_metadata(kv_metas)
+            .build();
+        let mut writer =
+            ArrowWriter::try_new(Vec::new(), record_batch.schema(), Some(write_props)).unwrap();
+        writer.write(&record_batch).unwrap();
+        writer.into_inner().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_count_meta_decode_failure() {
+        let (_dir, store) = new_store();
+
+        let bad_header_meta = KeyValue {
+            key: encoding::META_KEY.to_string(),
+            // Base64 of `[1]`, whose header is invalid.
+            value: Some("AQ==".to_string()),
+        };
+        let bad_proto_meta = KeyValue {
+            key: encoding::META_KEY.to_string(),
+            // Base64 of `[0, 255]`, whose header is valid but the protobuf is truncated.
+            value: Some("AP8=".to_string()),
+        };
+        let cases = vec![
+            (
+                b"not a parquet file".to_vec(),
+                MetaDecodeFailure::CorruptFooter,
+            ),
+            (encode_parquet(None), MetaDecodeFailure::MissingKv),
+            (
+                encode_parquet(Some(vec![bad_header_meta])),
+                MetaDecodeFailure::BadHeader,
+            ),
+            (
+                encode_parquet(Some(vec![bad_proto_meta])),
+                MetaDecodeFailure::ProtoError,
+            ),
+        ];
+
+        for (idx, (sst_bytes, reason)) in cases.into_iter().enumerate() {
+            let sst_path = Path::from(format!("{}.sst", idx));
+            store.put(&sst_path, sst_bytes.into()).await.unwrap();
+
+            let counter = SST_META_DECODE_FAILURE_COUNTER.with_label_values(&[reason.as_str()]);
+            let count_before = counter.get();
+            assert!(try_meta_from_sst(&store, &sst_path).await.is_err());
+            assert_eq!(count_before + 1, counter.get());
+        }
+
+        // The corrupt footer is also counted by other helpers parsing the footer.
+        let counter = SST_META_DECODE_FAILURE_COUNTER
+            .with_label_values(&[MetaDecodeFailure::CorruptFooter.as_str()]);
+        let count_before = counter.get();
+        assert!(list_kv_meta_keys(&store, &Path::from("0.sst"))
+            .await
+            .is_err());
+        assert_eq!(count_before + 1, counter.get());
+    }
+
+    #[tokio::test]
+    async fn test_list_kv_meta_keys() {
+        let (_dir, store) = new_store();
+        let schema = build_schema();
+        let meta_kv =
+            encoding::encode_sst_meta_data(build_meta_data(&schema, 3, StorageFormat::Columnar))
+                .unwrap();
+        let custom_kv = |key: &str| KeyValue {
+            key: key.to_string(),
+            value: Some("value".to_string()),
+        };
+
+        let sst_path = Path::from("custom_keys.sst");
+        let kv_metas = vec![meta_kv, custom_kv("custom_a"), custom_kv("custom_b")];
+        store
+            .put(&sst_path, encode_parquet(Some(kv_metas)).into())
+            .await
+            .unwrap();
+        // The arrow schema is always appended by the parquet writer.
+        let keys = list_kv_meta_keys(&store, &sst_path).await.unwrap();
+        assert_eq!(
+            vec![
+                encoding::META_KEY,
+                "custom_a",
+                "custom_b",
+                ARROW_SCHEMA_META_KEY
+            ],
+            keys
+        );
+
+        let sst_path = Path::from("no_keys.sst");
+        store
+            .put(&sst_path, encode_parquet(None).into())
+            .await
+            .unwrap();
+        let keys = list_kv_meta_keys(&store, &sst_path).await.unwrap();
+        assert_eq!(vec![ARROW_SCHEMA_META_KEY], keys);
+    }
+
+    #[tokio::test]
+    async fn test_find_meta_by_key() {
+        let (_dir, store) = new_store();
+        let schema = build_schema();
+        let meta_data = build_meta_data(&schema, 3, StorageFormat::Columnar);
+        let meta_kv = encoding::encode_sst_meta_data(meta_data.clone()).unwrap();
+        let custom_kv = KeyValue {
+            key: "custom".to_string(),
+            value: Some("value".to_string()),
+        };
+
+        // The meta data is not the first key value meta data.
+        let sst_path = Path::from("meta_not_first.sst");
+        store
+            .put(
+                &sst_path,
+                encode_parquet(Some(vec![custom_kv, meta_kv])).into(),
+            )
+            .await
+            .unwrap();
+
+        let chunk_reader = read_sst(&store, &sst_path, DEFAULT_OBJECT_STORE_TIMEOUT)
+            .await
+            .unwrap();
+        let parquet_meta_data = footer::parse_metadata(&chunk_reader).unwrap();
+        let decoded = sst_meta_from_parquet(&parquet_meta_data, &sst_path).unwrap();
+        assert_eq!(meta_data.row_num, decoded.row_num);
+        assert_eq!(meta_data.schema, decoded.schema);
+        let decoded = try_meta_from_sst(&store, &sst_path).await.unwrap();
+        assert_eq!(meta_data.row_num, decoded.row_num);
+    }
+
+    #[tokio::test]
+    async fn test_row_group_time_ranges() {
+        let (_dir, store) = new_store();
+        let schema = build_schema();
+        let record_batch = build_record_batch(&schema, 5);
+        let mut meta_data = build_meta_data(&schema, 5, StorageFormat::Columnar);
+        let row_groups: Vec<_> = (0..5)
+            .step_by(2)
+            .map(|offset| vec![record_batch.slice(offset, 2.min(5 - offset))])
+            .collect();
+        meta_data.row_group_time_ranges =
+            encoding::compute_row_group_time_ranges(&schema, &row_groups).unwrap();
+        let time_range = meta_data.time_range;
+        let sst_path = Path::from("multi_group.sst");
+        write_sst(&store, &sst_path, 2, meta_data, record_batch).await;
+
+        let time_ranges = row_group_time_ranges(&store, &sst_path).await.unwrap();
+        let expected: Vec<_> = [(100, 102), (102, 104), (104, 105)]
+            .into_iter()
+            .map(|(start, end)| {
+                TimeRange::new_unchecked(Timestamp::new(start), Timestamp::new(end))
+            })
+            .collect();
+        assert_eq!(expected, time_ranges);
+        for row_group_time_range in time_ranges {
+            assert_ne!(time_range, row_group_time_range);
+            assert!(time_range.inclusive_start() <= row_group_time_range.inclusive_start());
+            assert!(row_group_time_range.exclusive_end() <= time_range.exclusive_end());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_decode_row_group() {
+        let (_dir, store) = new_store();
+        let schema = build_schema();
+        let record_batch = build_record_batch(&schema, 5);
+        let meta_data = build_meta_data(&schema, 5, StorageFormat::Columnar);
+        let sst_path = Path::from("multi_group.sst");
+        write_sst(&store, &sst_path, 2, meta_data, record_batch.clone()).await;
+
+        let decoded = decode_row_group(&store, &sst_path, 1).await.unwrap();
+        let expected = record_batch.slice(2, 2);
+        assert_eq!(expected.num_rows(), decoded.num_rows());
+        assert_eq!(expected.columns(), decoded.columns());
+
+        // The last row group only contains the left row.
+        let decoded = decode_row_group(&store, &sst_path, 2).await.unwrap();
+        assert_eq!(record_batch.slice(4, 1).columns(), decoded.columns());
+
+        assert!(decode_row_group(&store, &sst_path, 3).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_recompress_sst() {
+        let (_dir, store) = new_store();
+        let schema = build_schema();
+        let record_batch = build_record_batch(&schema, 5);
+        let meta_data = build_meta_data(&schema, 5, StorageFormat::Columnar);
+        let src_path = Path::from("zstd.sst");
+        let dst_path = Path::from("snappy.sst");
+        write_sst(&store, &src_path, 2, meta_data, record_batch).await;
+
+        let recompressed_meta = recompress_sst(&store, &src_path, &dst_path, Compression::SNAPPY)
+            .await
+            .unwrap();
+        let src_meta = meta_from_sst(&store, &src_path).await;
+        let dst_meta = meta_from_sst(&store, &dst_path).await;
+        assert_eq!(src_meta, recompressed_meta);
+        assert_eq!(src_meta, dst_meta);
+
+        for row_group_idx in 0..3 {
+            let src_batch = decode_row_group(&store, &src_path, row_group_idx)
+                .await
+                .unwrap();
+            let dst_batch = decode_row_group(&store, &dst_path, row_group_idx)
+                .await
+                .unwrap();
+            assert_eq!(src_batch, dst_batch);
+        }
+
+        let dst_bytes = store.get(&dst_path).await.unwrap().bytes().await.unwrap();
+        let parquet_meta_data = footer::parse_metadata(&dst_bytes).unwrap();
+        assert_eq!(3, parquet_meta_data.num_row_groups());
+        for row_group in parquet_meta_data.row_groups() {
+            for column in row_group.columns() {
+                assert_eq!(Compression::SNAPPY, column.compression());
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_delete_sst_key_range() {
+        let (_dir, store) = new_store();
+        let schema = build_schema();
+        let record_batch = build_record_batch(&schema, 6);
+        let meta_data = build_meta_data(&schema, 6, StorageFormat::Columnar);
+        let src_path = Path::from("src.sst");
+        let dst_path = Path::from("dst.sst");
+        write_sst(&store, &src_path, 2, meta_data, record_batch).await;
+
+        // Delete the rows of key0 and key1, which make up the first row group.
+        let key_range = KeyRange {
+            start: vec![Datum::from("key0")],
+            end: vec![Datum::from("key2")],
+        };
+        let new_meta = delete_sst_key_range(&store, &src_path, &dst_path, &key_range)
+            .await
+            .unwrap();
+        assert_eq!(new_meta, meta_from_sst(&store, &dst_path).await);
+        assert_eq!(4, new_meta.row_num);
+        let expect_min_key =
+            encode_primary_key(&[Datum::from("key2"), Datum::Timestamp(Timestamp::new(102))])
+                .unwrap();
+        let expect_max_key =
+            encode_primary_key(&[Datum::from("key5"), Datum::Timestamp(Timestamp::new(105))])
+                .unwrap();
+        assert_eq!(expect_min_key, new_meta.min_key);
+        assert_eq!(expect_max_key, new_meta.max_key);
+        assert_eq!(
+            TimeRange::new_unchecked(Timestamp::new(102), Timestamp::new(106)),
+            new_meta.time_range
+        );
+        assert_eq!(2, new_meta.row_group_time_ranges.len());
+        assert!(new_meta.bloom_filter.is_none());
+
+        let expected = r#"{"key":"key2","timestamp":102,"value":2}
+{"key":"key3","timestamp":103,"value":3}
+{"key":"key4","timestamp":104,"value":4}
+{"key":"key5","timestamp":105,"value":5}
+"#;
+        assert_eq!(expected, dump_sst_rows(&store, &dst_path).await.unwrap());
+
+        // Delete a range in the middle of a row group.
+        let key_range = KeyRange {
+            start: vec![Datum::from("key3")],
+            end: vec![Datum::from("key4")],
+        };
+        let new_meta = delete_sst_key_range(&store, &dst_path, &src_path, &key_range)
+            .await
+            .unwrap();
+        assert_eq!(3, new_meta.row_num);
+        assert_eq!(expect_min_key, new_meta.min_key);
+        assert_eq!(expect_max_key, new_meta.max_key);
+        let expected = r#"{"key":"key2","timestamp":102,"value":2}
+{"key":"key4","timestamp":104,"value":4}
+{"key":"key5","timestamp":105,"value":5}
+"#;
+        assert_eq!(expected, dump_sst_rows(&store, &src_path).await.unwrap());
+
+        // Deleting all the rows is rejected.
+        let key_range = KeyRange {
+            start: vec![Datum::from("key0")],
+            end: vec![Datum::from("key9")],
+        };
+        assert!(
+            delete_sst_key_range(&store, &src_path, &dst_path, &key_range)
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_verify_sst_pages() {
+        let (_dir, store) = new_store();
+        let schema = build_schema();
+        let record_batch = build_record_batch(&schema, 5);
+        let meta_data = build_meta_data(&schema, 5, StorageFormat::Columnar);
+        let sst_path = Path::from("verify_pages.sst");
+        write_sst(&store, &sst_path, 3, meta_data, record_batch).await;
+        assert!(verify_sst_pages(&store, &sst_path)
+            .await
+            .unwrap()
+            .is_empty());
+
+        // Corrupt the pages of the value column in the second row group.
+        let mut sst_bytes = read_sst(&store, &sst_path, DEFAULT_OBJECT_STORE_TIMEOUT)
+            .await
+            .unwrap()
+            .to_vec();
+        let parquet_meta_data = footer::parse_metadata(&Bytes::from(sst_bytes.clone())).unwrap();
+        let (start, len) = parquet_meta_data.row_group(1).column(2).byte_range();
+        let start = start as usize;
+        sst_bytes[start..start + len as usize].fill(0xff);
+        let corrupt_path = Path::from("corrupt_pages.sst");
+        store.put(&corrupt_path, sst_bytes.into()).await.unwrap();
+
+        let corrupt_pages = verify_sst_pages(&store, &corrupt_path).await.unwrap();
+        assert_eq!(1, corrupt_pages.len());
+        assert_eq!(1, corrupt_pages[0].row_group_idx);
+        assert_eq!(2, corrupt_pages[0].column_idx);
+        assert_eq!("value", corrupt_pages[0].column);
+        assert!(!corrupt_pages[0].error.is_empty());
+
+        // Flip a bit of the page data rather than the page header, so the pages
+        // can still be read but the decoded value is out of the statistics.
+        let mut encoder = ParquetEncoder::try_new(
+            ParquetEncoderOptions {
+                num_rows_per_row_group: 3,
+                compression: Compression::UNCOMPRESSED,
+                column_encodings: [(
+                    "value".to_string(),
+                    ColumnEncoding {
+                        dictionary_enabled: Some(false),
+                        encoding: Some(Encoding::PLAIN),
+                        ..Default::default()
+                    },
+                )]
+                .into_iter()
+                .collect(),
+                ..Default::default()
+            },
+            build_meta_data(&schema, 5, StorageFormat::Columnar),
+        )
+        .unwrap();
+        encoder
+            .encode_record_batch(vec![build_record_batch(&schema, 5)])
+            .unwrap();
+        let mut sst_bytes = encoder.close().unwrap();
+        let parquet_meta_data = footer::parse_metadata(&Bytes::from(sst_bytes.clone())).unwrap();
+        // The values of the PLAIN page are at the end of the column chunk, and
+        // the last byte is the most significant byte of the last value 4.
+        let (start, len) = parquet_meta_data.row_group(1).column(2).byte_range();
+        sst_bytes[(start + len) as usize - 1] ^= 0x80;
+        let corrupt_path = Path::from("corrupt_page_data.sst");
+        store.put(&corrupt_path, sst_bytes.into()).await.unwrap();
+
+        let corrupt_pages = verify_sst_pages(&store, &corrupt_path).await.unwrap();
+        assert_eq!(1, corrupt_pages.len());
+        assert_eq!(1, corrupt_pages[0].row_group_idx);
+        assert_eq!(2, corrupt_pages[0].column_idx);
+        assert!(corrupt_pages[0]
+            .error
+            .contains("Value out of the range in the statistics"));
+    }
+
+    #[tokio::test]
+    async fn test_check_sst_consistency() {
+        let (_dir, store) = new_store();
+        let schema = build_schema();
+        let encode_key = |i: i64| {
+            encode_primary_key(&[
+                Datum::from(format!("key{}", i).as_str()),
+                Datum::Timestamp(Timestamp::new(100 + i)),
+            ])
+            .unwrap()
+        };
+
+        let mut meta_data = build_meta_data(&schema, 6, StorageFormat::Columnar);
+        meta_data.min_key = encode_key(0);
+        meta_data.max_key = encode_key(5);
+        let sst_path = Path::from("data.sst");
+        write_sst(
+            &store,
+            &sst_path,
+            2,
+            meta_data.clone(),
+            build_record_batch(&schema, 6),
+        )
+        .await;
+        assert!(check_sst_consistency(&store, &sst_path)
+            .await
+            .unwrap()
+            .is_empty());
+
+        // Tamper the meta data.
+        meta_data.row_num = 10;
+        meta_data.min_key = encode_key(1);
+        meta_data.max_key = encode_key(3);
+        meta_data.time_range = TimeRange::new_unchecked(Timestamp::new(101), Timestamp::new(104));
+        let tampered_path = Path::from("tampered.sst");
+        write_sst(
+            &store,
+            &tampered_path,
+            2,
+            meta_data,
+            build_record_batch(&schema, 6),
+        )
+        .await;
+        let inconsistencies = check_sst_consistency(&store, &tampered_path).await.unwrap();
+        let expected = vec![
+            SstInconsistency::RowNum {
+                meta: 10,
+                actual: 6,
+            },
+            SstInconsistency::MinKey {
+                meta: encode_key(1),
+                actual: encode_key(0),
+            },
+            SstInconsistency::MaxKey {
+                meta: encode_key(3),
+                actual: encode_key(5),
+            },
+            SstInconsistency::TimeRange {
+                meta: TimeRange::new_unchecked(Timestamp::new(101), Timestamp::new(104)),
+                actual: TimeRange::new_unchecked(Timestamp::new(100), Timestamp::new(106)),
+            },
+        ];
+        assert_eq!(expected, inconsistencies);
+    }
+
+    #[test]
+    fn test_check_flushed_sst_consistency() {
+        let env = TestEnv::builder().build();
+        let mut test_ctx = env.new_context(MemoryEngineContext::default());
+
+        env.block_on(async {
+            test_ctx.open().await;
+
+            let test_table = "test_table";
+            let fixed_schema_table = test_ctx.create_fixed_schema_table(test_table).await;
+            let start_ms = test_ctx.start_ms();
+            let rows = [
+                (
+                    "key1",
+                    Timestamp::new(start_ms),
+                    "tag1-1",
+                    11.0,
+                    110.0,
+                    "tag2-1",
+                ),
+                (
+                    "key2",
+                    Timestamp::new(start_ms + 1),
+                    "tag1-2",
+                    12.0,
+                    120.0,
+                    "tag2-2",
+                ),
+            ];
+            let row_group = fixed_schema_table.rows_to_row_group(&rows);
+            test_ctx.write_to_table(test_table, row_group).await;
+            test_ctx.flush_table(test_table).await;
+
+            let instance = test_ctx.instance();
+            let table_data = instance
+                .find_table_in_schema(test_ctx.schema_id, test_table)
+                .await
+                .unwrap()
+                .unwrap();
+            let read_view = table_data
+                .current_version()
+                .pick_read_view(TimeRange::min_to_max());
+            let file = &read_view.leveled_ssts[0][0];
+            let sst_path = table_data.set_sst_file_path(file.id());
+
+            let inconsistencies = check_sst_consistency(instance.default_store(), &sst_path)
+                .await
+                .unwrap();
+            assert!(inconsistencies.is_empty(), "{:?}", inconsistencies);
+        });
+    }
+
+    #[tokio::test]
+    async fn test_read_page_index() {
+        let (_dir, store) = new_store();
+        let schema = build_schema();
+        let record_batch = build_record_batch(&schema, 5);
+        let meta_data = build_meta_data(&schema, 5, StorageFormat::Columnar);
+        let sst_path = Path::from("page_index.sst");
+        write_sst(&store, &sst_path, 3, meta_data, record_batch).await;
+
+        let page_index = read_page_index(&store, &sst_path).await.unwrap();
+        assert_eq!(2, page_index.len());
+
+        // (min timestamp, max timestamp, min value, max value) of every row group.
+        let expect_bounds = [(100, 102, 0, 2), (103, 104, 3, 4)];
+        for (row_group_page_index, (min_ts, max_ts, min_value, max_value)) in
+            page_index.iter().zip(expect_bounds)
+        {
+            assert_eq!(3, row_group_page_index.column_index.len());
+            assert_eq!(3, row_group_page_index.offset_index.len());
+            for page_locations in &row_group_page_index.offset_index {
+                assert!(!page_locations.is_empty());
+                assert_eq!(0, page_locations[0].first_row_index);
+            }
+
+            match &row_group_page_index.column_index[1] {
+                Index::INT64(index) => {
+                    assert_eq!(Some(min_ts), index.indexes[0].min);
+                    assert_eq!(Some(max_ts), index.indexes[0].max);
+                }
+                index => panic!("unexpected timestamp index:{:?}", index),
+            }
+            match &row_group_page_index.column_index[2] {
+                Index::INT32(index) => {
+                    assert_eq!(Some(min_value), index.indexes[0].min);
+                    assert_eq!(Some(max_value), index.indexes[0].max);
+                }
+                index => panic!("unexpected value index:{:?}", index),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dump_sst_rows() {
+        let (_dir, store) = new_store();
+        let schema = build_schema();
+        let record_batch = build_record_batch(&schema, 5);
+        let sst_path = Path::from("dump.sst");
+
+        let expected = r#"{"key":"key0","timestamp":100,"value":0}
+{"key":"key1","timestamp":101,"value":1}
+{"key":"key2","timestamp":102,"value":2}
+{"key":"key3","timestamp":103,"value":3}
+{"key":"key4","timestamp":104,"value":4}
+"#;
+        for format in [StorageFormat::Columnar, StorageFormat::ColumnarDeltaTs] {
+            let meta_data = build_meta_data(&schema, 5, format);
+            write_sst(&store, &sst_path, 2, meta_data, record_batch.clone()).await;
+
+            let dump = dump_sst_rows(&store, &sst_path).await.unwrap();
+            assert_eq!(expected, dump);
+        }
+    }
+
+    fn build_hybrid_schema() -> Schema {
+        schema::Builder::new()
+            .auto_increment_column_id(true)
+            .add_key_column(
+                column_schema::Builder::new(TSID_COLUMN.to_string(), DatumKind::UInt64)
+                    .build()
+                    .unwrap(),
+            )
+            .unwrap()
+            .add_key_column(
+                column_schema::Builder::new("timestamp".to_string(), DatumKind::Timestamp)
+                    .build()
+                    .unwrap(),
+            )
+            .unwrap()
+            .add_normal_column(
+                column_schema::Builder::new("host".to_string(), DatumKind::String)
+                    .is_tag(true)
+                    .build()
+                    .unwrap(),
+            )
+            .unwrap()
+            .add_normal_column(
+                column_schema::Builder::new("value".to_string(), DatumKind::Int32)
+                    .build()
+                    .unwrap(),
+            )
+            .unwrap()
+            .build()
+            .unwrap()
+    }
+
+    /// Build the rows of 2 series with `num_rows` rows in total.
+    fn build_hybrid_record_batch(schema: &Schema, num_rows: usize) -> ArrowRecordBatch {
+        let tsids = (0..num_rows)
+            .map(|i| (i * 2 / num_rows) as u64)
+            .collect::<Vec<_>>();
+        let hosts = tsids
+            .iter()
+            .map(|tsid| format!("host{}", tsid))
+            .collect::<Vec<_>>();
+        let columns = vec![
+            Arc::new(UInt64Array::from(tsids)) as ArrayRef,
+            Arc::new(TimestampMillisecondArray::from_iter_values(
+                (0..num_rows).map(|i| 100 + i as i64),
+            )) as ArrayRef,
+            Arc::new(StringArray::from_iter_values(hosts)) as ArrayRef,
+            Arc::new(Int32Array::from_iter_values(0..num_rows as i32)) as ArrayRef,
+        ];
+        ArrowRecordBatch::try_new(schema.to_arrow_schema_ref(), columns).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_sst_equal() {
+        let (_dir, store) = new_store();
+        let schema = build_hybrid_schema();
+        let record_batch = build_hybrid_record_batch(&schema, 6);
+        let columnar_path = Path::from("columnar.sst");
+        let hybrid_path = Path::from("hybrid.sst");
+
+        let meta_data = build_meta_data(&schema, 6, StorageFormat::Columnar);
+        write_sst(&store, &columnar_path, 4, meta_data, record_batch.clone()).await;
+        let meta_data = build_meta_data(&schema, 6, StorageFormat::Hybrid);
+        write_sst(&store, &hybrid_path, 4, meta_data, record_batch.clone()).await;
+
+        assert!(sst_equal(&store, &columnar_path, &hybrid_path)
+            .await
+            .unwrap()
+            .is_none());
+        assert!(sst_equal(&store, &hybrid_path, &columnar_path)
+            .await
+            .unwrap()
+            .is_none());
+
+        // Drop the last row.
+        let mismatch_path = Path::from("mismatch.sst");
+        let meta_data = build_meta_data(&schema, 5, StorageFormat::Hybrid);
+        write_sst(
+            &store,
+            &mismatch_path,
+            4,
+            meta_data,
+            record_batch.slice(0, 5),
+        )
+        .await;
+        let diff = sst_equal(&store, &columnar_path, &mismatch_path)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(diff.schema_diff.is_none());
+        assert_eq!((6, 5), (diff.num_rows_a, diff.num_rows_b));
+        assert_eq!((1, 0), (diff.num_rows_only_in_a, diff.num_rows_only_in_b));
+        assert_eq!(
+            vec![r#"{"tsid":1,"timestamp":105,"host":"host1","value":5}"#.to_string()],
+            diff.rows_only_in_a
+        );
+        assert!(diff.rows_only_in_b.is_empty());
+
+        // Different schemas.
+        let other_schema = build_schema();
+        let other_path = Path::from("other.sst");
+        let meta_data = build_meta_data(&other_schema, 5, StorageFormat::Columnar);
+        write_sst(
+            &store,
+            &other_path,
+            4,
+            meta_data,
+            build_record_batch(&other_schema, 5),
+        )
+        .await;
+        let diff = sst_equal(&store, &columnar_path, &other_path)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(diff.schema_diff.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_convert_sst_storage_format() {
+        let (_dir, store) = new_store();
+        let schema = build_hybrid_schema();
+        let record_batch = build_hybrid_record_batch(&schema, 6);
+        let origin_path = Path::from("origin.sst");
+        let sst_path = Path::from("convert.sst");
+        let meta_data = build_meta_data(&schema, 6, StorageFormat::Columnar);
+        write_sst(
+            &store,
+            &origin_path,
+            4,
+            meta_data.clone(),
+            record_batch.clone(),
+        )
+        .await;
+        write_sst(&store, &sst_path, 4, meta_data, record_batch).await;
+
+        let new_meta = convert_sst_storage_format(&store, &sst_path, StorageFormat::Hybrid)
+            .await
+            .unwrap();
+        assert_eq!(new_meta, meta_from_sst(&store, &sst_path).await);
+        assert_eq!(StorageFormat::Hybrid, new_meta.storage_format_opts.format);
+        let origin_meta = meta_from_sst(&store, &origin_path).await;
+        assert_eq!(origin_meta.min_key, new_meta.min_key);
+        assert_eq!(origin_meta.max_key, new_meta.max_key);
+        assert_eq!(origin_meta.time_range, new_meta.time_range);
+        assert_eq!(origin_meta.row_num, new_meta.row_num);
+
+        assert!(sst_equal(&store, &origin_path, &sst_path)
+            .await
+            .unwrap()
+            .is_none());
+        assert_eq!(
+            dump_sst_rows(&store, &origin_path).await.unwrap(),
+            dump_sst_rows(&store, &sst_path).await.unwrap()
+        );
+
+        // The temporary sst is removed after the swap.
+        let tmp_path = Path::from("convert.sst.tmp");
+        assert!(store.head(&tmp_path).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_convert_sst_without_row_groups() {
+        let (_dir, store) = new_store();
+        let schema = build_hybrid_schema();
+        let meta_data = build_meta_data(&schema, 0, StorageFormat::Columnar);
+        let encoder = ParquetEncoder::try_new(
+            ParquetEncoderOptions {
+                num_rows_per_row_group: 4,
+                ..Default::default()
+            },
+            meta_data,
+        )
+        .unwrap();
+        let sst_path = Path::from("empty.sst");
+        store
+            .put(&sst_path, encoder.close().unwrap().into())
+            .await
+            .unwrap();
+
+        let new_meta = convert_sst_storage_format(&store, &sst_path, StorageFormat::Hybrid)
+            .await
+            .unwrap();
+        assert_eq!(StorageFormat::Hybrid, new_meta.storage_format_opts.format);
+        assert_eq!(0, new_meta.row_num);
+        assert_eq!(new_meta, meta_from_sst(&store, &sst_path).await);
+    }
+
+    #[tokio::test]
+    async fn test_convert_sst_with_failed_cleanup() {
+        let (_dir, local_store) = new_store();
+        let store: ObjectStoreRef = Arc::new(RecordingStore {
+            inner: local_store,
+            fetched_ranges: Default::default(),
+            fail_delete: true,
+            pending_file_head: false,
+        });
+        let schema = build_hybrid_schema();
+        let record_batch = build_hybrid_record_batch(&schema, 6);
+        let sst_path = Path::from("convert.sst");
+        let meta_data = build_meta_data(&schema, 6, StorageFormat::Columnar);
+        write_sst(&store, &sst_path, 4, meta_data, record_batch).await;
+
+        // The original sst is replaced even if the temporary one is left behind.
+        let new_meta = convert_sst_storage_format(&store, &sst_path, StorageFormat::Hybrid)
+            .await
+            .unwrap();
+        assert_eq!(StorageFormat::Hybrid, new_meta.storage_format_opts.format);
+        assert_eq!(new_meta, meta_from_sst(&store, &sst_path).await);
+        let tmp_path = Path::from("convert.sst.tmp");
+        assert!(store.head(&tmp_path).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_decode_sst_columns() {
+        let (_dir, local_store) = new_store();
+        let recording_store = Arc::new(RecordingStore {
+            inner: local_store,
+            fetched_ranges: Default::default(),
+            fail_delete: false,
+            pending_file_head: false,
+        });
+        let store: ObjectStoreRef = recording_store.clone();
+        let schema = build_hybrid_schema();
+        let record_batch = build_hybrid_record_batch(&schema, 6);
+        let sst_path = Path::from("columnar.sst");
+        let meta_data = build_meta_data(&schema, 6, StorageFormat::Columnar);
+        write_sst(&store, &sst_path, 4, meta_data, record_batch.clone()).await;
+
+        let record_batches = decode_sst_columns(&store, &sst_path, &["value", "timestamp"])
+            .await
+            .unwrap();
+        let mut output = String::new();
+        for record_batch in &record_batches {
+            dump_record_batch(record_batch, &mut output).unwrap();
+        }
+        let mut expected = String::new();
+        dump_record_batch(&record_batch.project(&[3, 1]).unwrap(), &mut expected).unwrap();
+        assert_eq!(expected, output);
+
+        // Only the chunks of the projected columns are fetched besides the meta data.
+        let sst_bytes = store.get(&sst_path).await.unwrap().bytes().await.unwrap();
+        let parquet_meta_data = footer::parse_metadata(&sst_bytes).unwrap();
+        let fetched_ranges = recording_store.fetched_ranges.lock().unwrap().clone();
+        let mut fetched_chunk_bytes = 0;
+        for row_group in parquet_meta_data.row_groups() {
+            for (column_idx, column) in row_group.columns().iter().enumerate() {
+                let (start, len) = column.byte_range();
+                let chunk_range = start as usize..(start + len) as usize;
+                let overlapped = fetched_ranges
+                    .iter()
+                    .filter(|range| range.start < chunk_range.end && chunk_range.start < range.end)
+                    .count();
+                if column_idx == 1 || column_idx == 3 {
+                    assert_eq!(1, overlapped, "column_idx:{}", column_idx);
+                    fetched_chunk_bytes += len as usize;
+                } else {
+                    assert_eq!(0, overlapped, "column_idx:{}", column_idx);
+                }
+            }
+        }
+        let data_end = parquet_meta_data
+            .row_groups()
+            .iter()
+            .flat_map(|row_group| row_group.columns())
+            .map(|column| {
+                let (start, len) = column.byte_range();
+                (start + len) as usize
+            })
+            .max()
+            .unwrap();
+        let fetched_data_bytes: usize = fetched_ranges
+            .iter()
+            .filter(|range| range.start < data_end)
+            .map(|range| range.len())
+            .sum();
+        assert_eq!(fetched_chunk_bytes, fetched_data_bytes);
+
+        // The hybrid record batches are stretched even if no collapsible column is
+        // projected.
+        let sst_path = Path::from("hybrid.sst");
+        let meta_data = build_meta_data(&schema, 6, StorageFormat::Hybrid);
+        write_sst(&store, &sst_path, 4, meta_data, record_batch.clone()).await;
+        let record_batches = decode_sst_columns(&store, &sst_path, &["host", "tsid"])
+            .await
+            .unwrap();
+        let mut output = String::new();
+        for record_batch in &record_batches {
+            dump_record_batch(record_batch, &mut output).unwrap();
+        }
+        let mut expected = String::new();
+        dump_record_batch(&record_batch.project(&[2, 0]).unwrap(), &mut expected).unwrap();
+        assert_eq!(expected, output);
+
+        assert!(decode_sst_columns(&store, &sst_path, &["not_exist"])
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_decode_sst_columns_timeout() {
+        let (_dir, local_store) = new_store();
+        let store: ObjectStoreRef = Arc::new(RecordingStore {
+            inner: local_store,
+            fetched_ranges: Default::default(),
+            fail_delete: false,
+            pending_file_head: true,
+        });
+        let schema = build_hybrid_schema();
+        let record_batch = build_hybrid_record_batch(&schema, 6);
+        let sst_path = Path::from("columnar.sst");
+        let meta_data = build_meta_data(&schema, 6, StorageFormat::Columnar);
+        write_sst(&store, &sst_path, 4, meta_data, record_batch).await;
+
+        // The chunk of the first column is never fetched.
+        let timeout = Duration::from_millis(100);
+        let res = tokio::time::timeout(
+            Duration::from_secs(5),
+            decode_sst_columns_with_timeout(&store, &sst_path, &["tsid"], timeout),
+        )
+        .await
+        .expect("the range request should be timeout");
+        let err = res.unwrap_err();
+        assert!(
+            format!("{:#}", err).contains("Object store request timeout"),
+            "err:{:#}",
+            err
+        );
+    }
+
+    #[tokio::test]
+    async fn test_scan_sst() {
+        let (_dir, store) = new_store();
+        let schema = build_schema();
+        let record_batch = build_record_batch(&schema, 5);
+        let meta_data = build_meta_data(&schema, 5, StorageFormat::Columnar);
+        let sst_path = Path::from("scan.sst");
+        write_sst(&store, &sst_path, 2, meta_data, record_batch.clone()).await;
+
+        let projection = vec!["key".to_string(), "value".to_string()];
+        let key = |s: &str| Datum::String(s.into());
+        let cases = [
+            (ScanCondition::Eq(key("key2")), vec!["key2"], vec![2]),
+            (
+                ScanCondition::Range {
+                    start: key("key2"),
+                    end: key("key4"),
+                },
+                vec!["key2", "key3"],
+                vec![2, 3],
+            ),
+        ];
+        for (condition, expect_keys, expect_values) in cases {
+            let predicate = ScanPredicate {
+                column: "key".to_string(),
+                condition,
+            };
+            let result = scan_sst(&store, &sst_path, Some(&projection), Some(&predicate))
+                .await
+                .unwrap();
+            // Only the second row group [key2, key3] may contain the matching rows.
+            assert_eq!(vec![1], result.read_row_groups);
+            assert_eq!(1, result.record_batches.len());
+
+            let scanned = &result.record_batches[0];
+            let expected = ArrowRecordBatch::try_from_iter(vec![
+                ("key", Arc::new(StringArray::from(expect_keys)) as ArrayRef),
+                (
+                    "value",
+                    Arc::new(Int32Array::from(expect_values)) as ArrayRef,
+                ),
+            ])
+            .unwrap();
+            assert_eq!(expected.columns(), scanned.columns());
+        }
+
+        // All the row groups are read without predicate.
+        let result = scan_sst(&store, &sst_path, None, None).await.unwrap();
+        assert_eq!(vec![0, 1, 2], result.read_row_groups);
+        let num_rows: usize = result.record_batches.iter().map(|v| v.num_rows()).sum();
+        assert_eq!(5, num_rows);
+
+        // The predicate on a non-key column is rejected.
+        let predicate = ScanPredicate {
+            column: "value".to_string(),
+            condition: ScanCondition::Eq(Datum::Int32(1)),
+        };
+        assert!(scan_sst(&store, &sst_path, None, Some(&predicate))
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_scan_sst_with_limit() {
+        let (_dir, store) = new_store();
+        let schema = build_schema();
+        let record_batch = build_record_batch(&schema, 5);
+        let meta_data = build_meta_data(&schema, 5, StorageFormat::Columnar);
+        let sst_path = Path::from("scan.sst");
+        // The row groups are [key0, key1], [key2, key3] and [key4].
+        write_sst(&store, &sst_path, 2, meta_data, record_batch.clone()).await;
+
+        let read_keys = |result: &ScanResult| {
+            result
+                .record_batches
+                .iter()
+                .flat_map(|batch| {
+                    let keys = batch
+                        .column(0)
+                        .as_any()
+                        .downcast_ref::<StringArray>()
+                        .unwrap();
+                    keys.iter()
+                        .map(|v| v.unwrap().to_string())
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let result = scan_sst_with_limit(&store, &sst_path, None, None, Some(2))
+            .await
+            .unwrap();
+        assert_eq!(vec![0, 1], result.read_row_groups);
+        assert_eq!(vec!["key0", "key1", "key2", "key3"], read_keys(&result));
+        assert!(result.truncated);
+
+        // The limit applies to the row groups left after pruning.
+        let predicate = ScanPredicate {
+            column: "key".to_string(),
+            condition: ScanCondition::Range {
+                start: Datum::String("key2".into()),
+                end: Datum::String("key5".into()),
+            },
+        };
+        let result = scan_sst_with_limit(&store, &sst_path, None, Some(&predicate), Some(1))
+            .await
+            .unwrap();
+        assert_eq!(vec![1], result.read_row_groups);
+        assert_eq!(vec!["key2", "key3"], read_keys(&result));
+        assert!(result.truncated);
+
+        // Not truncated if the limit is not reached.
+        for max_row_groups in [Some(3), None] {
+            let result = scan_sst_with_limit(&store, &sst_path, None, None, max_row_groups)
+                .await
+                .unwrap();
+            assert_eq!(vec![0, 1, 2], result.read_row_groups);
+            assert!(!result.truncated);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_scan_ssts_since() {
+        let (_dir, store) = new_store();
+        let schema = build_schema();
+        let record_batch = build_record_batch(&schema, 5);
+
+        let mut sst_paths = Vec::new();
+        for max_sequence in [1, 5, 10] {
+            let sst_path = Path::from(format!("{}.sst", max_sequence));
+            let mut meta_data = build_meta_data(&schema, 5, StorageFormat::Columnar);
+            meta_data.max_sequence = max_sequence;
+            write_sst(&store, &sst_path, 2, meta_data, record_batch.clone()).await;
+            sst_paths.push(sst_path);
+        }
+
+        // The sst with max sequence 1 is excluded.
+        let results = scan_ssts_since(&store, &sst_paths, 5, None, None)
+            .await
+            .unwrap();
+        let scanned_paths = results
+            .iter()
+            .map(|(path, _)| path.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(sst_paths[1..].to_vec(), scanned_paths);
+        for (_, result) in &results {
+            assert_eq!(
+                5,
+                result
+                    .record_batches
+                    .iter()
+                    .map(|v| v.num_rows())
+                    .sum::<usize>()
+            );
+        }
+
+        // All the ssts are older.
+        let results = scan_ssts_since(&store, &sst_paths, 11, None, None)
+            .await
+            .unwrap();
+        assert!(results.is_empty());
+
+        // None of the ssts is older.
+        let results = scan_ssts_since(&store, &sst_paths, 0, None, None)
+            .await
+            .unwrap();
+        assert_eq!(3, results.len());
+    }
+
+    #[tokio::test]
+    async fn test_object_store_timeout() {
+        let store: ObjectStoreRef = Arc::new(PendingObjectStore);
+        let sst_path = Path::from("pending.par");
+        let timeout = Duration::from_millis(10);
 
-    encoding::decode_sst_meta_data(&kv_metas[0]).unwrap()
+        let err = try_meta_from_sst_with_timeout(&store, &sst_path, timeout)
+            .await
+            .unwrap_err();
+        let expect = ObjectStoreTimeout {
+            path: sst_path.to_string(),
+            timeout,
+        };
+        assert_eq!(Some(&expect), err.downcast_ref::<ObjectStoreTimeout>());
+    }
 }